@@ -0,0 +1,496 @@
+//! A wasm-bindgen wrapper around `automerge_frontend::Frontend`, so the whole
+//! of automerge can run on the rust implementation. Changes made with
+//! `Frontend.change` are passed to `applyLocalChange` and the resulting patch
+//! back to `Frontend.applyPatch`.
+
+use crate::{js_to_rust, rust_to_js, to_js_err};
+use automerge_frontend::{LocalChange, MutableDocument, Path, Primitive, Value};
+use automerge_protocol::{ActorId, MapType, ObjType, Patch, SequenceType};
+use js_sys::{Array, Function, Number, Object, Proxy, Reflect, Symbol};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(js_name = Frontend)]
+pub struct WasmFrontend(automerge_frontend::Frontend);
+
+#[wasm_bindgen(js_class = Frontend)]
+impl WasmFrontend {
+    /// A new, empty document. If `actor_id` is not given a random one is used.
+    #[wasm_bindgen(constructor)]
+    pub fn new(actor_id: Option<String>) -> Result<WasmFrontend, JsValue> {
+        let mut frontend = automerge_frontend::Frontend::new();
+        if let Some(actor_id) = actor_id {
+            frontend.actor_id = ActorId::try_from(actor_id.as_str()).map_err(to_js_err)?;
+        }
+        Ok(WasmFrontend(frontend))
+    }
+
+    #[wasm_bindgen(getter, js_name = actorId)]
+    pub fn actor_id(&self) -> String {
+        self.0.actor_id.to_hex_string()
+    }
+
+    /// Call `callback` with a proxy of the document to edit, maps and lists
+    /// read from it are proxies too and can be edited the same way. Lists
+    /// have `insertAt`, `deleteAt` and `push`, counters `increment`. Returns
+    /// the change request to pass to `applyLocalChange`, or `undefined` if
+    /// the callback didn't change anything. If the callback throws the
+    /// document is left unchanged and the exception is rethrown. The proxies
+    /// can't be used once the callback has returned.
+    pub fn change(
+        &mut self,
+        message: Option<String>,
+        callback: &Function,
+    ) -> Result<JsValue, JsValue> {
+        let change = self.0.change(message, |doc| {
+            Draft::new(doc).run(callback).map_err(ChangeError::Js)
+        });
+        match change {
+            Ok(Some(change)) => rust_to_js(&change),
+            Ok(None) => Ok(JsValue::UNDEFINED),
+            Err(ChangeError::Js(e)) => Err(e),
+        }
+    }
+
+    /// The whole document as a plain JS object
+    pub fn value(&mut self) -> Result<JsValue, JsValue> {
+        rust_to_js(self.0.state().to_json())
+    }
+
+    /// Every value at `path`, keyed by the ID of the op which set it, or
+    /// `undefined` if there is nothing at `path`
+    #[wasm_bindgen(js_name = getConflicts)]
+    pub fn get_conflicts(&self, path: Array) -> Result<JsValue, JsValue> {
+        match self.0.get_conflicts(&import_path(&path)?) {
+            Some(conflicts) => {
+                let conflicts: serde_json::Map<String, serde_json::Value> = conflicts
+                    .iter()
+                    .map(|(opid, value)| (opid.to_string(), value.to_json()))
+                    .collect();
+                rust_to_js(conflicts)
+            }
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    #[wasm_bindgen(js_name = applyPatch)]
+    pub fn apply_patch(&mut self, patch: JsValue) -> Result<(), JsValue> {
+        let patch: Patch = js_to_rust(&patch)?;
+        self.0.apply_patch(patch).map_err(to_js_err)
+    }
+}
+
+/// The traps of the proxies a change callback edits the document through,
+/// and of the list and counter methods they hand out. The JS functions made
+/// from these borrow the document, so they are dropped, and throw if called,
+/// once the callback returns.
+struct Draft<'a> {
+    doc: RefCell<&'a mut dyn MutableDocument>,
+    /// The handler of every proxy in the draft
+    handler: Object,
+    /// Makes a method which calls `call` with an operation name and the
+    /// path of the object it was taken from
+    method: Function,
+    /// The JS function calling `Draft::call`, set by `run`
+    call: RefCell<JsValue>,
+}
+
+impl<'a> Draft<'a> {
+    fn new(doc: &'a mut dyn MutableDocument) -> Draft<'a> {
+        Draft {
+            doc: RefCell::new(doc),
+            handler: Object::new(),
+            method: Function::new_with_args(
+                "call, op, path",
+                "return function(...args) { \
+                    const result = call(op, path, args); \
+                    return result === undefined ? this : result \
+                }",
+            ),
+            call: RefCell::new(JsValue::UNDEFINED),
+        }
+    }
+
+    /// Call `callback` with a proxy of the root of the document
+    fn run(&self, callback: &Function) -> Result<(), JsValue> {
+        let get = |target, prop, _receiver| self.get(&target, &prop);
+        let set = |target, prop, value, _receiver| self.set(&target, &prop, &value);
+        let delete_property = |target, prop| self.delete_property(&target, &prop);
+        let has = |target, prop| self.has(&target, &prop);
+        let own_keys = |target| self.own_keys(&target);
+        let descriptor = |target, prop| self.own_property_descriptor(&target, &prop);
+        let call = |op: String, path, args| self.call(&op, &path, &args);
+
+        let get =
+            Closure::<dyn Fn(JsValue, JsValue, JsValue) -> Result<JsValue, JsValue>>::borrow(&get);
+        let set =
+            Closure::<dyn Fn(JsValue, JsValue, JsValue, JsValue) -> Result<bool, JsValue>>::borrow(
+                &set,
+            );
+        let delete_property =
+            Closure::<dyn Fn(JsValue, JsValue) -> Result<bool, JsValue>>::borrow(&delete_property);
+        let has = Closure::<dyn Fn(JsValue, JsValue) -> Result<bool, JsValue>>::borrow(&has);
+        let own_keys = Closure::<dyn Fn(JsValue) -> Result<Array, JsValue>>::borrow(&own_keys);
+        let descriptor =
+            Closure::<dyn Fn(JsValue, JsValue) -> Result<JsValue, JsValue>>::borrow(&descriptor);
+        let call =
+            Closure::<dyn Fn(String, Array, Array) -> Result<JsValue, JsValue>>::borrow(&call);
+
+        Reflect::set(&self.handler, &"get".into(), get.as_ref())?;
+        Reflect::set(&self.handler, &"set".into(), set.as_ref())?;
+        Reflect::set(
+            &self.handler,
+            &"deleteProperty".into(),
+            delete_property.as_ref(),
+        )?;
+        Reflect::set(&self.handler, &"has".into(), has.as_ref())?;
+        Reflect::set(&self.handler, &"ownKeys".into(), own_keys.as_ref())?;
+        Reflect::set(
+            &self.handler,
+            &"getOwnPropertyDescriptor".into(),
+            descriptor.as_ref(),
+        )?;
+        *self.call.borrow_mut() = call.as_ref().clone();
+
+        let root = self.proxy(Object::new().into(), Array::new())?;
+        callback.call1(&JsValue::NULL, &root)?;
+        Ok(())
+    }
+
+    fn get(&self, target: &JsValue, prop: &JsValue) -> Result<JsValue, JsValue> {
+        let key = match prop.as_string() {
+            Some(key) => key,
+            None => return Reflect::get(target, prop),
+        };
+        let path = path_of(target)?;
+        if Array::is_array(target) {
+            match key.as_str() {
+                "length" => return Ok(self.list_len(&path)?.into()),
+                "insertAt" | "deleteAt" | "push" => return self.method(&key, &path),
+                _ => {}
+            }
+            if let Some(index) = parse_index(&key) {
+                let path = path.concat(&Array::of1(&index.into()));
+                return Ok(self.export(path)?.unwrap_or(JsValue::UNDEFINED));
+            }
+        } else if let Some(value) = self.export(path.concat(&Array::of1(prop)))? {
+            return Ok(value);
+        }
+        Reflect::get(target, prop)
+    }
+
+    fn set(&self, target: &JsValue, prop: &JsValue, value: &JsValue) -> Result<bool, JsValue> {
+        let key = prop
+            .as_string()
+            .ok_or_else(|| js_sys::TypeError::new("Only string keys can be set in a document"))?;
+        let path = path_of(target)?;
+        let value = import_value(value)?;
+        if Array::is_array(target) {
+            let index = parse_index(&key).ok_or_else(|| {
+                js_sys::TypeError::new("Lists can only be assigned to at an index")
+            })?;
+            let len = self.list_len(&path)?;
+            let path = import_path(&path)?.index(index);
+            if index == len {
+                self.edit(LocalChange::insert(path, value))?;
+            } else {
+                self.edit(LocalChange::set(path, value))?;
+            }
+        } else {
+            self.edit(LocalChange::set(import_path(&path)?.key(key), value))?;
+        }
+        Ok(true)
+    }
+
+    fn delete_property(&self, target: &JsValue, prop: &JsValue) -> Result<bool, JsValue> {
+        if Array::is_array(target) {
+            return Err(js_sys::TypeError::new("Use deleteAt to remove list elements").into());
+        }
+        let key = match prop.as_string() {
+            Some(key) => key,
+            None => return Ok(true),
+        };
+        let path = path_of(target)?.concat(&Array::of1(prop));
+        if self.has_value(&path)? {
+            self.edit(LocalChange::delete(
+                import_path(&path_of(target)?)?.key(key),
+            ))?;
+        }
+        Ok(true)
+    }
+
+    fn has(&self, target: &JsValue, prop: &JsValue) -> Result<bool, JsValue> {
+        if let Some(key) = prop.as_string() {
+            let path = path_of(target)?;
+            if Array::is_array(target) {
+                if let Some(index) = parse_index(&key) {
+                    return Ok(index < self.list_len(&path)?);
+                }
+            } else if self.has_value(&path.concat(&Array::of1(prop)))? {
+                return Ok(true);
+            }
+        }
+        Reflect::has(target, prop)
+    }
+
+    fn own_keys(&self, target: &JsValue) -> Result<Array, JsValue> {
+        let path = path_of(target)?;
+        let keys = Array::new();
+        if Array::is_array(target) {
+            for index in 0..self.list_len(&path)? {
+                keys.push(&index.to_string().into());
+            }
+            keys.push(&"length".into());
+        } else {
+            let keys_at_path = self.doc.borrow().keys_at_path(&import_path(&path)?);
+            for key in keys_at_path.unwrap_or_default() {
+                keys.push(&key.into());
+            }
+        }
+        Ok(keys)
+    }
+
+    fn own_property_descriptor(
+        &self,
+        target: &JsValue,
+        prop: &JsValue,
+    ) -> Result<JsValue, JsValue> {
+        if prop.as_string().is_none() {
+            return Reflect::get_own_property_descriptor(target.unchecked_ref::<Object>(), prop);
+        }
+        let descriptor = Object::new();
+        if Array::is_array(target) && prop.as_string().as_deref() == Some("length") {
+            // `length` is a non-configurable property of the target, so the
+            // proxy has to report it as one
+            Reflect::set(
+                &descriptor,
+                &"value".into(),
+                &self.list_len(&path_of(target)?)?.into(),
+            )?;
+            Reflect::set(&descriptor, &"writable".into(), &JsValue::TRUE)?;
+            Reflect::set(&descriptor, &"enumerable".into(), &JsValue::FALSE)?;
+            Reflect::set(&descriptor, &"configurable".into(), &JsValue::FALSE)?;
+        } else if self.has(target, prop)? && !Reflect::has(target, prop)? {
+            Reflect::set(&descriptor, &"value".into(), &self.get(target, prop)?)?;
+            Reflect::set(&descriptor, &"writable".into(), &JsValue::TRUE)?;
+            Reflect::set(&descriptor, &"enumerable".into(), &JsValue::TRUE)?;
+            Reflect::set(&descriptor, &"configurable".into(), &JsValue::TRUE)?;
+        } else {
+            return Ok(JsValue::UNDEFINED);
+        }
+        Ok(descriptor.into())
+    }
+
+    /// The list and counter methods, `path` is the list or counter they were
+    /// taken from
+    fn call(&self, op: &str, path: &Array, args: &Array) -> Result<JsValue, JsValue> {
+        let list = import_path(path)?;
+        match op {
+            "insertAt" => {
+                let index = index_arg(&args.get(0))?;
+                for (offset, value) in args.iter().skip(1).enumerate() {
+                    let path = list.clone().index(index + offset as u32);
+                    self.edit(LocalChange::insert(path, import_value(&value)?))?;
+                }
+                Ok(JsValue::UNDEFINED)
+            }
+            "deleteAt" => {
+                let index = index_arg(&args.get(0))?;
+                let count = match args.get(1) {
+                    count if count.is_undefined() => 1,
+                    count => index_arg(&count)?,
+                };
+                for _ in 0..count {
+                    self.edit(LocalChange::delete(list.clone().index(index)))?;
+                }
+                Ok(JsValue::UNDEFINED)
+            }
+            "push" => {
+                let mut len = self.list_len(path)?;
+                for value in args.iter() {
+                    self.edit(LocalChange::insert(
+                        list.clone().index(len),
+                        import_value(&value)?,
+                    ))?;
+                    len += 1;
+                }
+                Ok(len.into())
+            }
+            "increment" => {
+                let by = match args.get(0) {
+                    by if by.is_undefined() => 1,
+                    by => safe_integer(&by)?,
+                };
+                self.edit(LocalChange::increment_by(list, by))?;
+                Ok(JsValue::UNDEFINED)
+            }
+            _ => Err(js_sys::Error::new(&format!("Unknown method {}", op)).into()),
+        }
+    }
+
+    /// The value to hand to JS for the value at `path`, if there is one.
+    /// Maps and lists are wrapped in proxies so they can be edited in place,
+    /// without building their contents, only other values are built.
+    fn export(&self, path: Array) -> Result<Option<JsValue>, JsValue> {
+        let doc_path = import_path(&path)?;
+        let object_type = self.doc.borrow().object_type_at_path(&doc_path);
+        let value = match object_type {
+            Some(ObjType::Map(MapType::Map)) | Some(ObjType::Map(MapType::Table)) => {
+                return self.proxy(Object::new().into(), path).map(Some)
+            }
+            Some(ObjType::Sequence(SequenceType::List)) => {
+                return self.proxy(Array::new().into(), path).map(Some)
+            }
+            _ => self.doc.borrow().value_at_path(&doc_path),
+        };
+        match value {
+            Some(Value::Primitive(Primitive::Counter(value))) => {
+                let counter = counter_object(value)?;
+                Reflect::set(
+                    &counter,
+                    &"increment".into(),
+                    &self.method("increment", &path)?,
+                )?;
+                Ok(Some(counter))
+            }
+            Some(other) => rust_to_js(other.to_json()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn proxy(&self, target: JsValue, path: Array) -> Result<JsValue, JsValue> {
+        Reflect::set(&target, &path_symbol(), &path)?;
+        Ok(Proxy::new(&target, &self.handler).into())
+    }
+
+    fn method(&self, op: &str, path: &Array) -> Result<JsValue, JsValue> {
+        self.method
+            .call3(&JsValue::NULL, &self.call.borrow(), &op.into(), path)
+    }
+
+    fn has_value(&self, path: &Array) -> Result<bool, JsValue> {
+        let path = import_path(path)?;
+        let doc = self.doc.borrow();
+        Ok(doc.object_type_at_path(&path).is_some() || doc.value_at_path(&path).is_some())
+    }
+
+    fn list_len(&self, path: &Array) -> Result<u32, JsValue> {
+        let path = import_path(path)?;
+        Ok(self.doc.borrow().len_at_path(&path).unwrap_or(0) as u32)
+    }
+
+    fn edit(&self, change: LocalChange) -> Result<(), JsValue> {
+        self.doc.borrow_mut().add_change(change).map_err(to_js_err)
+    }
+}
+
+/// A counter starting at `value`, assign it into a document in a change
+/// callback to create the counter
+#[wasm_bindgen]
+pub fn counter(value: JsValue) -> Result<JsValue, JsValue> {
+    counter_object(safe_integer(&value)?)
+}
+
+fn counter_object(value: i64) -> Result<JsValue, JsValue> {
+    let counter = Object::new();
+    Reflect::set(&counter, &counter_symbol(), &JsValue::TRUE)?;
+    Reflect::set(&counter, &"value".into(), &(value as f64).into())?;
+    Ok(counter.into())
+}
+
+fn counter_symbol() -> JsValue {
+    Symbol::for_("automerge.counter").into()
+}
+
+/// The property of a proxy's target holding the path of the object it
+/// stands for
+fn path_symbol() -> JsValue {
+    Symbol::for_("automerge.path").into()
+}
+
+fn path_of(target: &JsValue) -> Result<Array, JsValue> {
+    Ok(Reflect::get(target, &path_symbol())?.unchecked_into())
+}
+
+fn parse_index(key: &str) -> Option<u32> {
+    key.parse::<u32>().ok().filter(|i| i.to_string() == key)
+}
+
+fn index_arg(value: &JsValue) -> Result<u32, JsValue> {
+    value
+        .as_f64()
+        .filter(|i| *i >= 0.0 && i.fract() == 0.0 && *i <= u32::MAX as f64)
+        .map(|i| i as u32)
+        .ok_or_else(|| js_sys::RangeError::new("List indexes must be non-negative integers").into())
+}
+
+/// Counters are `i64`s in rust, only integers which a JS number represents
+/// exactly are accepted so nothing is silently rounded
+fn safe_integer(value: &JsValue) -> Result<i64, JsValue> {
+    match value.as_f64() {
+        Some(n) if Number::is_safe_integer(value) => Ok(n as i64),
+        _ => Err(js_sys::RangeError::new("Counter values must be safe integers").into()),
+    }
+}
+
+/// The only error a change callback can produce, invalid changes are thrown
+/// as JS exceptions from the proxy traps
+#[derive(Debug)]
+enum ChangeError {
+    Js(JsValue),
+}
+
+impl fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeError::Js(e) => write!(f, "change callback threw {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChangeError {}
+
+fn import_path(path: &Array) -> Result<Path, JsValue> {
+    path.iter().try_fold(Path::root(), |path, elem| {
+        if let Some(key) = elem.as_string() {
+            Ok(path.key(key))
+        } else if let Some(index) = elem.as_f64().filter(|i| *i >= 0.0 && i.fract() == 0.0) {
+            Ok(path.index(index as u32))
+        } else {
+            Err(js_sys::Error::new("Path elements must be strings or non-negative integers").into())
+        }
+    })
+}
+
+/// Convert a plain JS value, integers become ints rather than floats, a
+/// `Uint8Array` becomes bytes and a `counter` a counter
+fn import_value(value: &JsValue) -> Result<Value, JsValue> {
+    if value.is_object() && Reflect::has(value, &counter_symbol())? {
+        let value = Reflect::get(value, &"value".into())?;
+        return Ok(Value::Primitive(Primitive::Counter(safe_integer(&value)?)));
+    }
+    if let Some(bytes) = value.dyn_ref::<js_sys::Uint8Array>() {
+        return Ok(Value::Primitive(Primitive::Bytes(bytes.to_vec())));
+    }
+    let json: serde_json::Value = js_to_rust(value)?;
+    Ok(value_from_json(&json))
+}
+
+fn value_from_json(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Primitive(Primitive::Int(i)),
+            None => Value::Primitive(Primitive::F64(n.as_f64().unwrap_or(0.0))),
+        },
+        serde_json::Value::Object(kvs) => Value::Map(
+            kvs.iter()
+                .map(|(k, v)| (k.clone(), value_from_json(v)))
+                .collect(),
+            automerge_protocol::MapType::Map,
+        ),
+        serde_json::Value::Array(vs) => Value::Sequence(vs.iter().map(value_from_json).collect()),
+        other => Value::from_json(other),
+    }
+}
@@ -2,13 +2,18 @@
 
 use automerge_backend::{AutomergeError, Backend, Change};
 use automerge_protocol::{ActorId, ChangeHash, UncompressedChange};
-use js_sys::{Array, Uint8Array};
+use js_sys::{Array, Uint32Array, Uint8Array};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fmt::Display;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
+mod frontend;
+mod serialize;
+pub use frontend::{counter, WasmFrontend};
+use serialize::SafeIntegers;
+
 extern crate web_sys;
 #[allow(unused_macros)]
 macro_rules! log {
@@ -33,8 +38,15 @@ fn js_to_rust<T: DeserializeOwned>(value: &JsValue) -> Result<T, JsValue> {
     value.into_serde().map_err(json_error_to_js)
 }
 
+/// Build the JS value directly rather than going via a JSON string. The
+/// result is the same as `JSON.parse` would give except that integers which a
+/// JS number can't represent exactly become a `BigInt`.
 fn rust_to_js<T: Serialize>(value: T) -> Result<JsValue, JsValue> {
-    JsValue::from_serde(&value).map_err(json_error_to_js)
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible()
+        .serialize_large_number_types_as_bigints(true);
+    value
+        .serialize(SafeIntegers(&serializer))
+        .map_err(JsValue::from)
 }
 
 #[wasm_bindgen]
@@ -120,6 +132,37 @@ pub fn load_changes(input: Object, changes: Array) -> Result<JsValue, JsValue> {
     })
 }
 
+/// Like `applyChanges` but the changes are concatenated into `buffer`, with
+/// `offsets` giving the start of each change. If `offsets` is not given the
+/// changes are found by parsing `buffer`.
+#[wasm_bindgen(js_name = applyChangesBuffer)]
+pub fn apply_changes_buffer(
+    input: Object,
+    buffer: Uint8Array,
+    offsets: Option<Uint32Array>,
+) -> Result<JsValue, JsValue> {
+    let ch = import_changes_buffer(&buffer, offsets.as_ref())?;
+    get_mut_input(input, move |state| {
+        let patch = state.0.apply_changes(ch)?;
+        Ok(array(&[patch]).unwrap())
+    })
+}
+
+/// Like `loadChanges` but the changes are concatenated into `buffer`, see
+/// `applyChangesBuffer`
+#[wasm_bindgen(js_name = loadChangesBuffer)]
+pub fn load_changes_buffer(
+    input: Object,
+    buffer: Uint8Array,
+    offsets: Option<Uint32Array>,
+) -> Result<JsValue, JsValue> {
+    let ch = import_changes_buffer(&buffer, offsets.as_ref())?;
+    get_mut_input(input, move |state| {
+        state.0.load_changes(ch)?;
+        Ok(Array::new())
+    })
+}
+
 #[wasm_bindgen(js_name = load)]
 pub fn load(data: JsValue) -> Result<JsValue, JsValue> {
     let data = data.dyn_into::<Uint8Array>().unwrap().to_vec();
@@ -144,9 +187,18 @@ pub fn clone(input: Object) -> Result<Object, JsValue> {
     Ok(wrapper(state, false, heads))
 }
 
+/// Fails if the backend was loaded from a document with compacted history,
+/// which would be saved with a snapshot the JS backend can't read, unless
+/// `allowSnapshot` is true
 #[wasm_bindgen(js_name = save)]
-pub fn save(input: Object) -> Result<JsValue, JsValue> {
+pub fn save(input: Object, allow_snapshot: Option<bool>) -> Result<JsValue, JsValue> {
     get_input(input, |state| {
+        if state.0.has_compacted_history() && allow_snapshot != Some(true) {
+            return Err(to_js_err(
+                "this document has compacted history, which is saved as a snapshot \
+                 only automerge-rs can load; pass allowSnapshot to save it anyway",
+            ));
+        }
         state
             .0
             .save()
@@ -164,6 +216,17 @@ pub fn get_changes(input: Object, have_deps: JsValue) -> Result<JsValue, JsValue
     })
 }
 
+/// Like `getChanges` but returns `[buffer, offsets]`, with the changes
+/// concatenated into one `Uint8Array` and a `Uint32Array` of the offset of
+/// each change
+#[wasm_bindgen(js_name = getChangesBuffer)]
+pub fn get_changes_buffer(input: Object, have_deps: JsValue) -> Result<JsValue, JsValue> {
+    let deps: Vec<ChangeHash> = js_to_rust(&have_deps)?;
+    get_input(input, |state| {
+        Ok(export_changes_buffer(state.0.get_changes(&deps)).into())
+    })
+}
+
 #[wasm_bindgen(js_name = getChangesForActor)]
 pub fn get_changes_for_actor(input: Object, actorid: JsValue) -> Result<JsValue, JsValue> {
     let actorid: ActorId = js_to_rust(&actorid)?;
@@ -176,6 +239,36 @@ pub fn get_changes_for_actor(input: Object, actorid: JsValue) -> Result<JsValue,
     })
 }
 
+/// The most recent change made by `applyLocalChange`, or `null` if there
+/// hasn't been one
+#[wasm_bindgen(js_name = getLastLocalChange)]
+pub fn get_last_local_change(input: Object) -> Result<JsValue, JsValue> {
+    get_input(input, |state| {
+        let last = state
+            .0
+            .last_local_actor()
+            .and_then(|actor| state.0.get_last_local_change(actor));
+        Ok(match last {
+            Some(change) => Uint8Array::from(change.bytes.as_slice()).into(),
+            None => JsValue::null(),
+        })
+    })
+}
+
+/// The changes made by `applyLocalChange`, for the actor of the most recent
+/// one, after `seq`, to resend after reconnecting
+#[wasm_bindgen(js_name = getChangesSinceLocal)]
+pub fn get_changes_since_local(input: Object, seq: JsValue) -> Result<JsValue, JsValue> {
+    let seq: u64 = js_to_rust(&seq)?;
+    get_input(input, |state| {
+        let changes = match state.0.last_local_actor() {
+            Some(actor) => state.0.get_changes_since_local(actor, seq),
+            None => Vec::new(),
+        };
+        Ok(export_changes(changes).into())
+    })
+}
+
 #[wasm_bindgen(js_name = getMissingDeps)]
 pub fn get_missing_deps(input: Object) -> Result<JsValue, JsValue> {
     get_input(input, |state| rust_to_js(state.0.get_missing_deps()))
@@ -190,6 +283,45 @@ fn import_changes(changes: &Array) -> Result<Vec<Change>, AutomergeError> {
     Ok(ch)
 }
 
+fn import_changes_buffer(
+    buffer: &Uint8Array,
+    offsets: Option<&Uint32Array>,
+) -> Result<Vec<Change>, JsValue> {
+    let offsets = match offsets {
+        Some(offsets) => offsets.to_vec(),
+        None => return Change::load_document(&buffer.to_vec()).map_err(to_js_err),
+    };
+    let mut ch = Vec::with_capacity(offsets.len());
+    for (i, start) in offsets.iter().enumerate() {
+        let end = offsets
+            .get(i + 1)
+            .copied()
+            .unwrap_or_else(|| buffer.length());
+        if *start > end || end > buffer.length() {
+            return Err(to_js_err(format!("invalid change offset {}", start)));
+        }
+        // Each change is copied into wasm memory once, straight from `buffer`
+        let bytes = buffer.subarray(*start, end).to_vec();
+        ch.push(Change::from_bytes(bytes).map_err(to_js_err)?);
+    }
+    Ok(ch)
+}
+
+fn export_changes_buffer(changes: Vec<&Change>) -> Array {
+    let mut bytes = Vec::with_capacity(changes.iter().map(|c| c.bytes.len()).sum());
+    let mut offsets = Vec::with_capacity(changes.len());
+    for c in changes {
+        offsets.push(bytes.len() as u32);
+        bytes.extend_from_slice(&c.bytes);
+    }
+    let buffer: Uint8Array = bytes.as_slice().into();
+    let offsets: Uint32Array = offsets.as_slice().into();
+    let result = Array::new();
+    result.push(buffer.as_ref());
+    result.push(offsets.as_ref());
+    result
+}
+
 fn export_changes(changes: Vec<&Change>) -> Array {
     let result = Array::new();
     for c in changes {
@@ -232,7 +364,7 @@ where
 
 fn get_mut_input<F>(input: Object, action: F) -> Result<JsValue, JsValue>
 where
-    F: Fn(&mut State) -> Result<Array, AutomergeError>,
+    F: FnOnce(&mut State) -> Result<Array, AutomergeError>,
 {
     let mut state: State = get_state(&input)?;
 
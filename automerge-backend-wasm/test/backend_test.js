@@ -48,5 +48,56 @@ describe('Automerge.Backend', () => {
       }
       const doc2 = Backend.applyLocalChange(doc1, change)
     })
+
+    it('should return integers too large for a JS number as a BigInt', () => {
+      const change = {
+        actor: '55f250d0f76b4e15923600f98ebed8d7', seq: 1, startOp: 1, deps: [], time: 0, message: '',
+        ops: [
+          {action: 'set', obj: '_root', key: 'big', insert: false, pred: [], value: 2 ** 60},
+          {action: 'set', obj: '_root', key: 'small', insert: false, pred: [], value: 3}
+        ]
+      }
+      const [backend, patch] = Backend.applyLocalChange(Backend.init(), change)
+      assert.strictEqual(patch.diffs.props.big['1@55f250d0f76b4e15923600f98ebed8d7'].value, 2n ** 60n)
+      assert.strictEqual(patch.diffs.props.small['2@55f250d0f76b4e15923600f98ebed8d7'].value, 3)
+      assert.strictEqual(patch.maxOp, 2)
+      assert.strictEqual(Backend.getPatch(backend).diffs.props.big['1@55f250d0f76b4e15923600f98ebed8d7'].value, 2n ** 60n)
+    })
+  })
+})
+
+describe('Automerge.Backend buffers', () => {
+  function makeChanges(actor, count) {
+    let backend = Backend.init()
+    for (let seq = 1; seq <= count; seq++) {
+      const change = {
+        actor, seq, startOp: seq, deps: [], time: 0, message: '',
+        ops: [{action: 'set', obj: '_root', key: 'x', insert: false, pred: [], value: seq}]
+      }
+      backend = Backend.applyLocalChange(backend, change)[0]
+    }
+    return backend
+  }
+
+  it('should round trip changes through a concatenated buffer', () => {
+    const source = makeChanges('55f250d0f76b4e15923600f98ebed8d7', 3)
+    const [buffer, offsets] = Backend.getChangesBuffer(source, [])
+    assert.strictEqual(offsets.length, 3)
+    assert.strictEqual(offsets[0], 0)
+    const changes = Backend.getChanges(source, [])
+    assert.strictEqual(buffer.length, changes.reduce((len, c) => len + c.length, 0))
+
+    const [withOffsets, patch] = Backend.applyChangesBuffer(Backend.init(), buffer, offsets)
+    assert.strictEqual(patch.maxOp, 3)
+    assert.deepStrictEqual(Backend.getHeads(withOffsets), Backend.getHeads(source))
+
+    const withoutOffsets = Backend.loadChangesBuffer(Backend.init(), buffer)
+    assert.deepStrictEqual(Backend.getHeads(withoutOffsets), Backend.getHeads(source))
+  })
+
+  it('should reject offsets outside the buffer', () => {
+    const source = makeChanges('55f250d0f76b4e15923600f98ebed8d7', 1)
+    const [buffer] = Backend.getChangesBuffer(source, [])
+    assert.throws(() => Backend.applyChangesBuffer(Backend.init(), buffer, new Uint32Array([buffer.length + 1])))
   })
 })
@@ -0,0 +1,112 @@
+const assert = require('assert')
+const Automerge = require('..')
+
+// Make a change with the frontend and feed it through a backend, as an
+// application using both would
+function change(frontend, backend, callback) {
+  const request = frontend.change(null, callback)
+  if (request === undefined) return backend
+  const [newBackend, patch] = Automerge.applyLocalChange(backend, request)
+  frontend.applyPatch(patch)
+  return newBackend
+}
+
+describe('Automerge.Frontend', () => {
+  it('should set and read back values', () => {
+    const frontend = new Automerge.Frontend()
+    let backend = Automerge.init()
+    backend = change(frontend, backend, doc => {
+      doc.bird = 'magpie'
+      doc.birds = []
+      doc.birds.push('wren')
+      assert.strictEqual(doc.birds[0], 'wren')
+      assert.strictEqual(doc.birds.length, 1)
+    })
+    assert.deepStrictEqual(frontend.value(), {bird: 'magpie', birds: ['wren']})
+  })
+
+  it('should edit nested maps and lists in place', () => {
+    const frontend = new Automerge.Frontend()
+    let backend = Automerge.init()
+    backend = change(frontend, backend, doc => {
+      doc.cards = [{title: 'one'}, {title: 'three'}]
+      doc.cards.insertAt(1, {title: 'two'})
+      doc.cards[2].title = 'four'
+      doc.cards.deleteAt(0)
+      doc.owner = {name: 'alice', email: 'alice@example.com'}
+      delete doc.owner.email
+      assert.deepStrictEqual(Object.keys(doc), ['cards', 'owner'])
+      assert.ok('owner' in doc)
+      assert.deepStrictEqual(doc.cards.map(card => card.title), ['two', 'four'])
+      assert.deepStrictEqual(JSON.parse(JSON.stringify(doc.owner)), {name: 'alice'})
+    })
+    assert.deepStrictEqual(frontend.value(), {
+      cards: [{title: 'two'}, {title: 'four'}],
+      owner: {name: 'alice'}
+    })
+  })
+
+  it('should not produce a change if nothing was changed', () => {
+    const frontend = new Automerge.Frontend()
+    assert.strictEqual(frontend.change(null, () => {}), undefined)
+  })
+
+  it('should leave the document unchanged if the callback throws', () => {
+    const frontend = new Automerge.Frontend()
+    assert.throws(() => {
+      frontend.change(null, doc => {
+        doc.bird = 'magpie'
+        throw new Error('oops')
+      })
+    }, /oops/)
+    assert.deepStrictEqual(frontend.value(), {})
+  })
+
+  it('should not allow a draft to be used after the change', () => {
+    const frontend = new Automerge.Frontend()
+    let draft
+    frontend.change(null, doc => { draft = doc })
+    assert.throws(() => { draft.bird = 'magpie' })
+  })
+
+  it('should increment counters', () => {
+    const frontend = new Automerge.Frontend()
+    let backend = Automerge.init()
+    backend = change(frontend, backend, doc => { doc.count = Automerge.counter(1) })
+    backend = change(frontend, backend, doc => { doc.count.increment(2) })
+    backend = change(frontend, backend, doc => { doc.count.increment() })
+    assert.deepStrictEqual(frontend.value(), {count: 4})
+  })
+
+  it('should reject counter values which are not safe integers', () => {
+    const frontend = new Automerge.Frontend()
+    assert.throws(() => Automerge.counter(1.5), RangeError)
+    assert.throws(() => Automerge.counter(2 ** 53), RangeError)
+    frontend.change(null, doc => { doc.count = Automerge.counter(1) })
+    assert.throws(() => frontend.change(null, doc => doc.count.increment(2 ** 60)), RangeError)
+    assert.throws(() => frontend.change(null, doc => doc.count.increment('1')), RangeError)
+  })
+
+  it('should store a Uint8Array as bytes', () => {
+    const frontend = new Automerge.Frontend()
+    let backend = Automerge.init()
+    backend = change(frontend, backend, doc => { doc.thumbnail = new Uint8Array([0, 1, 2, 255]) })
+    assert.deepStrictEqual(frontend.value(), {thumbnail: 'AAEC/w=='})
+  })
+
+  it('should report conflicts', () => {
+    const frontend1 = new Automerge.Frontend('01234567')
+    const frontend2 = new Automerge.Frontend('89abcdef')
+    let backend1 = change(frontend1, Automerge.init(), doc => { doc.bird = 'magpie' })
+    let backend2 = change(frontend2, Automerge.init(), doc => { doc.bird = 'wren' })
+    const changes = Automerge.getChanges(backend2, [])
+    const [newBackend, patch] = Automerge.applyChanges(backend1, changes)
+    frontend1.applyPatch(patch)
+    assert.deepStrictEqual(frontend1.getConflicts(['bird']), {
+      '1@01234567': 'magpie',
+      '1@89abcdef': 'wren'
+    })
+    assert.strictEqual(frontend1.value().bird, 'wren')
+    assert.strictEqual(frontend1.getConflicts(['fish']), undefined)
+  })
+})
@@ -0,0 +1,44 @@
+const assert = require('assert')
+const Backend = require('..')
+const Automerge = require('automerge')
+
+// The binary format has to agree with the JS implementation, so these load
+// documents and changes the JS backend produced rather than ones of our own
+describe('Automerge.Backend with the JS implementation', () => {
+  function makeDoc() {
+    let doc = Automerge.init()
+    doc = Automerge.change(doc, 'Add birds', d => {
+      d.birds = ['magpie', 'wren']
+      d.count = new Automerge.Counter(3)
+      d.notes = new Automerge.Text()
+      d.notes.insertAt(0, 'h', 'i')
+    })
+    doc = Automerge.change(doc, 'Edit birds', d => {
+      d.birds.deleteAt(0)
+      d.birds.push('robin')
+      d.count.increment(2)
+      d.title = 'Birds'
+    })
+    return doc
+  }
+
+  function plain(doc) {
+    return JSON.parse(JSON.stringify(doc))
+  }
+
+  it('should load a document saved by the JS backend', () => {
+    const doc = makeDoc()
+    const backend = Backend.load(Automerge.save(doc))
+    const fromChanges = Backend.applyChanges(Backend.init(), Automerge.getAllChanges(doc))[0]
+    assert.deepStrictEqual(Backend.getHeads(backend), Backend.getHeads(fromChanges))
+    assert.deepStrictEqual(Backend.getPatch(backend), Backend.getPatch(fromChanges))
+  })
+
+  it('should save a document the JS backend can load', () => {
+    const doc = makeDoc()
+    const backend = Backend.load(Automerge.save(doc))
+    assert.deepStrictEqual(plain(Automerge.load(Backend.save(backend))), plain(doc))
+    const changes = Backend.getChanges(backend, [])
+    assert.deepStrictEqual(plain(Automerge.applyChanges(Automerge.init(), changes)), plain(doc))
+  })
+})
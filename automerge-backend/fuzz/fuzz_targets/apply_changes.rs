@@ -0,0 +1,14 @@
+#![no_main]
+use automerge_backend::{Backend, Change};
+use libfuzzer_sys::fuzz_target;
+
+// The input is any number of concatenated change chunks
+fuzz_target!(|data: &[u8]| {
+    if let Ok(changes) = Change::load_document(data) {
+        let mut backend = Backend::init();
+        if backend.apply_changes(changes).is_ok() {
+            let _ = backend.get_patch();
+            let _ = backend.save();
+        }
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use automerge_backend::Change;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(change) = Change::from_bytes(data.to_vec()) {
+        let _ = change.decode();
+        let _ = change.max_op();
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+use automerge_backend::{Backend, Change};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Change::load_document(data);
+    if let Ok(backend) = Backend::load(data.to_vec()) {
+        let _ = backend.get_patch();
+        let _ = backend.save();
+    }
+});
@@ -64,6 +64,10 @@ impl ActorMap {
             amp::OpType::Del => InternalOpType::Del,
             amp::OpType::Inc(val) => InternalOpType::Inc(*val),
             amp::OpType::Set(val) => InternalOpType::Set(val.clone()),
+            amp::OpType::Unknown { action, value } => InternalOpType::Unknown {
+                action: *action,
+                value: value.clone(),
+            },
         }
     }
 
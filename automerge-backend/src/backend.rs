@@ -1,29 +1,45 @@
 use crate::actor_map::ActorMap;
-use crate::change::encode_document;
-use crate::error::AutomergeError;
+use crate::change::{
+    decode_block, decode_document_with_ops, encode_document, fill_doc_op_preds, is_document_block,
+    is_snapshot_block, read_block, split_blocks, DEFAULT_DEFLATE_THRESHOLD,
+};
+use crate::error::{AutomergeError, DecodeError, InvalidChangeError};
 use crate::internal::ObjectId;
 use crate::op_handle::OpHandle;
 use crate::op_set::OpSet;
 use crate::pending_diff::PendingDiff;
+use crate::snapshot::{Snapshot, SnapshotClock};
+use crate::stats::BackendStats;
 use crate::Change;
 use automerge_protocol as amp;
 use core::cmp::max;
 use std::collections::{HashMap, HashSet};
-use std::rc::Rc;
+use std::io::Read;
+use std::sync::Arc;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Backend {
-    queue: Vec<Rc<Change>>,
-    op_set: Rc<OpSet>,
-    states: HashMap<amp::ActorId, Vec<Rc<Change>>>,
+    queue: Vec<Arc<Change>>,
+    op_set: Arc<OpSet>,
+    states: HashMap<amp::ActorId, Vec<Arc<Change>>>,
     actors: ActorMap,
-    hashes: HashMap<amp::ChangeHash, Rc<Change>>,
+    hashes: HashMap<amp::ChangeHash, Arc<Change>>,
     history: Vec<amp::ChangeHash>,
+    /// The changes squashed by `compact`, which aren't in `states`,
+    /// `hashes` or `history`
+    snapshot: Option<Arc<Snapshot>>,
+    /// The heads each peer has acknowledged, see `ack`
+    acks: HashMap<String, Vec<amp::ChangeHash>>,
+    /// The last change made by `apply_local_change` for each actor which
+    /// has made one
+    local_changes: HashMap<amp::ActorId, Arc<Change>>,
+    /// The actor of the most recent change made by `apply_local_change`
+    last_local_actor: Option<amp::ActorId>,
 }
 
 impl Backend {
     pub fn init() -> Backend {
-        let op_set = Rc::new(OpSet::init());
+        let op_set = Arc::new(OpSet::init());
         Backend {
             op_set,
             queue: Vec::new(),
@@ -31,6 +47,10 @@ impl Backend {
             states: HashMap::new(),
             history: Vec::new(),
             hashes: HashMap::new(),
+            snapshot: None,
+            acks: HashMap::new(),
+            local_changes: HashMap::new(),
+            last_local_actor: None,
         }
     }
 
@@ -51,31 +71,55 @@ impl Backend {
             self.op_set.deps.iter().cloned().collect()
         };
         deps.sort_unstable();
+        let mut clock: HashMap<_, _> = self
+            .snapshot
+            .iter()
+            .flat_map(|snapshot| snapshot.clock.iter())
+            .map(|(k, v)| (k.clone(), v.seq))
+            .collect();
+        for (k, v) in self.states.iter() {
+            *clock.entry(k.clone()).or_default() += v.len() as u64;
+        }
         Ok(amp::Patch {
             diffs,
             deps,
             max_op: self.op_set.max_op,
-            clock: self
-                .states
-                .iter()
-                .map(|(k, v)| (k.clone(), v.len() as u64))
-                .collect(),
+            clock,
             actor: actor_seq.clone().map(|(actor, _)| actor),
             seq: actor_seq.map(|(_, seq)| seq),
         })
     }
 
     pub fn load_changes(&mut self, mut changes: Vec<Change>) -> Result<(), AutomergeError> {
-        let changes = changes.drain(0..).map(Rc::new).collect();
+        let changes = changes.drain(0..).map(Arc::new).collect();
         self.apply(changes, None)?;
         Ok(())
     }
 
+    /// Apply changes from another peer. If this backend has been compacted
+    /// a change which was made without all the compacted changes fails with
+    /// `AutomergeError::NeedsFullResync`, as it may refer to ops the
+    /// compaction dropped. A change whose deps we don't have is queued, and
+    /// only fails once one of them turns out to be compacted.
     pub fn apply_changes(
         &mut self,
         mut changes: Vec<Change>,
     ) -> Result<amp::Patch, AutomergeError> {
-        let changes = changes.drain(0..).map(Rc::new).collect();
+        let compacted: HashSet<_> = changes
+            .iter()
+            .filter(|change| self.is_compacted(change))
+            .map(|change| change.hash)
+            .collect();
+        let queued = self.queue.iter().map(|change| change.as_ref());
+        for change in changes.iter().chain(queued) {
+            if !self.has_change(&change.hash)
+                && !self.is_compacted(change)
+                && !self.reaches_snapshot(&change.deps, &compacted)
+            {
+                return Err(AutomergeError::NeedsFullResync);
+            }
+        }
+        let changes = changes.drain(0..).map(Arc::new).collect();
         self.apply(changes, None)
     }
 
@@ -85,7 +129,7 @@ impl Backend {
 
     fn apply(
         &mut self,
-        mut changes: Vec<Rc<Change>>,
+        mut changes: Vec<Arc<Change>>,
         actor: Option<(amp::ActorId, u64)>,
     ) -> Result<amp::Patch, AutomergeError> {
         let mut pending_diffs = HashMap::new();
@@ -94,24 +138,44 @@ impl Backend {
             self.add_change(change, actor.is_some(), &mut pending_diffs)?;
         }
 
-        let op_set = Rc::make_mut(&mut self.op_set);
+        let op_set = Arc::make_mut(&mut self.op_set);
         let diffs = op_set.finalize_diffs(pending_diffs, &self.actors)?;
         self.make_patch(diffs, actor)
     }
 
     fn get_hash(&self, actor: &amp::ActorId, seq: u64) -> Result<amp::ChangeHash, AutomergeError> {
-        self.states
-            .get(actor)
-            .and_then(|v| v.get(seq as usize - 1))
+        let compacted = self.compacted_clock(actor);
+        if let Some(clock) = compacted.filter(|clock| clock.seq == seq) {
+            return Ok(clock.hash);
+        }
+        let compacted_seq = compacted.map_or(0, |clock| clock.seq);
+        seq.checked_sub(compacted_seq + 1)
+            .and_then(|index| self.states.get(actor)?.get(index as usize))
             .map(|c| c.hash)
             .ok_or(AutomergeError::InvalidSeq(seq))
     }
 
+    /// The last change of `actor` which has been compacted, if any
+    fn compacted_clock(&self, actor: &amp::ActorId) -> Option<&SnapshotClock> {
+        self.snapshot.as_ref()?.clock.get(actor)
+    }
+
+    /// The number of changes from `actor` which have been applied, including
+    /// compacted changes
+    fn actor_seq(&self, actor: &amp::ActorId) -> u64 {
+        self.compacted_clock(actor).map_or(0, |clock| clock.seq)
+            + self
+                .states
+                .get(actor)
+                .map_or(0, |changes| changes.len() as u64)
+    }
+
     pub fn apply_local_change(
         &mut self,
         mut change: amp::UncompressedChange,
-    ) -> Result<(amp::Patch, Rc<Change>), AutomergeError> {
+    ) -> Result<(amp::Patch, Arc<Change>), AutomergeError> {
         self.check_for_duplicate(&change)?; // Change has already been applied
+        check_unknown_ops(&change)?;
 
         let actor_seq = (change.actor_id.clone(), change.seq);
 
@@ -122,20 +186,49 @@ impl Backend {
             }
         }
 
-        let bin_change: Rc<Change> = Rc::new(change.into());
+        let bin_change: Arc<Change> = Arc::new(change.into());
         let patch: amp::Patch = self.apply(vec![bin_change.clone()], Some(actor_seq))?;
 
+        self.local_changes
+            .insert(bin_change.actor_id().clone(), bin_change.clone());
+        self.last_local_actor = Some(bin_change.actor_id().clone());
         Ok((patch, bin_change))
     }
 
+    /// The actor of the most recent change made by `apply_local_change`, if
+    /// any, for callers which only use one actor at a time
+    pub fn last_local_actor(&self) -> Option<&amp::ActorId> {
+        self.last_local_actor.as_ref()
+    }
+
+    /// The last change `actor` made with `apply_local_change`, if any
+    pub fn get_last_local_change(&self, actor: &amp::ActorId) -> Option<&Change> {
+        self.local_changes.get(actor).map(|change| change.as_ref())
+    }
+
+    /// The changes `actor` made with `apply_local_change` which come after
+    /// `seq`, to send again after reconnecting. Compacted changes aren't
+    /// included, see `compact`.
+    pub fn get_changes_since_local(&self, actor: &amp::ActorId, seq: u64) -> Vec<&Change> {
+        let last = match self.get_last_local_change(actor) {
+            Some(last) => last,
+            None => return Vec::new(),
+        };
+        // `states` holds the actor's uncompacted changes in seq order
+        let compacted_seq = self
+            .compacted_clock(last.actor_id())
+            .map_or(0, |clock| clock.seq);
+        let start = seq.saturating_sub(compacted_seq) as usize;
+        let end = last.seq.saturating_sub(compacted_seq) as usize;
+        self.states
+            .get(last.actor_id())
+            .and_then(|changes| changes.get(start..end))
+            .map(|changes| changes.iter().map(|change| change.as_ref()).collect())
+            .unwrap_or_default()
+    }
+
     fn check_for_duplicate(&self, change: &amp::UncompressedChange) -> Result<(), AutomergeError> {
-        if self
-            .states
-            .get(&change.actor_id)
-            .map(|v| v.len() as u64)
-            .unwrap_or(0)
-            >= change.seq
-        {
+        if self.actor_seq(&change.actor_id) >= change.seq {
             return Err(AutomergeError::DuplicateChange(format!(
                 "Change request has already been applied {}:{}",
                 change.actor_id.to_hex_string(),
@@ -147,14 +240,16 @@ impl Backend {
 
     fn add_change(
         &mut self,
-        change: Rc<Change>,
+        change: Arc<Change>,
         local: bool,
         diffs: &mut HashMap<ObjectId, Vec<PendingDiff>>,
     ) -> Result<(), AutomergeError> {
         if local {
             self.apply_change(change, diffs)
         } else {
-            self.queue.push(change);
+            if !self.queue.iter().any(|queued| queued.hash == change.hash) {
+                self.queue.push(change);
+            }
             self.apply_queued_ops(diffs)
         }
     }
@@ -171,16 +266,36 @@ impl Backend {
 
     fn apply_change(
         &mut self,
-        change: Rc<Change>,
+        change: Arc<Change>,
         diffs: &mut HashMap<ObjectId, Vec<PendingDiff>>,
     ) -> Result<(), AutomergeError> {
-        if self.hashes.contains_key(&change.hash) {
+        if self.has_change(&change.hash) || self.is_compacted(&change) {
             return Ok(());
         }
 
+        let expected_seq = self.actor_seq(change.actor_id()) + 1;
+        if change.seq != expected_seq {
+            return Err(AutomergeError::InvalidSeq(change.seq));
+        }
+        let last_max_op = match self
+            .states
+            .get(change.actor_id())
+            .and_then(|changes| changes.last())
+        {
+            Some(last_change) => Some(last_change.max_op()),
+            None => self
+                .compacted_clock(change.actor_id())
+                .map(|clock| clock.max_op),
+        };
+        if let Some(last_max_op) = last_max_op {
+            if change.start_op <= last_max_op {
+                return Err(AutomergeError::InvalidStartOp(change.start_op));
+            }
+        }
+
         self.update_history(&change);
 
-        let op_set = Rc::make_mut(&mut self.op_set);
+        let op_set = Arc::make_mut(&mut self.op_set);
 
         let start_op = change.start_op;
 
@@ -188,14 +303,17 @@ impl Backend {
 
         let ops = OpHandle::extract(change, &mut self.actors);
 
-        op_set.max_op = max(op_set.max_op, start_op + (ops.len() as u64) - 1);
+        op_set.max_op = max(
+            op_set.max_op,
+            (start_op + ops.len() as u64).saturating_sub(1),
+        );
 
         op_set.apply_ops(ops, diffs, &mut self.actors)?;
 
         Ok(())
     }
 
-    fn update_history(&mut self, change: &Rc<Change>) {
+    fn update_history(&mut self, change: &Arc<Change>) {
         self.states
             .entry(change.actor_id().clone())
             .or_default()
@@ -205,11 +323,11 @@ impl Backend {
         self.hashes.insert(change.hash, change.clone());
     }
 
-    fn pop_next_causally_ready_change(&mut self) -> Option<Rc<Change>> {
+    fn pop_next_causally_ready_change(&mut self) -> Option<Arc<Change>> {
         let mut index = 0;
         while index < self.queue.len() {
             let change = self.queue.get(index).unwrap();
-            if change.deps.iter().all(|d| self.hashes.contains_key(d)) {
+            if change.deps.iter().all(|d| self.has_change(d)) {
                 return Some(self.queue.remove(index));
             }
             index += 1
@@ -224,6 +342,7 @@ impl Backend {
         self.make_patch(Some(diffs), None)
     }
 
+    /// The changes from `actor_id` which haven't been compacted
     pub fn get_changes_for_actor_id(
         &self,
         actor_id: &amp::ActorId,
@@ -235,6 +354,8 @@ impl Backend {
             .unwrap_or_default())
     }
 
+    /// The changes which aren't in the history up to `have_deps`, other
+    /// than compacted changes, which can't be sent, see `compact`
     pub fn get_changes(&self, have_deps: &[amp::ChangeHash]) -> Vec<&Change> {
         let mut stack = have_deps.to_owned();
         let mut has_seen = HashSet::new();
@@ -253,22 +374,293 @@ impl Backend {
     }
 
     pub fn save(&self) -> Result<Vec<u8>, AutomergeError> {
+        self.save_with_deflate_threshold(Some(DEFAULT_DEFLATE_THRESHOLD))
+    }
+
+    /// Save with columns of at least `deflate_threshold` bytes DEFLATE
+    /// compressed, or with no compression if it's `None`
+    pub fn save_with_deflate_threshold(
+        &self,
+        deflate_threshold: Option<usize>,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        if let Some(snapshot) = &self.snapshot {
+            // the changes after the snapshot can't be put in a document
+            // chunk, which has to have the whole history
+            let mut bytes = snapshot.encoded(deflate_threshold)?.into_owned();
+            for change in self.history.iter().filter_map(|hash| self.hashes.get(hash)) {
+                bytes.extend(&change.bytes);
+            }
+            return Ok(bytes);
+        }
         let changes: Vec<amp::UncompressedChange> = self
             .history
             .iter()
             .filter_map(|hash| self.hashes.get(&hash))
             .map(|r| r.as_ref().into())
             .collect();
-        encode_document(changes)
+        encode_document(changes, deflate_threshold)
+    }
+
+    /// Squash the history up to `heads`, which every peer must already have,
+    /// into a snapshot. The deleted map keys and list elements which none of
+    /// the changes after `heads` refer to are dropped, which makes the
+    /// backend and the saved document smaller, but the compacted changes are
+    /// gone: `get_changes` can't return them and a change which was made
+    /// without them fails to apply with `AutomergeError::NeedsFullResync`,
+    /// as does syncing with a peer which doesn't have them.
+    ///
+    /// Once a backend has compacted history, `save` writes a snapshot chunk
+    /// (chunk type 3) followed by the changes after it. Only `Backend::load`
+    /// can read that: the JavaScript implementation can't, and
+    /// `Change::load_document` fails with `DecodeError::UnexpectedSnapshot`.
+    /// `has_compacted_history` tells whether a backend would save one.
+    pub fn compact(&mut self, heads: &[amp::ChangeHash]) -> Result<(), AutomergeError> {
+        if let Some(head) = heads.iter().find(|head| !self.has_change(head)) {
+            return Err(AutomergeError::UnknownHead(*head));
+        }
+
+        let mut before = HashSet::new();
+        let mut stack = heads.to_vec();
+        while let Some(hash) = stack.pop() {
+            if let Some(change) = self.hashes.get(&hash) {
+                if before.insert(hash) {
+                    stack.extend(change.deps.iter().cloned());
+                }
+            }
+        }
+
+        // each actor's changes have to be compacted in order for the seq and
+        // start op checks to work, so a change which depends on one which
+        // isn't compacted isn't either. The deps which aren't in `hashes`
+        // were compacted before.
+        let previous = self.snapshot.clone().unwrap_or_default();
+        let mut compacted = HashSet::new();
+        let mut seqs = HashMap::new();
+        let mut changes = Vec::new();
+        let mut uncompacted = Vec::new();
+        for change in self.history.iter().map(|hash| &self.hashes[hash]) {
+            let actor = change.actor_id();
+            let seq = seqs
+                .entry(actor)
+                .or_insert_with(|| previous.clock.get(actor).map_or(0, |clock| clock.seq));
+            if before.contains(&change.hash)
+                && change.seq == *seq + 1
+                && change
+                    .deps
+                    .iter()
+                    .all(|dep| compacted.contains(dep) || !self.hashes.contains_key(dep))
+            {
+                *seq += 1;
+                compacted.insert(change.hash);
+                changes.push(change.as_ref());
+            } else {
+                uncompacted.push(change.as_ref());
+            }
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let queued: Vec<_> = self.queue.iter().map(|change| change.as_ref()).collect();
+        let keep: Vec<_> = uncompacted.iter().chain(queued.iter()).cloned().collect();
+        let snapshot = Snapshot::build(self.snapshot.as_deref(), &changes, &keep)?;
+
+        let mut backend = Backend::init();
+        backend.load_snapshot(&snapshot.bytes, false)?;
+        backend.apply_in_history_order(uncompacted.into_iter().cloned().collect())?;
+        backend.load_changes(queued.into_iter().cloned().collect())?;
+        backend.acks = std::mem::take(&mut self.acks);
+        backend.local_changes = std::mem::take(&mut self.local_changes);
+        backend.last_local_actor = self.last_local_actor.take();
+        *self = backend;
+        Ok(())
+    }
+
+    /// Whether some of the history was squashed by `compact`, either here or
+    /// before the document was saved, in which case `save` writes a snapshot
+    pub fn has_compacted_history(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// Record that the peer `peer_id` has the changes up to `heads`, as well
+    /// as any it had already acknowledged. Acknowledgements aren't saved
+    /// with the document.
+    pub fn ack(&mut self, peer_id: &str, heads: &[amp::ChangeHash]) -> Result<(), AutomergeError> {
+        if let Some(head) = heads.iter().find(|head| !self.has_change(head)) {
+            return Err(AutomergeError::UnknownHead(*head));
+        }
+        let mut acked: Vec<_> = self
+            .acked_heads(peer_id)
+            .into_iter()
+            .chain(heads.iter().cloned())
+            .collect();
+        let ancestors = self.ancestors(&acked);
+        acked.retain(|hash| !ancestors.contains(hash));
+        acked.sort_unstable();
+        acked.dedup();
+        self.acks.insert(peer_id.to_string(), acked);
+        Ok(())
+    }
+
+    /// The heads `peer_id` has acknowledged, empty if it hasn't acknowledged
+    /// anything
+    pub fn acked_heads(&self, peer_id: &str) -> Vec<amp::ChangeHash> {
+        self.acks.get(peer_id).cloned().unwrap_or_default()
+    }
+
+    /// The changes `peer_id` hasn't acknowledged, to send to it again. Fails
+    /// with `AutomergeError::NeedsFullResync` if some of them have been
+    /// compacted.
+    pub fn unacked_changes(&self, peer_id: &str) -> Result<Vec<&Change>, AutomergeError> {
+        let acked = self.acks.get(peer_id).map_or(&[][..], |heads| heads);
+        if !self.includes_snapshot(acked) {
+            return Err(AutomergeError::NeedsFullResync);
+        }
+        Ok(self.get_changes(acked))
+    }
+
+    /// The heads of the history every peer which has acknowledged anything
+    /// has, which is what can be passed to `compact`. Empty if no peer has
+    /// acknowledged anything.
+    pub fn common_acked_heads(&self) -> Vec<amp::ChangeHash> {
+        let mut common: Option<HashSet<amp::ChangeHash>> = None;
+        for heads in self.acks.values() {
+            let mut history = self.ancestors(heads);
+            history.extend(heads.iter().cloned());
+            common = Some(match common {
+                Some(common) => common.intersection(&history).cloned().collect(),
+                None => history,
+            });
+        }
+        let common = common.unwrap_or_default();
+        // `common` includes all its ancestors, so its heads are the changes
+        // which none of the others depend on
+        let deps: HashSet<_> = common
+            .iter()
+            .filter_map(|hash| self.hashes.get(hash))
+            .flat_map(|change| change.deps.iter())
+            .collect();
+        let mut heads: Vec<_> = common
+            .iter()
+            .filter(|hash| !deps.contains(hash))
+            .cloned()
+            .collect();
+        heads.sort_unstable();
+        heads
+    }
+
+    /// The changes `heads` depend on, directly or indirectly, not including
+    /// `heads` themselves unless one depends on another. The ancestors of
+    /// compacted changes aren't known so aren't included.
+    fn ancestors(&self, heads: &[amp::ChangeHash]) -> HashSet<amp::ChangeHash> {
+        let mut ancestors = HashSet::new();
+        let mut stack: Vec<_> = heads
+            .iter()
+            .filter_map(|hash| self.hashes.get(hash))
+            .flat_map(|change| change.deps.iter().cloned())
+            .collect();
+        while let Some(hash) = stack.pop() {
+            if ancestors.insert(hash) {
+                if let Some(change) = self.hashes.get(&hash) {
+                    stack.extend(change.deps.iter().cloned());
+                }
+            }
+        }
+        ancestors
     }
 
     pub fn load(data: Vec<u8>) -> Result<Self, AutomergeError> {
-        let changes = Change::load_document(&data)?;
         let mut backend = Self::init();
-        backend.load_changes(changes)?;
+        for block in split_blocks(&data) {
+            backend.load_block(block, true)?;
+        }
+        Ok(backend)
+    }
+
+    /// Like `load` but reads the document from `reader` one chunk at a time,
+    /// so only one chunk is held in memory at once rather than the whole
+    /// document
+    pub fn load_from_reader<R: Read>(mut reader: R) -> Result<Self, AutomergeError> {
+        let mut backend = Self::init();
+        while let Some(block) = read_block(&mut reader)? {
+            backend.load_block(&block, true)?;
+        }
+        Ok(backend)
+    }
+
+    /// Like `load` but doesn't check checksums or heads, see
+    /// `Change::load_document_unverified`
+    pub fn load_unverified(data: Vec<u8>) -> Result<Self, AutomergeError> {
+        let mut backend = Self::init();
+        for block in split_blocks(&data) {
+            backend.load_block(block, false)?;
+        }
         Ok(backend)
     }
 
+    /// Decode a chunk and apply its changes. A document loaded into an empty
+    /// backend has its op set built straight from the document's ops, see
+    /// `OpSet::from_document`, and a snapshot can only be loaded into an
+    /// empty backend.
+    fn load_block(&mut self, bytes: &[u8], verify: bool) -> Result<(), AutomergeError> {
+        let empty = self.hashes.is_empty() && self.queue.is_empty() && self.snapshot.is_none();
+        if empty && is_snapshot_block(bytes) {
+            return self.load_snapshot(bytes, verify);
+        }
+        if empty && is_document_block(bytes) {
+            let mut doc = decode_document_with_ops(bytes, verify)?;
+            let mut actors = ActorMap::new();
+            if let Some(op_set) = OpSet::from_document(&mut doc, &mut actors) {
+                self.op_set = Arc::new(op_set);
+                self.actors = actors;
+                for change in doc.changes {
+                    self.update_history(&Arc::new(change));
+                }
+                return Ok(());
+            }
+            return self.load_changes(doc.changes);
+        }
+        let mut changes = Vec::new();
+        decode_block(bytes, &mut changes, verify)?;
+        if self.snapshot.is_some() {
+            return self.apply_in_history_order(changes);
+        }
+        self.load_changes(changes)
+    }
+
+    /// Apply changes which were saved after a snapshot. They're in the order
+    /// they were applied, so the deps of theirs we don't have were compacted,
+    /// and as only some compacted changes are known by their hash they would
+    /// never be causally ready if they were queued.
+    fn apply_in_history_order(&mut self, changes: Vec<Change>) -> Result<(), AutomergeError> {
+        let mut pending_diffs = HashMap::new();
+        for change in changes {
+            self.apply_change(Arc::new(change), &mut pending_diffs)?;
+        }
+        let op_set = Arc::make_mut(&mut self.op_set);
+        op_set.finalize_diffs(pending_diffs, &self.actors)?;
+        Ok(())
+    }
+
+    fn load_snapshot(&mut self, bytes: &[u8], verify: bool) -> Result<(), AutomergeError> {
+        let decoded = Snapshot::decode(bytes, verify)?;
+        let mut ops = decoded.ops;
+        fill_doc_op_preds(&mut ops, &decoded.actors)?;
+        let mut actors = ActorMap::new();
+        let op_set = OpSet::from_snapshot(
+            &decoded.actors,
+            &ops,
+            &decoded.snapshot.heads,
+            decoded.snapshot.max_op,
+            &mut actors,
+        )
+        .ok_or(DecodeError::InvalidSnapshot)?;
+        self.op_set = Arc::new(op_set);
+        self.actors = actors;
+        self.snapshot = Some(Arc::new(decoded.snapshot));
+        Ok(())
+    }
+
     pub fn get_missing_deps(&self) -> Vec<amp::ChangeHash> {
         let in_queue: Vec<_> = self.queue.iter().map(|change| &change.hash).collect();
         self.queue
@@ -277,4 +669,153 @@ impl Backend {
             .filter(|h| !in_queue.contains(&h))
             .collect()
     }
+
+    /// Like `get_missing_deps` but also includes any of `heads` which we
+    /// don't have, skips deps we already have and returns the hashes sorted.
+    pub fn get_missing_deps_for(&self, heads: &[amp::ChangeHash]) -> Vec<amp::ChangeHash> {
+        let in_queue: HashSet<_> = self.queue.iter().map(|change| change.hash).collect();
+        let mut missing: Vec<_> = self
+            .queue
+            .iter()
+            .flat_map(|change| change.deps.iter())
+            .chain(heads.iter())
+            .filter(|h| !in_queue.contains(h) && !self.has_change(h))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    /// `None` for compacted changes as well as changes we don't have, see
+    /// `has_change`
+    pub fn get_change_by_hash(&self, hash: &amp::ChangeHash) -> Option<&Change> {
+        self.hashes.get(hash).map(|c| c.as_ref())
+    }
+
+    /// Whether the change has been applied. Of the compacted changes only
+    /// the snapshot's heads and each actor's last compacted change are known
+    /// by their hash, see `is_compacted` for the others.
+    pub(crate) fn has_change(&self, hash: &amp::ChangeHash) -> bool {
+        self.hashes.contains_key(hash)
+            || matches!(&self.snapshot, Some(snapshot) if snapshot.has_hash(hash))
+    }
+
+    /// Whether applying `change` would do nothing, as it has already been
+    /// applied or compacted, or is queued waiting for its deps
+    pub fn has_received(&self, change: &Change) -> bool {
+        self.has_change(&change.hash)
+            || self.is_compacted(change)
+            || self.queue.iter().any(|queued| queued.hash == change.hash)
+    }
+
+    /// Whether `change` has been compacted. Each actor's changes are
+    /// compacted in order, so those up to its last compacted seq are.
+    fn is_compacted(&self, change: &Change) -> bool {
+        matches!(self.compacted_clock(change.actor_id()), Some(clock) if change.seq <= clock.seq)
+    }
+
+    /// Whether the history up to `heads` includes every compacted change,
+    /// that is whether it reaches all the snapshot's heads. A history we
+    /// don't have all of is assumed to, the changes we're missing are
+    /// checked when they arrive.
+    pub(crate) fn includes_snapshot(&self, heads: &[amp::ChangeHash]) -> bool {
+        self.reaches_snapshot(heads, &HashSet::new())
+    }
+
+    /// `includes_snapshot`, where a hash we don't have is compacted if one
+    /// of our changes depends on it or it's in `compacted`, and missing
+    /// otherwise
+    fn reaches_snapshot(
+        &self,
+        heads: &[amp::ChangeHash],
+        compacted: &HashSet<amp::ChangeHash>,
+    ) -> bool {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            None => return true,
+        };
+        let mut seen = HashSet::new();
+        let mut reached = HashSet::new();
+        let mut stack: Vec<_> = heads.iter().map(|hash| (*hash, false)).collect();
+        while let Some((hash, ours)) = stack.pop() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            if let Some(change) = self.hashes.get(&hash) {
+                stack.extend(change.deps.iter().map(|dep| (*dep, true)));
+            } else if snapshot.heads.contains(&hash) {
+                reached.insert(hash);
+                if reached.len() == snapshot.heads.len() {
+                    return true;
+                }
+            } else if !ours && !compacted.contains(&hash) && !snapshot.has_hash(&hash) {
+                return true;
+            }
+        }
+        reached.len() == snapshot.heads.len()
+    }
+
+    /// Counts of the things in this backend and an estimate of the memory
+    /// they use. This walks the whole document so it isn't free, but it
+    /// doesn't decode any changes.
+    pub fn stats(&self) -> BackendStats {
+        let mut stats = BackendStats {
+            changes: self.history.len(),
+            compacted_changes: self.snapshot.as_ref().map_or(0, |snapshot| {
+                snapshot
+                    .clock
+                    .values()
+                    .map(|clock| clock.seq as usize)
+                    .sum()
+            }),
+            queued_changes: self.queue.len(),
+            actors: self.states.len(),
+            ..BackendStats::default()
+        };
+        stats.add_op_set(&self.op_set);
+        for change in self.hashes.values().chain(self.queue.iter()) {
+            stats.add_change(change);
+        }
+        let hash_size = std::mem::size_of::<amp::ChangeHash>();
+        let arc_size = std::mem::size_of::<Arc<Change>>();
+        stats.heap_bytes.history = self.history.len() * hash_size
+            + self.hashes.len() * (hash_size + arc_size)
+            + self.states.len() * std::mem::size_of::<(amp::ActorId, Vec<Arc<Change>>)>()
+            + self
+                .states
+                .values()
+                .map(|v| v.len() * arc_size)
+                .sum::<usize>();
+        if let Some(snapshot) = &self.snapshot {
+            stats.heap_bytes.history += (snapshot.heads.len() + snapshot.clock.len()) * hash_size;
+            stats.heap_bytes.changes += snapshot.bytes.len();
+            stats.heap_bytes.changes += snapshot.deflated.get().map_or(0, Vec::len);
+        }
+        stats
+    }
+}
+
+/// Values and ops of unknown type are encoded with their type code or
+/// action as it is, so one which claims a known code would be read back as
+/// something else
+fn check_unknown_ops(change: &amp::UncompressedChange) -> Result<(), InvalidChangeError> {
+    for op in &change.operations {
+        let value = match &op.action {
+            amp::OpType::Unknown { action, .. } if !amp::OpType::is_unknown_action(*action) => {
+                return Err(InvalidChangeError::KnownActionInUnknownOp { action: *action })
+            }
+            amp::OpType::Unknown { value, .. } | amp::OpType::Set(value) => value,
+            _ => continue,
+        };
+        if let amp::ScalarValue::Unknown { type_code, .. } = value {
+            if !amp::ScalarValue::is_unknown_type_code(*type_code) {
+                return Err(InvalidChangeError::KnownTypeCodeInUnknownValue {
+                    type_code: *type_code,
+                });
+            }
+        }
+    }
+    Ok(())
 }
@@ -1,26 +1,30 @@
 //use crate::columnar;
 use crate::columnar::{
     ChangeEncoder, ChangeIterator, ColumnEncoder, DocChange, DocOp, DocOpEncoder, DocOpIterator,
-    OperationIterator,
+    OperationIterator, COLUMN_TYPE_DEFLATE,
 };
 use crate::encoding::{Decodable, Encodable};
-use crate::error::{AutomergeError, InvalidChangeError};
+use crate::error::{AutomergeError, DecodeError, InvalidChangeError};
 use automerge_protocol as amp;
 use core::fmt::Debug;
+use flate2::read::DeflateDecoder;
 use itertools::Itertools;
 use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::convert::TryInto;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::ops::Range;
 use std::str;
 
-const HASH_BYTES: usize = 32;
+pub(crate) const HASH_BYTES: usize = 32;
 const BLOCK_TYPE_DOC: u8 = 0;
 const BLOCK_TYPE_CHANGE: u8 = 1;
-const CHUNK_START: usize = 8;
-const HASH_RANGE: Range<usize> = 4..8;
+// 2 is a DEFLATE compressed change in the JS implementation
+pub(crate) const BLOCK_TYPE_SNAPSHOT: u8 = 3;
+pub(crate) const CHUNK_START: usize = 8;
+pub(crate) const HASH_RANGE: Range<usize> = 4..8;
 
 impl From<amp::UncompressedChange> for Change {
     fn from(value: amp::UncompressedChange) -> Self {
@@ -175,18 +179,26 @@ impl Change {
         &self.actors[0]
     }
 
+    /// Decode a document or any number of concatenated changes, checking the
+    /// checksum of every chunk and the heads of every document chunk
     pub fn load_document(bytes: &[u8]) -> Result<Vec<Change>, AutomergeError> {
-        load_blocks(bytes)
+        load_blocks(bytes, true)
+    }
+
+    /// Like `load_document` but skips the checksum and heads checks, for
+    /// recovering what can be recovered from a corrupted file
+    pub fn load_document_unverified(bytes: &[u8]) -> Result<Vec<Change>, AutomergeError> {
+        load_blocks(bytes, false)
     }
 
     pub fn from_bytes(bytes: Vec<u8>) -> Result<Change, AutomergeError> {
-        decode_change(bytes)
+        decode_change(bytes, true)
     }
 
     pub fn max_op(&self) -> u64 {
         // TODO - this could be a lot more efficient
         let len = self.iter_ops().count();
-        self.start_op + (len as u64) - 1
+        (self.start_op + len as u64).saturating_sub(1)
     }
 
     fn message(&self) -> Option<String> {
@@ -234,33 +246,43 @@ impl TryFrom<&[u8]> for Change {
     }
 }
 
-fn read_leb128(bytes: &mut &[u8]) -> Result<(usize, usize), AutomergeError> {
-    let mut buf = &bytes[..];
-    let val = leb128::read::unsigned(&mut buf)? as usize;
-    let leb128_bytes = bytes.len() - buf.len();
-    Ok((val, leb128_bytes))
-}
-
-fn read_slice<T: Decodable + Debug>(
+pub(crate) fn read_slice<T: Decodable + Debug>(
     bytes: &[u8],
     cursor: &mut Range<usize>,
-) -> Result<T, AutomergeError> {
-    let mut view = &bytes[cursor.clone()];
+) -> Result<T, DecodeError> {
+    let mut view = bytes
+        .get(cursor.clone())
+        .ok_or(DecodeError::UnexpectedEnd {
+            offset: cursor.start,
+        })?;
     let init_len = view.len();
-    let val = T::decode::<&[u8]>(&mut view).ok_or(AutomergeError::EncodingError);
+    let val = T::decode::<&[u8]>(&mut view).ok_or(DecodeError::InvalidNumber {
+        offset: cursor.start,
+    })?;
     let bytes_read = init_len - view.len();
     *cursor = (cursor.start + bytes_read)..cursor.end;
-    val
+    Ok(val)
 }
 
-fn slice_bytes(bytes: &[u8], cursor: &mut Range<usize>) -> Result<Range<usize>, AutomergeError> {
-    let (val, len) = read_leb128(&mut &bytes[cursor.clone()])?;
-    let start = cursor.start + len;
-    let end = start + val;
+/// Advance the cursor past the next `len` bytes and return their range
+pub(crate) fn take_bytes(
+    cursor: &mut Range<usize>,
+    len: usize,
+) -> Result<Range<usize>, DecodeError> {
+    let start = cursor.start;
+    let end = start
+        .checked_add(len)
+        .filter(|end| *end <= cursor.end)
+        .ok_or(DecodeError::UnexpectedEnd { offset: cursor.end })?;
     *cursor = end..cursor.end;
     Ok(start..end)
 }
 
+fn slice_bytes(bytes: &[u8], cursor: &mut Range<usize>) -> Result<Range<usize>, DecodeError> {
+    let len = read_slice(bytes, cursor)?;
+    take_bytes(cursor, len)
+}
+
 fn increment_range(range: &mut Range<usize>, len: usize) {
     range.end += len;
     range.start += len;
@@ -287,53 +309,68 @@ pub(crate) struct Document {
     extra_bytes: Range<usize>,
 }
 
-fn decode_header(bytes: &[u8]) -> Result<(u8, amp::ChangeHash, Range<usize>), AutomergeError> {
-    if bytes.len() <= HEADER_BYTES {
-        return Err(AutomergeError::EncodingError);
+pub(crate) fn decode_header(
+    bytes: &[u8],
+    verify: bool,
+) -> Result<(u8, amp::ChangeHash, Range<usize>), AutomergeError> {
+    if bytes.get(0..4) != Some(&MAGIC_BYTES[..]) {
+        return Err(DecodeError::WrongMagicBytes { offset: 0 }.into());
     }
 
-    if bytes[0..4] != MAGIC_BYTES {
-        return Err(AutomergeError::EncodingError);
+    if bytes.len() <= HEADER_BYTES {
+        return Err(DecodeError::UnexpectedEnd {
+            offset: bytes.len(),
+        }
+        .into());
     }
 
-    let (val, len) = read_leb128(&mut &bytes[HEADER_BYTES..])?;
-    let body = (HEADER_BYTES + len)..(HEADER_BYTES + len + val);
+    let mut cursor = HEADER_BYTES..bytes.len();
+    let len = read_slice(bytes, &mut cursor)?;
+    let body = take_bytes(&mut cursor, len)?;
     if bytes.len() != body.end {
-        return Err(AutomergeError::EncodingError);
+        return Err(DecodeError::TrailingData { offset: body.end }.into());
     }
 
     let chunktype = bytes[PREAMBLE_BYTES];
 
     let mut hasher = Sha256::new();
     hasher.input(&bytes[PREAMBLE_BYTES..]);
-    let hash = hasher.result()[..]
+    let hash: amp::ChangeHash = hasher.result()[..]
         .try_into()
         .map_err(InvalidChangeError::from)?;
 
+    if verify && bytes[HASH_RANGE] != hash.0[0..4] {
+        let mut stored = [0; 4];
+        stored.copy_from_slice(&bytes[HASH_RANGE]);
+        let mut calculated = [0; 4];
+        calculated.copy_from_slice(&hash.0[0..4]);
+        return Err(DecodeError::ChecksumMismatch { stored, calculated }.into());
+    }
+
     Ok((chunktype, hash, body))
 }
 
-fn decode_hashes(
+pub(crate) fn decode_hashes(
     bytes: &[u8],
     cursor: &mut Range<usize>,
 ) -> Result<Vec<amp::ChangeHash>, AutomergeError> {
     let num_hashes = read_slice(bytes, cursor)?;
-    let mut hashes = Vec::with_capacity(num_hashes);
+    // the number of hashes is untrusted so don't allocate space for them up front
+    let mut hashes = Vec::new();
     for _ in 0..num_hashes {
-        let hash = cursor.start..(cursor.start + HASH_BYTES);
-        *cursor = hash.end..cursor.end;
+        let hash = take_bytes(cursor, HASH_BYTES)?;
         hashes.push(bytes[hash].try_into().map_err(InvalidChangeError::from)?);
     }
     Ok(hashes)
 }
 
-fn decode_actors(
+pub(crate) fn decode_actors(
     bytes: &[u8],
     cursor: &mut Range<usize>,
     first: Option<amp::ActorId>,
 ) -> Result<Vec<amp::ActorId>, AutomergeError> {
     let num_actors: usize = read_slice(bytes, cursor)?;
-    let mut actors = Vec::with_capacity(num_actors + 1);
+    let mut actors = Vec::new();
     if let Some(actor) = first {
         actors.push(actor)
     }
@@ -343,58 +380,120 @@ fn decode_actors(
     Ok(actors)
 }
 
-fn decode_column_info(
+pub(crate) fn decode_column_info(
     bytes: &[u8],
     cursor: &mut Range<usize>,
 ) -> Result<Vec<(u32, usize)>, AutomergeError> {
     let num_columns = read_slice(bytes, cursor)?;
-    let mut columns = Vec::with_capacity(num_columns);
+    let mut columns = Vec::new();
     let mut last_id = 0;
     for _ in 0..num_columns {
+        let offset = cursor.start;
         let id: u32 = read_slice(bytes, cursor)?;
-        if id <= last_id {
-            return Err(AutomergeError::EncodingError);
+        // columns are sorted by their ID without the deflate bit
+        if id & !COLUMN_TYPE_DEFLATE <= last_id {
+            return Err(DecodeError::ColumnOutOfOrder { column: id, offset }.into());
         }
-        last_id = id;
+        last_id = id & !COLUMN_TYPE_DEFLATE;
         let length = read_slice(bytes, cursor)?;
         columns.push((id, length));
     }
     Ok(columns)
 }
 
-fn decode_columns(
+pub(crate) fn decode_columns(
     cursor: &mut Range<usize>,
     columns: Vec<(u32, usize)>,
-) -> HashMap<u32, Range<usize>> {
+) -> Result<HashMap<u32, Range<usize>>, DecodeError> {
     let mut ops = HashMap::new();
     for (id, length) in columns.iter() {
-        let start = cursor.start;
-        let end = start + length;
-        *cursor = end..cursor.end;
-        ops.insert(*id, start..end);
+        let column =
+            take_bytes(cursor, *length).map_err(|_| DecodeError::ColumnTooLong { column: *id })?;
+        ops.insert(*id, column);
+    }
+    Ok(ops)
+}
+
+/// The decompressed columns of a chunk can be at most this many times the
+/// size of the chunk. Columns of real documents compress far less than this,
+/// it stops a small hostile chunk from inflating to use all the memory there
+/// is.
+const MAX_INFLATE_RATIO: usize = 256;
+
+/// Decompress any DEFLATE compressed columns. If there are any every column
+/// is copied into a new buffer which is returned, and the ranges in `columns`
+/// are changed to be into that rather than `bytes`. In either case the
+/// deflate bit is cleared from the IDs.
+pub(crate) fn inflate_columns<'a>(
+    bytes: &'a [u8],
+    columns: &mut HashMap<u32, Range<usize>>,
+) -> Result<Cow<'a, [u8]>, DecodeError> {
+    if columns.keys().all(|id| id & COLUMN_TYPE_DEFLATE == 0) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let limit = bytes.len().saturating_mul(MAX_INFLATE_RATIO);
+    let mut data = Vec::new();
+    for (id, range) in std::mem::take(columns) {
+        let start = data.len();
+        if id & COLUMN_TYPE_DEFLATE == 0 {
+            data.extend(&bytes[range]);
+        } else {
+            // Read one byte past the limit to tell a column which fits
+            // exactly from one which is too big
+            let remaining = limit.saturating_sub(data.len()) as u64 + 1;
+            DeflateDecoder::new(&bytes[range])
+                .take(remaining)
+                .read_to_end(&mut data)
+                .map_err(|_| DecodeError::InvalidCompressedColumn { column: id })?;
+            if data.len() > limit {
+                return Err(DecodeError::InvalidCompressedColumn { column: id });
+            }
+        }
+        columns.insert(id & !COLUMN_TYPE_DEFLATE, start..data.len());
     }
-    ops
+    Ok(Cow::Owned(data))
 }
 
-fn decode_block(bytes: &[u8], changes: &mut Vec<Change>) -> Result<(), AutomergeError> {
-    match bytes[PREAMBLE_BYTES] {
-        BLOCK_TYPE_DOC => {
-            changes.extend(decode_document(bytes)?);
+pub(crate) fn is_document_block(bytes: &[u8]) -> bool {
+    bytes.get(PREAMBLE_BYTES) == Some(&BLOCK_TYPE_DOC)
+}
+
+pub(crate) fn is_snapshot_block(bytes: &[u8]) -> bool {
+    bytes.get(PREAMBLE_BYTES) == Some(&BLOCK_TYPE_SNAPSHOT)
+}
+
+pub(crate) fn decode_block(
+    bytes: &[u8],
+    changes: &mut Vec<Change>,
+    verify: bool,
+) -> Result<(), AutomergeError> {
+    match bytes.get(PREAMBLE_BYTES) {
+        Some(&BLOCK_TYPE_DOC) => {
+            changes.extend(decode_document(bytes, verify)?);
             Ok(())
         }
-        BLOCK_TYPE_CHANGE => {
-            changes.push(decode_change(bytes.to_vec())?);
+        Some(&BLOCK_TYPE_CHANGE) => {
+            changes.push(decode_change(bytes.to_vec(), verify)?);
             Ok(())
         }
-        _ => Err(AutomergeError::EncodingError),
+        Some(&BLOCK_TYPE_SNAPSHOT) => Err(DecodeError::UnexpectedSnapshot.into()),
+        Some(&chunk_type) => Err(DecodeError::UnknownChunkType { chunk_type }.into()),
+        None => Err(DecodeError::UnexpectedEnd {
+            offset: bytes.len(),
+        }
+        .into()),
     }
 }
 
-fn decode_change(bytes: Vec<u8>) -> Result<Change, AutomergeError> {
-    let (chunktype, hash, body) = decode_header(&bytes)?;
+fn decode_change(bytes: Vec<u8>, verify: bool) -> Result<Change, AutomergeError> {
+    let (chunktype, hash, body) = decode_header(&bytes, verify)?;
 
     if chunktype != BLOCK_TYPE_CHANGE {
-        return Err(AutomergeError::EncodingError);
+        return Err(DecodeError::WrongChunkType {
+            expected: BLOCK_TYPE_CHANGE,
+            found: chunktype,
+        }
+        .into());
     }
 
     let mut cursor = body.clone();
@@ -403,16 +502,23 @@ fn decode_change(bytes: Vec<u8>) -> Result<Change, AutomergeError> {
 
     let actor = amp::ActorId::from(&bytes[slice_bytes(&bytes, &mut cursor)?]);
     let seq = read_slice(&bytes, &mut cursor)?;
-    let start_op = read_slice(&bytes, &mut cursor)?;
+    let start_op: u64 = read_slice(&bytes, &mut cursor)?;
     let time = read_slice(&bytes, &mut cursor)?;
     let message = slice_bytes(&bytes, &mut cursor)?;
 
     let actors = decode_actors(&bytes, &mut cursor, Some(actor))?;
 
     let ops_info = decode_column_info(&bytes, &mut cursor)?;
-    let ops = decode_columns(&mut cursor, ops_info);
+    // the JS implementation rejects these too, see `ColumnEncoder::finish`
+    if let Some((column, _)) = ops_info
+        .iter()
+        .find(|(id, _)| id & COLUMN_TYPE_DEFLATE != 0)
+    {
+        return Err(DecodeError::CompressedChangeColumn { column: *column }.into());
+    }
+    let ops = decode_columns(&mut cursor, ops_info)?;
 
-    Ok(Change {
+    let change = Change {
         bytes,
         hash,
         body,
@@ -424,19 +530,31 @@ fn decode_change(bytes: Vec<u8>) -> Result<Change, AutomergeError> {
         deps,
         ops,
         extra_bytes: cursor,
-    })
+    };
+
+    // Decode every op up front so that `iter_ops` can't stop early on bad data
+    let mut ops = change.iter_ops();
+    let mut num_ops: u64 = 0;
+    while ops.try_next()?.is_some() {
+        num_ops += 1;
+    }
+    if start_op.checked_add(num_ops).is_none() {
+        return Err(DecodeError::OpCounterOverflow.into());
+    }
+
+    Ok(change)
 }
 
 //
 // group all the ops together with the appropriate change and reconstitute the del ops
-// mutates the arguments - returns nothing
+// returns the indexes in `ops` of each change's ops, in order
 //
 
 fn group_doc_change_and_doc_ops(
-    changes: &mut [DocChange],
+    changes: &[DocChange],
     ops: &mut Vec<DocOp>,
     actors: &[amp::ActorId],
-) -> Result<(), AutomergeError> {
+) -> Result<Vec<Vec<usize>>, AutomergeError> {
     let mut change_actors = HashMap::new();
     let mut actor_max = HashMap::new();
 
@@ -455,6 +573,39 @@ fn group_doc_change_and_doc_ops(
         actor_max.insert(change.actor, change.seq + 1);
     }
 
+    fill_doc_op_preds(ops, actors)?;
+
+    let mut change_ops = vec![Vec::new(); changes.len()];
+    'outer: for (op_index, op) in ops.iter().enumerate() {
+        let max_seq = *actor_max
+            .get(&op.actor)
+            .ok_or_else(|| AutomergeError::ChangeDecompressError("Doc Op.Actor Invalid".into()))?;
+        for seq in 1..max_seq {
+            // this is safe - invalid seq would have thrown an error earlier
+            let idx: usize = *change_actors.get(&(op.actor, seq)).unwrap();
+            if op.ctr <= changes[idx].max_op {
+                change_ops[idx].push(op_index);
+                continue 'outer;
+            }
+        }
+        return Err(AutomergeError::ChangeDecompressError(
+            "Doc MaxOp Invalid".into(),
+        ));
+    }
+
+    for indexes in change_ops.iter_mut() {
+        indexes.sort_unstable_by_key(|index| ops[*index].ctr);
+    }
+
+    Ok(change_ops)
+}
+
+/// Fill in the preds of `ops` from their succ, adding a del op for each
+/// successor which isn't in `ops`
+pub(crate) fn fill_doc_op_preds(
+    ops: &mut Vec<DocOp>,
+    actors: &[amp::ActorId],
+) -> Result<(), AutomergeError> {
     let mut op_by_id = HashMap::new();
     ops.iter().enumerate().for_each(|(i, op)| {
         op_by_id.insert((op.ctr, op.actor), i);
@@ -466,7 +617,10 @@ fn group_doc_change_and_doc_ops(
         for succ in op.succ.iter() {
             if !op_by_id.contains_key(&succ) {
                 let key = if op.insert {
-                    amp::OpId(op.ctr, actors[op.actor].clone()).into()
+                    let actor = actors.get(op.actor).ok_or_else(|| {
+                        AutomergeError::ChangeDecompressError("Doc Op.Actor Invalid".into())
+                    })?;
+                    amp::OpId(op.ctr, actor.clone()).into()
                 } else {
                     op.key.clone()
                 };
@@ -491,30 +645,6 @@ fn group_doc_change_and_doc_ops(
             }
         }
     }
-
-    'outer: for op in ops.iter() {
-        let max_seq = *actor_max
-            .get(&op.actor)
-            .ok_or_else(|| AutomergeError::ChangeDecompressError("Doc Op.Actor Invalid".into()))?;
-        for seq in 1..max_seq {
-            // this is safe - invalid seq would have thrown an error earlier
-            let idx: usize = *change_actors.get(&(op.actor, seq)).unwrap();
-            // this is safe since I build the array above ^^
-            let change = &mut changes[idx];
-            if op.ctr <= change.max_op {
-                change.ops.push(op.clone());
-                continue 'outer;
-            }
-        }
-        return Err(AutomergeError::ChangeDecompressError(
-            "Doc MaxOp Invalid".into(),
-        ));
-    }
-
-    changes
-        .iter_mut()
-        .for_each(|change| change.ops.sort_unstable());
-
     Ok(())
 }
 
@@ -524,47 +654,116 @@ fn pred_into(pred: &[(u64, usize)], actors: &[amp::ActorId]) -> Vec<amp::OpId> {
         .collect()
 }
 
-fn doc_changes_to_uncompressed_changes(
-    changes: &[DocChange],
+/// Turn each change from a document back into a `Change`, `change_ops` are
+/// the indexes of each change's ops in `ops`. Only the change being encoded
+/// has its ops copied out of `ops`, so the ops are never all in both forms.
+fn doc_changes_to_changes(
+    doc_changes: Vec<DocChange>,
+    change_ops: Vec<Vec<usize>>,
+    ops: &[DocOp],
     actors: &[amp::ActorId],
-) -> Vec<amp::UncompressedChange> {
-    changes
-        .iter()
-        .map(|change| amp::UncompressedChange {
+) -> Result<Vec<Change>, AutomergeError> {
+    let mut changes: Vec<Change> = Vec::with_capacity(doc_changes.len());
+
+    // fill out the hashes as we go
+    for (i, (change, op_indexes)) in doc_changes.into_iter().zip(change_ops).enumerate() {
+        let start_op = change
+            .max_op
+            .checked_add(1)
+            .and_then(|end| end.checked_sub(op_indexes.len() as u64))
+            .ok_or_else(|| AutomergeError::ChangeDecompressError("Doc MaxOp Invalid".into()))?;
+        let mut deps = Vec::with_capacity(change.deps.len());
+        for dep in change.deps.iter() {
+            let dep_change = changes.get(*dep).ok_or(DecodeError::InvalidDependency {
+                change: i,
+                dep: *dep,
+            })?;
+            deps.push(dep_change.hash)
+        }
+        let uncompressed_change = amp::UncompressedChange {
             // we've already confirmed that all change.actor's are valid
             actor_id: actors[change.actor].clone(),
             seq: change.seq,
             time: change.time,
-            start_op: change.max_op - change.ops.len() as u64 + 1,
+            start_op,
             hash: None,
-            message: change.message.clone(),
-            operations: change
-                .ops
-                .iter()
-                .map(|op| amp::Op {
-                    action: op.action.clone(),
-                    insert: op.insert,
-                    key: op.key.clone(),
-                    obj: op.obj.clone(),
-                    // we've already confirmed that all op.actor's are valid
-                    pred: pred_into(&op.pred, actors),
+            message: change.message,
+            operations: op_indexes
+                .into_iter()
+                .map(|index| {
+                    let op = &ops[index];
+                    amp::Op {
+                        // we've already confirmed that all op.actor's are valid
+                        pred: pred_into(&op.pred, actors),
+                        action: op.action.clone(),
+                        insert: op.insert,
+                        key: op.key.clone(),
+                        obj: op.obj.clone(),
+                    }
                 })
                 .collect(),
-            deps: Vec::new(),
-            extra_bytes: change.extra_bytes.clone(),
-        })
-        .collect()
+            deps,
+            extra_bytes: change.extra_bytes,
+        };
+        changes.push((&uncompressed_change).into());
+    }
+
+    Ok(changes)
 }
 
-fn load_blocks(bytes: &[u8]) -> Result<Vec<Change>, AutomergeError> {
+fn load_blocks(bytes: &[u8], verify: bool) -> Result<Vec<Change>, AutomergeError> {
     let mut changes = Vec::new();
     for slice in split_blocks(bytes).into_iter() {
-        decode_block(slice, &mut changes)?;
+        decode_block(slice, &mut changes, verify)?;
     }
     Ok(changes)
 }
 
-fn split_blocks(bytes: &[u8]) -> Vec<&[u8]> {
+/// Read the next chunk from `reader`, `None` at the end of the data. As with
+/// `split_blocks` a truncated or corrupted chunk at the end is treated as the
+/// end of the data.
+pub(crate) fn read_block<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, AutomergeError> {
+    let mut bytes = vec![0; HEADER_BYTES];
+    if !read_exact_or_end(reader, &mut bytes)? || bytes[0..4] != MAGIC_BYTES {
+        return Ok(None);
+    }
+    // the body length is LEB128 encoded, so at most 10 bytes
+    loop {
+        let mut byte = [0];
+        if !read_exact_or_end(reader, &mut byte)? {
+            return Ok(None);
+        }
+        bytes.push(byte[0]);
+        if byte[0] & 0x80 == 0 || bytes.len() == HEADER_BYTES + 10 {
+            break;
+        }
+    }
+    let mut cursor = HEADER_BYTES..bytes.len();
+    let len: usize = match read_slice(&bytes, &mut cursor) {
+        Ok(len) => len,
+        Err(_) => return Ok(None),
+    };
+    // not allocating `len` bytes up front, it could be anything
+    reader
+        .take(len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| AutomergeError::ReadError(e.to_string()))?;
+    if bytes.len() - cursor.start < len {
+        return Ok(None);
+    }
+    Ok(Some(bytes))
+}
+
+/// `read_exact` but returns false rather than an error at the end of the data
+fn read_exact_or_end<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, AutomergeError> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(AutomergeError::ReadError(e.to_string())),
+    }
+}
+
+pub(crate) fn split_blocks(bytes: &[u8]) -> Vec<&[u8]> {
     // split off all valid blocks - ignore the rest if its corrupted or truncated
     let mut blocks = Vec::new();
     let mut cursor = bytes;
@@ -579,102 +778,154 @@ fn split_blocks(bytes: &[u8]) -> Vec<&[u8]> {
 }
 
 fn pop_block(bytes: &[u8]) -> Option<Range<usize>> {
-    if bytes.len() < 4 || bytes[0..4] != MAGIC_BYTES {
+    if bytes.len() < HEADER_BYTES || bytes[0..4] != MAGIC_BYTES {
         // not reporting error here - file got corrupted?
         return None;
     }
-    let (val, len) = read_leb128(&mut &bytes[HEADER_BYTES..]).unwrap();
-    let end = HEADER_BYTES + len + val;
-    if end > bytes.len() {
-        // not reporting error here - file got truncated?
-        return None;
-    }
-    Some(0..end)
+    let mut cursor = HEADER_BYTES..bytes.len();
+    let len = read_slice(bytes, &mut cursor).ok()?;
+    // not reporting error here either - file got truncated?
+    let body = take_bytes(&mut cursor, len).ok()?;
+    Some(0..body.end)
 }
 
-fn decode_document(bytes: &[u8]) -> Result<Vec<Change>, AutomergeError> {
-    let (chunktype, _hash, mut cursor) = decode_header(&bytes)?;
+fn decode_document(bytes: &[u8], verify: bool) -> Result<Vec<Change>, AutomergeError> {
+    Ok(decode_document_with_ops(bytes, verify)?.changes)
+}
 
-    if chunktype > 0 {
-        return Err(AutomergeError::EncodingError);
+/// A document's changes along with the ops they were made from, so the op
+/// set can be built from the ops directly
+pub(crate) struct DecodedDocument {
+    pub actors: Vec<amp::ActorId>,
+    pub changes: Vec<Change>,
+    /// The max op of each change
+    pub max_ops: Vec<u64>,
+    /// The document's ops with their preds filled in and a del op for each
+    /// successor which isn't in the document
+    pub ops: Vec<DocOp>,
+}
+
+pub(crate) fn decode_document_with_ops(
+    bytes: &[u8],
+    verify: bool,
+) -> Result<DecodedDocument, AutomergeError> {
+    let (chunktype, _hash, mut cursor) = decode_header(&bytes, verify)?;
+
+    if chunktype != BLOCK_TYPE_DOC {
+        return Err(DecodeError::WrongChunkType {
+            expected: BLOCK_TYPE_DOC,
+            found: chunktype,
+        }
+        .into());
     }
 
     let actors = decode_actors(&bytes, &mut cursor, None)?;
-    // FIXME
-    // I should calculate the deads generated on decode and confirm they match these
-    let _heads = decode_hashes(&bytes, &mut cursor)?;
+    let heads = decode_hashes(&bytes, &mut cursor)?;
 
     let changes_info = decode_column_info(&bytes, &mut cursor)?;
     let ops_info = decode_column_info(&bytes, &mut cursor)?;
 
-    let changes_data = decode_columns(&mut cursor, changes_info);
-    let mut doc_changes: Vec<_> = ChangeIterator::new(&bytes, &changes_data).collect();
-
-    let ops_data = decode_columns(&mut cursor, ops_info);
-    let mut doc_ops: Vec<_> = DocOpIterator::new(&bytes, &actors, &ops_data).collect();
-
-    group_doc_change_and_doc_ops(&mut doc_changes, &mut doc_ops, &actors)?;
-
-    let mut uncompressed_changes = doc_changes_to_uncompressed_changes(&doc_changes, &actors);
+    let mut changes_data = decode_columns(&mut cursor, changes_info)?;
+    let changes_bytes = inflate_columns(&bytes, &mut changes_data)?;
+    let mut change_iter = ChangeIterator::new(&changes_bytes, &changes_data);
+    let mut doc_changes = Vec::new();
+    while let Some(change) = change_iter.try_next()? {
+        doc_changes.push(change);
+    }
 
-    compress_doc_changes(&mut uncompressed_changes, &doc_changes)
-        .ok_or(AutomergeError::EncodingError)
-}
+    let mut ops_data = decode_columns(&mut cursor, ops_info)?;
+    let ops_bytes = inflate_columns(&bytes, &mut ops_data)?;
+    let mut op_iter = DocOpIterator::new(&ops_bytes, &actors, &ops_data);
+    let mut doc_ops = Vec::new();
+    while let Some(op) = op_iter.try_next()? {
+        doc_ops.push(op);
+    }
 
-fn compress_doc_changes(
-    uncompressed_changes: &mut [amp::UncompressedChange],
-    doc_changes: &[DocChange],
-) -> Option<Vec<Change>> {
-    let mut changes: Vec<Change> = Vec::with_capacity(doc_changes.len());
+    let change_ops = group_doc_change_and_doc_ops(&doc_changes, &mut doc_ops, &actors)?;
 
-    // fill out the hashes as we go
+    let max_ops = doc_changes.iter().map(|change| change.max_op).collect();
+    let changes = doc_changes_to_changes(doc_changes, change_ops, &doc_ops, &actors)?;
 
-    for i in 0..doc_changes.len() {
-        let deps = &mut uncompressed_changes.get_mut(i)?.deps;
-        for idx in doc_changes.get(i)?.deps.iter() {
-            deps.push(changes.get(*idx)?.hash)
+    if verify {
+        let mut stored = heads;
+        stored.sort();
+        let mut calculated: Vec<_> = get_change_heads(&changes).into_iter().collect();
+        calculated.sort();
+        if stored != calculated {
+            return Err(DecodeError::HeadsMismatch { stored, calculated }.into());
         }
-        changes.push(uncompressed_changes.get(i)?.into());
     }
 
-    Some(changes)
+    Ok(DecodedDocument {
+        actors,
+        changes,
+        max_ops,
+        ops: doc_ops,
+    })
 }
 
 fn group_doc_ops(changes: &[amp::UncompressedChange], actors: &[amp::ActorId]) -> Vec<DocOp> {
-    let mut by_obj_id = HashMap::<amp::ObjectId, HashMap<amp::Key, HashMap<amp::OpId, _>>>::new();
-    let mut by_ref = HashMap::<amp::ObjectId, HashMap<amp::Key, Vec<amp::OpId>>>::new();
-    let mut is_seq = HashSet::<amp::ObjectId>::new();
-    let mut ops = Vec::new();
-
-    for change in changes {
-        for (i, op) in change.operations.iter().enumerate() {
-            let opid = amp::OpId(change.start_op + i as u64, change.actor_id.clone());
-            let objid = op.obj.clone();
-            if let amp::OpType::Make(amp::ObjType::Sequence(_)) = op.action {
-                is_seq.insert(opid.clone().into());
-            }
+    let mut builder = DocOpBuilder::default();
+    builder.add_changes(changes, actors);
+    builder.finish()
+}
 
-            let key = if !op.insert {
-                op.key.clone()
-            } else {
-                by_ref
-                    .entry(objid.clone())
-                    .or_default()
-                    .entry(op.key.clone())
-                    .or_default()
-                    .push(opid.clone());
-                opid.clone().into()
-            };
-
-            by_obj_id
-                .entry(objid.clone())
+/// The ops of a document grouped by object and key, with the succ of each op
+/// filled in from the preds of the ops added after it
+#[derive(Default)]
+pub(crate) struct DocOpBuilder {
+    pub by_obj_id: HashMap<amp::ObjectId, HashMap<amp::Key, HashMap<amp::OpId, DocOp>>>,
+    /// The elements inserted after each element of each sequence
+    pub by_ref: HashMap<amp::ObjectId, HashMap<amp::Key, Vec<amp::OpId>>>,
+    pub is_seq: HashSet<amp::ObjectId>,
+}
+
+impl DocOpBuilder {
+    /// Add an op which already has its succ, e.g. one from a snapshot
+    pub(crate) fn add_doc_op(&mut self, opid: amp::OpId, op: DocOp) {
+        if let amp::OpType::Make(amp::ObjType::Sequence(_)) = op.action {
+            self.is_seq.insert(opid.clone().into());
+        }
+
+        let key = if !op.insert {
+            op.key.clone()
+        } else {
+            self.by_ref
+                .entry(op.obj.clone())
                 .or_default()
-                .entry(key.clone())
+                .entry(op.key.clone())
                 .or_default()
-                .insert(
+                .push(opid.clone());
+            opid.clone().into()
+        };
+
+        self.by_obj_id
+            .entry(op.obj.clone())
+            .or_default()
+            .entry(key)
+            .or_default()
+            .insert(opid, op);
+    }
+
+    pub(crate) fn add_changes(
+        &mut self,
+        changes: &[amp::UncompressedChange],
+        actors: &[amp::ActorId],
+    ) {
+        for change in changes {
+            let actor = actors.iter().position(|a| a == &change.actor_id).unwrap();
+            for (i, op) in change.operations.iter().enumerate() {
+                let opid = amp::OpId(change.start_op + i as u64, change.actor_id.clone());
+                let key = if op.insert {
+                    opid.clone().into()
+                } else {
+                    op.key.clone()
+                };
+
+                self.add_doc_op(
                     opid.clone(),
                     DocOp {
-                        actor: actors.iter().position(|a| a == &opid.1).unwrap(),
+                        actor,
                         ctr: opid.0,
                         action: op.action.clone(),
                         obj: op.obj.clone(),
@@ -685,60 +936,81 @@ fn group_doc_ops(changes: &[amp::UncompressedChange], actors: &[amp::ActorId]) -
                     },
                 );
 
-            for pred in &op.pred {
-                by_obj_id
-                    .entry(objid.clone())
-                    .or_default()
-                    .entry(key.clone())
-                    .or_default()
-                    .get_mut(pred)
-                    .unwrap()
-                    .succ
-                    .push((opid.0, actors.iter().position(|a| a == &opid.1).unwrap()));
+                for pred in &op.pred {
+                    // a pred which isn't in the same object and key doesn't overwrite anything
+                    if let Some(pred_op) = self
+                        .by_obj_id
+                        .entry(op.obj.clone())
+                        .or_default()
+                        .entry(key.clone())
+                        .or_default()
+                        .get_mut(pred)
+                    {
+                        pred_op.succ.push((opid.0, actor));
+                    }
+                }
             }
         }
     }
 
-    for objid in by_obj_id.keys().sorted() {
-        let mut keys = Vec::new();
-        if is_seq.contains(objid) {
-            let mut stack = vec![amp::ElementId::Head];
-            while !stack.is_empty() {
-                let key = stack.pop().unwrap();
-                if key != amp::ElementId::Head {
-                    keys.push(amp::Key::Seq(key.clone()))
-                }
-                for opid in by_ref
-                    .entry(objid.clone())
-                    .or_default()
-                    .entry(key.into())
-                    .or_default()
-                    .iter()
-                    .sorted()
-                {
-                    stack.push(opid.into())
+    /// The ops in the order they are stored in a document, by object, then
+    /// by key, with the keys of a sequence in the sequence's order
+    pub(crate) fn finish(mut self) -> Vec<DocOp> {
+        let mut ops = Vec::new();
+
+        for objid in self.by_obj_id.keys().sorted() {
+            let mut keys = Vec::new();
+            if self.is_seq.contains(objid) {
+                let mut stack = vec![amp::ElementId::Head];
+                while !stack.is_empty() {
+                    let key = stack.pop().unwrap();
+                    if key != amp::ElementId::Head {
+                        keys.push(amp::Key::Seq(key.clone()))
+                    }
+                    for opid in self
+                        .by_ref
+                        .entry(objid.clone())
+                        .or_default()
+                        .entry(key.into())
+                        .or_default()
+                        .iter()
+                        .sorted()
+                    {
+                        stack.push(opid.into())
+                    }
                 }
+            } else {
+                keys = self
+                    .by_obj_id
+                    .get(objid)
+                    .map(|d| d.keys().sorted().cloned().collect())
+                    .unwrap_or_default()
             }
-        } else {
-            keys = by_obj_id
-                .get(objid)
-                .map(|d| d.keys().sorted().cloned().collect())
-                .unwrap_or_default()
-        }
 
-        for key in keys {
-            if let Some(key_ops) = by_obj_id.get(objid).and_then(|d| d.get(&key)) {
-                for opid in key_ops.keys().sorted() {
-                    let op = key_ops.get(opid).unwrap();
-                    if op.action != amp::OpType::Del {
-                        ops.push(op.clone());
+            for key in keys {
+                if let Some(key_ops) = self.by_obj_id.get(objid).and_then(|d| d.get(&key)) {
+                    for opid in key_ops.keys().sorted() {
+                        let op = key_ops.get(opid).unwrap();
+                        if op.action != amp::OpType::Del {
+                            ops.push(op.clone());
+                        }
                     }
                 }
             }
         }
+
+        ops
     }
+}
 
-    ops
+fn get_change_heads(changes: &[Change]) -> HashSet<amp::ChangeHash> {
+    changes.iter().fold(HashSet::new(), |mut acc, c| {
+        acc.insert(c.hash);
+        for dep in c.deps.iter() {
+            acc.remove(dep);
+        }
+        acc
+    })
 }
 
 fn get_heads(changes: &[amp::UncompressedChange]) -> HashSet<amp::ChangeHash> {
@@ -753,8 +1025,13 @@ fn get_heads(changes: &[amp::UncompressedChange]) -> HashSet<amp::ChangeHash> {
     })
 }
 
+/// Columns at least this many bytes long are DEFLATE compressed when saving a
+/// document, unless another threshold is given
+pub const DEFAULT_DEFLATE_THRESHOLD: usize = 256;
+
 pub(crate) fn encode_document(
     changes: Vec<amp::UncompressedChange>,
+    deflate_threshold: Option<usize>,
 ) -> Result<Vec<u8>, AutomergeError> {
     let mut bytes: Vec<u8> = Vec::new();
     let mut hasher = Sha256::new();
@@ -771,11 +1048,13 @@ pub(crate) fn encode_document(
         .cloned()
         .collect();
 
-    let (change_bytes, change_info) = ChangeEncoder::encode_changes(&changes, &actors);
+    let (change_bytes, change_info) =
+        ChangeEncoder::encode_changes(&changes, &actors, deflate_threshold);
 
     let doc_ops = group_doc_ops(&changes, &actors);
 
-    let (ops_bytes, ops_info) = DocOpEncoder::encode_doc_ops(&doc_ops, &mut actors);
+    let (ops_bytes, ops_info) =
+        DocOpEncoder::encode_doc_ops(&doc_ops, &mut actors, deflate_threshold);
 
     bytes.extend(&MAGIC_BYTES);
     bytes.extend(vec![0, 0, 0, 0]); // we dont know the hash yet so fill in a fake
@@ -892,6 +1171,13 @@ mod tests {
                     insert,
                     pred: vec![opid1.clone(), opid2],
                 },
+                amp::Op {
+                    action: amp::OpType::Set(amp::ScalarValue::Bytes(vec![0, 1, 255])),
+                    key: key2.clone(),
+                    obj: obj2.clone(),
+                    insert,
+                    pred: vec![opid3.clone()],
+                },
                 amp::Op {
                     action: amp::OpType::Set(amp::ScalarValue::Str("some value".into())),
                     key: key2.clone(),
@@ -958,6 +1244,61 @@ mod tests {
         assert_eq!(bin1, bin2);
     }
 
+    #[test]
+    fn test_unknown_actions_and_value_types_round_trip() {
+        let actor = amp::ActorId::from_str("deadbeefdeadbeef").unwrap();
+        let change1 = amp::UncompressedChange {
+            start_op: 1,
+            seq: 1,
+            time: 0,
+            message: None,
+            hash: None,
+            actor_id: actor.clone(),
+            deps: vec![],
+            operations: vec![
+                amp::Op {
+                    action: amp::OpType::Set(amp::ScalarValue::Unknown {
+                        type_code: 11,
+                        bytes: vec![1, 2, 3],
+                    }),
+                    key: "field1".into(),
+                    obj: amp::ObjectId::Root,
+                    insert: false,
+                    pred: Vec::new(),
+                },
+                amp::Op {
+                    action: amp::OpType::Unknown {
+                        action: 17,
+                        value: amp::ScalarValue::Unknown {
+                            type_code: 15,
+                            bytes: Vec::new(),
+                        },
+                    },
+                    key: "field2".into(),
+                    obj: amp::ObjectId::Root,
+                    insert: false,
+                    pred: vec![actor.op_id_at(1)],
+                },
+                amp::Op {
+                    action: amp::OpType::Unknown {
+                        action: 7,
+                        value: amp::ScalarValue::Cursor(actor.op_id_at(1)),
+                    },
+                    key: amp::Key::head(),
+                    obj: actor.op_id_at(1).into(),
+                    insert: true,
+                    pred: Vec::new(),
+                },
+            ],
+            extra_bytes: vec![],
+        };
+        let bin1 = Change::try_from(change1.clone()).unwrap();
+        let change2 = bin1.decode();
+        let bin2 = Change::try_from(change2.clone()).unwrap();
+        assert_eq!(change1, change2);
+        assert_eq!(bin1.bytes, bin2.bytes);
+    }
+
     #[test]
     fn test_encode_decode_document() {
         let actor = amp::ActorId::random();
@@ -1041,4 +1382,264 @@ mod tests {
             changes.into_iter().cloned().collect::<Vec<Change>>()
         );
     }
+
+    #[test]
+    fn test_large_document_columns_are_compressed() {
+        let actor = amp::ActorId::from_str("deadbeefdeadbeef").unwrap();
+        let mut backend = crate::Backend::init();
+        for (i, bird) in ["magpie", "wren", "robin"]
+            .iter()
+            .cycle()
+            .take(200)
+            .enumerate()
+        {
+            let change = Change::from(amp::UncompressedChange {
+                start_op: i as u64 + 1,
+                seq: i as u64 + 1,
+                time: 0,
+                message: Some(format!("a {}", bird)),
+                hash: None,
+                actor_id: actor.clone(),
+                deps: backend.get_heads(),
+                operations: vec![amp::Op {
+                    action: amp::OpType::Set((*bird).into()),
+                    obj: amp::ObjectId::Root,
+                    key: format!("bird {}", i).as_str().into(),
+                    insert: false,
+                    pred: Vec::new(),
+                }],
+                extra_bytes: Vec::new(),
+            });
+            backend.apply_changes(vec![change]).unwrap();
+        }
+
+        let compressed = backend.save().unwrap();
+        let uncompressed = backend.save_with_deflate_threshold(None).unwrap();
+        assert!(compressed.len() < uncompressed.len());
+
+        let changes: Vec<Change> = backend.get_changes(&[]).into_iter().cloned().collect();
+        assert_eq!(Change::load_document(&compressed).unwrap(), changes);
+        assert_eq!(Change::load_document(&uncompressed).unwrap(), changes);
+        let everything = backend.save_with_deflate_threshold(Some(1)).unwrap();
+        assert_eq!(Change::load_document(&everything).unwrap(), changes);
+    }
+
+    #[test]
+    fn test_compressed_change_columns_are_errors() {
+        // no deps, actor 0xaa, seq 1, start op 1, time 0, no message, no other actors
+        let mut body = vec![0, 1, 0xaa, 1, 1, 0, 0, 0];
+        // a compressed action column
+        body.extend(&[1, 66 | COLUMN_TYPE_DEFLATE as u8, 3, 0x63, 0x04, 0x00]);
+        assert_eq!(
+            decode_error(chunk(BLOCK_TYPE_CHANGE, &body)),
+            Some(DecodeError::CompressedChangeColumn {
+                column: 66 | COLUMN_TYPE_DEFLATE
+            })
+        );
+    }
+
+    #[test]
+    fn test_columns_which_inflate_too_much_are_errors() {
+        use flate2::write::DeflateEncoder;
+
+        let compress = |len| {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(&vec![0; len]).unwrap();
+            encoder.finish().unwrap()
+        };
+        let column = 66 | COLUMN_TYPE_DEFLATE;
+
+        let small = compress(100);
+        let mut columns = HashMap::new();
+        columns.insert(column, 0..small.len());
+        assert_eq!(inflate_columns(&small, &mut columns).unwrap(), vec![0; 100]);
+        assert_eq!(columns.get(&66), Some(&(0..100)));
+
+        let bomb = compress(1 << 20);
+        assert!(bomb.len() * MAX_INFLATE_RATIO < 1 << 20);
+        let mut columns = HashMap::new();
+        columns.insert(column, 0..bomb.len());
+        assert_eq!(
+            inflate_columns(&bomb, &mut columns),
+            Err(DecodeError::InvalidCompressedColumn { column })
+        );
+    }
+
+    /// A chunk with a valid header around `body`
+    fn chunk(chunk_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC_BYTES.to_vec();
+        bytes.extend(&[0, 0, 0, 0, chunk_type]);
+        leb128::write::unsigned(&mut bytes, body.len() as u64).unwrap();
+        bytes.extend(body);
+        let hash = Sha256::digest(&bytes[CHUNK_START..]);
+        bytes.splice(HASH_RANGE, hash[0..4].iter().cloned());
+        bytes
+    }
+
+    fn decode_error(bytes: Vec<u8>) -> Option<DecodeError> {
+        match Change::from_bytes(bytes) {
+            Err(AutomergeError::Decode { source }) => Some(source),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_malformed_changes_are_errors() {
+        let change = Change::from(amp::UncompressedChange {
+            start_op: 1,
+            seq: 1,
+            time: 0,
+            message: Some("birds".into()),
+            hash: None,
+            actor_id: amp::ActorId::from_str("deadbeefdeadbeef").unwrap(),
+            deps: vec![amp::ChangeHash([7; 32])],
+            operations: vec![amp::Op {
+                action: amp::OpType::Set("magpie".into()),
+                obj: amp::ObjectId::Root,
+                key: "bird".into(),
+                insert: false,
+                pred: Vec::new(),
+            }],
+            extra_bytes: Vec::new(),
+        });
+
+        for len in 0..change.bytes.len() {
+            assert!(Change::from_bytes(change.bytes[..len].to_vec()).is_err());
+        }
+
+        let mut bytes = change.bytes.clone();
+        bytes[0] = 0;
+        assert_eq!(
+            decode_error(bytes),
+            Some(DecodeError::WrongMagicBytes { offset: 0 })
+        );
+
+        let mut bytes = change.bytes.clone();
+        bytes.push(0);
+        assert_eq!(
+            decode_error(bytes),
+            Some(DecodeError::TrailingData {
+                offset: change.bytes.len()
+            })
+        );
+
+        let bytes = chunk(7, &change.bytes[change.body.clone()]);
+        assert_eq!(
+            decode_error(bytes.clone()),
+            Some(DecodeError::WrongChunkType {
+                expected: BLOCK_TYPE_CHANGE,
+                found: 7
+            })
+        );
+        assert_eq!(
+            Change::load_document(&bytes),
+            Err(DecodeError::UnknownChunkType { chunk_type: 7 }.into())
+        );
+    }
+
+    #[test]
+    fn test_malformed_columns_are_errors() {
+        // no deps, actor 0xaa, seq 1, start op 1, time 0, no message, no other actors
+        let header = [0, 1, 0xaa, 1, 1, 0, 0, 0];
+
+        // a one byte action column which claims to be five bytes long
+        let mut body = header.to_vec();
+        body.extend(&[1, 66, 5, 1]);
+        assert_eq!(
+            decode_error(chunk(BLOCK_TYPE_CHANGE, &body)),
+            Some(DecodeError::ColumnTooLong { column: 66 })
+        );
+
+        // the action column is in front of the obj column
+        let mut body = header.to_vec();
+        body.extend(&[2, 66, 0, 1, 0]);
+        assert_eq!(
+            decode_error(chunk(BLOCK_TYPE_CHANGE, &body)),
+            Some(DecodeError::ColumnOutOfOrder {
+                column: 1,
+                offset: HEADER_BYTES + 1 + header.len() + 3
+            })
+        );
+
+        // one set op, but no key column
+        let mut body = header.to_vec();
+        body.extend(&[1, 66, 2, 1, 1]);
+        assert_eq!(
+            decode_error(chunk(BLOCK_TYPE_CHANGE, &body)),
+            Some(DecodeError::InvalidColumn { column: "key" })
+        );
+    }
+
+    #[test]
+    fn test_checksums_and_heads_are_verified() {
+        let actor = amp::ActorId::from_str("deadbeefdeadbeef").unwrap();
+        let mut backend = crate::Backend::init();
+        let change = Change::from(amp::UncompressedChange {
+            start_op: 1,
+            seq: 1,
+            time: 0,
+            message: None,
+            hash: None,
+            actor_id: actor,
+            deps: Vec::new(),
+            operations: vec![amp::Op {
+                action: amp::OpType::Set("magpie".into()),
+                obj: amp::ObjectId::Root,
+                key: "bird".into(),
+                insert: false,
+                pred: Vec::new(),
+            }],
+            extra_bytes: Vec::new(),
+        });
+        backend.apply_changes(vec![change.clone()]).unwrap();
+        let doc = backend.save().unwrap();
+
+        let mut corrupt_change = change.bytes.clone();
+        let value = corrupt_change
+            .windows(6)
+            .position(|window| window == b"magpie")
+            .unwrap();
+        corrupt_change[value] = b'n';
+        assert!(matches!(
+            decode_error(corrupt_change.clone()),
+            Some(DecodeError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            Change::load_document(&corrupt_change),
+            Err(AutomergeError::Decode {
+                source: DecodeError::ChecksumMismatch { .. }
+            })
+        ));
+        assert_eq!(
+            Change::load_document_unverified(&corrupt_change)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // the stored head is the hash of the only change, change it and fix
+        // up the checksum
+        let head = doc
+            .windows(32)
+            .position(|window| window == change.hash.0)
+            .unwrap();
+        let mut wrong_heads = doc.clone();
+        wrong_heads[head] ^= 1;
+        let (_, _, body) = decode_header(&doc, true).unwrap();
+        let wrong_heads = chunk(BLOCK_TYPE_DOC, &wrong_heads[body]);
+        let mut stored = change.hash;
+        stored.0[0] ^= 1;
+        assert_eq!(
+            Change::load_document(&wrong_heads),
+            Err(DecodeError::HeadsMismatch {
+                stored: vec![stored],
+                calculated: vec![change.hash],
+            }
+            .into())
+        );
+        assert_eq!(
+            Change::load_document_unverified(&wrong_heads).unwrap(),
+            vec![change]
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use crate::encoding::{BooleanDecoder, Decodable, Decoder, DeltaDecoder, RleDecoder};
 use crate::encoding::{BooleanEncoder, ColData, DeltaEncoder, Encodable, RleEncoder};
+use crate::error::DecodeError;
 use automerge_protocol as amp;
 use core::fmt::Debug;
 use std::cmp::Ordering;
@@ -11,7 +12,7 @@ use std::str;
 
 impl Encodable for Action {
     fn encode<R: Write>(&self, buf: &mut R) -> io::Result<usize> {
-        (*self as u32).encode(buf)
+        self.number().encode(buf)
     }
 }
 
@@ -104,31 +105,43 @@ impl<'a> OperationIterator<'a> {
     }
 }
 
-impl<'a> Iterator for OperationIterator<'a> {
-    type Item = amp::Op;
-    fn next(&mut self) -> Option<amp::Op> {
-        let action = self.action.next()??;
-        let insert = self.insert.next()?;
-        let obj = self.objs.next()?;
-        let key = self.keys.next()?;
-        let pred = self.pred.next()?;
-        let value = self.value.next()?;
+impl<'a> OperationIterator<'a> {
+    /// The next op, or the column which couldn't be decoded
+    pub(crate) fn try_next(&mut self) -> Result<Option<amp::Op>, DecodeError> {
+        let action = match self.action.next().ok_or(invalid_column("action"))? {
+            Some(action) => action,
+            None => return Ok(None),
+        };
+        let insert = self.insert.next().ok_or(invalid_column("insert"))?;
+        let obj = self.objs.next().ok_or(invalid_column("obj"))?;
+        let key = self.keys.next().ok_or(invalid_column("key"))?;
+        let pred = self.pred.next().ok_or(invalid_column("pred"))?;
+        let value = self.value.next().ok_or(invalid_column("value"))?;
         let action = match action {
             Action::Set => amp::OpType::Set(value),
             Action::MakeList => amp::OpType::Make(amp::ObjType::list()),
             Action::MakeText => amp::OpType::Make(amp::ObjType::text()),
             Action::MakeMap => amp::OpType::Make(amp::ObjType::map()),
             Action::MakeTable => amp::OpType::Make(amp::ObjType::table()),
+            Action::MakeSet => amp::OpType::Make(amp::ObjType::set()),
             Action::Del => amp::OpType::Del,
-            Action::Inc => amp::OpType::Inc(value.to_i64()?),
+            Action::Inc => amp::OpType::Inc(value.to_i64().ok_or(invalid_column("value"))?),
+            Action::Unknown(action) => amp::OpType::Unknown { action, value },
         };
-        Some(amp::Op {
+        Ok(Some(amp::Op {
             action,
             obj,
             key,
             pred,
             insert,
-        })
+        }))
+    }
+}
+
+impl<'a> Iterator for OperationIterator<'a> {
+    type Item = amp::Op;
+    fn next(&mut self) -> Option<amp::Op> {
+        self.try_next().ok().flatten()
     }
 }
 
@@ -146,24 +159,36 @@ pub(crate) struct DocOpIterator<'a> {
 impl<'a> Iterator for DocOpIterator<'a> {
     type Item = DocOp;
     fn next(&mut self) -> Option<DocOp> {
-        let action = self.action.next()??;
-        let actor = self.actor.next()??;
-        let ctr = self.ctr.next()??;
-        let insert = self.insert.next()?;
-        let obj = self.objs.next()?;
-        let key = self.keys.next()?;
-        let succ = self.succ.next()?;
-        let value = self.value.next()?;
+        self.try_next().ok().flatten()
+    }
+}
+
+impl<'a> DocOpIterator<'a> {
+    /// The next op, or the column which couldn't be decoded
+    pub(crate) fn try_next(&mut self) -> Result<Option<DocOp>, DecodeError> {
+        let action = match self.action.next().ok_or(invalid_column("action"))? {
+            Some(action) => action,
+            None => return Ok(None),
+        };
+        let actor = self.actor.next().flatten().ok_or(invalid_column("id"))?;
+        let ctr = self.ctr.next().flatten().ok_or(invalid_column("id"))?;
+        let insert = self.insert.next().ok_or(invalid_column("insert"))?;
+        let obj = self.objs.next().ok_or(invalid_column("obj"))?;
+        let key = self.keys.next().ok_or(invalid_column("key"))?;
+        let succ = self.succ.next().ok_or(invalid_column("succ"))?;
+        let value = self.value.next().ok_or(invalid_column("value"))?;
         let action = match action {
             Action::Set => amp::OpType::Set(value),
             Action::MakeList => amp::OpType::Make(amp::ObjType::list()),
             Action::MakeText => amp::OpType::Make(amp::ObjType::text()),
             Action::MakeMap => amp::OpType::Make(amp::ObjType::map()),
             Action::MakeTable => amp::OpType::Make(amp::ObjType::table()),
+            Action::MakeSet => amp::OpType::Make(amp::ObjType::set()),
             Action::Del => amp::OpType::Del,
-            Action::Inc => amp::OpType::Inc(value.to_i64()?),
+            Action::Inc => amp::OpType::Inc(value.to_i64().ok_or(invalid_column("value"))?),
+            Action::Unknown(action) => amp::OpType::Unknown { action, value },
         };
-        Some(DocOp {
+        Ok(Some(DocOp {
             actor,
             ctr,
             action,
@@ -172,11 +197,9 @@ impl<'a> Iterator for DocOpIterator<'a> {
             succ,
             pred: Vec::new(),
             insert,
-        })
+        }))
     }
-}
 
-impl<'a> DocOpIterator<'a> {
     pub(crate) fn new(
         bytes: &'a [u8],
         actors: &'a [amp::ActorId],
@@ -242,19 +265,24 @@ impl<'a> ChangeIterator<'a> {
             },
         }
     }
-}
 
-impl<'a> Iterator for ChangeIterator<'a> {
-    type Item = DocChange;
-    fn next(&mut self) -> Option<DocChange> {
-        let actor = self.actor.next()??;
-        let seq = self.seq.next()??;
-        let max_op = self.max_op.next()??;
-        let time = self.time.next()?? as i64;
-        let message = self.message.next()?;
-        let deps = self.deps.next()?;
+    /// The next change, or the column which couldn't be decoded
+    pub(crate) fn try_next(&mut self) -> Result<Option<DocChange>, DecodeError> {
+        let actor = match self.actor.next().ok_or(invalid_column("actor"))? {
+            Some(actor) => actor,
+            None => return Ok(None),
+        };
+        let seq = self.seq.next().flatten().ok_or(invalid_column("seq"))?;
+        let max_op = self
+            .max_op
+            .next()
+            .flatten()
+            .ok_or(invalid_column("maxOp"))?;
+        let time = self.time.next().flatten().ok_or(invalid_column("time"))? as i64;
+        let message = self.message.next().ok_or(invalid_column("message"))?;
+        let deps = self.deps.next().ok_or(invalid_column("deps"))?;
         let extra_bytes = self.extra.next().unwrap_or_else(Vec::new);
-        Some(DocChange {
+        Ok(Some(DocChange {
             actor,
             seq,
             max_op,
@@ -262,8 +290,14 @@ impl<'a> Iterator for ChangeIterator<'a> {
             message,
             deps,
             extra_bytes,
-            ops: Vec::new(),
-        })
+        }))
+    }
+}
+
+impl<'a> Iterator for ChangeIterator<'a> {
+    type Item = DocChange;
+    fn next(&mut self) -> Option<DocChange> {
+        self.try_next().ok().flatten()
     }
 }
 
@@ -317,7 +351,7 @@ impl<'a> Iterator for DepsIterator<'a> {
     fn next(&mut self) -> Option<Vec<usize>> {
         let num = self.num.next()??;
         // I bet there's something simple like `self.dep.take(num).collect()`
-        let mut p = Vec::with_capacity(num);
+        let mut p = Vec::new();
         for _ in 0..num {
             let dep = self.dep.next()??;
             p.push(dep as usize);
@@ -340,7 +374,7 @@ impl<'a> Iterator for PredIterator<'a> {
     type Item = Vec<amp::OpId>;
     fn next(&mut self) -> Option<Vec<amp::OpId>> {
         let num = self.pred_num.next()??;
-        let mut p = Vec::with_capacity(num);
+        let mut p = Vec::new();
         for _ in 0..num {
             let actor = self.pred_actor.next()??;
             let ctr = self.pred_ctr.next()??;
@@ -356,7 +390,7 @@ impl<'a> Iterator for SuccIterator<'a> {
     type Item = Vec<(u64, usize)>;
     fn next(&mut self) -> Option<Vec<(u64, usize)>> {
         let num = self.succ_num.next()??;
-        let mut p = Vec::with_capacity(num);
+        let mut p = Vec::new();
         for _ in 0..num {
             let actor = self.succ_actor.next()??;
             let ctr = self.succ_ctr.next()??;
@@ -416,15 +450,16 @@ impl<'a> Iterator for ValueIterator<'a> {
             }
             v if v % 16 == VALUE_TYPE_BYTES => {
                 let len = v >> 4;
-                let _data = self.val_raw.read_bytes(len).ok()?;
-                unimplemented!()
-                //Some((amp::Value::Bytes(data))
+                let data = self.val_raw.read_bytes(len).ok()?;
+                Some(amp::ScalarValue::Bytes(data.to_vec()))
             }
             v if v % 16 >= VALUE_TYPE_MIN_UNKNOWN && v % 16 <= VALUE_TYPE_MAX_UNKNOWN => {
                 let len = v >> 4;
-                let _data = self.val_raw.read_bytes(len).ok()?;
-                unimplemented!()
-                //Some((amp::Value::Bytes(data))
+                let data = self.val_raw.read_bytes(len).ok()?;
+                Some(amp::ScalarValue::Unknown {
+                    type_code: (v % 16) as u8,
+                    bytes: data.to_vec(),
+                })
             }
             v if v % 16 == VALUE_TYPE_IEEE754 => {
                 let len = v >> 4;
@@ -493,7 +528,6 @@ pub(crate) struct DocChange {
     pub message: Option<String>,
     pub deps: Vec<usize>,
     pub extra_bytes: Vec<u8>,
-    pub ops: Vec<DocOp>,
 }
 
 #[derive(Debug, Clone)]
@@ -566,6 +600,11 @@ impl ValEncoder {
                 self.raw.extend(bytes);
                 self.len.append_value(len << 4 | VALUE_TYPE_UTF8)
             }
+            amp::ScalarValue::Bytes(bytes) => {
+                let len = bytes.len();
+                self.raw.extend(bytes);
+                self.len.append_value(len << 4 | VALUE_TYPE_BYTES)
+            }
             amp::ScalarValue::Counter(count) => {
                 let len = count.encode(&mut self.raw).unwrap();
                 self.len.append_value(len << 4 | VALUE_TYPE_COUNTER)
@@ -597,6 +636,11 @@ impl ValEncoder {
                 self.ref_actor.append_value(actor_index);
                 self.ref_counter.append_value(opid.0);
             }
+            amp::ScalarValue::Unknown { type_code, bytes } => {
+                let len = bytes.len();
+                self.raw.extend(bytes);
+                self.len.append_value(len << 4 | *type_code as usize)
+            }
         }
     }
 
@@ -774,13 +818,17 @@ pub(crate) struct ChangeEncoder {
 }
 
 impl ChangeEncoder {
-    pub fn encode_changes<'a, 'b, I>(changes: I, actors: &'a [amp::ActorId]) -> (Vec<u8>, Vec<u8>)
+    pub fn encode_changes<'a, 'b, I>(
+        changes: I,
+        actors: &'a [amp::ActorId],
+        deflate_threshold: Option<usize>,
+    ) -> (Vec<u8>, Vec<u8>)
     where
         I: IntoIterator<Item = &'b amp::UncompressedChange>,
     {
         let mut e = Self::new();
         e.encode(changes, actors);
-        e.finish()
+        e.finish(deflate_threshold)
     }
 
     fn new() -> ChangeEncoder {
@@ -811,7 +859,7 @@ impl ChangeEncoder {
                 .append_value(actors.iter().position(|a| a == &change.actor_id).unwrap());
             self.seq.append_value(change.seq);
             self.max_op
-                .append_value(change.start_op + change.operations.len() as u64 - 1);
+                .append_value((change.start_op + change.operations.len() as u64).saturating_sub(1));
             self.time.append_value(change.time as u64);
             self.message.append_value(change.message.clone());
             self.deps_num.append_value(change.deps.len());
@@ -832,7 +880,7 @@ impl ChangeEncoder {
         }
     }
 
-    fn finish(self) -> (Vec<u8>, Vec<u8>) {
+    fn finish(self, deflate_threshold: Option<usize>) -> (Vec<u8>, Vec<u8>) {
         let mut coldata = vec![
             self.actor.finish(DOC_ACTOR),
             self.seq.finish(DOC_SEQ),
@@ -848,6 +896,9 @@ impl ChangeEncoder {
             },
         ];
         coldata.sort_by(|a, b| a.col.cmp(&b.col));
+        for d in coldata.iter_mut() {
+            d.deflate(deflate_threshold);
+        }
 
         let mut data = Vec::new();
         let mut info = Vec::new();
@@ -884,13 +935,14 @@ impl DocOpEncoder {
     pub(crate) fn encode_doc_ops<'a, 'b, I>(
         ops: I,
         actors: &'a mut Vec<amp::ActorId>,
+        deflate_threshold: Option<usize>,
     ) -> (Vec<u8>, Vec<u8>)
     where
         I: IntoIterator<Item = &'b DocOp>,
     {
         let mut e = Self::new();
         e.encode(ops, actors);
-        e.finish()
+        e.finish(deflate_threshold)
     }
 
     fn new() -> DocOpEncoder {
@@ -931,12 +983,17 @@ impl DocOpEncoder {
                     self.val.append_null();
                     Action::Del
                 }
+                amp::OpType::Unknown { action, value } => {
+                    self.val.append_value(value, actors);
+                    Action::Unknown(*action)
+                }
                 amp::OpType::Make(kind) => {
                     self.val.append_null();
                     match kind {
                         amp::ObjType::Sequence(amp::SequenceType::List) => Action::MakeList,
                         amp::ObjType::Map(amp::MapType::Map) => Action::MakeMap,
                         amp::ObjType::Map(amp::MapType::Table) => Action::MakeTable,
+                        amp::ObjType::Map(amp::MapType::Set) => Action::MakeSet,
                         amp::ObjType::Sequence(amp::SequenceType::Text) => Action::MakeText,
                     }
                 }
@@ -945,7 +1002,7 @@ impl DocOpEncoder {
         }
     }
 
-    fn finish(self) -> (Vec<u8>, Vec<u8>) {
+    fn finish(self, deflate_threshold: Option<usize>) -> (Vec<u8>, Vec<u8>) {
         let mut coldata = vec![
             self.actor.finish(COL_ID_ACTOR),
             self.ctr.finish(COL_ID_CTR),
@@ -957,6 +1014,9 @@ impl DocOpEncoder {
         coldata.extend(self.val.finish());
         coldata.extend(self.succ.finish());
         coldata.sort_by(|a, b| a.col.cmp(&b.col));
+        for d in coldata.iter_mut() {
+            d.deflate(deflate_threshold);
+        }
 
         let mut info = Vec::new();
         let mut data = Vec::new();
@@ -1038,12 +1098,17 @@ impl ColumnEncoder {
                 self.val.append_null();
                 Action::Del
             }
+            amp::OpType::Unknown { action, value } => {
+                self.val.append_value(value, actors);
+                Action::Unknown(*action)
+            }
             amp::OpType::Make(kind) => {
                 self.val.append_null();
                 match kind {
                     amp::ObjType::Sequence(amp::SequenceType::List) => Action::MakeList,
                     amp::ObjType::Map(amp::MapType::Map) => Action::MakeMap,
                     amp::ObjType::Map(amp::MapType::Table) => Action::MakeTable,
+                    amp::ObjType::Map(amp::MapType::Set) => Action::MakeSet,
                     amp::ObjType::Sequence(amp::SequenceType::Text) => Action::MakeText,
                 }
             }
@@ -1060,6 +1125,9 @@ impl ColumnEncoder {
         coldata.extend(self.key.finish());
         coldata.extend(self.val.finish());
         coldata.extend(self.pred.finish());
+        // A change's columns are never compressed, its hash covers them and
+        // the columns of a change loaded from a document are re-encoded from
+        // its ops, which has to give the same bytes
         coldata.sort_by(|a, b| a.col.cmp(&b.col));
 
         let mut data = Vec::new();
@@ -1084,6 +1152,10 @@ impl ColumnEncoder {
     }
 }
 
+fn invalid_column(column: &'static str) -> DecodeError {
+    DecodeError::InvalidColumn { column }
+}
+
 fn col_iter<'a, T>(bytes: &'a [u8], ops: &'a HashMap<u32, Range<usize>>, col_id: u32) -> T
 where
     T: From<&'a [u8]>,
@@ -1115,9 +1187,10 @@ pub(crate) const COLUMN_TYPE_BOOLEAN: u32 = 4;
 pub(crate) const COLUMN_TYPE_STRING_RLE: u32 = 5;
 pub(crate) const COLUMN_TYPE_VALUE_LEN: u32 = 6;
 pub(crate) const COLUMN_TYPE_VALUE_RAW: u32 = 7;
+/// Set in a column ID when the column's data is DEFLATE compressed
+pub(crate) const COLUMN_TYPE_DEFLATE: u32 = 8;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
-#[repr(u32)]
 pub(crate) enum Action {
     MakeMap,
     Set,
@@ -1126,67 +1199,162 @@ pub(crate) enum Action {
     MakeText,
     Inc,
     MakeTable,
+    MakeSet,
+    /// An action added by a later version, the op is kept as it is so that
+    /// it can be encoded again
+    Unknown(u64),
+}
+
+impl Action {
+    fn number(self) -> u64 {
+        match self {
+            Action::MakeMap => 0,
+            Action::Set => 1,
+            Action::MakeList => 2,
+            Action::Del => 3,
+            Action::MakeText => 4,
+            Action::Inc => 5,
+            Action::MakeTable => 6,
+            // 7 is `link` in the JS implementation
+            Action::MakeSet => 8,
+            Action::Unknown(num) => num,
+        }
+    }
 }
-const ACTIONS: [Action; 7] = [
-    Action::MakeMap,
-    Action::Set,
-    Action::MakeList,
-    Action::Del,
-    Action::MakeText,
-    Action::Inc,
-    Action::MakeTable,
-];
 
 impl Decodable for Action {
     fn decode<R>(bytes: &mut R) -> Option<Self>
     where
         R: Read,
     {
-        let num = usize::decode::<R>(bytes)?;
-        ACTIONS.get(num).cloned()
+        let action = match u64::decode::<R>(bytes)? {
+            0 => Action::MakeMap,
+            1 => Action::Set,
+            2 => Action::MakeList,
+            3 => Action::Del,
+            4 => Action::MakeText,
+            5 => Action::Inc,
+            6 => Action::MakeTable,
+            8 => Action::MakeSet,
+            num => Action::Unknown(num),
+        };
+        Some(action)
     }
 }
 
 const COL_OBJ_ACTOR: u32 = COLUMN_TYPE_ACTOR_ID;
 const COL_OBJ_CTR: u32 = COLUMN_TYPE_INT_RLE;
-const COL_KEY_ACTOR: u32 = 1 << 3 | COLUMN_TYPE_ACTOR_ID;
-const COL_KEY_CTR: u32 = 1 << 3 | COLUMN_TYPE_INT_DELTA;
-const COL_KEY_STR: u32 = 1 << 3 | COLUMN_TYPE_STRING_RLE;
-const COL_ID_ACTOR: u32 = 2 << 3 | COLUMN_TYPE_ACTOR_ID;
-const COL_ID_CTR: u32 = 2 << 3 | COLUMN_TYPE_INT_DELTA;
-const COL_INSERT: u32 = 3 << 3 | COLUMN_TYPE_BOOLEAN;
-const COL_ACTION: u32 = 4 << 3 | COLUMN_TYPE_INT_RLE;
-const COL_VAL_LEN: u32 = 5 << 3 | COLUMN_TYPE_VALUE_LEN;
-const COL_VAL_RAW: u32 = 5 << 3 | COLUMN_TYPE_VALUE_RAW;
-const COL_PRED_NUM: u32 = 7 << 3 | COLUMN_TYPE_GROUP_CARD;
-const COL_PRED_ACTOR: u32 = 7 << 3 | COLUMN_TYPE_ACTOR_ID;
-const COL_PRED_CTR: u32 = 7 << 3 | COLUMN_TYPE_INT_DELTA;
-const COL_SUCC_NUM: u32 = 8 << 3 | COLUMN_TYPE_GROUP_CARD;
-const COL_SUCC_ACTOR: u32 = 8 << 3 | COLUMN_TYPE_ACTOR_ID;
-const COL_SUCC_CTR: u32 = 8 << 3 | COLUMN_TYPE_INT_DELTA;
-const COL_REF_CTR: u32 = 6 << 3 | COLUMN_TYPE_INT_RLE;
-const COL_REF_ACTOR: u32 = 6 << 3 | COLUMN_TYPE_ACTOR_ID;
-
-const DOC_ACTOR: u32 = /* 0 << 3 */ COLUMN_TYPE_ACTOR_ID;
-const DOC_SEQ: u32 = /* 0 << 3 */ COLUMN_TYPE_INT_DELTA;
-const DOC_MAX_OP: u32 = 1 << 3 | COLUMN_TYPE_INT_DELTA;
-const DOC_TIME: u32 = 2 << 3 | COLUMN_TYPE_INT_DELTA;
-const DOC_MESSAGE: u32 = 3 << 3 | COLUMN_TYPE_STRING_RLE;
-const DOC_DEPS_NUM: u32 = 4 << 3 | COLUMN_TYPE_GROUP_CARD;
-const DOC_DEPS_INDEX: u32 = 4 << 3 | COLUMN_TYPE_INT_DELTA;
-const DOC_EXTRA_LEN: u32 = 5 << 3 | COLUMN_TYPE_VALUE_LEN;
-const DOC_EXTRA_RAW: u32 = 5 << 3 | COLUMN_TYPE_VALUE_RAW;
+const COL_KEY_ACTOR: u32 = 1 << 4 | COLUMN_TYPE_ACTOR_ID;
+const COL_KEY_CTR: u32 = 1 << 4 | COLUMN_TYPE_INT_DELTA;
+const COL_KEY_STR: u32 = 1 << 4 | COLUMN_TYPE_STRING_RLE;
+const COL_ID_ACTOR: u32 = 2 << 4 | COLUMN_TYPE_ACTOR_ID;
+const COL_ID_CTR: u32 = 2 << 4 | COLUMN_TYPE_INT_DELTA;
+const COL_INSERT: u32 = 3 << 4 | COLUMN_TYPE_BOOLEAN;
+const COL_ACTION: u32 = 4 << 4 | COLUMN_TYPE_INT_RLE;
+const COL_VAL_LEN: u32 = 5 << 4 | COLUMN_TYPE_VALUE_LEN;
+const COL_VAL_RAW: u32 = 5 << 4 | COLUMN_TYPE_VALUE_RAW;
+const COL_PRED_NUM: u32 = 7 << 4 | COLUMN_TYPE_GROUP_CARD;
+const COL_PRED_ACTOR: u32 = 7 << 4 | COLUMN_TYPE_ACTOR_ID;
+const COL_PRED_CTR: u32 = 7 << 4 | COLUMN_TYPE_INT_DELTA;
+const COL_SUCC_NUM: u32 = 8 << 4 | COLUMN_TYPE_GROUP_CARD;
+const COL_SUCC_ACTOR: u32 = 8 << 4 | COLUMN_TYPE_ACTOR_ID;
+const COL_SUCC_CTR: u32 = 8 << 4 | COLUMN_TYPE_INT_DELTA;
+const COL_REF_CTR: u32 = 6 << 4 | COLUMN_TYPE_INT_RLE;
+const COL_REF_ACTOR: u32 = 6 << 4 | COLUMN_TYPE_ACTOR_ID;
+
+const DOC_ACTOR: u32 = /* 0 << 4 */ COLUMN_TYPE_ACTOR_ID;
+const DOC_SEQ: u32 = /* 0 << 4 */ COLUMN_TYPE_INT_DELTA;
+const DOC_MAX_OP: u32 = 1 << 4 | COLUMN_TYPE_INT_DELTA;
+const DOC_TIME: u32 = 2 << 4 | COLUMN_TYPE_INT_DELTA;
+const DOC_MESSAGE: u32 = 3 << 4 | COLUMN_TYPE_STRING_RLE;
+const DOC_DEPS_NUM: u32 = 4 << 4 | COLUMN_TYPE_GROUP_CARD;
+const DOC_DEPS_INDEX: u32 = 4 << 4 | COLUMN_TYPE_INT_DELTA;
+const DOC_EXTRA_LEN: u32 = 5 << 4 | COLUMN_TYPE_VALUE_LEN;
+const DOC_EXTRA_RAW: u32 = 5 << 4 | COLUMN_TYPE_VALUE_RAW;
 
 /*
 const DOCUMENT_COLUMNS = {
-  actor:     0 << 3 | COLUMN_TYPE.ACTOR_ID,
-  seq:       0 << 3 | COLUMN_TYPE.INT_DELTA,
-  maxOp:     1 << 3 | COLUMN_TYPE.INT_DELTA,
-  time:      2 << 3 | COLUMN_TYPE.INT_DELTA,
-  message:   3 << 3 | COLUMN_TYPE.STRING_RLE,
-  depsNum:   4 << 3 | COLUMN_TYPE.GROUP_CARD,
-  depsIndex: 4 << 3 | COLUMN_TYPE.INT_DELTA,
-  extraLen:  5 << 3 | COLUMN_TYPE.VALUE_LEN,
-  extraRaw:  5 << 3 | COLUMN_TYPE.VALUE_RAW
+  actor:     0 << 4 | COLUMN_TYPE.ACTOR_ID,
+  seq:       0 << 4 | COLUMN_TYPE.INT_DELTA,
+  maxOp:     1 << 4 | COLUMN_TYPE.INT_DELTA,
+  time:      2 << 4 | COLUMN_TYPE.INT_DELTA,
+  message:   3 << 4 | COLUMN_TYPE.STRING_RLE,
+  depsNum:   4 << 4 | COLUMN_TYPE.GROUP_CARD,
+  depsIndex: 4 << 4 | COLUMN_TYPE.INT_DELTA,
+  extraLen:  5 << 4 | COLUMN_TYPE.VALUE_LEN,
+  extraRaw:  5 << 4 | COLUMN_TYPE.VALUE_RAW
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_ids() {
+        // The JS implementation's `group << 4 | type` IDs, which leave bit 3
+        // for the deflate flag. Documents and changes saved by earlier
+        // versions of this crate used `group << 3 | type` and can't be read.
+        let columns = [
+            (COL_OBJ_ACTOR, 1),
+            (COL_OBJ_CTR, 2),
+            (COL_KEY_ACTOR, 17),
+            (COL_KEY_CTR, 19),
+            (COL_KEY_STR, 21),
+            (COL_ID_ACTOR, 33),
+            (COL_ID_CTR, 35),
+            (COL_INSERT, 52),
+            (COL_ACTION, 66),
+            (COL_VAL_LEN, 86),
+            (COL_VAL_RAW, 87),
+            (COL_REF_CTR, 98),
+            (COL_REF_ACTOR, 97),
+            (COL_PRED_NUM, 112),
+            (COL_PRED_ACTOR, 113),
+            (COL_PRED_CTR, 115),
+            (COL_SUCC_NUM, 128),
+            (COL_SUCC_ACTOR, 129),
+            (COL_SUCC_CTR, 131),
+            (DOC_ACTOR, 1),
+            (DOC_SEQ, 3),
+            (DOC_MAX_OP, 19),
+            (DOC_TIME, 35),
+            (DOC_MESSAGE, 53),
+            (DOC_DEPS_NUM, 64),
+            (DOC_DEPS_INDEX, 67),
+            (DOC_EXTRA_LEN, 86),
+            (DOC_EXTRA_RAW, 87),
+        ];
+        for (column, id) in columns.iter() {
+            assert_eq!(column, id);
+            assert_eq!(column & COLUMN_TYPE_DEFLATE, 0);
+        }
+    }
+
+    #[test]
+    fn test_action_numbers() {
+        // These are part of the binary format and must agree with the JS
+        // implementation, which uses 7 for `link`
+        let actions = [
+            (Action::MakeMap, 0),
+            (Action::Set, 1),
+            (Action::MakeList, 2),
+            (Action::Del, 3),
+            (Action::MakeText, 4),
+            (Action::Inc, 5),
+            (Action::MakeTable, 6),
+            (Action::Unknown(7), 7),
+            (Action::MakeSet, 8),
+        ];
+        for (action, number) in actions.iter() {
+            assert_eq!(action.number(), *number);
+            let mut bytes = Vec::new();
+            number.encode(&mut bytes).unwrap();
+            assert_eq!(Action::decode(&mut &bytes[..]), Some(*action));
+            assert_eq!(
+                amp::OpType::is_unknown_action(*number),
+                matches!(action, Action::Unknown(_))
+            );
+        }
+    }
+}
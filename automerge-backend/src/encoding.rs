@@ -1,6 +1,9 @@
+use crate::columnar::COLUMN_TYPE_DEFLATE;
 use crate::error::AutomergeError;
 use automerge_protocol as amp;
 use core::fmt::Debug;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
 use std::convert::TryFrom;
 use std::io;
 use std::io::{Read, Write};
@@ -117,7 +120,8 @@ impl<'a> From<&'a [u8]> for BooleanDecoder<'a> {
     }
 }
 
-// this is an endless iterator that returns false after input is exhausted
+// this is an endless iterator that returns false after input is exhausted,
+// it returns `None` if the input is invalid
 impl<'a> Iterator for BooleanDecoder<'a> {
     type Item = bool;
 
@@ -126,7 +130,7 @@ impl<'a> Iterator for BooleanDecoder<'a> {
             if self.decoder.done() && self.count == 0 {
                 return Some(false);
             }
-            self.count = self.decoder.read().unwrap_or_default();
+            self.count = self.decoder.read().ok()?;
             self.last_value = !self.last_value;
         }
         self.count -= 1;
@@ -317,7 +321,7 @@ impl<'a, T> From<&'a [u8]> for RleDecoder<'a, T> {
 
 // this decoder needs to be able to send type T or 'null'
 // it is an endless iterator that will return all 'null's
-// once input is exhausted
+// once input is exhausted, it returns `None` if the input is invalid
 impl<'a, T> Iterator for RleDecoder<'a, T>
 where
     T: Clone + Debug + Decodable,
@@ -329,26 +333,25 @@ where
             if self.decoder.done() {
                 return Some(None);
             }
-            match self.decoder.read() {
-                Ok(count) if count > 0 => {
-                    self.count = count;
-                    self.last_value = self.decoder.read().ok();
-                    self.literal = false;
-                }
-                Ok(count) if count < 0 => {
-                    self.count = count.abs();
-                    self.literal = true;
-                }
-                _ => {
-                    self.count = self.decoder.read().unwrap_or_default();
-                    self.last_value = None;
-                    self.literal = false;
-                }
+            let count: isize = self.decoder.read().ok()?;
+            if count > 0 {
+                self.count = count;
+                self.last_value = Some(self.decoder.read().ok()?);
+                self.literal = false;
+            } else if count < 0 {
+                self.count = count.checked_abs()?;
+                self.literal = true;
+            } else {
+                // the length of a run of nulls is unsigned
+                let nulls: usize = self.decoder.read().ok()?;
+                self.count = isize::try_from(nulls).ok()?;
+                self.last_value = None;
+                self.literal = false;
             }
         }
         self.count -= 1;
         if self.literal {
-            Some(self.decoder.read().ok())
+            self.decoder.read().ok().map(Some)
         } else {
             Some(self.last_value.clone())
         }
@@ -379,11 +382,11 @@ impl<'a> Iterator for DeltaDecoder<'a> {
 
     fn next(&mut self) -> Option<Option<u64>> {
         if let Some(delta) = self.rle.next()? {
-            if delta < 0 {
-                self.absolute_val -= delta.abs() as u64;
+            self.absolute_val = if delta < 0 {
+                self.absolute_val.checked_sub(delta.unsigned_abs())?
             } else {
-                self.absolute_val += delta as u64;
-            }
+                self.absolute_val.checked_add(delta as u64)?
+            };
             Some(Some(self.absolute_val))
         } else {
             Some(None)
@@ -493,9 +496,14 @@ impl Decodable for Vec<u8> {
         if len == 0 {
             return Some(vec![]);
         }
-        let mut buffer = vec![0; len];
-        bytes.read_exact(buffer.as_mut_slice()).ok()?;
-        Some(buffer)
+        // The length comes from the input so we don't allocate it up front
+        let mut buffer = Vec::new();
+        bytes.take(len as u64).read_to_end(&mut buffer).ok()?;
+        if buffer.len() == len {
+            Some(buffer)
+        } else {
+            None
+        }
     }
 }
 impl Decodable for String {
@@ -631,4 +639,19 @@ impl ColData {
         }
         Ok(len)
     }
+
+    /// DEFLATE compress the data if it's at least `threshold` bytes long,
+    /// setting the deflate bit in the column ID
+    pub fn deflate(&mut self, threshold: Option<usize>) {
+        match threshold {
+            Some(threshold) if !self.data.is_empty() && self.data.len() >= threshold => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                // writing to a Vec can't fail
+                encoder.write_all(&self.data).unwrap();
+                self.data = encoder.finish().unwrap();
+                self.col |= COLUMN_TYPE_DEFLATE;
+            }
+            _ => {}
+        }
+    }
 }
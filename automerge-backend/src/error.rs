@@ -37,8 +37,12 @@ pub enum AutomergeError {
     DivergedState(String),
     #[error("Change decompression error: {0}")]
     ChangeDecompressError(String),
+    #[error("Error reading the document: {0}")]
+    ReadError(String),
     #[error("Invalid seq {0}")]
     InvalidSeq(u64),
+    #[error("Invalid start op {0}, it reuses op IDs from an earlier change by the same actor")]
+    InvalidStartOp(u64),
     #[error("Map key in seq")]
     MapKeyInSeq,
     #[error("Head to opid")]
@@ -63,8 +67,67 @@ pub enum AutomergeError {
     },
     #[error("Encoding error")]
     EncodingError,
+    #[error("Decoding error: {source}")]
+    Decode {
+        #[from]
+        source: DecodeError,
+    },
     #[error("Attempted to create a cursor for opid {opid} which was not an element in a sequence")]
     InvalidCursor { opid: amp::OpId },
+    #[error("Change {0:?} isn't in this document")]
+    UnknownHead(amp::ChangeHash),
+    #[error("The peer is missing history which has been compacted, it needs a full resync")]
+    NeedsFullResync,
+}
+
+/// Why a change or document couldn't be decoded. Offsets are from the start
+/// of the chunk being decoded.
+#[derive(Error, Debug, PartialEq)]
+pub enum DecodeError {
+    #[error("Missing magic bytes at offset {offset}")]
+    WrongMagicBytes { offset: usize },
+    #[error("Unexpected end of data at offset {offset}")]
+    UnexpectedEnd { offset: usize },
+    #[error("Unexpected data after the end of the chunk at offset {offset}")]
+    TrailingData { offset: usize },
+    #[error("Invalid number at offset {offset}")]
+    InvalidNumber { offset: usize },
+    #[error("Unknown chunk type {chunk_type}")]
+    UnknownChunkType { chunk_type: u8 },
+    #[error("Expected a chunk of type {expected} but found type {found}")]
+    WrongChunkType { expected: u8, found: u8 },
+    #[error("Column {column} is out of order at offset {offset}")]
+    ColumnOutOfOrder { column: u32, offset: usize },
+    #[error("Column {column} runs past the end of the chunk")]
+    ColumnTooLong { column: u32 },
+    #[error("Invalid data in the {column} column")]
+    InvalidColumn { column: &'static str },
+    #[error("The change has ops with counters larger than the maximum")]
+    OpCounterOverflow,
+    #[error(
+        "Stored checksum {stored:02x?} doesn't match the calculated checksum {calculated:02x?}"
+    )]
+    ChecksumMismatch {
+        stored: [u8; 4],
+        calculated: [u8; 4],
+    },
+    #[error("Stored heads {stored:?} don't match the heads of the decoded changes {calculated:?}")]
+    HeadsMismatch {
+        stored: Vec<amp::ChangeHash>,
+        calculated: Vec<amp::ChangeHash>,
+    },
+    #[error("Change {change} depends on change {dep} which doesn't come before it")]
+    InvalidDependency { change: usize, dep: usize },
+    #[error("Column {column} isn't valid DEFLATE compressed data")]
+    InvalidCompressedColumn { column: u32 },
+    #[error("Column {column} of a change is compressed, only document columns can be")]
+    CompressedChangeColumn { column: u32 },
+    #[error("Unexpected snapshot, a snapshot can only be the first chunk of a document loaded by a backend")]
+    UnexpectedSnapshot,
+    #[error("The snapshot's ops don't make a valid document")]
+    InvalidSnapshot,
+    #[error("A bloom filter has invalid parameters or the wrong number of bits")]
+    InvalidBloomFilter,
 }
 
 #[derive(Error, Debug)]
@@ -99,4 +162,8 @@ pub enum InvalidChangeError {
         #[from]
         source: amp::error::InvalidChangeHashSlice,
     },
+    #[error("Change contained a value of unknown type with the known type code {type_code}")]
+    KnownTypeCodeInUnknownValue { type_code: u8 },
+    #[error("Change contained an op of unknown action with the known action number {action}")]
+    KnownActionInUnknownOp { action: u64 },
 }
@@ -52,6 +52,12 @@ pub(crate) enum InternalOpType {
     Del,
     Inc(i64),
     Set(amp::ScalarValue),
+    /// An op from a later version, it overwrites its predecessors and its
+    /// value is shown as is
+    Unknown {
+        action: u64,
+        value: amp::ScalarValue,
+    },
 }
 
 impl Key {
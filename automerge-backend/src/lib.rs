@@ -25,7 +25,12 @@ mod op_handle;
 mod op_set;
 mod ordered_set;
 mod pending_diff;
+mod snapshot;
+mod stats;
+mod sync;
 
 pub use backend::Backend;
-pub use change::Change;
-pub use error::AutomergeError;
+pub use change::{Change, DEFAULT_DEFLATE_THRESHOLD};
+pub use error::{AutomergeError, DecodeError};
+pub use stats::{BackendStats, HeapBytes};
+pub use sync::{BloomFilter, SyncHave, SyncMessage, SyncState};
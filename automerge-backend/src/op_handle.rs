@@ -1,7 +1,7 @@
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::actor_map::ActorMap;
 use crate::internal::{InternalOp, InternalOpType, Key, ObjectId, OpId};
@@ -16,7 +16,7 @@ pub(crate) struct OpHandle {
 }
 
 impl OpHandle {
-    pub fn extract(change: Rc<Change>, actors: &mut ActorMap) -> Vec<OpHandle> {
+    pub fn extract(change: Arc<Change>, actors: &mut ActorMap) -> Vec<OpHandle> {
         change
             .iter_ops()
             .enumerate()
@@ -34,9 +34,9 @@ impl OpHandle {
     pub fn adjusted_value(&self) -> amp::ScalarValue {
         match &self.action {
             InternalOpType::Set(amp::ScalarValue::Counter(a)) => {
-                amp::ScalarValue::Counter(a + self.delta)
+                amp::ScalarValue::Counter(a.wrapping_add(self.delta))
             }
-            InternalOpType::Set(val) => val.clone(),
+            InternalOpType::Set(val) | InternalOpType::Unknown { value: val, .. } => val.clone(),
             _ => amp::ScalarValue::Null,
         }
     }
@@ -56,11 +56,13 @@ impl OpHandle {
         }
     }
 
+    /// Counters wrap on overflow rather than failing, so that every peer
+    /// ends up with the same value whatever order the increments arrive in
     pub fn maybe_increment(&mut self, inc: &OpHandle) {
         if let InternalOpType::Inc(amount) = inc.action {
             if inc.pred.contains(&self.id) {
                 if let InternalOpType::Set(amp::ScalarValue::Counter(_)) = self.action {
-                    self.delta += amount;
+                    self.delta = self.delta.wrapping_add(amount);
                 }
             }
         }
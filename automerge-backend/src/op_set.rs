@@ -7,8 +7,10 @@
 //! and then recursively walks through the tree of histories constructing the
 //! state. Obviously this is not very efficient.
 use crate::actor_map::ActorMap;
+use crate::change::DecodedDocument;
+use crate::columnar::DocOp;
 use crate::error::AutomergeError;
-use crate::internal::{InternalOpType, ObjectId};
+use crate::internal::{ElementId, InternalOp, InternalOpType, ObjectId, OpId};
 use crate::object_store::ObjState;
 use crate::op_handle::OpHandle;
 use crate::ordered_set::OrderedSet;
@@ -20,7 +22,7 @@ use fxhash::FxBuildHasher;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::convert::AsRef;
-use std::rc::Rc;
+use std::sync::Arc;
 use tracing::instrument;
 
 /// The OpSet manages an ObjectStore, and a queue of incoming changes in order
@@ -40,7 +42,7 @@ use tracing::instrument;
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) struct OpSet {
-    pub objs: HashMap<ObjectId, Rc<ObjState>, FxBuildHasher>,
+    pub objs: HashMap<ObjectId, Arc<ObjState>, FxBuildHasher>,
     pub deps: HashSet<amp::ChangeHash>,
     pub max_op: u64,
     cursors: HashMap<ObjectId, Vec<CursorState>>,
@@ -49,7 +51,7 @@ pub(crate) struct OpSet {
 impl OpSet {
     pub fn init() -> OpSet {
         let mut objs = HashMap::default();
-        objs.insert(ObjectId::Root, Rc::new(ObjState::new(amp::ObjType::map())));
+        objs.insert(ObjectId::Root, Arc::new(ObjState::new(amp::ObjType::map())));
 
         OpSet {
             objs,
@@ -59,6 +61,240 @@ impl OpSet {
         }
     }
 
+    /// Build the op set for a document straight from its ops rather than by
+    /// applying its changes, which skips re-encoding the changes, the
+    /// pending diffs and keeping each sequence's index up to date as its
+    /// elements are inserted. The ops are still applied in the order
+    /// `Backend::apply_change` would apply them, but each sequence is only
+    /// put in order at the end.
+    ///
+    /// Returns `None` if the result could differ from applying the changes,
+    /// e.g. if an op refers to an object which a later change creates, or an
+    /// actor's seqs don't go 1, 2, 3, ... The
+    /// caller should apply the changes instead, which gives the same error
+    /// as loading the document any other way.
+    pub(crate) fn from_document(doc: &mut DecodedDocument, actors: &mut ActorMap) -> Option<OpSet> {
+        let mut op_set = OpSet::init();
+
+        // the index and max op of each actor's changes, to find which change
+        // an op is in
+        let mut actor_changes: HashMap<&amp::ActorId, Vec<(u64, usize)>> = HashMap::new();
+        let mut hashes = HashSet::new();
+        for (index, (change, max_op)) in doc.changes.iter().zip(&doc.max_ops).enumerate() {
+            if !hashes.insert(change.hash) {
+                return None;
+            }
+            let changes = actor_changes.entry(change.actor_id()).or_default();
+            // applying the changes would fail on a seq which isn't the one
+            // after the actor's last change
+            if change.seq != changes.len() as u64 + 1 {
+                return None;
+            }
+            if let Some((last_max_op, _)) = changes.last() {
+                if change.start_op <= *last_max_op {
+                    return None;
+                }
+            }
+            changes.push((*max_op, index));
+            op_set.update_deps(change);
+            op_set.max_op = max(op_set.max_op, *max_op);
+        }
+
+        let actor_ids: Vec<_> = doc.actors.iter().map(|a| actors.import_actor(a)).collect();
+        // each op is dropped once it's imported, so the ops aren't all held
+        // in both forms at once
+        let doc_ops = std::mem::take(&mut doc.ops);
+        let mut ops = Vec::with_capacity(doc_ops.len());
+        let mut ops_per_change = vec![0; doc.changes.len()];
+        let mut op_ids = HashSet::new();
+        for op in doc_ops {
+            let changes = actor_changes.get(doc.actors.get(op.actor)?)?;
+            let index = match changes.binary_search_by_key(&op.ctr, |(max_op, _)| *max_op) {
+                Ok(index) | Err(index) => changes.get(index)?.1,
+            };
+            // applying a change numbers its ops from its start op, so they
+            // have to be numbered that way already
+            if op.ctr < doc.changes[index].start_op || !op_ids.insert((op.ctr, op.actor)) {
+                return None;
+            }
+            ops_per_change[index] += 1;
+            ops.push(((index, op.ctr), import_doc_op(&op, &actor_ids, actors)?));
+        }
+        for (count, (change, max_op)) in ops_per_change
+            .iter()
+            .zip(doc.changes.iter().zip(&doc.max_ops))
+        {
+            if *count != (max_op + 1).checked_sub(change.start_op)? {
+                return None;
+            }
+        }
+        ops.sort_unstable_by_key(|(order, _)| *order);
+
+        // the objects which would have had diffs, and the object each list
+        // element is in
+        let mut changed = HashSet::new();
+        let mut elements = HashMap::new();
+        for (_, op) in ops {
+            op_set.apply_document_op(op, actors, &mut changed, &mut elements)?;
+        }
+
+        op_set.order_sequences(actors, Some(&changed))?;
+        Some(op_set)
+    }
+
+    /// Build the op set for a snapshot from its ops, which have their preds
+    /// filled in and are applied in the order of their IDs
+    pub(crate) fn from_snapshot(
+        doc_actors: &[amp::ActorId],
+        doc_ops: &[DocOp],
+        heads: &[amp::ChangeHash],
+        max_op: u64,
+        actors: &mut ActorMap,
+    ) -> Option<OpSet> {
+        let mut op_set = OpSet::init();
+        op_set.deps = heads.iter().cloned().collect();
+        op_set.max_op = max_op;
+
+        let actor_ids: Vec<_> = doc_actors.iter().map(|a| actors.import_actor(a)).collect();
+        let mut ops = doc_ops
+            .iter()
+            .map(|op| import_doc_op(op, &actor_ids, actors))
+            .collect::<Option<Vec<_>>>()?;
+        ops.sort_unstable_by_key(|op| (op.id.0, op.id.1 .0));
+
+        let mut changed = HashSet::new();
+        let mut elements = HashMap::new();
+        for op in ops {
+            op_set.apply_document_op(op, actors, &mut changed, &mut elements)?;
+        }
+        op_set.order_sequences(actors, None)?;
+        Some(op_set)
+    }
+
+    /// Put the elements of each sequence built by `apply_document_op` in
+    /// order and set the index of each cursor. If `changed` is given cursors
+    /// into objects which aren't in it are an error, as `finalize_diffs`
+    /// wouldn't have set their index.
+    fn order_sequences(
+        &mut self,
+        actors: &ActorMap,
+        changed: Option<&HashSet<ObjectId>>,
+    ) -> Option<()> {
+        for (obj_id, obj) in self.objs.iter_mut() {
+            if obj.is_seq() {
+                let obj = Arc::make_mut(obj);
+                for following in obj.following.values_mut() {
+                    following.sort_unstable_by(|a, b| actors.cmp(b, a));
+                }
+                let mut stack = vec![ElementId::Head];
+                let mut last = None;
+                while let Some(elem) = stack.pop() {
+                    if let ElementId::Id(id) = elem {
+                        if matches!(obj.props.get(&id.into()), Some(ops) if !ops.is_empty()) {
+                            match last {
+                                Some(last) => obj.seq.insert_after(&last, id),
+                                None => obj.seq.insert_head(id),
+                            };
+                            last = Some(id);
+                        }
+                    }
+                    if let Some(following) = obj.following.get(&elem) {
+                        stack.extend(following.iter().rev());
+                    }
+                }
+            }
+            if let Some(cursors) = self.cursors.get_mut(obj_id) {
+                // `finalize_diffs` only updates the index of cursors into
+                // objects which changed
+                if !cursors.is_empty()
+                    && matches!(changed, Some(changed) if !changed.contains(obj_id))
+                {
+                    return None;
+                }
+                for cursor in cursors.iter_mut() {
+                    cursor.index = obj.index_of(cursor.internal_element_opid).unwrap_or(0);
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    /// `apply_op` without the sequence indexes and the diffs, returns `None`
+    /// where `apply_op` would return an error or a sequence's order would
+    /// depend on its indexes
+    fn apply_document_op(
+        &mut self,
+        op: OpHandle,
+        actors: &mut ActorMap,
+        changed: &mut HashSet<ObjectId>,
+        elements: &mut HashMap<OpId, ObjectId>,
+    ) -> Option<()> {
+        if let (Some(child), Some(obj_type)) = (op.child(), op.obj_type()) {
+            self.objs.insert(child, Arc::new(ObjState::new(obj_type)));
+        }
+
+        if let InternalOpType::Set(amp::ScalarValue::Cursor(ref oid)) = op.op.action {
+            let internal_opid = actors.import_opid(oid);
+            let obj_id = *elements.get(&internal_opid)?;
+            self.cursors.entry(obj_id).or_default().push(CursorState {
+                referring_object_id: actors.export_obj(&op.obj),
+                internal_referring_object_id: op.obj,
+                key: op.key.clone(),
+                element_opid: oid.clone(),
+                internal_element_opid: internal_opid,
+                index: 0,
+                referred_object_id: actors.export_obj(&obj_id),
+                internal_referred_object_id: obj_id,
+            });
+        }
+
+        let object = self.get_obj_mut(&op.obj).ok()?;
+        let key = if object.is_seq() {
+            let elem = op.key.as_element_id()?;
+            if op.insert {
+                if elem != ElementId::Head && !object.insertions.contains_key(&elem) {
+                    return None;
+                }
+                object.insertions.insert(op.id.into(), op.clone());
+                object.following.entry(elem).or_default().push(op.id.into());
+                elements.insert(op.id, op.obj);
+            } else if !object.insertions.contains_key(&elem) {
+                // `apply_op` would put the element at the start of the list
+                return None;
+            }
+            op.operation_key()
+        } else {
+            op.key.clone()
+        };
+
+        let ops = object.props.entry(key).or_default();
+        let before = !ops.is_empty();
+        let overwritten = ops.incorporate_new_op(&op).ok()?;
+        if before || !ops.is_empty() {
+            changed.insert(op.obj);
+        }
+        self.unlink(&op, &overwritten).ok()?;
+
+        for old in overwritten {
+            if let InternalOpType::Set(amp::ScalarValue::Cursor(ref oid)) = old.op.action {
+                if let Some(opids) = self.cursors.get_mut(&old.op.obj) {
+                    // only the overwritten cursor, the op which overwrote it
+                    // may be a cursor to the same element
+                    let overwritten = opids.iter().position(|o| {
+                        o.element_opid == *oid
+                            && o.internal_referring_object_id == old.op.obj
+                            && o.key == old.op.key
+                    });
+                    if let Some(index) = overwritten {
+                        opids.remove(index);
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+
     pub(crate) fn apply_ops(
         &mut self,
         mut ops: Vec<OpHandle>,
@@ -91,7 +327,7 @@ impl OpSet {
     ) -> Result<Option<PendingDiff>, AutomergeError> {
         if let (Some(child), Some(obj_type)) = (op.child(), op.obj_type()) {
             //let child = actors.import_obj(child);
-            self.objs.insert(child, Rc::new(ObjState::new(obj_type)));
+            self.objs.insert(child, Arc::new(ObjState::new(obj_type)));
         }
 
         if let InternalOpType::Set(amp::ScalarValue::Cursor(ref oid)) = op.op.action {
@@ -123,11 +359,16 @@ impl OpSet {
 
         let (diff, overwritten) = if object.is_seq() {
             if op.insert {
-                object.insert_after(
-                    op.key.as_element_id().ok_or(AutomergeError::MapKeyInSeq)?,
-                    op.clone(),
-                    actors,
-                );
+                let elem = op.key.as_element_id().ok_or(AutomergeError::MapKeyInSeq)?;
+                if let ElementId::Id(elem_id) = elem {
+                    if !object.insertions.contains_key(&elem) {
+                        return Err(AutomergeError::MissingElement(
+                            actors.export_obj(&op.obj),
+                            amp::ElementId::Id(actors.export_opid(&elem_id)),
+                        ));
+                    }
+                }
+                object.insert_after(elem, op.clone(), actors);
             }
 
             let ops = object.props.entry(op.operation_key()).or_default();
@@ -184,7 +425,16 @@ impl OpSet {
         for op in overwritten {
             if let InternalOpType::Set(amp::ScalarValue::Cursor(ref oid)) = op.op.action {
                 if let Some(opids) = self.cursors.get_mut(&op.op.obj) {
-                    opids.retain(|o| o.element_opid != *oid);
+                    // only the overwritten cursor, the op which overwrote it
+                    // may be a cursor to the same element
+                    let overwritten = opids.iter().position(|o| {
+                        o.element_opid == *oid
+                            && o.internal_referring_object_id == op.op.obj
+                            && o.key == op.op.key
+                    });
+                    if let Some(index) = overwritten {
+                        opids.remove(index);
+                    }
                 }
             }
         }
@@ -214,7 +464,7 @@ impl OpSet {
     fn get_obj_mut(&mut self, object_id: &ObjectId) -> Result<&mut ObjState, AutomergeError> {
         self.objs
             .get_mut(&object_id)
-            .map(|rc| Rc::make_mut(rc))
+            .map(Arc::make_mut)
             .ok_or(AutomergeError::MissingObjectError)
     }
 
@@ -376,7 +626,9 @@ impl OpSet {
             let mut opid_to_value = HashMap::new();
             for op in obj.props.get(&key).iter().flat_map(|i| i.iter()) {
                 let link = match op.action {
-                    InternalOpType::Set(ref value) => self.gen_value_diff(op, value),
+                    InternalOpType::Set(ref value) | InternalOpType::Unknown { ref value, .. } => {
+                        self.gen_value_diff(op, value)
+                    }
                     InternalOpType::Make(_) => {
                         self.gen_obj_diff(&op.id.into(), pending_diffs, actors)?
                     }
@@ -417,7 +669,9 @@ impl OpSet {
             let mut opid_to_value = HashMap::new();
             for op in obj.props.get(&key).iter().flat_map(|i| i.iter()) {
                 let link = match op.action {
-                    InternalOpType::Set(ref value) => self.gen_value_diff(op, value),
+                    InternalOpType::Set(ref value) | InternalOpType::Unknown { ref value, .. } => {
+                        self.gen_value_diff(op, value)
+                    }
                     InternalOpType::Make(_) => {
                         // FIXME
                         self.gen_obj_diff(&op.id.into(), pending_diffs, actors)?
@@ -471,7 +725,9 @@ impl OpSet {
 
     fn gen_value_diff(&self, op: &OpHandle, value: &amp::ScalarValue) -> amp::Diff {
         match value {
-            amp::ScalarValue::Cursor(oid) => {
+            // Only set ops have their cursors tracked, a cursor in an unknown op
+            // is passed on as it is
+            amp::ScalarValue::Cursor(oid) if matches!(op.action, InternalOpType::Set(_)) => {
                 // .expect() is okay here because we check that the cursr exists at the start of
                 // `OpSet::apply_op()`
                 let cursor_state = self
@@ -530,3 +786,73 @@ struct CursorState {
     internal_element_opid: crate::internal::OpId,
     index: usize,
 }
+
+/// Turn an op from a document into an `OpHandle`, `actor_ids` are the
+/// document's actors imported into `actors`
+fn import_doc_op(
+    op: &DocOp,
+    actor_ids: &[crate::internal::ActorId],
+    actors: &mut ActorMap,
+) -> Option<OpHandle> {
+    Some(OpHandle {
+        id: OpId(op.ctr, *actor_ids.get(op.actor)?),
+        op: InternalOp {
+            action: actors.import_optype(&op.action),
+            obj: actors.import_obj(&op.obj),
+            key: actors.import_key(&op.key),
+            pred: op
+                .pred
+                .iter()
+                .map(|(ctr, actor)| Some(OpId(*ctr, *actor_ids.get(*actor)?)))
+                .collect::<Option<_>>()?,
+            insert: op.insert,
+        },
+        delta: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change::decode_document_with_ops;
+    use crate::{Backend, Change};
+
+    #[test]
+    fn test_from_document_needs_contiguous_seqs() {
+        let actor = amp::ActorId::from_bytes(&[1; 16]);
+        let mut backend = Backend::init();
+        for seq in 1..=2 {
+            let change = Change::from(amp::UncompressedChange {
+                actor_id: actor.clone(),
+                seq,
+                start_op: seq,
+                time: 0,
+                message: None,
+                hash: None,
+                deps: backend.get_heads(),
+                operations: vec![amp::Op {
+                    action: amp::OpType::Set(amp::ScalarValue::Int(seq as i64)),
+                    obj: amp::ObjectId::Root,
+                    key: "bird".into(),
+                    insert: false,
+                    pred: if seq == 1 {
+                        Vec::new()
+                    } else {
+                        vec![actor.op_id_at(seq - 1)]
+                    },
+                }],
+                extra_bytes: Vec::new(),
+            });
+            backend.apply_changes(vec![change]).unwrap();
+        }
+        let data = backend.save().unwrap();
+
+        let mut doc = decode_document_with_ops(&data, true).unwrap();
+        assert!(OpSet::from_document(&mut doc, &mut ActorMap::new()).is_some());
+        for seq in [1, 3].iter() {
+            let mut doc = decode_document_with_ops(&data, true).unwrap();
+            doc.changes[1].seq = *seq;
+            assert!(OpSet::from_document(&mut doc, &mut ActorMap::new()).is_none());
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! A snapshot replaces the start of a document's history, see
+//! `Backend::compact`. It's stored as its own chunk type, with the ops of the
+//! compacted changes which are still needed in the same columns as a
+//! document, the heads of the compacted history and the last seq, max op and
+//! hash of each actor, so the changes after it can still be checked and
+//! applied. The hashes of the other compacted changes aren't kept, so the
+//! snapshot doesn't grow with the history it replaces.
+use crate::change::{
+    decode_actors, decode_column_info, decode_columns, decode_hashes, decode_header,
+    inflate_columns, read_slice, take_bytes, DocOpBuilder, BLOCK_TYPE_SNAPSHOT, CHUNK_START,
+    DEFAULT_DEFLATE_THRESHOLD, HASH_BYTES, HASH_RANGE, MAGIC_BYTES,
+};
+use crate::columnar::{DocOp, DocOpEncoder, DocOpIterator};
+use crate::encoding::Encodable;
+use crate::error::{AutomergeError, DecodeError, InvalidChangeError};
+use crate::Change;
+use automerge_protocol as amp;
+use itertools::Itertools;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// The last compacted change of an actor
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SnapshotClock {
+    pub seq: u64,
+    pub max_op: u64,
+    pub hash: amp::ChangeHash,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Snapshot {
+    /// The heads of the compacted history, sorted
+    pub heads: Vec<amp::ChangeHash>,
+    pub clock: HashMap<amp::ActorId, SnapshotClock>,
+    pub max_op: u64,
+    /// The encoded snapshot chunk
+    pub bytes: Vec<u8>,
+    /// The chunk with columns of at least `DEFAULT_DEFLATE_THRESHOLD` bytes
+    /// compressed, encoded the first time it's saved that way
+    pub deflated: OnceLock<Vec<u8>>,
+}
+
+// `deflated` is just another encoding of `bytes`
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Snapshot) -> bool {
+        self.heads == other.heads
+            && self.clock == other.clock
+            && self.max_op == other.max_op
+            && self.bytes == other.bytes
+    }
+}
+
+pub(crate) struct DecodedSnapshot {
+    pub snapshot: Snapshot,
+    pub actors: Vec<amp::ActorId>,
+    /// The snapshot's ops with their succ, but not their preds
+    pub ops: Vec<DocOp>,
+}
+
+impl Snapshot {
+    /// Whether `hash` is one of the compacted changes known by their hash,
+    /// the heads and each actor's last compacted change
+    pub(crate) fn has_hash(&self, hash: &amp::ChangeHash) -> bool {
+        self.heads.contains(hash) || self.clock.values().any(|clock| &clock.hash == hash)
+    }
+
+    /// Squash `changes`, which are in history order, into `previous`. Ops
+    /// which aren't visible any more are dropped unless they're needed to
+    /// place a visible op or an op of one of `uncompacted`, the changes which
+    /// aren't being compacted.
+    pub(crate) fn build(
+        previous: Option<&Snapshot>,
+        changes: &[&Change],
+        uncompacted: &[&Change],
+    ) -> Result<Snapshot, AutomergeError> {
+        let (previous_actors, previous_ops) = match previous {
+            Some(previous) => {
+                let decoded = Snapshot::decode(&previous.bytes, false)?;
+                (decoded.actors, decoded.ops)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let actors: Vec<_> = previous_actors
+            .iter()
+            .chain(changes.iter().map(|change| change.actor_id()))
+            .unique()
+            .sorted()
+            .cloned()
+            .collect();
+        let new_index: Vec<_> = previous_actors
+            .iter()
+            .map(|actor| actors.iter().position(|a| a == actor).unwrap())
+            .collect();
+
+        let mut builder = DocOpBuilder::default();
+        for mut op in previous_ops {
+            op.actor = *new_index
+                .get(op.actor)
+                .ok_or(DecodeError::InvalidSnapshot)?;
+            for succ in op.succ.iter_mut() {
+                succ.1 = *new_index.get(succ.1).ok_or(DecodeError::InvalidSnapshot)?;
+            }
+            builder.add_doc_op(amp::OpId(op.ctr, actors[op.actor].clone()), op);
+        }
+        let decoded: Vec<amp::UncompressedChange> =
+            changes.iter().map(|change| change.decode()).collect();
+        builder.add_changes(&decoded, &actors);
+        collect_garbage(&mut builder, uncompacted);
+        let ops = builder.finish();
+
+        let mut heads: HashSet<_> = previous
+            .map(|previous| previous.heads.iter().cloned().collect())
+            .unwrap_or_default();
+        let mut clock = previous
+            .map(|previous| previous.clock.clone())
+            .unwrap_or_default();
+        let mut max_op = previous.map_or(0, |previous| previous.max_op);
+        for change in changes {
+            heads.insert(change.hash);
+            clock.insert(
+                change.actor_id().clone(),
+                SnapshotClock {
+                    seq: change.seq,
+                    max_op: change.max_op(),
+                    hash: change.hash,
+                },
+            );
+            max_op = max_op.max(change.max_op());
+        }
+        for change in changes {
+            for dep in change.deps.iter() {
+                heads.remove(dep);
+            }
+        }
+
+        let mut snapshot = Snapshot {
+            heads: heads.into_iter().sorted().collect(),
+            clock,
+            max_op,
+            bytes: Vec::new(),
+            deflated: OnceLock::new(),
+        };
+        snapshot.bytes = snapshot.encode(actors, &ops, None)?;
+        Ok(snapshot)
+    }
+
+    /// The encoded snapshot chunk with columns of at least
+    /// `deflate_threshold` bytes DEFLATE compressed
+    pub(crate) fn encoded(
+        &self,
+        deflate_threshold: Option<usize>,
+    ) -> Result<Cow<'_, [u8]>, AutomergeError> {
+        match deflate_threshold {
+            None => Ok(Cow::Borrowed(&self.bytes)),
+            Some(DEFAULT_DEFLATE_THRESHOLD) => {
+                if let Some(deflated) = self.deflated.get() {
+                    return Ok(Cow::Borrowed(deflated));
+                }
+                let decoded = Snapshot::decode(&self.bytes, false)?;
+                let deflated = self.encode(decoded.actors, &decoded.ops, deflate_threshold)?;
+                Ok(Cow::Borrowed(self.deflated.get_or_init(|| deflated)))
+            }
+            Some(_) => {
+                let decoded = Snapshot::decode(&self.bytes, false)?;
+                let encoded = self.encode(decoded.actors, &decoded.ops, deflate_threshold)?;
+                Ok(Cow::Owned(encoded))
+            }
+        }
+    }
+
+    /// Encode the snapshot chunk with `ops`, with columns of at least
+    /// `deflate_threshold` bytes DEFLATE compressed
+    pub(crate) fn encode(
+        &self,
+        mut actors: Vec<amp::ActorId>,
+        ops: &[DocOp],
+        deflate_threshold: Option<usize>,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut hasher = Sha256::new();
+
+        // the clock refers to actors by index, so they all need to be there
+        // before the ops add any others
+        for actor in self.clock.keys().sorted() {
+            if !actors.contains(actor) {
+                actors.push(actor.clone());
+            }
+        }
+
+        let (ops_bytes, ops_info) =
+            DocOpEncoder::encode_doc_ops(ops, &mut actors, deflate_threshold);
+
+        bytes.extend(&MAGIC_BYTES);
+        bytes.extend(vec![0, 0, 0, 0]); // we dont know the hash yet so fill in a fake
+        bytes.push(BLOCK_TYPE_SNAPSHOT);
+
+        let mut chunk = Vec::new();
+
+        actors.len().encode(&mut chunk)?;
+        for a in actors.iter() {
+            a.to_bytes().encode(&mut chunk)?;
+        }
+
+        self.heads.len().encode(&mut chunk)?;
+        for head in self.heads.iter() {
+            chunk.write_all(&head.0)?;
+        }
+
+        self.clock.len().encode(&mut chunk)?;
+        for (actor, clock) in self.clock.iter().sorted_by_key(|(actor, _)| *actor) {
+            let index = actors.iter().position(|a| a == actor).unwrap();
+            index.encode(&mut chunk)?;
+            clock.seq.encode(&mut chunk)?;
+            clock.max_op.encode(&mut chunk)?;
+            chunk.write_all(&clock.hash.0)?;
+        }
+
+        self.max_op.encode(&mut chunk)?;
+
+        chunk.extend(ops_info);
+        chunk.extend(ops_bytes);
+
+        leb128::write::unsigned(&mut bytes, chunk.len() as u64).unwrap();
+
+        bytes.extend(&chunk);
+
+        hasher.input(&bytes[CHUNK_START..bytes.len()]);
+        let hash_result = hasher.result();
+
+        bytes.splice(HASH_RANGE, hash_result[0..4].iter().cloned());
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn decode(bytes: &[u8], verify: bool) -> Result<DecodedSnapshot, AutomergeError> {
+        let (chunktype, _hash, mut cursor) = decode_header(bytes, verify)?;
+
+        if chunktype != BLOCK_TYPE_SNAPSHOT {
+            return Err(DecodeError::WrongChunkType {
+                expected: BLOCK_TYPE_SNAPSHOT,
+                found: chunktype,
+            }
+            .into());
+        }
+
+        let actors = decode_actors(bytes, &mut cursor, None)?;
+        let mut heads = decode_hashes(bytes, &mut cursor)?;
+        heads.sort();
+
+        let num_clocks: usize = read_slice(bytes, &mut cursor)?;
+        let mut clock = HashMap::new();
+        for _ in 0..num_clocks {
+            let actor: usize = read_slice(bytes, &mut cursor)?;
+            let actor = actors.get(actor).ok_or_else(|| {
+                AutomergeError::ChangeDecompressError("Snapshot Clock.Actor Invalid".into())
+            })?;
+            let seq = read_slice(bytes, &mut cursor)?;
+            let max_op = read_slice(bytes, &mut cursor)?;
+            let hash = bytes[take_bytes(&mut cursor, HASH_BYTES)?]
+                .try_into()
+                .map_err(InvalidChangeError::from)?;
+            clock.insert(actor.clone(), SnapshotClock { seq, max_op, hash });
+        }
+
+        let max_op = read_slice(bytes, &mut cursor)?;
+
+        let ops_info = decode_column_info(bytes, &mut cursor)?;
+        let mut ops_data = decode_columns(&mut cursor, ops_info)?;
+        let ops_bytes = inflate_columns(bytes, &mut ops_data)?;
+        let mut op_iter = DocOpIterator::new(&ops_bytes, &actors, &ops_data);
+        let mut ops = Vec::new();
+        while let Some(op) = op_iter.try_next()? {
+            ops.push(op);
+        }
+        let compressed = matches!(ops_bytes, Cow::Owned(_));
+
+        let mut snapshot = Snapshot {
+            heads,
+            clock,
+            max_op,
+            bytes: Vec::new(),
+            deflated: OnceLock::new(),
+        };
+        // keep the snapshot uncompressed so it doesn't need inflating each
+        // time it's read
+        snapshot.bytes = if compressed {
+            snapshot.encode(actors.clone(), &ops, None)?
+        } else {
+            bytes.to_vec()
+        };
+        Ok(DecodedSnapshot {
+            snapshot,
+            actors,
+            ops,
+        })
+    }
+}
+
+/// Drop the ops in `builder` which aren't visible, other than the ops which
+/// create the objects and insert the elements that visible ops and the ops
+/// of `uncompacted` refer to, and the ops which create the objects and insert
+/// the elements they refer to in turn.
+fn collect_garbage(builder: &mut DocOpBuilder, uncompacted: &[&Change]) {
+    let mut incs = HashSet::new();
+    // the object and key of the op which made each object, and the object
+    // each element was inserted into
+    let mut makes = HashMap::new();
+    let mut elements = HashMap::new();
+    for (obj, keys) in builder.by_obj_id.iter() {
+        for (key, ops) in keys.iter() {
+            for (opid, op) in ops.iter() {
+                match op.action {
+                    amp::OpType::Inc(_) => {
+                        incs.insert((op.ctr, op.actor));
+                    }
+                    amp::OpType::Make(_) => {
+                        makes.insert(amp::ObjectId::Id(opid.clone()), (obj.clone(), key.clone()));
+                    }
+                    _ => {}
+                }
+                if op.insert {
+                    elements.insert(opid.clone(), obj.clone());
+                }
+            }
+        }
+    }
+
+    let mut keep = HashSet::new();
+    // objects whose live ops are all kept, the elements which are kept along
+    // with the elements they were inserted after, and the objects which are
+    // kept along with the objects they're in
+    let mut visible = vec![amp::ObjectId::Root];
+    let mut need_elems = Vec::new();
+    let mut need_objs = Vec::new();
+    // a cursor's index counts the visible elements before it, even if the
+    // list itself isn't visible any more
+    let mut cursors = Vec::new();
+
+    // the indexes of the uncompacted changes' ops in a list depend on the
+    // list's visible elements, so the objects they're in are treated as
+    // visible even if they're not
+    for change in uncompacted {
+        for op in change.iter_ops() {
+            visible.push(op.obj.clone());
+            if let amp::Key::Seq(amp::ElementId::Id(elem)) = op.key {
+                need_elems.push(elem);
+            }
+            if let amp::OpType::Set(amp::ScalarValue::Cursor(elem)) = op.action {
+                cursors.push(elem);
+            }
+        }
+    }
+
+    let mut seen_visible = HashSet::new();
+    let mut seen_elems = HashSet::new();
+    let mut seen_objs = HashSet::new();
+    loop {
+        if let Some(elem) = cursors.pop() {
+            if let Some(obj) = elements.get(&elem) {
+                visible.push(obj.clone());
+            }
+            need_elems.push(elem);
+        } else if let Some(obj) = visible.pop() {
+            if !seen_visible.insert(obj.clone()) {
+                continue;
+            }
+            for (key, ops) in builder.by_obj_id.get(&obj).into_iter().flatten() {
+                for (opid, op) in ops.iter() {
+                    let live = !matches!(op.action, amp::OpType::Del | amp::OpType::Inc(_))
+                        && op.succ.iter().all(|succ| incs.contains(succ));
+                    if !live {
+                        continue;
+                    }
+                    keep.insert(opid.clone());
+                    keep.extend(
+                        ops.iter()
+                            .filter(|(_, inc)| op.succ.contains(&(inc.ctr, inc.actor)))
+                            .map(|(inc_id, _)| inc_id.clone()),
+                    );
+                    if let amp::OpType::Make(_) = op.action {
+                        visible.push(amp::ObjectId::Id(opid.clone()));
+                    }
+                    if let amp::Key::Seq(amp::ElementId::Id(elem)) = key {
+                        need_elems.push(elem.clone());
+                    }
+                    if let amp::OpType::Set(amp::ScalarValue::Cursor(elem)) = &op.action {
+                        cursors.push(elem.clone());
+                    }
+                }
+            }
+            need_objs.push(obj);
+        } else if let Some(elem) = need_elems.pop() {
+            let obj = match elements.get(&elem) {
+                Some(obj) if seen_elems.insert(elem.clone()) => obj,
+                _ => continue,
+            };
+            let key = amp::Key::Seq(amp::ElementId::Id(elem.clone()));
+            if let Some(op) = builder.by_obj_id[obj][&key].get(&elem) {
+                if let amp::Key::Seq(amp::ElementId::Id(parent)) = &op.key {
+                    need_elems.push(parent.clone());
+                }
+                if let amp::OpType::Set(amp::ScalarValue::Cursor(target)) = &op.action {
+                    cursors.push(target.clone());
+                }
+            }
+            keep.insert(elem);
+            need_objs.push(obj.clone());
+        } else if let Some(obj) = need_objs.pop() {
+            let (parent, key) = match makes.get(&obj) {
+                Some(make) if seen_objs.insert(obj.clone()) => make,
+                _ => continue,
+            };
+            if let amp::ObjectId::Id(opid) = obj {
+                keep.insert(opid);
+            }
+            if let amp::Key::Seq(amp::ElementId::Id(elem)) = key {
+                need_elems.push(elem.clone());
+            }
+            need_objs.push(parent.clone());
+        } else {
+            break;
+        }
+    }
+
+    for keys in builder.by_obj_id.values_mut() {
+        for ops in keys.values_mut() {
+            ops.retain(|opid, _| keep.contains(opid));
+        }
+        keys.retain(|_, ops| !ops.is_empty());
+    }
+    builder.by_obj_id.retain(|_, keys| !keys.is_empty());
+    for refs in builder.by_ref.values_mut() {
+        for following in refs.values_mut() {
+            following.retain(|opid| keep.contains(opid));
+        }
+    }
+}
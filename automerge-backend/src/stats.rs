@@ -0,0 +1,101 @@
+//! Counts and approximate memory use of a `Backend`, see `Backend::stats`
+
+use crate::concurrent_operations::ConcurrentOperations;
+use crate::internal::{ElementId, Key, OpId};
+use crate::object_store::ObjState;
+use crate::op_handle::OpHandle;
+use crate::op_set::OpSet;
+use crate::Change;
+use automerge_protocol as amp;
+use serde::Serialize;
+use std::mem::size_of;
+
+/// A snapshot of the size of a `Backend`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendStats {
+    /// Objects in the document, including the root and any objects which
+    /// have since been deleted
+    pub objects: usize,
+    /// Operations which currently determine the value of a map key or list
+    /// element, conflicting operations are counted separately
+    pub ops: usize,
+    /// Changes which have been applied, other than compacted changes
+    pub changes: usize,
+    /// Changes which have been squashed into a snapshot by `Backend::compact`
+    pub compacted_changes: usize,
+    /// Changes waiting for their dependencies
+    pub queued_changes: usize,
+    pub actors: usize,
+    /// Map keys and list elements which have been deleted, these are kept
+    /// so that concurrent operations on them can be merged
+    pub tombstones: usize,
+    pub heap_bytes: HeapBytes,
+}
+
+/// Approximate heap memory used by each part of a `Backend`. These are
+/// estimates based on the number and size of the things stored, they don't
+/// include allocator overhead or unused capacity, and memory shared with
+/// clones of the backend is counted in full for each clone.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeapBytes {
+    /// The objects and operations in the op set
+    pub op_set: usize,
+    /// The list of applied change hashes and the indexes of changes by hash
+    /// and by actor
+    pub history: usize,
+    /// The encoded changes themselves, including queued changes and the
+    /// snapshot of any compacted changes
+    pub changes: usize,
+}
+
+impl HeapBytes {
+    pub fn total(&self) -> usize {
+        self.op_set + self.history + self.changes
+    }
+}
+
+impl BackendStats {
+    pub(crate) fn add_op_set(&mut self, op_set: &OpSet) {
+        self.objects = op_set.objs.len();
+        self.heap_bytes.op_set = op_set.objs.len()
+            * (size_of::<crate::internal::ObjectId>() + size_of::<ObjState>())
+            + op_set.deps.len() * size_of::<amp::ChangeHash>();
+        for obj in op_set.objs.values() {
+            self.add_obj(obj);
+        }
+    }
+
+    fn add_obj(&mut self, obj: &ObjState) {
+        let mut bytes = 0;
+        for (key, ops) in &obj.props {
+            if ops.is_empty() {
+                self.tombstones += 1;
+            }
+            self.ops += ops.len();
+            bytes += size_of::<Key>() + size_of::<ConcurrentOperations>();
+            bytes += ops.len() * size_of::<OpHandle>();
+            if let Key::Map(s) = key {
+                bytes += s.len();
+            }
+        }
+        bytes += obj.inbound.len() * size_of::<OpHandle>();
+        bytes += obj.insertions.len() * (size_of::<ElementId>() + size_of::<OpHandle>());
+        bytes += obj
+            .following
+            .values()
+            .map(|f| size_of::<ElementId>() * (f.len() + 1))
+            .sum::<usize>();
+        // The skip list stores a link per level for each element, on average
+        // there are two levels per element
+        bytes += obj.seq.len * size_of::<OpId>() * 3;
+        self.heap_bytes.op_set += bytes;
+    }
+
+    pub(crate) fn add_change(&mut self, change: &Change) {
+        self.heap_bytes.changes += size_of::<Change>()
+            + change.bytes.len()
+            + change.deps.len() * size_of::<amp::ChangeHash>();
+    }
+}
@@ -0,0 +1,547 @@
+//! The sync protocol, which lets two peers bring each other up to date by
+//! exchanging messages until neither has anything left to send.
+//!
+//! Each peer keeps a [`SyncState`] per peer it is syncing with. A message
+//! contains the sender's heads, the hashes it knows it is missing, a bloom
+//! filter summarising the changes it has added since the last sync and any
+//! changes it thinks the other peer needs.
+
+use crate::encoding::Decoder;
+use crate::error::{AutomergeError, DecodeError};
+use crate::{Backend, Change};
+use automerge_protocol as amp;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Write;
+
+const HASH_SIZE: usize = 32;
+const MESSAGE_TYPE_SYNC: u8 = 0x42;
+const PEER_STATE_TYPE: u8 = 0x43;
+
+const BITS_PER_ENTRY: u32 = 10;
+const NUM_PROBES: u32 = 7;
+/// The largest filter parameters we accept from a peer, as both decide how
+/// much work checking a hash against the filter is
+const MAX_BITS_PER_ENTRY: u32 = 32;
+const MAX_PROBES: u32 = 32;
+
+/// What we know about the state of a single peer we are syncing with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncState {
+    /// The heads we know we have in common with the peer
+    pub shared_heads: Vec<amp::ChangeHash>,
+    /// The heads we sent in our last message
+    pub last_sent_heads: Vec<amp::ChangeHash>,
+    pub their_heads: Option<Vec<amp::ChangeHash>>,
+    pub their_need: Option<Vec<amp::ChangeHash>>,
+    pub their_have: Option<Vec<SyncHave>>,
+    /// Changes we have already sent and so won't send again
+    pub sent_hashes: HashSet<amp::ChangeHash>,
+}
+
+impl SyncState {
+    pub fn new() -> SyncState {
+        SyncState::default()
+    }
+
+    /// Encode the part of the state which is worth keeping between
+    /// connections, that is the heads we have in common with the peer.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![PEER_STATE_TYPE];
+        encode_hashes(&mut buf, &self.shared_heads);
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SyncState, AutomergeError> {
+        let mut decoder = Decoder::new(bytes);
+        if decoder.read::<u8>()? != PEER_STATE_TYPE {
+            return Err(AutomergeError::EncodingError);
+        }
+        let shared_heads = decode_hashes(&mut decoder)?;
+        Ok(SyncState {
+            shared_heads,
+            ..SyncState::default()
+        })
+    }
+}
+
+/// A summary of the changes the sender has added since `last_sync`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncHave {
+    pub last_sync: Vec<amp::ChangeHash>,
+    pub bloom: BloomFilter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncMessage {
+    pub heads: Vec<amp::ChangeHash>,
+    pub need: Vec<amp::ChangeHash>,
+    pub have: Vec<SyncHave>,
+    pub changes: Vec<Change>,
+}
+
+impl SyncMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![MESSAGE_TYPE_SYNC];
+        encode_hashes(&mut buf, &self.heads);
+        encode_hashes(&mut buf, &self.need);
+        encode_uint(&mut buf, self.have.len());
+        for have in &self.have {
+            encode_hashes(&mut buf, &have.last_sync);
+            encode_bytes(&mut buf, &have.bloom.to_bytes());
+        }
+        encode_uint(&mut buf, self.changes.len());
+        for change in &self.changes {
+            encode_bytes(&mut buf, &change.bytes);
+        }
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SyncMessage, AutomergeError> {
+        let mut decoder = Decoder::new(bytes);
+        if decoder.read::<u8>()? != MESSAGE_TYPE_SYNC {
+            return Err(AutomergeError::EncodingError);
+        }
+        let heads = decode_hashes(&mut decoder)?;
+        let need = decode_hashes(&mut decoder)?;
+        let have_count = decoder.read::<usize>()?;
+        let mut have = Vec::new();
+        for _ in 0..have_count {
+            let last_sync = decode_hashes(&mut decoder)?;
+            let bloom = BloomFilter::try_from(decode_bytes(&mut decoder)?)?;
+            have.push(SyncHave { last_sync, bloom });
+        }
+        let change_count = decoder.read::<usize>()?;
+        let mut changes = Vec::new();
+        for _ in 0..change_count {
+            changes.push(Change::from_bytes(decode_bytes(&mut decoder)?.to_vec())?);
+        }
+        Ok(SyncMessage {
+            heads,
+            need,
+            have,
+            changes,
+        })
+    }
+}
+
+/// A bloom filter over change hashes, using 10 bits per entry and 7 probes,
+/// which gives a false positive rate of about 1%.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BloomFilter {
+    num_entries: u32,
+    num_bits_per_entry: u32,
+    num_probes: u32,
+    bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    pub fn from_hashes<'a, I: IntoIterator<Item = &'a amp::ChangeHash>>(hashes: I) -> Self {
+        let hashes: Vec<_> = hashes.into_iter().collect();
+        let num_entries = hashes.len() as u32;
+        let mut filter = BloomFilter {
+            num_entries,
+            num_bits_per_entry: BITS_PER_ENTRY,
+            num_probes: NUM_PROBES,
+            bits: vec![0; bits_capacity(num_entries, BITS_PER_ENTRY)],
+        };
+        for hash in hashes {
+            for probe in filter.probes(hash) {
+                filter.bits[(probe >> 3) as usize] |= 1 << (probe & 7);
+            }
+        }
+        filter
+    }
+
+    /// Whether `hash` might be in the filter, false positives are possible
+    /// but false negatives are not
+    pub fn contains_hash(&self, hash: &amp::ChangeHash) -> bool {
+        if self.num_entries == 0 {
+            return false;
+        }
+        self.probes(hash)
+            .into_iter()
+            .all(|probe| self.bits[(probe >> 3) as usize] & (1 << (probe & 7)) != 0)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if self.num_entries != 0 {
+            encode_uint(&mut buf, self.num_entries as usize);
+            encode_uint(&mut buf, self.num_bits_per_entry as usize);
+            encode_uint(&mut buf, self.num_probes as usize);
+            buf.extend(&self.bits);
+        }
+        buf
+    }
+
+    fn probes(&self, hash: &amp::ChangeHash) -> Vec<u64> {
+        let modulo = 8 * self.bits.len() as u64;
+        let word = |i: usize| {
+            let bytes = [hash.0[i], hash.0[i + 1], hash.0[i + 2], hash.0[i + 3]];
+            u64::from(u32::from_le_bytes(bytes)) % modulo
+        };
+        let mut x = word(0);
+        let mut y = word(4);
+        let z = word(8);
+        let mut probes = vec![x];
+        for _ in 1..self.num_probes {
+            x = (x + y) % modulo;
+            y = (y + z) % modulo;
+            probes.push(x);
+        }
+        probes
+    }
+}
+
+impl TryFrom<&[u8]> for BloomFilter {
+    type Error = AutomergeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.is_empty() {
+            return Ok(BloomFilter::default());
+        }
+        let mut decoder = Decoder::new(bytes);
+        let num_entries = decoder.read::<u32>()?;
+        let num_bits_per_entry = decoder.read::<u32>()?;
+        let num_probes = decoder.read::<u32>()?;
+        if !(1..=MAX_BITS_PER_ENTRY).contains(&num_bits_per_entry)
+            || !(1..=MAX_PROBES).contains(&num_probes)
+        {
+            return Err(DecodeError::InvalidBloomFilter.into());
+        }
+        let bits = decoder
+            .read_bytes(bits_capacity(num_entries, num_bits_per_entry))?
+            .to_vec();
+        if !decoder.done() || (num_entries != 0 && bits.is_empty()) {
+            return Err(DecodeError::InvalidBloomFilter.into());
+        }
+        Ok(BloomFilter {
+            num_entries,
+            num_bits_per_entry,
+            num_probes,
+            bits,
+        })
+    }
+}
+
+fn bits_capacity(num_entries: u32, num_bits_per_entry: u32) -> usize {
+    (num_entries as f64 * num_bits_per_entry as f64 / 8.0).ceil() as usize
+}
+
+impl Backend {
+    /// Generate the next message to send to the peer described by
+    /// `sync_state`, or `None` if we have nothing to tell them.
+    pub fn generate_sync_message(&self, sync_state: &mut SyncState) -> Option<SyncMessage> {
+        let our_heads = sorted(self.get_heads());
+        let our_need = self.get_missing_deps_for(sync_state.their_heads.as_deref().unwrap_or(&[]));
+
+        let mut our_have = Vec::new();
+        let they_have_our_needs = sync_state
+            .their_heads
+            .as_ref()
+            .map(|their_heads| our_need.iter().all(|hash| their_heads.contains(hash)))
+            .unwrap_or(true);
+        if they_have_our_needs {
+            our_have.push(self.make_bloom_filter(sync_state.shared_heads.clone()));
+        }
+
+        // If the peer's last sync point refers to changes we don't have we
+        // have been reset (e.g. restored from a backup), so start over
+        if let Some(first_have) = sync_state.their_have.as_ref().and_then(|h| h.first()) {
+            if !first_have
+                .last_sync
+                .iter()
+                .all(|hash| self.has_change(hash))
+            {
+                return Some(SyncMessage {
+                    heads: our_heads,
+                    need: Vec::new(),
+                    have: vec![SyncHave::default()],
+                    changes: Vec::new(),
+                });
+            }
+        }
+
+        let mut changes_to_send = match (&sync_state.their_have, &sync_state.their_need) {
+            (Some(their_have), Some(their_need)) => {
+                self.get_changes_to_send(their_have, their_need)
+            }
+            _ => Vec::new(),
+        };
+
+        let heads_unchanged = sync_state.last_sent_heads == our_heads;
+        let heads_equal = sync_state.their_heads.as_ref() == Some(&our_heads);
+        if heads_unchanged && heads_equal && changes_to_send.is_empty() {
+            return None;
+        }
+
+        changes_to_send.retain(|change| !sync_state.sent_hashes.contains(&change.hash));
+        sync_state
+            .sent_hashes
+            .extend(changes_to_send.iter().map(|change| change.hash));
+        sync_state.last_sent_heads = our_heads.clone();
+
+        Some(SyncMessage {
+            heads: our_heads,
+            need: our_need,
+            have: our_have,
+            changes: changes_to_send.into_iter().cloned().collect(),
+        })
+    }
+
+    /// Apply a message from the peer described by `sync_state`, returns a
+    /// patch if the message contained changes.
+    pub fn receive_sync_message(
+        &mut self,
+        sync_state: &mut SyncState,
+        message: SyncMessage,
+    ) -> Result<Option<amp::Patch>, AutomergeError> {
+        let mut patch = None;
+        let before_heads = sorted(self.get_heads());
+
+        let SyncMessage {
+            heads: message_heads,
+            need,
+            have,
+            changes,
+        } = message;
+        let message_heads = sorted(message_heads);
+
+        // the changes the peer is missing may have been compacted, in which
+        // case we can't send them
+        if !self.includes_snapshot(&message_heads) {
+            return Err(AutomergeError::NeedsFullResync);
+        }
+
+        if !changes.is_empty() {
+            patch = Some(self.apply_changes(changes)?);
+            sync_state.shared_heads =
+                advance_heads(&before_heads, &self.get_heads(), &sync_state.shared_heads);
+        } else if message_heads == before_heads {
+            sync_state.last_sent_heads = message_heads.clone();
+        }
+
+        let known_heads: Vec<_> = message_heads
+            .iter()
+            .filter(|hash| self.has_change(hash))
+            .cloned()
+            .collect();
+        if known_heads.len() == message_heads.len() {
+            sync_state.shared_heads = message_heads.clone();
+            // The peer has reset, so forget everything we sent to it
+            if message_heads.is_empty() {
+                sync_state.last_sent_heads = Vec::new();
+                sync_state.sent_hashes = HashSet::new();
+            }
+        } else {
+            let mut shared_heads: Vec<_> = known_heads
+                .into_iter()
+                .chain(sync_state.shared_heads.drain(..))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            shared_heads.sort();
+            sync_state.shared_heads = shared_heads;
+        }
+
+        sync_state.their_have = Some(have);
+        sync_state.their_heads = Some(message_heads);
+        sync_state.their_need = Some(need);
+
+        Ok(patch)
+    }
+
+    fn make_bloom_filter(&self, last_sync: Vec<amp::ChangeHash>) -> SyncHave {
+        let new_changes = self.get_changes(&last_sync);
+        let bloom = BloomFilter::from_hashes(new_changes.iter().map(|change| &change.hash));
+        SyncHave { last_sync, bloom }
+    }
+
+    fn get_changes_to_send(&self, have: &[SyncHave], need: &[amp::ChangeHash]) -> Vec<&Change> {
+        if have.is_empty() {
+            return need
+                .iter()
+                .filter_map(|hash| self.get_change_by_hash(hash))
+                .collect();
+        }
+
+        let mut last_sync_hashes = HashSet::new();
+        let mut bloom_filters = Vec::with_capacity(have.len());
+        for SyncHave { last_sync, bloom } in have {
+            last_sync_hashes.extend(last_sync);
+            bloom_filters.push(bloom);
+        }
+        let last_sync_hashes: Vec<_> = last_sync_hashes.into_iter().cloned().collect();
+
+        let changes = self.get_changes(&last_sync_hashes);
+
+        let mut change_hashes = HashSet::with_capacity(changes.len());
+        let mut dependents: HashMap<amp::ChangeHash, Vec<amp::ChangeHash>> = HashMap::new();
+        let mut hashes_to_send = HashSet::new();
+        for change in &changes {
+            change_hashes.insert(change.hash);
+            for dep in &change.deps {
+                dependents.entry(*dep).or_default().push(change.hash);
+            }
+            if bloom_filters
+                .iter()
+                .all(|bloom| !bloom.contains_hash(&change.hash))
+            {
+                hashes_to_send.insert(change.hash);
+            }
+        }
+
+        // Anything which depends on a change the peer is missing is also
+        // missing, whatever the bloom filter says
+        let mut stack: Vec<_> = hashes_to_send.iter().cloned().collect();
+        while let Some(hash) = stack.pop() {
+            if let Some(deps) = dependents.get(&hash) {
+                for dep in deps {
+                    if hashes_to_send.insert(*dep) {
+                        stack.push(*dep);
+                    }
+                }
+            }
+        }
+
+        let mut changes_to_send = Vec::new();
+        for hash in need {
+            hashes_to_send.insert(*hash);
+            if !change_hashes.contains(hash) {
+                if let Some(change) = self.get_change_by_hash(hash) {
+                    changes_to_send.push(change);
+                }
+            }
+        }
+        for change in changes {
+            if hashes_to_send.contains(&change.hash) {
+                changes_to_send.push(change);
+            }
+        }
+        changes_to_send
+    }
+}
+
+/// The heads we have in common with a peer after applying changes from it
+fn advance_heads(
+    my_old_heads: &[amp::ChangeHash],
+    my_new_heads: &[amp::ChangeHash],
+    our_old_shared_heads: &[amp::ChangeHash],
+) -> Vec<amp::ChangeHash> {
+    let new_heads = my_new_heads
+        .iter()
+        .filter(|head| !my_old_heads.contains(head));
+    let common_heads = our_old_shared_heads
+        .iter()
+        .filter(|head| my_new_heads.contains(head));
+    let advanced: HashSet<_> = new_heads.chain(common_heads).cloned().collect();
+    sorted(advanced.into_iter().collect())
+}
+
+fn sorted(mut hashes: Vec<amp::ChangeHash>) -> Vec<amp::ChangeHash> {
+    hashes.sort();
+    hashes
+}
+
+fn encode_uint(buf: &mut Vec<u8>, value: usize) {
+    leb128::write::unsigned(buf, value as u64).unwrap();
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    encode_uint(buf, bytes.len());
+    buf.write_all(bytes).unwrap();
+}
+
+fn encode_hashes(buf: &mut Vec<u8>, hashes: &[amp::ChangeHash]) {
+    encode_uint(buf, hashes.len());
+    for hash in hashes {
+        buf.extend(&hash.0);
+    }
+}
+
+fn decode_bytes<'a>(decoder: &mut Decoder<'a>) -> Result<&'a [u8], AutomergeError> {
+    let len = decoder.read::<usize>()?;
+    decoder.read_bytes(len)
+}
+
+fn decode_hashes(decoder: &mut Decoder) -> Result<Vec<amp::ChangeHash>, AutomergeError> {
+    let count = decoder.read::<usize>()?;
+    let mut hashes = Vec::new();
+    for _ in 0..count {
+        let hash = amp::ChangeHash::try_from(decoder.read_bytes(HASH_SIZE)?)
+            .map_err(|source| AutomergeError::ChangeBadFormat { source })?;
+        hashes.push(hash);
+    }
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(byte: u8) -> amp::ChangeHash {
+        amp::ChangeHash([byte; 32])
+    }
+
+    #[test]
+    fn bloom_filter_contains_its_hashes() {
+        let hashes: Vec<_> = (0..100).map(hash).collect();
+        let bloom = BloomFilter::from_hashes(&hashes);
+        assert!(hashes.iter().all(|h| bloom.contains_hash(h)));
+
+        let decoded = BloomFilter::try_from(bloom.to_bytes().as_slice()).unwrap();
+        assert_eq!(decoded, bloom);
+        assert!(!BloomFilter::default().contains_hash(&hash(1)));
+    }
+
+    #[test]
+    fn bloom_filter_rejects_hostile_parameters() {
+        let filter = |num_entries: usize, bits_per_entry: usize, probes: usize, len: usize| {
+            let mut bytes = Vec::new();
+            encode_uint(&mut bytes, num_entries);
+            encode_uint(&mut bytes, bits_per_entry);
+            encode_uint(&mut bytes, probes);
+            bytes.extend(vec![0xff; len]);
+            BloomFilter::try_from(bytes.as_slice())
+        };
+        let invalid = Err(AutomergeError::from(DecodeError::InvalidBloomFilter));
+        assert!(filter(1, 10, 7, 2).is_ok());
+        assert_eq!(filter(1, 10, u32::MAX as usize, 2), invalid);
+        assert_eq!(filter(1, 10, 0, 2), invalid);
+        assert_eq!(filter(1, 33, 7, 5), invalid);
+        assert_eq!(filter(1, 0, 7, 0), invalid);
+        // the bits must be exactly as long as the entry count says
+        assert_eq!(filter(1, 10, 7, 3), invalid);
+        assert_eq!(
+            filter(u32::MAX as usize, 10, 7, 2),
+            Err(AutomergeError::EncodingError)
+        );
+    }
+
+    #[test]
+    fn sync_message_round_trips() {
+        let message = SyncMessage {
+            heads: vec![hash(1), hash(2)],
+            need: vec![hash(3)],
+            have: vec![SyncHave {
+                last_sync: vec![hash(4)],
+                bloom: BloomFilter::from_hashes(&[hash(5)]),
+            }],
+            changes: Vec::new(),
+        };
+        assert_eq!(SyncMessage::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn sync_state_round_trips_shared_heads() {
+        let state = SyncState {
+            shared_heads: vec![hash(1)],
+            their_heads: Some(vec![hash(2)]),
+            ..SyncState::default()
+        };
+        let decoded = SyncState::decode(&state.encode()).unwrap();
+        assert_eq!(decoded.shared_heads, state.shared_heads);
+        assert_eq!(decoded.their_heads, None);
+    }
+}
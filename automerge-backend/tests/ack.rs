@@ -0,0 +1,145 @@
+extern crate automerge_backend;
+use automerge_backend::{AutomergeError, Backend, Change};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ObjectId, Op, UncompressedChange};
+use std::convert::TryInto;
+
+fn set_key(
+    actor: &ActorId,
+    seq: u64,
+    start_op: u64,
+    deps: Vec<amp::ChangeHash>,
+    key: &str,
+    pred: Vec<amp::OpId>,
+) -> Change {
+    UncompressedChange {
+        actor_id: actor.clone(),
+        seq,
+        start_op,
+        time: 0,
+        message: None,
+        hash: None,
+        deps,
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Set(amp::ScalarValue::Int(seq as i64)),
+            key: key.into(),
+            insert: false,
+            pred,
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .into()
+}
+
+fn hashes(changes: Vec<&Change>) -> Vec<amp::ChangeHash> {
+    changes.into_iter().map(|change| change.hash).collect()
+}
+
+#[test]
+fn test_unacked_changes() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let change1 = set_key(&actor, 1, 1, Vec::new(), "bird", Vec::new());
+    let change2 = set_key(
+        &actor,
+        2,
+        2,
+        vec![change1.hash],
+        "bird",
+        vec![actor.op_id_at(1)],
+    );
+    let change3 = set_key(
+        &actor,
+        3,
+        3,
+        vec![change2.hash],
+        "bird",
+        vec![actor.op_id_at(2)],
+    );
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1.clone(), change2.clone(), change3.clone()])
+        .unwrap();
+
+    assert_eq!(backend.acked_heads("relay"), Vec::new());
+    assert_eq!(
+        hashes(backend.unacked_changes("relay").unwrap()),
+        vec![change1.hash, change2.hash, change3.hash]
+    );
+
+    backend.ack("relay", &[change2.hash]).unwrap();
+    assert_eq!(backend.acked_heads("relay"), vec![change2.hash]);
+    assert_eq!(
+        hashes(backend.unacked_changes("relay").unwrap()),
+        vec![change3.hash]
+    );
+
+    // acknowledging older heads doesn't forget newer ones
+    backend.ack("relay", &[change1.hash]).unwrap();
+    assert_eq!(backend.acked_heads("relay"), vec![change2.hash]);
+
+    backend.ack("relay", &[change3.hash]).unwrap();
+    assert!(backend.unacked_changes("relay").unwrap().is_empty());
+    assert_eq!(
+        hashes(backend.unacked_changes("phone").unwrap()),
+        vec![change1.hash, change2.hash, change3.hash]
+    );
+}
+
+#[test]
+fn test_common_acked_heads() {
+    let actor1: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let actor2: ActorId = "02ef21f3c9eb4087880ebedd7c4bbe43".try_into().unwrap();
+    let change1 = set_key(&actor1, 1, 1, Vec::new(), "bird", Vec::new());
+    let change2 = set_key(
+        &actor1,
+        2,
+        2,
+        vec![change1.hash],
+        "bird",
+        vec![actor1.op_id_at(1)],
+    );
+    let concurrent = set_key(&actor2, 1, 2, vec![change1.hash], "fish", Vec::new());
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1.clone(), change2.clone(), concurrent.clone()])
+        .unwrap();
+    assert_eq!(backend.common_acked_heads(), Vec::new());
+
+    backend.ack("laptop", &[change2.hash]).unwrap();
+    assert_eq!(backend.common_acked_heads(), vec![change2.hash]);
+
+    backend.ack("phone", &[concurrent.hash]).unwrap();
+    assert_eq!(backend.common_acked_heads(), vec![change1.hash]);
+
+    backend
+        .ack("phone", &[change2.hash, concurrent.hash])
+        .unwrap();
+    backend.ack("laptop", &[concurrent.hash]).unwrap();
+    let mut heads = vec![change2.hash, concurrent.hash];
+    heads.sort();
+    assert_eq!(backend.common_acked_heads(), heads);
+
+    // everyone has the common heads, so they can be compacted
+    let patch = backend.get_patch().unwrap();
+    backend.compact(&backend.common_acked_heads()).unwrap();
+    assert_eq!(backend.get_patch().unwrap(), patch);
+    assert_eq!(backend.common_acked_heads(), heads);
+    assert!(backend.unacked_changes("phone").unwrap().is_empty());
+    assert_eq!(
+        backend.unacked_changes("tablet"),
+        Err(AutomergeError::NeedsFullResync)
+    );
+}
+
+#[test]
+fn test_ack_an_unknown_head() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let change1 = set_key(&actor, 1, 1, Vec::new(), "bird", Vec::new());
+    let mut backend = Backend::init();
+    assert_eq!(
+        backend.ack("relay", &[change1.hash]),
+        Err(AutomergeError::UnknownHead(change1.hash))
+    );
+    assert_eq!(backend.acked_heads("relay"), Vec::new());
+}
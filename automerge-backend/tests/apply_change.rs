@@ -119,6 +119,116 @@ fn test_increment_key_in_map() {
     assert_eq!(patch, expected_patch);
 }
 
+#[test]
+fn test_counter_overflow_wraps() {
+    let actor1: ActorId = "cdee6963c1664645920be8b41a933c2b".try_into().unwrap();
+    let actor2: ActorId = "02ef21f3c9eb4087880ebedd7c4bbe43".try_into().unwrap();
+    let change1: Change = UncompressedChange {
+        actor_id: actor1.clone(),
+        seq: 1,
+        start_op: 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: Vec::new(),
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Set(ScalarValue::Counter(i64::MIN + 1)),
+            key: "counter".into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .try_into()
+    .unwrap();
+
+    // Two concurrent increments which are each valid, but overflow together
+    let increment = |actor: &ActorId, by: i64| -> Change {
+        UncompressedChange {
+            actor_id: actor.clone(),
+            seq: if *actor == actor1 { 2 } else { 1 },
+            start_op: 2,
+            time: 0,
+            message: None,
+            hash: None,
+            deps: vec![change1.hash],
+            operations: vec![Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Inc(by),
+                key: "counter".into(),
+                insert: false,
+                pred: vec![actor1.op_id_at(1)],
+            }],
+            extra_bytes: Vec::new(),
+        }
+        .try_into()
+        .unwrap()
+    };
+    let change2 = increment(&actor1, -1);
+    let change3 = increment(&actor2, -2);
+
+    let mut backend1 = Backend::init();
+    backend1
+        .apply_changes(vec![change1.clone(), change2.clone(), change3.clone()])
+        .unwrap();
+    let mut backend2 = Backend::init();
+    backend2
+        .apply_changes(vec![change1, change3, change2])
+        .unwrap();
+
+    let patch = backend1.get_patch().unwrap();
+    assert_eq!(patch, backend2.get_patch().unwrap());
+    let expected: amp::Diff = MapDiff {
+        object_id: ObjectId::Root,
+        obj_type: MapType::Map,
+        props: hashmap!(
+        "counter".into() => hashmap!{
+            actor1.op_id_at(1) => ScalarValue::Counter(i64::MAX - 1).into(),
+        }),
+    }
+    .into();
+    assert_eq!(patch.diffs, Some(expected));
+}
+
+#[test]
+fn test_reusing_a_seq_or_op_ids_is_an_error() {
+    let actor: ActorId = "d52c2b2e5b5e4a5d9b0c6a8f7e1d3c4b".try_into().unwrap();
+    let change = |seq: u64, start_op: u64, value: &str| -> Change {
+        UncompressedChange {
+            actor_id: actor.clone(),
+            seq,
+            start_op,
+            time: 0,
+            message: None,
+            hash: None,
+            deps: Vec::new(),
+            operations: vec![Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Set(value.into()),
+                key: value.into(),
+                insert: false,
+                pred: Vec::new(),
+            }],
+            extra_bytes: Vec::new(),
+        }
+        .try_into()
+        .unwrap()
+    };
+
+    let mut backend = Backend::init();
+    backend.apply_changes(vec![change(1, 1, "magpie")]).unwrap();
+    assert_eq!(
+        backend.apply_changes(vec![change(1, 2, "wren")]),
+        Err(AutomergeError::InvalidSeq(1))
+    );
+    assert_eq!(
+        backend.apply_changes(vec![change(2, 1, "wren")]),
+        Err(AutomergeError::InvalidStartOp(1))
+    );
+    backend.apply_changes(vec![change(2, 2, "wren")]).unwrap();
+}
+
 #[test]
 fn test_conflict_on_assignment_to_same_map_key() {
     let actor_1 = ActorId::from_str("ac11").unwrap();
@@ -694,7 +804,7 @@ fn test_handle_list_element_insertion_and_deletion_in_same_change() {
         seq: None,
         actor: None,
         max_op: 3,
-        deps: vec![change2.hash, change1.hash],
+        deps: vec![change1.hash, change2.hash],
         diffs: Some(Diff::Map(MapDiff {
             object_id: ObjectId::Root,
             obj_type: MapType::Map,
@@ -792,7 +902,7 @@ fn test_handle_changes_within_conflicted_objects() {
             actor2.clone() => 2,
         },
         max_op: 2,
-        deps: vec![change3.hash, change1.hash],
+        deps: vec![change1.hash, change3.hash],
         diffs: Some(Diff::Map(MapDiff {
             object_id: ObjectId::Root,
             obj_type: MapType::Map,
@@ -1235,3 +1345,174 @@ fn test_updating_sequences_updates_referring_cursors_with_deleted_items() {
     };
     assert_eq!(patch, expected_patch);
 }
+
+#[test]
+fn concurrent_add_wins_over_remove_in_set() {
+    let actor_1 = ActorId::from_str("ac11").unwrap();
+    let actor_2 = ActorId::from_str("ac22").unwrap();
+    let set_id = actor_1.op_id_at(1);
+    let change1: Change = UncompressedChange {
+        actor_id: actor_1.clone(),
+        seq: 1,
+        start_op: 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: Vec::new(),
+        operations: vec![
+            Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Make(ObjType::set()),
+                key: "tags".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+            Op {
+                obj: set_id.clone().into(),
+                action: amp::OpType::Set(ScalarValue::Boolean(true)),
+                key: "urgent".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+        ],
+        extra_bytes: Vec::new(),
+    }
+    .try_into()
+    .unwrap();
+
+    let remove: Change = UncompressedChange {
+        actor_id: actor_2.clone(),
+        seq: 1,
+        start_op: 3,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: vec![change1.hash],
+        operations: vec![Op {
+            obj: set_id.clone().into(),
+            action: amp::OpType::Del,
+            key: "urgent".into(),
+            insert: false,
+            pred: vec![actor_1.op_id_at(2)],
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .try_into()
+    .unwrap();
+
+    let re_add: Change = UncompressedChange {
+        actor_id: actor_1.clone(),
+        seq: 2,
+        start_op: 3,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: vec![change1.hash],
+        operations: vec![Op {
+            obj: set_id.clone().into(),
+            action: amp::OpType::Set(ScalarValue::Boolean(true)),
+            key: "urgent".into(),
+            insert: false,
+            pred: vec![actor_1.op_id_at(2)],
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .try_into()
+    .unwrap();
+
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1, remove, re_add])
+        .unwrap();
+    let patch = backend.get_patch().unwrap();
+    assert_eq!(
+        patch.diffs,
+        Some(Diff::Map(MapDiff {
+            object_id: ObjectId::Root,
+            obj_type: MapType::Map,
+            props: hashmap! {
+                "tags".into() => hashmap!{
+                    set_id.clone() => Diff::Map(MapDiff {
+                        object_id: set_id.into(),
+                        obj_type: MapType::Set,
+                        props: hashmap!{
+                            "urgent".into() => hashmap!{
+                                actor_1.op_id_at(3) => Diff::Value(ScalarValue::Boolean(true)),
+                            }
+                        },
+                    })
+                }
+            },
+        }))
+    );
+}
+
+#[test]
+fn test_unknown_ops_are_kept_and_saved() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let change1: Change = UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 1,
+        start_op: 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: Vec::new(),
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Set("magpie".into()),
+            key: "bird".into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .try_into()
+    .unwrap();
+    let unknown_value = ScalarValue::Unknown {
+        type_code: 12,
+        bytes: vec![1, 2, 3],
+    };
+    let change2: Change = UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 2,
+        start_op: 2,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: vec![change1.hash],
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Unknown {
+                action: 42,
+                value: unknown_value.clone(),
+            },
+            key: "bird".into(),
+            insert: false,
+            pred: vec![actor.op_id_at(1)],
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .try_into()
+    .unwrap();
+
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1.clone(), change2.clone()])
+        .unwrap();
+    let expected_diffs = Some(
+        MapDiff {
+            object_id: ObjectId::Root,
+            obj_type: MapType::Map,
+            props: hashmap!( "bird".into() => hashmap!( actor.op_id_at(2) => Diff::Value(unknown_value) )),
+        }
+        .into(),
+    );
+    assert_eq!(backend.get_patch().unwrap().diffs, expected_diffs);
+
+    let loaded = Backend::load(backend.save().unwrap()).unwrap();
+    assert_eq!(loaded.get_patch().unwrap().diffs, expected_diffs);
+    let loaded_changes: Vec<&Change> = loaded.get_changes(&[]);
+    assert_eq!(loaded_changes, vec![&change1, &change2]);
+    assert_eq!(loaded_changes[1].bytes, change2.bytes);
+}
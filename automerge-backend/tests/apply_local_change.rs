@@ -1,6 +1,6 @@
 extern crate automerge_backend;
-use automerge_backend::Backend;
 use automerge_backend::Change;
+use automerge_backend::{AutomergeError, Backend};
 use automerge_protocol as protocol;
 use automerge_protocol::{
     ActorId, ChangeHash, Diff, DiffEdit, ElementId, MapDiff, MapType, ObjType, ObjectId, Op,
@@ -122,6 +122,62 @@ fn test_error_on_duplicate_requests() {
     assert!(backend.apply_local_change(change_request2).is_err());
 }
 
+#[test]
+fn test_unknown_ops_with_known_codes_are_rejected() {
+    let actor: ActorId = "37704788917a499cb0206fa8519ac4d9".try_into().unwrap();
+    let request = |action| UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 1,
+        message: None,
+        hash: None,
+        time: 0,
+        deps: Vec::new(),
+        start_op: 1,
+        operations: vec![Op {
+            action,
+            obj: ObjectId::Root,
+            key: "bird".into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    };
+    let unknown_value = |type_code| protocol::ScalarValue::Unknown {
+        type_code,
+        bytes: vec![1, 2, 3],
+    };
+
+    let mut backend = Backend::init();
+    for action in [
+        protocol::OpType::Set(unknown_value(6)),
+        protocol::OpType::Set(unknown_value(16)),
+        protocol::OpType::Unknown {
+            action: 1,
+            value: protocol::ScalarValue::Null,
+        },
+        protocol::OpType::Unknown {
+            action: 20,
+            value: unknown_value(255),
+        },
+    ]
+    .iter()
+    .cloned()
+    {
+        assert!(matches!(
+            backend.apply_local_change(request(action)),
+            Err(AutomergeError::InvalidChange { .. })
+        ));
+    }
+    assert!(backend.get_heads().is_empty());
+
+    backend
+        .apply_local_change(request(protocol::OpType::Unknown {
+            action: 20,
+            value: unknown_value(12),
+        }))
+        .unwrap();
+}
+
 #[test]
 fn test_handle_concurrent_frontend_and_backend_changes() {
     let actor: ActorId = "cb55260e9d7e457886a4fc73fd949202".try_into().unwrap();
@@ -595,6 +651,97 @@ fn test_handle_list_insertion_and_deletion_in_same_change() {
     assert_eq!(change2, expected_change2);
 }
 
+#[test]
+fn test_get_last_local_change_and_changes_since_local() {
+    let actor: ActorId = "d1a5a9f2c1f94a6e8c36bd2c83b2b8e1".try_into().unwrap();
+    let other: ActorId = "3c5d9b4f0e2a4b7f9a1e6c8d2f4b6a80".try_into().unwrap();
+    let set_bird =
+        |actor: &ActorId, seq: u64, start_op: u64, deps: Vec<ChangeHash>, value: &str| {
+            UncompressedChange {
+                actor_id: actor.clone(),
+                seq,
+                message: None,
+                hash: None,
+                time: 0,
+                deps,
+                start_op,
+                operations: vec![Op {
+                    action: protocol::OpType::Set(value.into()),
+                    obj: ObjectId::Root,
+                    key: "bird".into(),
+                    insert: false,
+                    pred: Vec::new(),
+                }],
+                extra_bytes: Vec::new(),
+            }
+        };
+    let mut backend = Backend::init();
+    assert!(backend.last_local_actor().is_none());
+    assert!(backend.get_last_local_change(&actor).is_none());
+    assert!(backend.get_changes_since_local(&actor, 0).is_empty());
+
+    backend
+        .apply_local_change(set_bird(&actor, 1, 1, Vec::new(), "magpie"))
+        .unwrap();
+    let (_, change2) = backend
+        .apply_local_change(set_bird(&actor, 2, 2, Vec::new(), "jay"))
+        .unwrap();
+    let (_, change3) = backend
+        .apply_local_change(set_bird(&actor, 3, 3, Vec::new(), "robin"))
+        .unwrap();
+    assert_eq!(backend.last_local_actor(), Some(&actor));
+    assert_eq!(
+        backend.get_last_local_change(&actor),
+        Some(change3.as_ref())
+    );
+    assert_eq!(
+        backend.get_changes_since_local(&actor, 1),
+        vec![change2.as_ref(), change3.as_ref()]
+    );
+
+    // remote changes aren't local changes
+    let remote: Change = set_bird(&other, 1, 4, vec![change3.hash], "wren").into();
+    backend.apply_changes(vec![remote.clone()]).unwrap();
+    assert_eq!(backend.last_local_actor(), Some(&actor));
+    assert!(backend.get_last_local_change(&other).is_none());
+
+    // switching actors keeps the changes of the previous one
+    let (_, other_change) = backend
+        .apply_local_change(set_bird(&other, 2, 5, vec![remote.hash], "owl"))
+        .unwrap();
+    assert_eq!(backend.last_local_actor(), Some(&other));
+    assert_eq!(
+        backend.get_last_local_change(&other),
+        Some(other_change.as_ref())
+    );
+    assert_eq!(
+        backend.get_changes_since_local(&other, 1),
+        vec![other_change.as_ref()]
+    );
+    assert_eq!(
+        backend.get_last_local_change(&actor),
+        Some(change3.as_ref())
+    );
+    assert_eq!(
+        backend.get_changes_since_local(&actor, 2),
+        vec![change3.as_ref()]
+    );
+
+    // compacted changes can't be sent again, but the last change is kept
+    backend.compact(&[change2.hash]).unwrap();
+    assert_eq!(backend.last_local_actor(), Some(&other));
+    assert_eq!(
+        backend.get_last_local_change(&actor),
+        Some(change3.as_ref())
+    );
+    assert_eq!(
+        backend.get_changes_since_local(&actor, 0),
+        vec![change3.as_ref()]
+    );
+    assert!(backend.get_changes_since_local(&actor, 3).is_empty());
+    assert!(backend.get_changes_since_local(&actor, 4).is_empty());
+}
+
 /// Asserts that the changes are equal without respect to order of the hashes
 /// in the change dependencies
 fn assert_changes_equal(mut change1: UncompressedChange, change2: UncompressedChange) {
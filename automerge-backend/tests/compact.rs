@@ -0,0 +1,432 @@
+extern crate automerge_backend;
+use automerge_backend::{AutomergeError, Backend, Change, DecodeError, SyncMessage, SyncState};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ElementId, ObjectId, Op, UncompressedChange};
+use std::convert::TryInto;
+
+fn change(
+    actor: &ActorId,
+    seq: u64,
+    start_op: u64,
+    deps: Vec<amp::ChangeHash>,
+    operations: Vec<Op>,
+) -> Change {
+    UncompressedChange {
+        actor_id: actor.clone(),
+        seq,
+        start_op,
+        time: 0,
+        message: None,
+        hash: None,
+        deps,
+        operations,
+        extra_bytes: Vec::new(),
+    }
+    .into()
+}
+
+fn set(obj: ObjectId, key: amp::Key, value: amp::ScalarValue, pred: Vec<amp::OpId>) -> Op {
+    Op {
+        obj,
+        action: amp::OpType::Set(value),
+        key,
+        insert: false,
+        pred,
+    }
+}
+
+fn del(obj: ObjectId, key: amp::Key, pred: amp::OpId) -> Op {
+    Op {
+        obj,
+        action: amp::OpType::Del,
+        key,
+        insert: false,
+        pred: vec![pred],
+    }
+}
+
+/// A text object with 200 characters and a map key, and a second change
+/// which deletes the map key and all but the first 10 characters
+fn typed_and_deleted(actor: &ActorId) -> (Change, Change) {
+    let text = ObjectId::from(actor.op_id_at(1));
+    let mut operations = vec![Op {
+        obj: ObjectId::Root,
+        action: amp::OpType::Make(amp::ObjType::text()),
+        key: "text".into(),
+        insert: false,
+        pred: Vec::new(),
+    }];
+    for i in 0..200 {
+        let key = match i {
+            0 => ElementId::Head.into(),
+            _ => actor.op_id_at(i + 1).into(),
+        };
+        operations.push(Op {
+            insert: true,
+            ..set(text.clone(), key, "a".into(), Vec::new())
+        });
+    }
+    operations.push(set(
+        ObjectId::Root,
+        "title".into(),
+        "draft".into(),
+        Vec::new(),
+    ));
+    operations.push(set(
+        ObjectId::Root,
+        "count".into(),
+        amp::ScalarValue::Counter(1),
+        Vec::new(),
+    ));
+    let change1 = change(actor, 1, 1, Vec::new(), operations);
+
+    let mut operations = vec![
+        del(ObjectId::Root, "title".into(), actor.op_id_at(202)),
+        Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Inc(2),
+            key: "count".into(),
+            insert: false,
+            pred: vec![actor.op_id_at(203)],
+        },
+    ];
+    for i in 12..=201 {
+        operations.push(del(
+            text.clone(),
+            actor.op_id_at(i).into(),
+            actor.op_id_at(i),
+        ));
+    }
+    let change2 = change(actor, 2, 204, vec![change1.hash], operations);
+    (change1, change2)
+}
+
+#[test]
+fn test_compact_drops_tombstones_and_keeps_the_patch() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let (change1, change2) = typed_and_deleted(&actor);
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1, change2.clone()])
+        .unwrap();
+    let patch = backend.get_patch().unwrap();
+    let stats = backend.stats();
+    let saved = backend.save().unwrap();
+    assert_eq!(stats.tombstones, 191);
+
+    backend.compact(&[change2.hash]).unwrap();
+
+    assert_eq!(backend.get_patch().unwrap(), patch);
+    assert_eq!(backend.get_heads(), vec![change2.hash]);
+    let compacted = backend.stats();
+    assert_eq!(compacted.tombstones, 0);
+    assert_eq!(compacted.changes, 0);
+    assert_eq!(compacted.compacted_changes, 2);
+    assert!(compacted.heap_bytes.total() < stats.heap_bytes.total());
+    assert!(backend.get_changes(&[]).is_empty());
+
+    assert!(backend.has_compacted_history());
+    let compacted_saved = backend.save().unwrap();
+    assert!(compacted_saved.len() < saved.len());
+    assert_eq!(backend.save().unwrap(), compacted_saved);
+    assert!(matches!(
+        Change::load_document(&compacted_saved),
+        Err(AutomergeError::Decode {
+            source: DecodeError::UnexpectedSnapshot
+        })
+    ));
+    let loaded = Backend::load(compacted_saved.clone()).unwrap();
+    assert_eq!(loaded.get_patch().unwrap(), patch);
+    assert_eq!(loaded.save().unwrap(), compacted_saved);
+    assert!(loaded.has_compacted_history());
+    for threshold in &[None, Some(0)] {
+        assert_eq!(
+            Backend::load(backend.save_with_deflate_threshold(*threshold).unwrap())
+                .unwrap()
+                .get_patch()
+                .unwrap(),
+            patch
+        );
+    }
+}
+
+#[test]
+fn test_changes_apply_after_compacting() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let (change1, change2) = typed_and_deleted(&actor);
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1, change2.clone()])
+        .unwrap();
+    let mut compacted = backend.clone();
+    compacted.compact(&[change2.hash]).unwrap();
+
+    // a change which depends on the compacted changes, made locally
+    let local = UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 3,
+        start_op: 396,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: Vec::new(),
+        operations: vec![Op {
+            insert: true,
+            ..set(
+                actor.op_id_at(1).into(),
+                actor.op_id_at(11).into(),
+                "b".into(),
+                Vec::new(),
+            )
+        }],
+        extra_bytes: Vec::new(),
+    };
+    let (patch, change3) = backend.apply_local_change(local.clone()).unwrap();
+    assert_eq!(compacted.apply_local_change(local).unwrap().0, patch);
+    assert_eq!(change3.deps, vec![change2.hash]);
+
+    // and one from another actor
+    let other: ActorId = "02ef21f3c9eb4087880ebedd7c4bbe43".try_into().unwrap();
+    let change4 = change(
+        &other,
+        1,
+        397,
+        vec![change3.hash],
+        vec![set(
+            ObjectId::Root,
+            "title".into(),
+            "final".into(),
+            Vec::new(),
+        )],
+    );
+    assert_eq!(
+        compacted.apply_changes(vec![change4.clone()]).unwrap(),
+        backend.apply_changes(vec![change4]).unwrap()
+    );
+    assert_eq!(compacted.get_patch().unwrap(), backend.get_patch().unwrap());
+    assert_eq!(compacted.get_changes(&[change2.hash]).len(), 2);
+
+    let loaded = Backend::load(compacted.save().unwrap()).unwrap();
+    assert_eq!(loaded.get_patch().unwrap(), backend.get_patch().unwrap());
+
+    // compacting again squashes the new changes into the snapshot
+    let heads = compacted.get_heads();
+    compacted.compact(&heads).unwrap();
+    assert_eq!(compacted.stats().compacted_changes, 4);
+    assert_eq!(compacted.get_patch().unwrap(), backend.get_patch().unwrap());
+    let loaded = Backend::load(compacted.save().unwrap()).unwrap();
+    assert_eq!(loaded.get_patch().unwrap(), backend.get_patch().unwrap());
+}
+
+#[test]
+fn test_compact_keeps_what_uncompacted_changes_refer_to() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let other: ActorId = "02ef21f3c9eb4087880ebedd7c4bbe43".try_into().unwrap();
+    let (change1, change2) = typed_and_deleted(&actor);
+    // concurrent with `change2`, sets a character it deletes and inserts
+    // after another
+    let concurrent = change(
+        &other,
+        1,
+        204,
+        vec![change1.hash],
+        vec![
+            set(
+                actor.op_id_at(1).into(),
+                actor.op_id_at(150).into(),
+                "b".into(),
+                vec![actor.op_id_at(150)],
+            ),
+            Op {
+                insert: true,
+                ..set(
+                    actor.op_id_at(1).into(),
+                    actor.op_id_at(100).into(),
+                    "c".into(),
+                    Vec::new(),
+                )
+            },
+        ],
+    );
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1, change2.clone(), concurrent.clone()])
+        .unwrap();
+    let patch = backend.get_patch().unwrap();
+
+    backend.compact(&[change2.hash]).unwrap();
+
+    assert_eq!(backend.get_patch().unwrap(), patch);
+    assert_eq!(backend.stats().compacted_changes, 2);
+    assert_eq!(
+        backend
+            .get_changes(&[])
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![concurrent]
+    );
+    let loaded = Backend::load(backend.save().unwrap()).unwrap();
+    assert_eq!(loaded.get_patch().unwrap(), patch);
+}
+
+#[test]
+fn test_change_without_compacted_history_needs_full_resync() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let other: ActorId = "02ef21f3c9eb4087880ebedd7c4bbe43".try_into().unwrap();
+    let (change1, change2) = typed_and_deleted(&actor);
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1.clone(), change2.clone()])
+        .unwrap();
+    backend.compact(&[change2.hash]).unwrap();
+
+    // made without seeing `change2`, so it could refer to what it deleted
+    let concurrent = change(
+        &other,
+        1,
+        204,
+        vec![change1.hash],
+        vec![set(
+            actor.op_id_at(1).into(),
+            actor.op_id_at(150).into(),
+            "b".into(),
+            vec![actor.op_id_at(150)],
+        )],
+    );
+    assert_eq!(
+        backend
+            .apply_changes(vec![concurrent.clone()])
+            .unwrap()
+            .deps,
+        vec![change2.hash]
+    );
+    // only the snapshot's heads and last changes are known by their hash, so
+    // the change waits for `change1` until that turns out to be compacted
+    assert_eq!(backend.get_missing_deps(), vec![change1.hash]);
+    assert_eq!(
+        backend.apply_changes(vec![change1.clone()]),
+        Err(AutomergeError::NeedsFullResync)
+    );
+    // as does one which depends on it directly
+    let mut fresh = Backend::init();
+    fresh
+        .apply_changes(vec![change1.clone(), change2.clone()])
+        .unwrap();
+    fresh.compact(&[change2.hash]).unwrap();
+    assert_eq!(
+        fresh.apply_changes(vec![change1, concurrent]),
+        Err(AutomergeError::NeedsFullResync)
+    );
+
+    // compacted changes are already applied
+    backend.apply_changes(vec![change2]).unwrap();
+    assert_eq!(backend.stats().changes, 0);
+}
+
+#[test]
+fn test_sync_after_compacting() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let (change1, change2) = typed_and_deleted(&actor);
+    let mut backend = Backend::init();
+    backend
+        .apply_changes(vec![change1, change2.clone()])
+        .unwrap();
+    let mut peer = backend.clone();
+    backend.compact(&[change2.hash]).unwrap();
+
+    // a peer which has the compacted changes can still sync
+    let other: ActorId = "02ef21f3c9eb4087880ebedd7c4bbe43".try_into().unwrap();
+    let change3 = change(
+        &other,
+        1,
+        396,
+        vec![change2.hash],
+        vec![set(
+            ObjectId::Root,
+            "title".into(),
+            "final".into(),
+            Vec::new(),
+        )],
+    );
+    peer.apply_changes(vec![change3]).unwrap();
+    let mut state = SyncState::default();
+    let mut peer_state = SyncState::default();
+    for _ in 0..5 {
+        if let Some(msg) = peer.generate_sync_message(&mut peer_state) {
+            backend.receive_sync_message(&mut state, msg).unwrap();
+        }
+        if let Some(msg) = backend.generate_sync_message(&mut state) {
+            peer.receive_sync_message(&mut peer_state, msg).unwrap();
+        }
+    }
+    assert_eq!(backend.get_heads(), peer.get_heads());
+    assert_eq!(backend.get_patch().unwrap(), peer.get_patch().unwrap());
+
+    // one which doesn't has to be sent the whole document
+    let mut empty = Backend::init();
+    let msg = empty
+        .generate_sync_message(&mut SyncState::default())
+        .unwrap();
+    let msg = SyncMessage::decode(&msg.encode()).unwrap();
+    assert_eq!(
+        backend.receive_sync_message(&mut SyncState::default(), msg),
+        Err(AutomergeError::NeedsFullResync)
+    );
+    empty = Backend::load(backend.save().unwrap()).unwrap();
+    assert_eq!(empty.get_patch().unwrap(), backend.get_patch().unwrap());
+}
+
+#[test]
+fn test_compact_with_an_unknown_head() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let (change1, change2) = typed_and_deleted(&actor);
+    let mut backend = Backend::init();
+    backend.apply_changes(vec![change1]).unwrap();
+    assert_eq!(
+        backend.compact(&[change2.hash]),
+        Err(AutomergeError::UnknownHead(change2.hash))
+    );
+    // compacting nothing changes nothing
+    let before = backend.clone();
+    backend.compact(&[]).unwrap();
+    assert_eq!(backend, before);
+}
+
+#[test]
+fn test_saved_size_stops_growing_when_compacting() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let mut backend = Backend::init();
+    let mut sizes = Vec::new();
+    for seq in 1..=300 {
+        let pred = match seq {
+            1 => Vec::new(),
+            _ => vec![actor.op_id_at(seq - 1)],
+        };
+        backend
+            .apply_local_change(UncompressedChange {
+                actor_id: actor.clone(),
+                seq,
+                start_op: seq,
+                time: 0,
+                message: None,
+                hash: None,
+                deps: Vec::new(),
+                operations: vec![set(
+                    ObjectId::Root,
+                    "title".into(),
+                    amp::ScalarValue::Int(seq as i64),
+                    pred,
+                )],
+                extra_bytes: Vec::new(),
+            })
+            .unwrap();
+        let heads = backend.get_heads();
+        backend.compact(&heads).unwrap();
+        sizes.push(backend.save().unwrap().len());
+    }
+    assert_eq!(backend.stats().compacted_changes, 300);
+    // the seqs and counters are LEB128 encoded so they only grow by a byte
+    // at each power of 128, the compacted hashes aren't kept
+    assert!(sizes[150..].iter().all(|size| *size == sizes[150]));
+}
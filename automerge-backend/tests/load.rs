@@ -0,0 +1,88 @@
+use automerge_backend::{AutomergeError, Backend, Change};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ObjectId, Op, UncompressedChange};
+use std::io::Read;
+use std::str::FromStr;
+
+fn set_change(backend: &Backend, actor: &ActorId, key: &str, value: &str) -> Change {
+    let seq = backend.get_changes_for_actor_id(actor).unwrap().len() as u64 + 1;
+    UncompressedChange {
+        actor_id: actor.clone(),
+        seq,
+        start_op: seq,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: backend.get_heads(),
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Set(value.into()),
+            key: key.into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .into()
+}
+
+/// A reader which fails after returning `data`
+struct FailingReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Read for FailingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "the disk is on fire",
+            ));
+        }
+        self.data.read(buf)
+    }
+}
+
+#[test]
+fn test_load_from_reader_reads_a_document_and_appended_changes() {
+    let actor = ActorId::from_str("deadbeefdeadbeef").unwrap();
+    let mut backend = Backend::init();
+    for (key, value) in &[("bird", "magpie"), ("tree", "oak")] {
+        let change = set_change(&backend, &actor, key, value);
+        backend.apply_changes(vec![change]).unwrap();
+    }
+    let mut data = backend.save().unwrap();
+
+    let change = set_change(&backend, &actor, "bird", "wren");
+    backend.apply_changes(vec![change.clone()]).unwrap();
+    data.extend(&change.bytes);
+
+    // a truncated change at the end is ignored, as with `load`
+    let truncated = set_change(&backend, &actor, "bird", "robin");
+    data.extend(&truncated.bytes[..truncated.bytes.len() - 1]);
+
+    let loaded = Backend::load_from_reader(&data[..]).unwrap();
+    assert_eq!(loaded.get_heads(), backend.get_heads());
+    assert_eq!(loaded.get_patch().unwrap(), backend.get_patch().unwrap());
+    assert_eq!(loaded.get_changes(&[]), backend.get_changes(&[]));
+    assert_eq!(
+        loaded.get_patch().unwrap(),
+        Backend::load(data).unwrap().get_patch().unwrap()
+    );
+
+    assert_eq!(
+        Backend::load_from_reader(&[][..]).unwrap().get_heads(),
+        Vec::new()
+    );
+}
+
+#[test]
+fn test_load_from_reader_reports_read_errors() {
+    let actor = ActorId::from_str("deadbeefdeadbeef").unwrap();
+    let backend = Backend::init();
+    let change = set_change(&backend, &actor, "bird", "magpie");
+    let result = Backend::load_from_reader(FailingReader {
+        data: &change.bytes[..20],
+    });
+    assert!(matches!(result, Err(AutomergeError::ReadError(_))));
+}
@@ -0,0 +1,132 @@
+//! Measures the memory used while loading a document. This is the only test
+//! in this binary, as the allocator counts the allocations of every thread.
+
+use automerge_backend::Backend;
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ObjectId, Op, UncompressedChange};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Keeps track of the bytes allocated and the most there have been since
+/// `reset_peak`
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn reset_peak() -> usize {
+    let allocated = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(allocated, Ordering::SeqCst);
+    allocated
+}
+
+/// A saved document of `changes` changes, each inserting `ops_per_change`
+/// characters into a list
+fn saved_document(changes: u64, ops_per_change: u64) -> Vec<u8> {
+    let actor = ActorId::from_str("deadbeefdeadbeef").unwrap();
+    let list = ObjectId::from(actor.op_id_at(1));
+    let mut backend = Backend::init();
+    let make_list = UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 1,
+        start_op: 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: Vec::new(),
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Make(amp::ObjType::list()),
+            key: "birds".into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    };
+    backend.apply_local_change(make_list).unwrap();
+    let mut last = actor.op_id_at(1);
+    for seq in 2..changes + 2 {
+        let start_op = last.0 + 1;
+        let operations = (start_op..start_op + ops_per_change)
+            .map(|ctr| {
+                let key = if ctr == 2 {
+                    amp::Key::head()
+                } else {
+                    actor.op_id_at(ctr - 1).into()
+                };
+                Op {
+                    obj: list.clone(),
+                    action: amp::OpType::Set("magpie".into()),
+                    key,
+                    insert: true,
+                    pred: Vec::new(),
+                }
+            })
+            .collect();
+        let change = UncompressedChange {
+            actor_id: actor.clone(),
+            seq,
+            start_op,
+            time: 0,
+            message: None,
+            hash: None,
+            deps: Vec::new(),
+            operations,
+            extra_bytes: Vec::new(),
+        };
+        backend.apply_local_change(change).unwrap();
+        last = actor.op_id_at(start_op + ops_per_change - 1);
+    }
+    backend.save().unwrap()
+}
+
+#[test]
+fn test_loading_a_document_does_not_copy_its_ops() {
+    let data = saved_document(200, 50);
+    // the same changes applied one by one rather than built from the ops
+    let changes = Backend::load(data.clone())
+        .unwrap()
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect();
+    let mut expected = Backend::init();
+    expected.load_changes(changes).unwrap();
+
+    let input = data.clone();
+    let before = reset_peak();
+    let backend = Backend::load(input).unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    let retained = ALLOCATED.load(Ordering::SeqCst) - before;
+
+    // Holding every op both as decoded from the document and as imported
+    // into the op set, or copying them into their changes, takes this well
+    // over a fifth of what the backend keeps
+    assert!(
+        peak - retained < retained / 5,
+        "loading used {} bytes at its peak but kept {}",
+        peak,
+        retained
+    );
+    assert_eq!(backend.get_patch().unwrap(), expected.get_patch().unwrap());
+}
@@ -0,0 +1,514 @@
+//! `Backend::load` builds the op set straight from the document, these check
+//! that gives the same backend as applying the document's changes one at a
+//! time, and that `Backend::compact`, which builds the op set from a
+//! snapshot, doesn't change the document either
+
+use automerge_backend::{Backend, Change};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, Diff, DiffEdit, ElementId, MapDiff, ObjectId, OpId};
+use proptest::prelude::*;
+
+const KEYS: [&str; 3] = ["magpie", "wren", "robin"];
+const PEERS: usize = 3;
+
+/// An edit made by one peer, anything which doesn't make sense in the
+/// peer's current state is skipped
+#[derive(Debug, Clone)]
+enum Edit {
+    Set {
+        key: usize,
+        value: i64,
+    },
+    SetCounter {
+        key: usize,
+        value: i64,
+    },
+    Inc {
+        key: usize,
+        by: i64,
+    },
+    Del {
+        key: usize,
+    },
+    MakeList {
+        key: usize,
+        text: bool,
+    },
+    Insert {
+        key: usize,
+        index: usize,
+        values: Vec<i64>,
+    },
+    SetElem {
+        key: usize,
+        index: usize,
+        value: i64,
+    },
+    DelElem {
+        key: usize,
+        index: usize,
+    },
+    /// A cursor pointing at an element of the list at `key`, stored under
+    /// the "cursor" key or in an element of the same list
+    Cursor {
+        key: usize,
+        index: usize,
+        in_list: Option<usize>,
+    },
+    /// Apply the changes another peer has which this one doesn't
+    Sync {
+        from: usize,
+    },
+}
+
+fn arb_edit() -> impl Strategy<Value = Edit> {
+    let key = 0..KEYS.len();
+    let value = -100i64..100;
+    prop_oneof![
+        (key.clone(), value.clone()).prop_map(|(key, value)| Edit::Set { key, value }),
+        (key.clone(), value.clone()).prop_map(|(key, value)| Edit::SetCounter { key, value }),
+        (key.clone(), value.clone()).prop_map(|(key, by)| Edit::Inc { key, by }),
+        key.clone().prop_map(|key| Edit::Del { key }),
+        (key.clone(), any::<bool>()).prop_map(|(key, text)| Edit::MakeList { key, text }),
+        (
+            key.clone(),
+            any::<usize>(),
+            proptest::collection::vec(value.clone(), 1..4)
+        )
+            .prop_map(|(key, index, values)| Edit::Insert { key, index, values }),
+        (key.clone(), any::<usize>(), value).prop_map(|(key, index, value)| Edit::SetElem {
+            key,
+            index,
+            value
+        }),
+        (key.clone(), any::<usize>()).prop_map(|(key, index)| Edit::DelElem { key, index }),
+        (key, any::<usize>(), proptest::option::of(any::<usize>())).prop_map(
+            |(key, index, in_list)| Edit::Cursor {
+                key,
+                index,
+                in_list
+            }
+        ),
+        (0..PEERS).prop_map(|from| Edit::Sync { from }),
+    ]
+}
+
+fn root(backend: &Backend) -> MapDiff {
+    match backend.get_patch().unwrap().diffs {
+        Some(Diff::Map(root)) => root,
+        other => panic!("unexpected root diff {:?}", other),
+    }
+}
+
+/// The ops at `key`, sorted as a change's preds have to be for the change
+/// to have the same hash when it's rebuilt from a document
+fn opids(root: &MapDiff, key: &str) -> Vec<OpId> {
+    let mut opids: Vec<_> = root
+        .props
+        .get(key)
+        .map(|values| values.keys().cloned().collect())
+        .unwrap_or_default();
+    opids.sort();
+    opids
+}
+
+/// An element of a list and the ops which set its value
+type Elem = (OpId, Vec<OpId>);
+
+/// A list at `key`, its ID and its elements
+fn list(root: &MapDiff, key: &str) -> Option<(ObjectId, Vec<Elem>)> {
+    let values = root.props.get(key)?;
+    let mut ids: Vec<_> = values.keys().collect();
+    ids.sort();
+    ids.into_iter().find_map(|id| match &values[id] {
+        Diff::Seq(seq) => {
+            let elems = seq
+                .edits
+                .iter()
+                .map(|edit| match edit {
+                    DiffEdit::Insert {
+                        index,
+                        elem_id: ElementId::Id(elem),
+                    } => {
+                        let mut pred: Vec<_> = seq.props[index].keys().cloned().collect();
+                        pred.sort();
+                        (elem.clone(), pred)
+                    }
+                    other => panic!("unexpected edit {:?}", other),
+                })
+                .collect();
+            Some((seq.object_id.clone(), elems))
+        }
+        _ => None,
+    })
+}
+
+fn op(obj: ObjectId, key: amp::Key, action: amp::OpType, pred: Vec<OpId>) -> amp::Op {
+    amp::Op {
+        action,
+        obj,
+        key,
+        insert: false,
+        pred,
+    }
+}
+
+fn edit_ops(backend: &Backend, actor: &ActorId, start_op: u64, edit: &Edit) -> Vec<amp::Op> {
+    let root = root(backend);
+    match *edit {
+        Edit::Set { key, value } => vec![op(
+            ObjectId::Root,
+            KEYS[key].into(),
+            amp::OpType::Set(amp::ScalarValue::Int(value)),
+            opids(&root, KEYS[key]),
+        )],
+        Edit::SetCounter { key, value } => vec![op(
+            ObjectId::Root,
+            KEYS[key].into(),
+            amp::OpType::Set(amp::ScalarValue::Counter(value)),
+            opids(&root, KEYS[key]),
+        )],
+        Edit::Inc { key, by } => {
+            let mut counters: Vec<_> = root
+                .props
+                .get(KEYS[key])
+                .into_iter()
+                .flatten()
+                .filter(|(_, value)| matches!(value, Diff::Value(amp::ScalarValue::Counter(_))))
+                .map(|(id, _)| id.clone())
+                .collect();
+            if counters.is_empty() {
+                return Vec::new();
+            }
+            counters.sort();
+            vec![op(
+                ObjectId::Root,
+                KEYS[key].into(),
+                amp::OpType::Inc(by),
+                counters,
+            )]
+        }
+        Edit::Del { key } => {
+            let pred = opids(&root, KEYS[key]);
+            if pred.is_empty() {
+                return Vec::new();
+            }
+            vec![op(ObjectId::Root, KEYS[key].into(), amp::OpType::Del, pred)]
+        }
+        Edit::MakeList { key, text } => {
+            let obj_type = if text {
+                amp::ObjType::text()
+            } else {
+                amp::ObjType::list()
+            };
+            vec![op(
+                ObjectId::Root,
+                KEYS[key].into(),
+                amp::OpType::Make(obj_type),
+                opids(&root, KEYS[key]),
+            )]
+        }
+        Edit::Insert {
+            key,
+            index,
+            ref values,
+        } => {
+            let (list, elems) = match list(&root, KEYS[key]) {
+                Some(list) => list,
+                None => return Vec::new(),
+            };
+            let index = index % (elems.len() + 1);
+            let mut after = match index {
+                0 => amp::ElementId::Head,
+                _ => amp::ElementId::Id(elems[index - 1].0.clone()),
+            };
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    let mut insert = op(
+                        list.clone(),
+                        after.clone().into(),
+                        amp::OpType::Set(amp::ScalarValue::Int(*value)),
+                        Vec::new(),
+                    );
+                    insert.insert = true;
+                    after = amp::ElementId::Id(actor.op_id_at(start_op + i as u64));
+                    insert
+                })
+                .collect()
+        }
+        Edit::SetElem { key, index, value } => match list(&root, KEYS[key]) {
+            Some((list, elems)) if !elems.is_empty() => {
+                let (elem, pred) = elems[index % elems.len()].clone();
+                vec![op(
+                    list,
+                    amp::ElementId::Id(elem).into(),
+                    amp::OpType::Set(amp::ScalarValue::Int(value)),
+                    pred,
+                )]
+            }
+            _ => Vec::new(),
+        },
+        Edit::DelElem { key, index } => match list(&root, KEYS[key]) {
+            Some((list, elems)) if !elems.is_empty() => {
+                let (elem, pred) = elems[index % elems.len()].clone();
+                vec![op(
+                    list,
+                    amp::ElementId::Id(elem).into(),
+                    amp::OpType::Del,
+                    pred,
+                )]
+            }
+            _ => Vec::new(),
+        },
+        Edit::Cursor {
+            key,
+            index,
+            in_list,
+        } => match list(&root, KEYS[key]) {
+            Some((list, elems)) if !elems.is_empty() => {
+                let target = amp::ScalarValue::Cursor(elems[index % elems.len()].0.clone());
+                match in_list {
+                    Some(at) => {
+                        let (elem, pred) = elems[at % elems.len()].clone();
+                        vec![op(
+                            list,
+                            amp::ElementId::Id(elem).into(),
+                            amp::OpType::Set(target),
+                            pred,
+                        )]
+                    }
+                    None => vec![op(
+                        ObjectId::Root,
+                        "cursor".into(),
+                        amp::OpType::Set(target),
+                        opids(&root, "cursor"),
+                    )],
+                }
+            }
+            _ => Vec::new(),
+        },
+        Edit::Sync { .. } => Vec::new(),
+    }
+}
+
+fn apply_edit(peers: &mut [Backend], actors: &[ActorId], peer: usize, edit: &Edit) {
+    if let Edit::Sync { from } = *edit {
+        let changes: Vec<Change> = peers[from]
+            .get_changes(&peers[peer].get_heads())
+            .into_iter()
+            .cloned()
+            .collect();
+        peers[peer].apply_changes(changes).unwrap();
+        return;
+    }
+    let backend = &mut peers[peer];
+    let actor = &actors[peer];
+    let start_op = backend.get_patch().unwrap().max_op + 1;
+    let operations = edit_ops(backend, actor, start_op, edit);
+    if operations.is_empty() {
+        return;
+    }
+    let change = amp::UncompressedChange {
+        actor_id: actor.clone(),
+        seq: backend.get_changes_for_actor_id(actor).unwrap().len() as u64 + 1,
+        start_op,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: backend.get_heads(),
+        operations,
+        extra_bytes: Vec::new(),
+    };
+    backend.apply_changes(vec![change.into()]).unwrap();
+}
+
+/// Load `data` with `Backend::load` and by applying its changes, and check
+/// the two backends are the same
+fn load_both_ways(data: Vec<u8>) -> (Backend, Backend) {
+    let fast = Backend::load(data.clone()).unwrap();
+    let mut slow = Backend::init();
+    slow.load_changes(Change::load_document(&data).unwrap())
+        .unwrap();
+    assert_eq!(fast.get_patch().unwrap(), slow.get_patch().unwrap());
+    assert_eq!(fast.get_heads(), slow.get_heads());
+    assert_eq!(fast.get_changes(&[]), slow.get_changes(&[]));
+    assert_eq!(fast.stats(), slow.stats());
+    assert_eq!(fast.save().unwrap(), slow.save().unwrap());
+    (fast, slow)
+}
+
+/// `data`, a document saved without compression, with the seq of its last
+/// change one higher, so that actor's seqs have a gap. The checksum and
+/// heads aren't updated, so the result has to be loaded unverified.
+fn with_seq_gap(data: &[u8]) -> Vec<u8> {
+    const DOC_SEQ: u64 = 3;
+    fn uleb(bytes: &mut &[u8]) -> u64 {
+        leb128::read::unsigned(bytes).unwrap()
+    }
+    fn column_info(bytes: &mut &[u8]) -> Vec<(u64, u64)> {
+        (0..uleb(bytes))
+            .map(|_| (uleb(bytes), uleb(bytes)))
+            .collect()
+    }
+    fn write_column_info(out: &mut Vec<u8>, columns: &[(u64, u64)]) {
+        leb128::write::unsigned(out, columns.len() as u64).unwrap();
+        for (id, len) in columns {
+            leb128::write::unsigned(out, *id).unwrap();
+            leb128::write::unsigned(out, *len).unwrap();
+        }
+    }
+
+    let mut seqs: Vec<u64> = Change::load_document(data)
+        .unwrap()
+        .iter()
+        .map(|c| c.seq)
+        .collect();
+    *seqs.last_mut().unwrap() += 1;
+    // one literal run of the deltas
+    let mut seq_column = Vec::new();
+    leb128::write::signed(&mut seq_column, -(seqs.len() as i64)).unwrap();
+    let mut last = 0;
+    for seq in seqs {
+        leb128::write::signed(&mut seq_column, seq as i64 - last as i64).unwrap();
+        last = seq;
+    }
+
+    // magic bytes, checksum and chunk type, then the body's length
+    let mut body = &data[9..];
+    let len = uleb(&mut body) as usize;
+    let body = &body[..len];
+    let mut rest = body;
+    for _ in 0..uleb(&mut rest) {
+        let len = uleb(&mut rest) as usize;
+        rest = &rest[len..];
+    }
+    let num_heads = uleb(&mut rest) as usize;
+    rest = &rest[num_heads * 32..];
+    let header = &body[..body.len() - rest.len()];
+    let mut change_info = column_info(&mut rest);
+    let ops_start = body.len() - rest.len();
+    column_info(&mut rest);
+    let ops_info = &body[ops_start..body.len() - rest.len()];
+
+    let mut change_data: Vec<u8> = Vec::new();
+    for (id, len) in change_info.iter_mut() {
+        let (column, remaining) = rest.split_at(*len as usize);
+        rest = remaining;
+        if *id == DOC_SEQ {
+            change_data.extend(&seq_column);
+            *len = seq_column.len() as u64;
+        } else {
+            change_data.extend(column);
+        }
+    }
+
+    let mut new_body = header.to_vec();
+    write_column_info(&mut new_body, &change_info);
+    new_body.extend(ops_info);
+    new_body.extend(change_data);
+    new_body.extend(rest);
+    let mut result = data[..9].to_vec();
+    leb128::write::unsigned(&mut result, new_body.len() as u64).unwrap();
+    result.extend(new_body);
+    result
+}
+
+proptest! {
+    #[test]
+    fn test_fast_load_matches_applying_changes(
+        edits in proptest::collection::vec((0..PEERS, arb_edit()), 0..40),
+        after in (0..PEERS, arb_edit()),
+    ) {
+        let actors: Vec<ActorId> = (0..PEERS)
+            .map(|i| ActorId::from_bytes(&[i as u8 + 1; 16]))
+            .collect();
+        let mut peers: Vec<Backend> = (0..PEERS).map(|_| Backend::init()).collect();
+        for (peer, edit) in edits.iter() {
+            apply_edit(&mut peers, &actors, *peer, edit);
+        }
+        for from in 1..PEERS {
+            apply_edit(&mut peers, &actors, 0, &Edit::Sync { from });
+        }
+
+        let (fast, slow) = load_both_ways(peers[0].save().unwrap());
+
+        // a change made after loading has the same effect on both, this
+        // checks the list indexes and cursors
+        let (peer, edit) = after;
+        apply_edit(&mut peers, &actors, peer, &edit);
+        let changes: Vec<Change> = peers[peer]
+            .get_changes(&fast.get_heads())
+            .into_iter()
+            .cloned()
+            .collect();
+        let (mut fast, mut slow) = (fast, slow);
+        let fast_patch = fast.apply_changes(changes.clone()).unwrap();
+        let slow_patch = slow.apply_changes(changes).unwrap();
+        prop_assert_eq!(fast_patch, slow_patch);
+        prop_assert_eq!(fast.get_patch().unwrap(), slow.get_patch().unwrap());
+    }
+
+    #[test]
+    fn test_fast_load_rejects_gaps_in_seqs(
+        edits in proptest::collection::vec((0..PEERS, arb_edit()), 1..20),
+    ) {
+        let actors: Vec<ActorId> = (0..PEERS)
+            .map(|i| ActorId::from_bytes(&[i as u8 + 1; 16]))
+            .collect();
+        let mut peers: Vec<Backend> = (0..PEERS).map(|_| Backend::init()).collect();
+        for (peer, edit) in edits.iter() {
+            apply_edit(&mut peers, &actors, *peer, edit);
+        }
+        for from in 1..PEERS {
+            apply_edit(&mut peers, &actors, 0, &Edit::Sync { from });
+        }
+        prop_assume!(!peers[0].get_heads().is_empty());
+
+        // neither building the op set from the document nor applying its
+        // changes accepts it
+        let data = with_seq_gap(&peers[0].save_with_deflate_threshold(None).unwrap());
+        prop_assert!(Change::load_document_unverified(&data).is_err());
+        prop_assert!(Backend::load_unverified(data).is_err());
+    }
+
+    #[test]
+    fn test_compacting_keeps_the_patch(
+        edits in proptest::collection::vec((0..PEERS, arb_edit()), 0..40),
+        at in 0..PEERS,
+        after in arb_edit(),
+    ) {
+        let actors: Vec<ActorId> = (0..PEERS)
+            .map(|i| ActorId::from_bytes(&[i as u8 + 1; 16]))
+            .collect();
+        let mut peers: Vec<Backend> = (0..PEERS).map(|_| Backend::init()).collect();
+        for (peer, edit) in edits.iter() {
+            apply_edit(&mut peers, &actors, *peer, edit);
+        }
+        for from in 1..PEERS {
+            apply_edit(&mut peers, &actors, 0, &Edit::Sync { from });
+        }
+
+        // compact at what one peer has, so there can be concurrent changes
+        // which aren't compacted
+        let mut uncompacted = peers[0].clone();
+        let mut compacted = peers[0].clone();
+        compacted.compact(&peers[at].get_heads()).unwrap();
+        prop_assert_eq!(compacted.get_patch().unwrap(), uncompacted.get_patch().unwrap());
+        let loaded = Backend::load(compacted.save().unwrap()).unwrap();
+        prop_assert_eq!(loaded.get_patch().unwrap(), uncompacted.get_patch().unwrap());
+        prop_assert_eq!(loaded.save().unwrap(), compacted.save().unwrap());
+
+        // that peer can still make changes
+        apply_edit(&mut peers, &actors, at, &after);
+        let changes: Vec<Change> = peers[at]
+            .get_changes(&[])
+            .into_iter()
+            .cloned()
+            .collect();
+        let patch = uncompacted.apply_changes(changes.clone()).unwrap();
+        prop_assert_eq!(compacted.apply_changes(changes).unwrap(), patch);
+        prop_assert_eq!(compacted.get_patch().unwrap(), uncompacted.get_patch().unwrap());
+    }
+}
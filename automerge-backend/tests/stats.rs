@@ -0,0 +1,102 @@
+extern crate automerge_backend;
+use automerge_backend::{Backend, BackendStats, Change};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ElementId, ObjectId, Op, UncompressedChange};
+use std::convert::TryInto;
+
+#[test]
+fn test_stats_of_an_empty_backend() {
+    let stats = Backend::init().stats();
+    assert_eq!(stats.objects, 1);
+    assert_eq!(stats.ops, 0);
+    assert_eq!(stats.changes, 0);
+    assert_eq!(stats.actors, 0);
+    assert_eq!(stats.tombstones, 0);
+    assert_eq!(stats.heap_bytes.changes, 0);
+}
+
+#[test]
+fn test_stats_count_objects_ops_and_tombstones() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let change1: Change = UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 1,
+        start_op: 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: Vec::new(),
+        operations: vec![
+            Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Make(amp::ObjType::list()),
+                key: "birds".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+            Op {
+                obj: ObjectId::from(actor.op_id_at(1)),
+                action: amp::OpType::Set("wren".into()),
+                key: ElementId::Head.into(),
+                insert: true,
+                pred: Vec::new(),
+            },
+            Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Set("magpie".into()),
+                key: "bird".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+        ],
+        extra_bytes: Vec::new(),
+    }
+    .into();
+    let change2: Change = UncompressedChange {
+        actor_id: actor.clone(),
+        seq: 2,
+        start_op: 4,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: vec![change1.hash],
+        operations: vec![
+            Op {
+                obj: ObjectId::from(actor.op_id_at(1)),
+                action: amp::OpType::Del,
+                key: actor.op_id_at(2).into(),
+                insert: false,
+                pred: vec![actor.op_id_at(2)],
+            },
+            Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Set("crow".into()),
+                key: "bird".into(),
+                insert: false,
+                pred: vec![actor.op_id_at(3)],
+            },
+        ],
+        extra_bytes: Vec::new(),
+    }
+    .into();
+    let change_bytes = change1.bytes.len() + change2.bytes.len();
+
+    let mut backend = Backend::init();
+    backend.apply_changes(vec![change1, change2]).unwrap();
+    let stats: BackendStats = backend.stats();
+
+    assert_eq!(stats.objects, 2);
+    // "birds" and "bird" on the root, the list element has been deleted
+    assert_eq!(stats.ops, 2);
+    assert_eq!(stats.changes, 2);
+    assert_eq!(stats.queued_changes, 0);
+    assert_eq!(stats.actors, 1);
+    assert_eq!(stats.tombstones, 1);
+    assert!(stats.heap_bytes.changes >= change_bytes);
+    assert!(stats.heap_bytes.op_set > 0);
+    assert!(stats.heap_bytes.history > 0);
+    assert_eq!(
+        stats.heap_bytes.total(),
+        stats.heap_bytes.op_set + stats.heap_bytes.history + stats.heap_bytes.changes
+    );
+}
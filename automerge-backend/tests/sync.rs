@@ -0,0 +1,126 @@
+extern crate automerge_backend;
+use automerge_backend::{Backend, SyncMessage, SyncState};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ObjectId, Op, UncompressedChange};
+use std::convert::TryInto;
+
+fn set_key(backend: &mut Backend, actor: &ActorId, seq: u64, key: &str, value: i64) {
+    let change = UncompressedChange {
+        actor_id: actor.clone(),
+        seq,
+        start_op: backend
+            .get_changes(&[])
+            .iter()
+            .map(|c| c.max_op())
+            .max()
+            .unwrap_or(0)
+            + 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: backend.get_heads(),
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Set(amp::ScalarValue::Int(value)),
+            key: key.into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    };
+    backend.apply_local_change(change).unwrap();
+}
+
+/// Pass messages back and forth, through the encoded form, until neither
+/// side has anything to send. Returns the number of messages sent.
+fn sync(
+    a: &mut Backend,
+    b: &mut Backend,
+    a_state: &mut SyncState,
+    b_state: &mut SyncState,
+) -> usize {
+    let mut messages = 0;
+    for _ in 0..10 {
+        let a_to_b = a.generate_sync_message(a_state);
+        let b_to_a = b.generate_sync_message(b_state);
+        if a_to_b.is_none() && b_to_a.is_none() {
+            return messages;
+        }
+        if let Some(msg) = a_to_b {
+            let msg = SyncMessage::decode(&msg.encode()).unwrap();
+            b.receive_sync_message(b_state, msg).unwrap();
+            messages += 1;
+        }
+        if let Some(msg) = b_to_a {
+            let msg = SyncMessage::decode(&msg.encode()).unwrap();
+            a.receive_sync_message(a_state, msg).unwrap();
+            messages += 1;
+        }
+    }
+    panic!("peers did not converge");
+}
+
+fn sorted_heads(backend: &Backend) -> Vec<amp::ChangeHash> {
+    let mut heads = backend.get_heads();
+    heads.sort();
+    heads
+}
+
+#[test]
+fn empty_documents_exchange_one_message_each() {
+    let mut a = Backend::init();
+    let mut b = Backend::init();
+    let messages = sync(&mut a, &mut b, &mut SyncState::new(), &mut SyncState::new());
+    assert_eq!(messages, 2);
+}
+
+#[test]
+fn peers_with_divergent_changes_converge() {
+    let actor_a: ActorId = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".try_into().unwrap();
+    let actor_b: ActorId = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb".try_into().unwrap();
+    let mut a = Backend::init();
+    let mut b = Backend::init();
+    for seq in 1..=10 {
+        set_key(&mut a, &actor_a, seq, "a", seq as i64);
+    }
+    for seq in 1..=5 {
+        set_key(&mut b, &actor_b, seq, "b", seq as i64);
+    }
+
+    let mut a_state = SyncState::new();
+    let mut b_state = SyncState::new();
+    sync(&mut a, &mut b, &mut a_state, &mut b_state);
+
+    assert_eq!(sorted_heads(&a), sorted_heads(&b));
+    assert_eq!(a.get_changes(&[]).len(), 15);
+    assert_eq!(b.get_changes(&[]).len(), 15);
+    assert_eq!(a_state.shared_heads, sorted_heads(&a));
+
+    // Syncing again after a new change only sends that change
+    set_key(&mut a, &actor_a, 11, "a", 11);
+    let message = a.generate_sync_message(&mut a_state).unwrap();
+    assert_eq!(message.changes.len(), 1);
+    b.receive_sync_message(&mut b_state, message).unwrap();
+    sync(&mut a, &mut b, &mut a_state, &mut b_state);
+    assert_eq!(sorted_heads(&a), sorted_heads(&b));
+}
+
+#[test]
+fn sync_resumes_from_a_saved_sync_state() {
+    let actor: ActorId = "cccccccccccccccccccccccccccccccc".try_into().unwrap();
+    let mut a = Backend::init();
+    let mut b = Backend::init();
+    for seq in 1..=5 {
+        set_key(&mut a, &actor, seq, "x", seq as i64);
+    }
+    let mut a_state = SyncState::new();
+    let mut b_state = SyncState::new();
+    sync(&mut a, &mut b, &mut a_state, &mut b_state);
+
+    set_key(&mut a, &actor, 6, "x", 6);
+    let mut a_state = SyncState::decode(&a_state.encode()).unwrap();
+    let mut b_state = SyncState::decode(&b_state.encode()).unwrap();
+    sync(&mut a, &mut b, &mut a_state, &mut b_state);
+    assert_eq!(sorted_heads(&a), sorted_heads(&b));
+    assert_eq!(b.get_changes(&[]).len(), 6);
+}
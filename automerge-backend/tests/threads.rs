@@ -0,0 +1,77 @@
+extern crate automerge_backend;
+use automerge_backend::Backend;
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ObjectId, Op, UncompressedChange};
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::thread;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_backend_is_send_and_sync() {
+    assert_send_sync::<Backend>();
+}
+
+#[test]
+fn test_snapshots_can_be_read_from_other_threads() {
+    let actor: ActorId = "7b7723afd9e6480397a4d467b7693156".try_into().unwrap();
+    let mut backend = Backend::init();
+    backend
+        .apply_local_change(UncompressedChange {
+            actor_id: actor.clone(),
+            seq: 1,
+            start_op: 1,
+            time: 0,
+            message: None,
+            hash: None,
+            deps: Vec::new(),
+            operations: vec![Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Set("magpie".into()),
+                key: "bird".into(),
+                insert: false,
+                pred: Vec::new(),
+            }],
+            extra_bytes: Vec::new(),
+        })
+        .unwrap();
+
+    let snapshot = Arc::new(backend.clone());
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let snapshot = snapshot.clone();
+            thread::spawn(move || snapshot.get_patch().unwrap())
+        })
+        .collect();
+
+    // Changing the original doesn't affect the snapshot
+    backend
+        .apply_local_change(UncompressedChange {
+            actor_id: actor.clone(),
+            seq: 2,
+            start_op: 2,
+            time: 0,
+            message: None,
+            hash: None,
+            deps: Vec::new(),
+            operations: vec![Op {
+                obj: ObjectId::Root,
+                action: amp::OpType::Set("crow".into()),
+                key: "bird".into(),
+                insert: false,
+                pred: vec![actor.op_id_at(1)],
+            }],
+            extra_bytes: Vec::new(),
+        })
+        .unwrap();
+
+    for reader in readers {
+        assert_eq!(reader.join().unwrap(), snapshot.get_patch().unwrap());
+    }
+    assert_ne!(backend.get_patch().unwrap(), snapshot.get_patch().unwrap());
+
+    // A backend can also be moved to another thread and changed there
+    let moved = thread::spawn(move || backend.get_heads()).join().unwrap();
+    assert_eq!(moved.len(), 1);
+}
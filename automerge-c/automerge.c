@@ -2,10 +2,48 @@
 #include <stdio.h>
 #include <string.h>
 #include <assert.h>
+#include <pthread.h>
 #include "automerge.h"
 
 #define BUFSIZE 4096
 
+// Each thread uses its own backend and owns every result it gets back
+void * make_changes(void * arg) {
+  const char * request = arg;
+  Backend * db = automerge_init();
+  AMresult * result = am_apply_local_change(db, request);
+  assert(am_result_error(result) == AMerror_Ok);
+  assert(am_result_binary_count(result) == 1);
+  am_result_free(result);
+  result = am_save(db);
+  assert(am_result_error(result) == AMerror_Ok);
+  automerge_free(db);
+  return result;
+}
+
+// Counts the changes applied to a backend
+void count_changes(void * user_data, const uint8_t * change, uintptr_t len) {
+  assert(change != NULL && len > 0);
+  (*(int *) user_data)++;
+}
+
+// Generate a message on one side and deliver it to the other, returns 0 when
+// there was nothing to send
+int send_sync_message(Backend * from, AMsyncState * from_state, Backend * to, AMsyncState * to_state) {
+  AMresult * message = am_generate_sync_message(from, from_state);
+  assert(am_result_error(message) == AMerror_Ok);
+  int sent = am_result_binary_count(message);
+  if (sent) {
+    uintptr_t len;
+    const uint8_t * bytes = am_result_binary(message, 0, &len);
+    AMresult * received = am_receive_sync_message(to, to_state, bytes, len);
+    assert(am_result_error(received) == AMerror_Ok);
+    am_result_free(received);
+  }
+  am_result_free(message);
+  return sent;
+}
+
 int main() {
   int len;
 
@@ -165,12 +203,196 @@ int main() {
   automerge_read_json(dbE, buff); // [] - nothing missing
   assert(strlen(buff) == 2);
 
+  printf("*** edit a document without building changes as json ***\n\n");
+  Document * docA = automerge_doc_new();
+  Backend * docA_backend = automerge_doc_backend(docA);
+  automerge_doc_set_str(docA, "/bird", "magpie");
+  automerge_doc_set_list(docA, "/birds");
+  automerge_doc_insert_str(docA, "/birds/0", "wren");
+  automerge_doc_insert_str(docA, "/birds/1", "robin");
+  automerge_doc_set_counter(docA, "/sightings", 1);
+  len = automerge_doc_commit(docA, "add some birds");
+  assert(len > 0);
+  assert(len <= BUFSIZE);
+  automerge_read_binary(docA_backend, buff);
+
+  automerge_doc_delete(docA, "/birds/0");
+  automerge_doc_increment(docA, "/sightings", 2);
+  len = automerge_doc_commit(docA, NULL);
+  assert(len > 0);
+  automerge_read_binary(docA_backend, buff);
+
+  len = automerge_doc_get(docA, "/birds");
+  assert(len <= BUFSIZE);
+  automerge_read_json(docA_backend, buff);
+  printf("*** docA birds *** %s\n\n", buff);
+  assert(strcmp(buff, "[\"robin\"]") == 0);
+
+  len = automerge_doc_get(docA, "/sightings");
+  automerge_read_json(docA_backend, buff);
+  assert(strcmp(buff, "3") == 0);
+
+  automerge_doc_increment(docA, "/bird", 1);
+  len = automerge_doc_commit(docA, NULL);
+  assert(len == -1);
+  printf("*** increment a string expected error string ** (%s)\n\n", automerge_error(docA_backend));
+
+  len = automerge_doc_get(docA, "/cat");
+  assert(len == -1);
+
+  printf("*** copy changes from docA to docB ***\n\n");
+  Document * docB = automerge_doc_new();
+  Backend * docB_backend = automerge_doc_backend(docB);
+  len = automerge_get_changes(docA_backend, 0, NULL);
+  while (len > 0) {
+    assert(len <= BUFSIZE);
+    int nextlen = automerge_read_binary(docA_backend, buff);
+    automerge_write_change(docB_backend, len, buff);
+    len = nextlen;
+  }
+  assert(automerge_doc_apply_changes(docB) == 0);
+  len = automerge_doc_get(docB, "/bird");
+  automerge_read_json(docB_backend, buff);
+  assert(strcmp(buff, "\"magpie\"") == 0);
+
+  printf("*** a change the backend refuses is undone in the document ***\n\n");
+  // apply a change with docA's actor and next seq behind the document's back
+  len = automerge_get_last_local_change(docA_backend);
+  assert(len > 0 && len <= BUFSIZE);
+  automerge_read_binary(docA_backend, buff);
+  automerge_decode_change(docA_backend, len, buff);
+  automerge_read_json(docA_backend, buff2);
+  const char * actor = strstr(buff2, "\"actor\":\"") + 9;
+  snprintf(buff3, BUFSIZE, "{\"actor\":\"%.*s\",\"seq\":3,\"time\":0,\"deps\":[],\"startOp\":100,\"ops\":[]}",
+           (int) (strchr(actor, '"') - actor), actor);
+  len = automerge_apply_local_change(docA_backend, buff3);
+  assert(len > 0 && len <= BUFSIZE);
+  automerge_read_json(docA_backend, buff);
+  automerge_doc_set_str(docA, "/bird", "crow");
+  assert(automerge_doc_commit(docA, NULL) == -1);
+  printf("*** duplicate seq expected error string ** (%s)\n\n", automerge_error(docA_backend));
+  automerge_doc_get(docA, "/bird");
+  automerge_read_json(docA_backend, buff);
+  assert(strcmp(buff, "\"magpie\"") == 0);
+
+  printf("*** owned results ***\n\n");
+  AMresult * result = am_apply_local_change(dbA, "{}");
+  assert(am_result_error(result) == AMerror_InvalidArgument);
+  printf("*** am_apply_local_change expected error string ** (%s)\n\n", am_result_error_message(result));
+  am_result_free(result);
+
+  result = am_get_patch(dbA);
+  AMresult * heads = am_get_heads(dbA);
+  assert(am_result_error(result) == AMerror_Ok);
+  assert(am_result_json(result) != NULL);
+  assert(am_result_error_message(result) == NULL);
+  assert(am_result_binary_count(heads) == 2);
+  // both results stay valid until they are freed
+  printf("*** am_get_patch *** %s\n\n", am_result_json(result));
+  am_result_free(result);
+
+  uintptr_t head_len;
+  const uint8_t * head = am_result_binary(heads, 0, &head_len);
+  assert(head_len == 32);
+  memcpy(buff, head, 32);
+  head = am_result_binary(heads, 1, &head_len);
+  memcpy(buff + 32, head, 32);
+  assert(am_result_binary(heads, 2, &head_len) == NULL && head_len == 0);
+  am_result_free(heads);
+  result = am_get_changes(dbA, buff, 2);
+  assert(am_result_binary_count(result) == 0);
+  am_result_free(result);
+
+  result = am_decode_change((const uint8_t *) "garbage", 7);
+  assert(am_result_error(result) == AMerror_Decoding);
+  am_result_free(result);
+
+  pthread_t threads[2];
+  pthread_create(&threads[0], NULL, make_changes, (void *) requestA1);
+  pthread_create(&threads[1], NULL, make_changes, (void *) requestB1);
+  Backend * dbF = automerge_init();
+  for (int i = 0; i < 2; i++) {
+    AMresult * saved;
+    pthread_join(threads[i], (void **) &saved);
+    uintptr_t saved_len;
+    const uint8_t * saved_bytes = am_result_binary(saved, 0, &saved_len);
+    result = am_apply_changes(dbF, saved_bytes, saved_len);
+    assert(am_result_error(result) == AMerror_Ok);
+    am_result_free(result);
+    am_result_free(saved);
+  }
+  result = am_get_changes(dbF, NULL, 0);
+  assert(am_result_binary_count(result) == 2);
+  am_result_free(result);
+
+  Backend * dbG = NULL;
+  result = am_save(dbF);
+  uintptr_t saved_len;
+  const uint8_t * saved_bytes = am_result_binary(result, 0, &saved_len);
+  AMresult * loaded = am_load(saved_bytes, saved_len, &dbG);
+  assert(am_result_error(loaded) == AMerror_Ok);
+  assert(dbG != NULL);
+  am_result_free(loaded);
+  am_result_free(result);
+
+  printf("*** sync ***\n\n");
+  Backend * dbH = automerge_init();
+  int applied = 0;
+  am_set_change_callback(dbH, count_changes, &applied);
+  AMsyncState * stateF = am_sync_state_init();
+  AMsyncState * stateH = am_sync_state_init();
+  int rounds = 0;
+  while (send_sync_message(dbF, stateF, dbH, stateH) + send_sync_message(dbH, stateH, dbF, stateF) > 0) {
+    assert(++rounds < 10);
+  }
+  assert(applied == 2);
+  result = am_get_changes(dbH, NULL, 0);
+  assert(am_result_binary_count(result) == 2);
+  am_result_free(result);
+
+  result = am_encode_sync_state(stateH);
+  AMsyncState * restored = NULL;
+  const uint8_t * state_bytes = am_result_binary(result, 0, &saved_len);
+  loaded = am_decode_sync_state(state_bytes, saved_len, &restored);
+  assert(am_result_error(loaded) == AMerror_Ok);
+  am_result_free(loaded);
+  am_result_free(result);
+
+  result = am_generate_sync_message(dbF, stateF);
+  assert(am_result_binary_count(result) == 0);
+  am_result_free(result);
+  am_set_change_callback(dbH, NULL, NULL);
+
+  AMsyncState * fresh = am_sync_state_init();
+  result = am_generate_sync_message(dbF, fresh);
+  const uint8_t * message_bytes = am_result_binary(result, 0, &saved_len);
+  AMresult * decoded = am_decode_sync_message(message_bytes, saved_len);
+  assert(am_result_error(decoded) == AMerror_Ok);
+  printf("*** am_decode_sync_message *** %s\n\n", am_result_json(decoded));
+  AMresult * encoded = am_encode_sync_message(am_result_json(decoded));
+  uintptr_t encoded_len;
+  const uint8_t * encoded_bytes = am_result_binary(encoded, 0, &encoded_len);
+  assert(encoded_len == saved_len && memcmp(encoded_bytes, message_bytes, saved_len) == 0);
+  am_result_free(encoded);
+  am_result_free(decoded);
+  am_result_free(result);
+
+  am_sync_state_free(stateF);
+  am_sync_state_free(stateH);
+  am_sync_state_free(restored);
+  am_sync_state_free(fresh);
+
   printf("free resources\n");
   automerge_free(dbA);
   automerge_free(dbB);
   automerge_free(dbC);
   automerge_free(dbD);
   automerge_free(dbE);
+  automerge_doc_free(docA);
+  automerge_doc_free(docB);
+  automerge_free(dbF);
+  automerge_free(dbG);
+  automerge_free(dbH);
 
   printf("end\n");
 }
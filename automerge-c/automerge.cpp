@@ -0,0 +1,58 @@
+#include <cassert>
+#include <iostream>
+
+#include "automerge.hpp"
+
+int main() {
+  automerge::Backend dbA;
+  automerge::Backend dbB;
+
+  auto [patchA, changeA] = dbA.apply_local_change(
+      "{\"actor\":\"111111\",\"seq\":1,\"time\":0,\"deps\":[],\"startOp\":1,\"ops\":[{\"action\":\"set\",\"obj\":"
+      "\"_root\",\"key\":\"bird\",\"value\":\"magpie\",\"pred\":[]}]}");
+  std::cout << "*** patchA ***\n\n" << patchA << "\n\n";
+  std::cout << "*** changeA decoded ***\n\n" << changeA.decode() << "\n\n";
+  assert(automerge::Change::encode(changeA.decode()).decode() == changeA.decode());
+
+  dbB.apply_local_change(
+      "{\"actor\":\"222222\",\"seq\":1,\"time\":0,\"deps\":[],\"startOp\":1,\"ops\":[{\"action\":\"set\",\"obj\":"
+      "\"_root\",\"key\":\"cat\",\"value\":\"tabby\",\"pred\":[]}]}");
+
+  try {
+    dbA.apply_local_change("{}");
+    assert(false);
+  } catch (const automerge::Error &e) {
+    assert(e.code() == AMerror_InvalidArgument);
+    std::cout << "*** expected error *** " << e.what() << "\n\n";
+  }
+
+  auto changes = dbB.get_changes();
+  assert(changes.size() == 1);
+  dbA.apply_changes(changes);
+  assert(dbA.get_heads().size() == 2);
+  assert(dbA.get_changes(dbA.get_heads()).empty());
+
+  automerge::Backend dbC = dbA;
+  auto saved = dbA.save();
+  auto dbD = automerge::Backend::load(saved);
+  assert(dbD.get_patch().size() == dbC.get_patch().size());
+  assert(dbD.get_changes_for_actor("222222").size() == 1);
+
+  automerge::Backend dbE;
+  automerge::SyncState stateA, stateE;
+  for (int round = 0;; round++) {
+    assert(round < 10);
+    auto toE = dbA.generate_sync_message(stateA);
+    auto toA = dbE.generate_sync_message(stateE);
+    if (!toE && !toA) {
+      break;
+    }
+    if (toE) dbE.receive_sync_message(stateE, *toE);
+    if (toA) dbA.receive_sync_message(stateA, *toA);
+  }
+  assert(dbE.get_heads().size() == 2);
+  auto restored = automerge::SyncState::decode(stateE.encode());
+  assert(restored.encode() == stateE.encode());
+
+  std::cout << "end\n";
+}
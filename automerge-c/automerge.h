@@ -6,8 +6,353 @@
 #include <stdint.h>
 #include <stdbool.h>
 
+/**
+ * The kind of error an `AMresult` holds
+ */
+typedef enum {
+  /**
+   * The call succeeded
+   */
+  AMerror_Ok = 0,
+  /**
+   * An argument was null, was not valid UTF-8, or could not be parsed
+   */
+  AMerror_InvalidArgument,
+  /**
+   * A change could not be decoded, or a document could not be loaded
+   */
+  AMerror_Decoding,
+  /**
+   * A change was not valid, e.g. it had the wrong sequence number
+   */
+  AMerror_InvalidChange,
+  /**
+   * A change with the same actor and sequence number but different
+   * contents has already been applied
+   */
+  AMerror_DuplicateChange,
+  /**
+   * A change referred to an object or element which does not exist
+   */
+  AMerror_MissingObject,
+  /**
+   * The peer is missing history which has been compacted, send it the
+   * whole document instead
+   */
+  AMerror_NeedsFullResync,
+  /**
+   * Anything else, these indicate a bug in automerge
+   */
+  AMerror_Internal,
+} AMerror;
+
+/**
+ * The result of an `am_*` call. Depending on the call a successful result
+ * holds some JSON, some binaries or nothing. A failed result holds an error
+ * code and message.
+ *
+ * Every `am_*` function which returns an `AMresult *` transfers ownership of
+ * the result to the caller, who must free it with `am_result_free`. Pointers
+ * obtained from a result (error messages, JSON and binaries) are owned by the
+ * result and are valid until it is freed.
+ *
+ * Nothing is stored in the backend, so there is no need to read a result
+ * back before making the next call. A backend can be used from any thread,
+ * but not from more than one thread at a time. Results do not refer to the
+ * backend they came from and can be read and freed on any thread.
+ */
+typedef struct AMresult AMresult;
+
+/**
+ * The state of a sync with one peer, created with `am_sync_state_init` or
+ * `am_decode_sync_state` and freed with `am_sync_state_free`. Use a separate
+ * sync state for each peer.
+ */
+typedef struct AMsyncState AMsyncState;
+
 typedef struct Backend Backend;
 
+/**
+ * A document which can be edited directly, without building changes as JSON.
+ *
+ * Edits are queued up and applied as a single change by
+ * `automerge_doc_commit`. Results and errors are stored in the document's
+ * backend, so they are read with `automerge_read_json`,
+ * `automerge_read_binary` and `automerge_error` on the pointer returned by
+ * `automerge_doc_backend`.
+ */
+typedef struct Document Document;
+
+/**
+ * Called with `user_data` and each change applied to a backend, see
+ * `am_set_change_callback`. The change is only valid for the duration of the
+ * call.
+ */
+typedef void (*AMchangeCallback)(void*, const uint8_t*, uintptr_t);
+
+/**
+ * Apply one or more encoded changes, concatenated into `data`. On success the
+ * result holds the patch as JSON.
+ *
+ * # Safety
+ * backend must be a valid backend pointer and data a valid pointer to len
+ * bytes
+ */
+AMresult *am_apply_changes(Backend *backend, const uint8_t *data, uintptr_t len);
+
+/**
+ * Apply a local change, given as JSON. On success the result holds the
+ * patch as JSON and the encoded change as its only binary.
+ *
+ * # Safety
+ * backend must be a valid backend pointer and request a valid pointer to a
+ * cstring
+ */
+AMresult *am_apply_local_change(Backend *backend, const char *request);
+
+/**
+ * The result holds the change encoded in `data` as JSON
+ *
+ * # Safety
+ * data must be a valid pointer to len bytes
+ */
+AMresult *am_decode_change(const uint8_t *data, uintptr_t len);
+
+/**
+ * The result holds the sync message encoded in `data` as JSON
+ *
+ * # Safety
+ * data must be a valid pointer to len bytes
+ */
+AMresult *am_decode_sync_message(const uint8_t *data, uintptr_t len);
+
+/**
+ * Decode a sync state encoded with `am_encode_sync_state`. On success the new
+ * sync state is written to `state`, it is owned by the caller and must be
+ * freed with `am_sync_state_free`.
+ *
+ * # Safety
+ * data must be a valid pointer to len bytes and state a valid pointer to
+ * write a sync state pointer to
+ */
+AMresult *am_decode_sync_state(const uint8_t *data, uintptr_t len, AMsyncState **state);
+
+/**
+ * The result holds the encoded form of the JSON `change` as its only binary
+ *
+ * # Safety
+ * change must be a valid pointer to a cstring
+ */
+AMresult *am_encode_change(const char *change);
+
+/**
+ * The result holds the encoded form of the JSON sync `message`, in the form
+ * returned by `am_decode_sync_message`, as its only binary
+ *
+ * # Safety
+ * message must be a valid pointer to a cstring
+ */
+AMresult *am_encode_sync_message(const char *message);
+
+/**
+ * The result holds the part of `state` worth keeping between connections as
+ * its only binary, load it again with `am_decode_sync_state`
+ *
+ * # Safety
+ * state must be a valid sync state pointer
+ */
+AMresult *am_encode_sync_state(AMsyncState *state);
+
+/**
+ * The result holds the next message to send to the peer described by
+ * `state` as its only binary, or no binaries if there is nothing to send.
+ * Updates `state`.
+ *
+ * # Safety
+ * backend must be a valid backend pointer and state a valid sync state
+ * pointer
+ */
+AMresult *am_generate_sync_message(Backend *backend, AMsyncState *state);
+
+/**
+ * The result holds each encoded change which is not an ancestor of the
+ * `count` heads in `heads`. Each head is 32 bytes.
+ *
+ * # Safety
+ * backend must be a valid backend pointer and heads a valid pointer to
+ * count * 32 bytes
+ */
+AMresult *am_get_changes(Backend *backend, const uint8_t *heads, uintptr_t count);
+
+/**
+ * The result holds each encoded change made by `actor`
+ *
+ * # Safety
+ * backend must be a valid backend pointer and actor a valid pointer to a
+ * cstring
+ */
+AMresult *am_get_changes_for_actor(Backend *backend, const char *actor);
+
+/**
+ * The result holds each encoded change made by `am_apply_local_change`, for
+ * the actor of the most recent one, which comes after `seq`
+ *
+ * # Safety
+ * backend must be a valid backend pointer
+ */
+AMresult *am_get_changes_since_local(Backend *backend, uint64_t seq);
+
+/**
+ * The result holds each head of the document as a 32 byte binary
+ *
+ * # Safety
+ * backend must be a valid backend pointer
+ */
+AMresult *am_get_heads(Backend *backend);
+
+/**
+ * The result holds the hashes of missing dependencies as JSON
+ *
+ * # Safety
+ * backend must be a valid backend pointer
+ */
+AMresult *am_get_missing_deps(Backend *backend);
+
+/**
+ * The result holds the patch for the whole document as JSON
+ *
+ * # Safety
+ * backend must be a valid backend pointer
+ */
+AMresult *am_get_patch(Backend *backend);
+
+/**
+ * Load a document saved with `am_save` or `automerge_save`. On success the new
+ * backend is written to `backend`, it is owned by the caller and must be
+ * freed with `automerge_free`.
+ *
+ * # Safety
+ * data must be a valid pointer to len bytes and backend a valid pointer to
+ * write a backend pointer to
+ */
+AMresult *am_load(const uint8_t *data, uintptr_t len, Backend **backend);
+
+/**
+ * Load one or more encoded changes, concatenated into `data`, without
+ * generating a patch
+ *
+ * # Safety
+ * backend must be a valid backend pointer and data a valid pointer to len
+ * bytes
+ */
+AMresult *am_load_changes(Backend *backend, const uint8_t *data, uintptr_t len);
+
+/**
+ * Apply a message received from the peer described by `state` and update
+ * `state`. If the message contained changes the result holds the patch as
+ * JSON, otherwise it holds no JSON.
+ *
+ * # Safety
+ * backend must be a valid backend pointer, state a valid sync state pointer
+ * and data a valid pointer to len bytes
+ */
+AMresult *am_receive_sync_message(Backend *backend, AMsyncState *state, const uint8_t *data, uintptr_t len);
+
+/**
+ * The binary at `index` in `result`, its length is written to `len`. Returns
+ * null, and writes 0 to `len`, if there is no binary at `index`. The binary is
+ * owned by `result`.
+ *
+ * # Safety
+ * result must be null or a valid result pointer, len must be a valid pointer
+ */
+const uint8_t *am_result_binary(const AMresult *result, uintptr_t index, uintptr_t *len);
+
+/**
+ * The number of binaries held by `result`
+ *
+ * # Safety
+ * result must be null or a valid result pointer
+ */
+uintptr_t am_result_binary_count(const AMresult *result);
+
+/**
+ * `AMerror::Ok` if the call which returned `result` succeeded. A null result
+ * is reported as `AMerror::InvalidArgument`.
+ *
+ * # Safety
+ * result must be null or a valid result pointer
+ */
+AMerror am_result_error(const AMresult *result);
+
+/**
+ * A description of the error, or null if there was no error. The string is
+ * owned by `result`.
+ *
+ * # Safety
+ * result must be null or a valid result pointer
+ */
+const char *am_result_error_message(const AMresult *result);
+
+/**
+ * Free a result returned by one of the `am_*` functions. Freeing null does
+ * nothing.
+ *
+ * # Safety
+ * result must be null or a valid result pointer which has not already been
+ * freed
+ */
+void am_result_free(AMresult *result);
+
+/**
+ * The null terminated JSON held by `result`, or null if it holds no JSON. The
+ * string is owned by `result`.
+ *
+ * # Safety
+ * result must be null or a valid result pointer
+ */
+const char *am_result_json(const AMresult *result);
+
+/**
+ * The result holds the saved document as its only binary. If the backend was
+ * loaded from a document with compacted history it starts with a snapshot,
+ * which only this library can load, not the JavaScript implementation.
+ *
+ * # Safety
+ * backend must be a valid backend pointer
+ */
+AMresult *am_save(Backend *backend);
+
+/**
+ * Call `callback` with `user_data` and each change applied to `backend`,
+ * whether it was made locally, received from a peer or loaded. Changes are
+ * passed in the order they are applied, which is not necessarily the order
+ * they were received in as changes wait for their dependencies. Passing a
+ * null callback stops the calls. Cloning a backend does not copy its
+ * callback.
+ *
+ * The callback must not call any functions on `backend`.
+ *
+ * # Safety
+ * backend must be a valid backend pointer, and user_data must be valid for
+ * as long as the callback is set
+ */
+void am_set_change_callback(Backend *backend, AMchangeCallback callback, void *user_data);
+
+/**
+ * Free a sync state. Freeing null does nothing.
+ *
+ * # Safety
+ * state must be null or a valid sync state pointer which has not already
+ * been freed
+ */
+void am_sync_state_free(AMsyncState *state);
+
+/**
+ * A new sync state for a peer we have never synced with
+ */
+AMsyncState *am_sync_state_init(void);
+
 /**
  * # Safety
  * This must me called with a valid backend pointer
@@ -33,6 +378,197 @@ Backend *automerge_clone(Backend *backend);
  */
 intptr_t automerge_decode_change(Backend *backend, uintptr_t len, const uint8_t *change);
 
+/**
+ * Apply the changes queued with `automerge_write_change` on the document's
+ * backend
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ */
+intptr_t automerge_doc_apply_changes(Document *doc);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer. The returned backend is
+ * owned by the document, it must not be freed and is only valid for as long
+ * as the document is. Use `automerge_doc_apply_changes` rather than
+ * `automerge_apply_changes` so the document sees the changes.
+ */
+Backend *automerge_doc_backend(Document *doc);
+
+/**
+ * Apply every pending edit as a single change. Returns the length of the
+ * encoded change, which can be read with `automerge_read_binary`, or 0 if
+ * there were no edits. If any edit fails nothing is applied, all of the
+ * pending edits are discarded and -1 is returned.
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * message must be null or a valid pointer to a cstring
+ */
+intptr_t automerge_doc_commit(Document *doc, const char *message);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_delete(Document *doc, const char *path);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ */
+void automerge_doc_free(Document *doc);
+
+/**
+ * Look up the value at `path`. The value is returned as JSON, the return
+ * value is the size of buffer needed to read it with `automerge_read_json`.
+ * Edits which have not been committed are not visible.
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_get(Document *doc, const char *path);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_increment(Document *doc, const char *path, int64_t by);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_insert_bool(Document *doc, const char *path, bool value);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_insert_f64(Document *doc, const char *path, double value);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_insert_int(Document *doc, const char *path, int64_t value);
+
+/**
+ * Insert an empty list at `path`
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_insert_list(Document *doc, const char *path);
+
+/**
+ * Insert an empty map at `path`
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_insert_map(Document *doc, const char *path);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_insert_null(Document *doc, const char *path);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path and value must be valid pointers to cstrings
+ */
+intptr_t automerge_doc_insert_str(Document *doc, const char *path, const char *value);
+
+/**
+ * # Safety
+ * data pointer must be a valid pointer to len bytes
+ */
+Document *automerge_doc_load(uintptr_t len, const uint8_t *data);
+
+Document *automerge_doc_new(void);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_bool(Document *doc, const char *path, bool value);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_counter(Document *doc, const char *path, int64_t value);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_f64(Document *doc, const char *path, double value);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_int(Document *doc, const char *path, int64_t value);
+
+/**
+ * Set the value at `path` to an empty list
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_list(Document *doc, const char *path);
+
+/**
+ * Set the value at `path` to an empty map
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_map(Document *doc, const char *path);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path must be a valid pointer to a cstring
+ */
+intptr_t automerge_doc_set_null(Document *doc, const char *path);
+
+/**
+ * # Safety
+ * This must me called with a valid document pointer
+ * path and value must be valid pointers to cstrings
+ */
+intptr_t automerge_doc_set_str(Document *doc, const char *path, const char *value);
+
+/**
+ * Set the value at `path` to a text object containing `value`
+ *
+ * # Safety
+ * This must me called with a valid document pointer
+ * path and value must be valid pointers to cstrings
+ */
+intptr_t automerge_doc_set_text(Document *doc, const char *path, const char *value);
+
 /**
  * # Safety
  * This must me called with a valid pointer a json string of a change
@@ -0,0 +1,262 @@
+#ifndef automerge_hpp
+#define automerge_hpp
+
+/* A header-only C++20 wrapper around the functions in automerge.h. This file
+ * is maintained by hand, when functions are added to or changed in the C API
+ * (automerge.h is regenerated by cbindgen on every build) update it to match. */
+
+#include <algorithm>
+#include <array>
+#include <cstdint>
+#include <memory>
+#include <optional>
+#include <span>
+#include <stdexcept>
+#include <string>
+#include <utility>
+#include <vector>
+
+extern "C" {
+#include "automerge.h"
+}
+
+namespace automerge {
+
+/* Thrown when a call fails, code() says what kind of failure it was */
+class Error : public std::runtime_error {
+ public:
+  Error(AMerror code, const char *message)
+      : std::runtime_error(message ? message : "unknown automerge error"), code_(code) {}
+
+  AMerror code() const noexcept { return code_; }
+
+ private:
+  AMerror code_;
+};
+
+using ChangeHash = std::array<uint8_t, 32>;
+
+namespace detail {
+
+struct ResultDeleter {
+  void operator()(AMresult *result) const noexcept { am_result_free(result); }
+};
+
+using Result = std::unique_ptr<AMresult, ResultDeleter>;
+
+inline Result check(AMresult *raw) {
+  Result result(raw);
+  AMerror code = am_result_error(result.get());
+  if (code != AMerror_Ok) {
+    throw Error(code, am_result_error_message(result.get()));
+  }
+  return result;
+}
+
+inline std::string json(const Result &result) {
+  const char *json = am_result_json(result.get());
+  return json ? std::string(json) : std::string();
+}
+
+inline std::vector<uint8_t> binary(const Result &result, uintptr_t index) {
+  uintptr_t len = 0;
+  const uint8_t *data = am_result_binary(result.get(), index, &len);
+  return std::vector<uint8_t>(data, data + len);
+}
+
+}  // namespace detail
+
+/* An encoded change */
+class Change {
+ public:
+  explicit Change(std::vector<uint8_t> bytes) : bytes_(std::move(bytes)) {}
+
+  /* Encode a change given as JSON */
+  static Change encode(const std::string &json) {
+    auto result = detail::check(am_encode_change(json.c_str()));
+    return Change(detail::binary(result, 0));
+  }
+
+  std::span<const uint8_t> bytes() const noexcept { return bytes_; }
+
+  /* The change as JSON */
+  std::string decode() const {
+    auto result = detail::check(am_decode_change(bytes_.data(), bytes_.size()));
+    return detail::json(result);
+  }
+
+ private:
+  std::vector<uint8_t> bytes_;
+};
+
+/* The state of a sync with one peer, use a separate SyncState per peer */
+class SyncState {
+ public:
+  SyncState() : handle_(am_sync_state_init()) {}
+
+  /* Load a sync state saved with encode() */
+  static SyncState decode(std::span<const uint8_t> data) {
+    AMsyncState *raw = nullptr;
+    detail::check(am_decode_sync_state(data.data(), data.size(), &raw));
+    return SyncState(raw);
+  }
+
+  /* The part of the state worth keeping between connections */
+  std::vector<uint8_t> encode() const {
+    auto result = detail::check(am_encode_sync_state(get()));
+    return detail::binary(result, 0);
+  }
+
+  /* The underlying C handle, which is still owned by this SyncState */
+  AMsyncState *get() const noexcept { return handle_.get(); }
+
+ private:
+  struct Deleter {
+    void operator()(AMsyncState *state) const noexcept { am_sync_state_free(state); }
+  };
+
+  explicit SyncState(AMsyncState *raw) : handle_(raw) {}
+
+  std::unique_ptr<AMsyncState, Deleter> handle_;
+};
+
+/* Owns an automerge backend. Copying a Backend clones the document, a
+ * moved-from Backend must not be used. */
+class Backend {
+ public:
+  Backend() : handle_(automerge_init()) {}
+
+  /* Load a document saved with save() */
+  static Backend load(std::span<const uint8_t> data) {
+    ::Backend *raw = nullptr;
+    detail::check(am_load(data.data(), data.size(), &raw));
+    return Backend(raw);
+  }
+
+  Backend(const Backend &other) : handle_(automerge_clone(other.get())) {}
+
+  Backend &operator=(const Backend &other) {
+    if (this != &other) {
+      handle_.reset(automerge_clone(other.get()));
+    }
+    return *this;
+  }
+
+  Backend(Backend &&) noexcept = default;
+  Backend &operator=(Backend &&) noexcept = default;
+
+  /* Apply a local change given as JSON, returns the patch as JSON and the
+   * encoded change */
+  std::pair<std::string, Change> apply_local_change(const std::string &request) {
+    auto result = detail::check(am_apply_local_change(get(), request.c_str()));
+    return {detail::json(result), Change(detail::binary(result, 0))};
+  }
+
+  /* Apply changes from another backend, returns the patch as JSON */
+  std::string apply_changes(std::span<const Change> changes) {
+    auto data = concat(changes);
+    auto result = detail::check(am_apply_changes(get(), data.data(), data.size()));
+    return detail::json(result);
+  }
+
+  /* Apply changes without generating a patch */
+  void load_changes(std::span<const Change> changes) {
+    auto data = concat(changes);
+    detail::check(am_load_changes(get(), data.data(), data.size()));
+  }
+
+  /* The patch for the whole document as JSON */
+  std::string get_patch() {
+    auto result = detail::check(am_get_patch(get()));
+    return detail::json(result);
+  }
+
+  std::vector<uint8_t> save() {
+    auto result = detail::check(am_save(get()));
+    return detail::binary(result, 0);
+  }
+
+  /* Every change which is not an ancestor of have_deps */
+  std::vector<Change> get_changes(std::span<const ChangeHash> have_deps = {}) {
+    std::vector<uint8_t> heads;
+    for (const auto &hash : have_deps) {
+      heads.insert(heads.end(), hash.begin(), hash.end());
+    }
+    auto result = detail::check(am_get_changes(get(), heads.data(), have_deps.size()));
+    return changes(result);
+  }
+
+  std::vector<Change> get_changes_for_actor(const std::string &actor) {
+    auto result = detail::check(am_get_changes_for_actor(get(), actor.c_str()));
+    return changes(result);
+  }
+
+  std::vector<ChangeHash> get_heads() {
+    auto result = detail::check(am_get_heads(get()));
+    std::vector<ChangeHash> heads;
+    for (uintptr_t i = 0; i < am_result_binary_count(result.get()); i++) {
+      auto bytes = detail::binary(result, i);
+      ChangeHash hash;
+      std::copy(bytes.begin(), bytes.end(), hash.begin());
+      heads.push_back(hash);
+    }
+    return heads;
+  }
+
+  /* The hashes of missing dependencies as JSON */
+  std::string get_missing_deps() {
+    auto result = detail::check(am_get_missing_deps(get()));
+    return detail::json(result);
+  }
+
+  /* The next message to send to the peer described by state, if any */
+  std::optional<std::vector<uint8_t>> generate_sync_message(SyncState &state) {
+    auto result = detail::check(am_generate_sync_message(get(), state.get()));
+    if (am_result_binary_count(result.get()) == 0) {
+      return std::nullopt;
+    }
+    return detail::binary(result, 0);
+  }
+
+  /* Apply a message from the peer described by state, returns the patch as
+   * JSON if the message contained changes */
+  std::optional<std::string> receive_sync_message(SyncState &state, std::span<const uint8_t> message) {
+    auto result = detail::check(am_receive_sync_message(get(), state.get(), message.data(), message.size()));
+    if (am_result_json(result.get()) == nullptr) {
+      return std::nullopt;
+    }
+    return detail::json(result);
+  }
+
+  /* The underlying C handle, which is still owned by this Backend */
+  ::Backend *get() const noexcept { return handle_.get(); }
+
+ private:
+  struct Deleter {
+    void operator()(::Backend *backend) const noexcept { automerge_free(backend); }
+  };
+
+  explicit Backend(::Backend *raw) : handle_(raw) {}
+
+  static std::vector<uint8_t> concat(std::span<const Change> changes) {
+    std::vector<uint8_t> data;
+    for (const auto &change : changes) {
+      data.insert(data.end(), change.bytes().begin(), change.bytes().end());
+    }
+    return data;
+  }
+
+  static std::vector<Change> changes(const detail::Result &result) {
+    std::vector<Change> changes;
+    for (uintptr_t i = 0; i < am_result_binary_count(result.get()); i++) {
+      changes.emplace_back(detail::binary(result, i));
+    }
+    return changes;
+  }
+
+  std::unique_ptr<::Backend, Deleter> handle_;
+};
+
+}  // namespace automerge
+
+#endif /* automerge_hpp */
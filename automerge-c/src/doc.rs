@@ -0,0 +1,478 @@
+use super::{from_buf_raw, Backend};
+use automerge_backend::{AutomergeError, Change};
+use automerge_frontend::{
+    Frontend, InvalidChangeRequest, InvalidPatch, LocalChange, Path, Primitive, Value,
+};
+use errno::{set_errno, Errno};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// A document which can be edited directly, without building changes as JSON.
+///
+/// Edits are queued up and applied as a single change by
+/// `automerge_doc_commit`. Results and errors are stored in the document's
+/// backend, so they are read with `automerge_read_json`,
+/// `automerge_read_binary` and `automerge_error` on the pointer returned by
+/// `automerge_doc_backend`.
+pub struct Document {
+    frontend: Frontend,
+    backend: Backend,
+    pending: Vec<(String, Edit)>,
+}
+
+enum Edit {
+    Set(Value),
+    Insert(Value),
+    Delete,
+    Increment(i64),
+}
+
+impl Edit {
+    fn into_local_change(self, path: Path) -> LocalChange {
+        match self {
+            Edit::Set(value) => LocalChange::set(path, value),
+            Edit::Insert(value) => LocalChange::insert(path, value),
+            Edit::Delete => LocalChange::delete(path),
+            Edit::Increment(by) => LocalChange::increment_by(path, by),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+enum DocumentError {
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+    #[error("No value at path: {0}")]
+    NoValueAtPath(String),
+    #[error(transparent)]
+    InvalidChange(#[from] InvalidChangeRequest),
+    #[error(transparent)]
+    Backend(#[from] AutomergeError),
+    #[error(transparent)]
+    InvalidPatch(#[from] InvalidPatch),
+}
+
+/// Parses a JSON pointer (RFC 6901), e.g. `/birds/0`, into a `Path`. The empty
+/// string is the root of the document. A segment is a list index if the value
+/// it is applied to is a list or text, otherwise it is a map key, so
+/// `value_at` is used to look up each prefix of the path.
+fn parse_path<F>(pointer: &str, value_at: F) -> Result<Path, DocumentError>
+where
+    F: Fn(&Path) -> Option<Value>,
+{
+    if pointer.is_empty() {
+        return Ok(Path::root());
+    }
+    if !pointer.starts_with('/') {
+        return Err(DocumentError::InvalidPath(pointer.to_string()));
+    }
+    let mut path = Path::root();
+    for segment in pointer[1..].split('/') {
+        let segment = segment.replace("~1", "/").replace("~0", "~");
+        path = match value_at(&path) {
+            Some(Value::Sequence(_)) | Some(Value::Text(_)) => {
+                let index = segment
+                    .parse()
+                    .map_err(|_| DocumentError::InvalidPath(pointer.to_string()))?;
+                path.index(index)
+            }
+            _ => path.key(segment),
+        };
+    }
+    Ok(path)
+}
+
+impl Document {
+    fn init(frontend: Frontend, backend: automerge_backend::Backend) -> Document {
+        Document {
+            frontend,
+            backend: Backend::init(backend),
+            pending: Vec::new(),
+        }
+    }
+
+    fn load(data: Vec<u8>) -> Result<Document, DocumentError> {
+        let backend = automerge_backend::Backend::load(data)?;
+        let mut frontend = Frontend::new();
+        frontend.apply_patch(backend.get_patch()?)?;
+        Ok(Document::init(frontend, backend))
+    }
+
+    unsafe fn queue(&mut self, path: *const c_char, edit: Edit) -> isize {
+        let path = CStr::from_ptr(path).to_string_lossy().into_owned();
+        if !path.is_empty() && !path.starts_with('/') {
+            return self.backend.handle_error(DocumentError::InvalidPath(path));
+        }
+        self.pending.push((path, edit));
+        self.backend.handle_ok()
+    }
+
+    fn get(&self, pointer: &str) -> Result<Value, DocumentError> {
+        let path = parse_path(pointer, |p| self.frontend.get_value(p))?;
+        self.frontend
+            .get_value(&path)
+            .ok_or_else(|| DocumentError::NoValueAtPath(pointer.to_string()))
+    }
+
+    /// Applies the pending edits as a single change. If any of them fail none
+    /// of them are applied.
+    fn commit(&mut self, message: Option<String>) -> Result<Option<Arc<Change>>, DocumentError> {
+        let pending = std::mem::take(&mut self.pending);
+        let change = self.frontend.change::<_, DocumentError>(message, |doc| {
+            for (pointer, edit) in pending {
+                let path = parse_path(&pointer, |p| doc.value_at_path(p))?;
+                doc.add_change(edit.into_local_change(path))?;
+            }
+            Ok(())
+        })?;
+        match change {
+            Some(change) => {
+                // the frontend has already applied the change, so it has to
+                // be undone if the backend refuses it
+                let (patch, change) = match self
+                    .backend
+                    .notify_changes(|b| b.apply_local_change(change))
+                {
+                    Ok(applied) => applied,
+                    Err(e) => {
+                        self.frontend.cancel_last_change();
+                        return Err(e.into());
+                    }
+                };
+                self.frontend.apply_patch(patch)?;
+                Ok(Some(change))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn apply_changes(&mut self, changes: Vec<Vec<u8>>) -> Result<(), DocumentError> {
+        let changes = changes
+            .into_iter()
+            .map(Change::from_bytes)
+            .collect::<Result<Vec<_>, _>>()?;
+        let patch = self.backend.notify_changes(|b| b.apply_changes(changes))?;
+        self.frontend.apply_patch(patch)?;
+        Ok(())
+    }
+}
+
+impl From<Document> for *mut Document {
+    fn from(d: Document) -> Self {
+        Box::into_raw(Box::new(d))
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn automerge_doc_new() -> *mut Document {
+    Document::init(Frontend::new(), automerge_backend::Backend::init()).into()
+}
+
+/// # Safety
+/// data pointer must be a valid pointer to len bytes
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_load(len: usize, data: *const u8) -> *mut Document {
+    let bytes = from_buf_raw(data, len);
+    if let Ok(doc) = Document::load(bytes) {
+        doc.into()
+    } else {
+        set_errno(Errno(1));
+        ptr::null_mut()
+    }
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_free(doc: *mut Document) {
+    let doc: Document = *Box::from_raw(doc);
+    drop(doc)
+}
+
+/// # Safety
+/// This must me called with a valid document pointer. The returned backend is
+/// owned by the document, it must not be freed and is only valid for as long
+/// as the document is. Use `automerge_doc_apply_changes` rather than
+/// `automerge_apply_changes` so the document sees the changes.
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_backend(doc: *mut Document) -> *mut Backend {
+    &mut (*doc).backend
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path and value must be valid pointers to cstrings
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_str(
+    doc: *mut Document,
+    path: *const c_char,
+    value: *const c_char,
+) -> isize {
+    let value = CStr::from_ptr(value).to_string_lossy().into_owned();
+    (*doc).queue(path, Edit::Set(Value::Primitive(Primitive::Str(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_int(
+    doc: *mut Document,
+    path: *const c_char,
+    value: i64,
+) -> isize {
+    (*doc).queue(path, Edit::Set(Value::Primitive(Primitive::Int(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_f64(
+    doc: *mut Document,
+    path: *const c_char,
+    value: f64,
+) -> isize {
+    (*doc).queue(path, Edit::Set(Value::Primitive(Primitive::F64(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_bool(
+    doc: *mut Document,
+    path: *const c_char,
+    value: bool,
+) -> isize {
+    (*doc).queue(path, Edit::Set(Value::Primitive(Primitive::Boolean(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_null(doc: *mut Document, path: *const c_char) -> isize {
+    (*doc).queue(path, Edit::Set(Value::Primitive(Primitive::Null)))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_counter(
+    doc: *mut Document,
+    path: *const c_char,
+    value: i64,
+) -> isize {
+    (*doc).queue(path, Edit::Set(Value::Primitive(Primitive::Counter(value))))
+}
+
+/// Set the value at `path` to an empty map
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_map(doc: *mut Document, path: *const c_char) -> isize {
+    (*doc).queue(
+        path,
+        Edit::Set(Value::Map(HashMap::new(), automerge_protocol::MapType::Map)),
+    )
+}
+
+/// Set the value at `path` to an empty list
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_list(doc: *mut Document, path: *const c_char) -> isize {
+    (*doc).queue(path, Edit::Set(Value::Sequence(Vec::new())))
+}
+
+/// Set the value at `path` to a text object containing `value`
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// path and value must be valid pointers to cstrings
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_set_text(
+    doc: *mut Document,
+    path: *const c_char,
+    value: *const c_char,
+) -> isize {
+    let chars = CStr::from_ptr(value).to_string_lossy().chars().collect();
+    (*doc).queue(path, Edit::Set(Value::Text(chars)))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path and value must be valid pointers to cstrings
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_str(
+    doc: *mut Document,
+    path: *const c_char,
+    value: *const c_char,
+) -> isize {
+    let value = CStr::from_ptr(value).to_string_lossy().into_owned();
+    (*doc).queue(path, Edit::Insert(Value::Primitive(Primitive::Str(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_int(
+    doc: *mut Document,
+    path: *const c_char,
+    value: i64,
+) -> isize {
+    (*doc).queue(path, Edit::Insert(Value::Primitive(Primitive::Int(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_f64(
+    doc: *mut Document,
+    path: *const c_char,
+    value: f64,
+) -> isize {
+    (*doc).queue(path, Edit::Insert(Value::Primitive(Primitive::F64(value))))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_bool(
+    doc: *mut Document,
+    path: *const c_char,
+    value: bool,
+) -> isize {
+    (*doc).queue(
+        path,
+        Edit::Insert(Value::Primitive(Primitive::Boolean(value))),
+    )
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_null(
+    doc: *mut Document,
+    path: *const c_char,
+) -> isize {
+    (*doc).queue(path, Edit::Insert(Value::Primitive(Primitive::Null)))
+}
+
+/// Insert an empty map at `path`
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_map(
+    doc: *mut Document,
+    path: *const c_char,
+) -> isize {
+    (*doc).queue(
+        path,
+        Edit::Insert(Value::Map(HashMap::new(), automerge_protocol::MapType::Map)),
+    )
+}
+
+/// Insert an empty list at `path`
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_insert_list(
+    doc: *mut Document,
+    path: *const c_char,
+) -> isize {
+    (*doc).queue(path, Edit::Insert(Value::Sequence(Vec::new())))
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_delete(doc: *mut Document, path: *const c_char) -> isize {
+    (*doc).queue(path, Edit::Delete)
+}
+
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_increment(
+    doc: *mut Document,
+    path: *const c_char,
+    by: i64,
+) -> isize {
+    (*doc).queue(path, Edit::Increment(by))
+}
+
+/// Look up the value at `path`. The value is returned as JSON, the return
+/// value is the size of buffer needed to read it with `automerge_read_json`.
+/// Edits which have not been committed are not visible.
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// path must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_get(doc: *mut Document, path: *const c_char) -> isize {
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let value = (*doc).get(&path).map(|v| v.to_json());
+    match value {
+        Ok(json) => (*doc).backend.generate_json(Ok(json)),
+        Err(e) => (*doc).backend.handle_error(e),
+    }
+}
+
+/// Apply every pending edit as a single change. Returns the length of the
+/// encoded change, which can be read with `automerge_read_binary`, or 0 if
+/// there were no edits. If any edit fails nothing is applied, all of the
+/// pending edits are discarded and -1 is returned.
+///
+/// # Safety
+/// This must me called with a valid document pointer
+/// message must be null or a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_commit(doc: *mut Document, message: *const c_char) -> isize {
+    let message = if message.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(message).to_string_lossy().into_owned())
+    };
+    match (*doc).commit(message) {
+        Ok(Some(change)) => (*doc).backend.handle_binary(Ok(change.bytes.clone())),
+        Ok(None) => (*doc).backend.handle_ok(),
+        Err(e) => (*doc).backend.handle_error(e),
+    }
+}
+
+/// Apply the changes queued with `automerge_write_change` on the document's
+/// backend
+///
+/// # Safety
+/// This must me called with a valid document pointer
+#[no_mangle]
+pub unsafe extern "C" fn automerge_doc_apply_changes(doc: *mut Document) -> isize {
+    match (*doc).backend.queue.take() {
+        Some(changes) => match (*doc).apply_changes(changes) {
+            Ok(()) => (*doc).backend.handle_ok(),
+            Err(e) => (*doc).backend.handle_error(e),
+        },
+        None => (*doc).backend.handle_error("no changes queued"),
+    }
+}
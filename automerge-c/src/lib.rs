@@ -11,18 +11,30 @@ use serde::ser::Serialize;
 use std::convert::TryInto;
 use std::ffi::{CStr, CString};
 use std::ops::{Deref, DerefMut};
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
 use std::ptr;
-use std::rc::Rc;
+
+mod doc;
+mod result;
+mod sync;
+pub use doc::Document;
+pub use result::{AMerror, AMresult};
+pub use sync::{AMchangeCallback, AMsyncState};
 
 #[derive(Clone)]
 pub struct Backend {
     handle: automerge_backend::Backend,
     text: Option<String>,
-    last_local_change: Option<Rc<Change>>,
     binary: Vec<Vec<u8>>,
     queue: Option<Vec<Vec<u8>>>,
     error: Option<CString>,
+    on_change: Option<ChangeCallback>,
+}
+
+#[derive(Clone, Copy)]
+struct ChangeCallback {
+    callback: unsafe extern "C" fn(*mut c_void, *const u8, usize),
+    user_data: *mut c_void,
 }
 
 struct BinaryResults(Result<Vec<Vec<u8>>, AutomergeError>);
@@ -54,11 +66,32 @@ impl Backend {
         Backend {
             handle,
             text: None,
-            last_local_change: None,
             binary: Vec::new(),
             queue: None,
             error: None,
+            on_change: None,
+        }
+    }
+
+    /// Run `f` on the backend, then pass each change it applied to the change
+    /// callback, if one has been set
+    fn notify_changes<T>(&mut self, f: impl FnOnce(&mut automerge_backend::Backend) -> T) -> T {
+        let on_change = match self.on_change {
+            Some(on_change) => on_change,
+            None => return f(&mut self.handle),
+        };
+        let before = self.handle.get_heads();
+        let result = f(&mut self.handle);
+        for change in self.handle.get_changes(&before) {
+            unsafe {
+                (on_change.callback)(
+                    on_change.user_data,
+                    change.bytes.as_ptr(),
+                    change.bytes.len(),
+                )
+            };
         }
+        result
     }
 
     fn handle_result(&mut self, result: Result<isize, String>) -> isize {
@@ -190,12 +223,9 @@ pub unsafe extern "C" fn automerge_apply_local_change(
     let request: Result<UncompressedChange, _> = serde_json::from_str(&request);
     match request {
         Ok(request) => {
-            let result = (*backend).apply_local_change(request);
+            let result = (*backend).notify_changes(|b| b.apply_local_change(request));
             match result {
-                Ok((patch, change)) => {
-                    (*backend).last_local_change = Some(change);
-                    (*backend).generate_json(Ok(patch))
-                }
+                Ok((patch, _)) => (*backend).generate_json(Ok(patch)),
                 Err(err) => (*backend).handle_error(err),
             }
         }
@@ -230,7 +260,7 @@ pub unsafe extern "C" fn automerge_apply_changes(backend: *mut Backend) -> isize
                 .iter()
                 .map(|c| Change::from_bytes(c.to_vec()).unwrap())
                 .collect();
-            let patch = (*backend).apply_changes(changes);
+            let patch = (*backend).notify_changes(|b| b.apply_changes(changes));
             (*backend).generate_json(patch)
         }
         None => (*backend).handle_error("no changes queued"),
@@ -254,7 +284,10 @@ pub unsafe extern "C" fn automerge_load_changes(backend: *mut Backend) -> isize
             .iter()
             .map(|c| Change::from_bytes(c.to_vec()).unwrap())
             .collect();
-        if (*backend).load_changes(changes).is_ok() {
+        if (*backend)
+            .notify_changes(|b| b.load_changes(changes))
+            .is_ok()
+        {
             return (*backend).handle_ok();
         }
     }
@@ -265,7 +298,12 @@ pub unsafe extern "C" fn automerge_load_changes(backend: *mut Backend) -> isize
 /// This must me called with a valid backend pointer
 #[no_mangle]
 pub unsafe extern "C" fn automerge_clone(backend: *mut Backend) -> *mut Backend {
-    (*backend).clone().into()
+    // The change callback belongs to the original backend
+    Backend {
+        on_change: None,
+        ..(*backend).clone()
+    }
+    .into()
 }
 
 /// # Safety
@@ -342,8 +380,14 @@ pub unsafe extern "C" fn automerge_encode_change(
 /// get_last_local_change call from the javascript api to solve the same problem
 #[no_mangle]
 pub unsafe extern "C" fn automerge_get_last_local_change(backend: *mut Backend) -> isize {
-    match (*backend).last_local_change.as_ref() {
-        Some(change) => (*backend).handle_binary(Ok(change.bytes.clone())),
+    let last = (*backend)
+        .last_local_actor()
+        .and_then(|actor| (*backend).get_last_local_change(actor));
+    match last {
+        Some(change) => {
+            let bytes = change.bytes.clone();
+            (*backend).handle_binary(Ok(bytes))
+        }
         None => (*backend).handle_error("no last change"),
     }
 }
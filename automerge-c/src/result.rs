@@ -0,0 +1,504 @@
+//! An alternative to the `automerge_*` functions which returns each result as
+//! an owned `AMresult` rather than storing it in the backend.
+
+use super::Backend;
+use automerge_backend::{AutomergeError, Change};
+use automerge_protocol::{ChangeHash, UncompressedChange};
+use serde::ser::Serialize;
+use std::convert::TryInto;
+use std::ffi::{CStr, CString};
+use std::fmt::Display;
+use std::os::raw::c_char;
+use std::ptr;
+
+/// The kind of error an `AMresult` holds
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AMerror {
+    /// The call succeeded
+    Ok = 0,
+    /// An argument was null, was not valid UTF-8, or could not be parsed
+    InvalidArgument,
+    /// A change could not be decoded, or a document could not be loaded
+    Decoding,
+    /// A change was not valid, e.g. it had the wrong sequence number
+    InvalidChange,
+    /// A change with the same actor and sequence number but different
+    /// contents has already been applied
+    DuplicateChange,
+    /// A change referred to an object or element which does not exist
+    MissingObject,
+    /// The peer is missing history which has been compacted, send it the
+    /// whole document instead
+    NeedsFullResync,
+    /// Anything else, these indicate a bug in automerge
+    Internal,
+}
+
+impl From<&AutomergeError> for AMerror {
+    fn from(e: &AutomergeError) -> AMerror {
+        match e {
+            AutomergeError::InvalidChange { .. }
+            | AutomergeError::InvalidSeq(_)
+            | AutomergeError::InvalidStartOp(_)
+            | AutomergeError::DivergentChange(_)
+            | AutomergeError::MapKeyInSeq
+            | AutomergeError::InvalidOpId(_)
+            | AutomergeError::InvalidObjectId(_)
+            | AutomergeError::InvalidCursor { .. } => AMerror::InvalidChange,
+            AutomergeError::DuplicateChange(_) => AMerror::DuplicateChange,
+            AutomergeError::MissingObjectError
+            | AutomergeError::MissingIndex(_)
+            | AutomergeError::MissingElement(..)
+            | AutomergeError::NoPathToObject(_)
+            | AutomergeError::CantExtractObject(_) => AMerror::MissingObject,
+            AutomergeError::ChangeDecompressError(_)
+            | AutomergeError::ChangeBadFormat { .. }
+            | AutomergeError::UnknownVersion(_)
+            | AutomergeError::DecodeFailed
+            | AutomergeError::EncodingError
+            | AutomergeError::Decode { .. }
+            | AutomergeError::ReadError(_)
+            | AutomergeError::DocFormatUnimplemented => AMerror::Decoding,
+            AutomergeError::NeedsFullResync => AMerror::NeedsFullResync,
+            AutomergeError::UnknownHead(_) => AMerror::InvalidArgument,
+            AutomergeError::SkipListError(_)
+            | AutomergeError::IndexOutOfBounds(_)
+            | AutomergeError::MissingValue
+            | AutomergeError::GeneralError(_)
+            | AutomergeError::MissingNumberValue
+            | AutomergeError::DivergedState(_)
+            | AutomergeError::HeadToOpId
+            | AutomergeError::EncodeFailed => AMerror::Internal,
+        }
+    }
+}
+
+/// The result of an `am_*` call. Depending on the call a successful result
+/// holds some JSON, some binaries or nothing. A failed result holds an error
+/// code and message.
+///
+/// Every `am_*` function which returns an `AMresult *` transfers ownership of
+/// the result to the caller, who must free it with `am_result_free`. Pointers
+/// obtained from a result (error messages, JSON and binaries) are owned by the
+/// result and are valid until it is freed.
+///
+/// Nothing is stored in the backend, so there is no need to read a result
+/// back before making the next call. A backend can be used from any thread,
+/// but not from more than one thread at a time. Results do not refer to the
+/// backend they came from and can be read and freed on any thread.
+pub struct AMresult {
+    error: AMerror,
+    error_message: Option<CString>,
+    json: Option<CString>,
+    binaries: Vec<Vec<u8>>,
+}
+
+impl AMresult {
+    pub(crate) fn ok() -> AMresult {
+        AMresult {
+            error: AMerror::Ok,
+            error_message: None,
+            json: None,
+            binaries: Vec::new(),
+        }
+    }
+
+    pub(crate) fn error<M: Display>(error: AMerror, message: M) -> AMresult {
+        AMresult {
+            error,
+            // An error message with an embedded null is truncated at the null
+            error_message: Some(CString::new(message.to_string()).unwrap_or_else(|e| {
+                let nul = e.nul_position();
+                CString::new(&e.into_vec()[..nul]).unwrap()
+            })),
+            ..AMresult::ok()
+        }
+    }
+
+    pub(crate) fn json<T: Serialize>(value: &T) -> AMresult {
+        match serde_json::to_string(value).map(CString::new) {
+            Ok(Ok(json)) => AMresult {
+                json: Some(json),
+                ..AMresult::ok()
+            },
+            Ok(Err(e)) => AMresult::error(AMerror::Internal, e),
+            Err(e) => AMresult::error(AMerror::Internal, e),
+        }
+    }
+
+    pub(crate) fn binaries(binaries: Vec<Vec<u8>>) -> AMresult {
+        AMresult {
+            binaries,
+            ..AMresult::ok()
+        }
+    }
+
+    pub(crate) fn into_raw(self) -> *mut AMresult {
+        Box::into_raw(Box::new(self))
+    }
+}
+
+impl From<AutomergeError> for AMresult {
+    fn from(e: AutomergeError) -> AMresult {
+        AMresult::error((&e).into(), e)
+    }
+}
+
+fn changes_result(changes: Vec<&Change>) -> AMresult {
+    AMresult::binaries(changes.iter().map(|c| c.bytes.clone()).collect())
+}
+
+fn heads_result(heads: Vec<ChangeHash>) -> AMresult {
+    AMresult::binaries(heads.iter().map(|h| h.0.to_vec()).collect())
+}
+
+pub(crate) unsafe fn backend_arg<'a>(backend: *mut Backend) -> Result<&'a mut Backend, AMresult> {
+    backend
+        .as_mut()
+        .ok_or_else(|| AMresult::error(AMerror::InvalidArgument, "backend was null"))
+}
+
+pub(crate) unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, AMresult> {
+    if s.is_null() {
+        return Err(AMresult::error(
+            AMerror::InvalidArgument,
+            format!("{} was null", name),
+        ));
+    }
+    CStr::from_ptr(s).to_str().map_err(|e| {
+        AMresult::error(
+            AMerror::InvalidArgument,
+            format!("{} was not valid UTF-8: {}", name, e),
+        )
+    })
+}
+
+pub(crate) unsafe fn bytes_arg<'a>(data: *const u8, len: usize) -> Result<&'a [u8], AMresult> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(AMresult::error(AMerror::InvalidArgument, "data was null"))
+    } else {
+        Ok(std::slice::from_raw_parts(data, len))
+    }
+}
+
+pub(crate) fn into_raw(result: Result<AMresult, AMresult>) -> *mut AMresult {
+    match result {
+        Ok(r) | Err(r) => r.into_raw(),
+    }
+}
+
+/// Apply a local change, given as JSON. On success the result holds the
+/// patch as JSON and the encoded change as its only binary.
+///
+/// # Safety
+/// backend must be a valid backend pointer and request a valid pointer to a
+/// cstring
+#[no_mangle]
+pub unsafe extern "C" fn am_apply_local_change(
+    backend: *mut Backend,
+    request: *const c_char,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let request: UncompressedChange = serde_json::from_str(str_arg(request, "request")?)
+            .map_err(|e| AMresult::error(AMerror::InvalidArgument, e))?;
+        let (patch, change) = backend.notify_changes(|b| b.apply_local_change(request))?;
+        let mut result = AMresult::json(&patch);
+        result.binaries = vec![change.bytes.clone()];
+        Ok(result)
+    })())
+}
+
+/// Apply one or more encoded changes, concatenated into `data`. On success the
+/// result holds the patch as JSON.
+///
+/// # Safety
+/// backend must be a valid backend pointer and data a valid pointer to len
+/// bytes
+#[no_mangle]
+pub unsafe extern "C" fn am_apply_changes(
+    backend: *mut Backend,
+    data: *const u8,
+    len: usize,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let changes = Change::load_document(bytes_arg(data, len)?)?;
+        let patch = backend.notify_changes(|b| b.apply_changes(changes))?;
+        Ok(AMresult::json(&patch))
+    })())
+}
+
+/// Load one or more encoded changes, concatenated into `data`, without
+/// generating a patch
+///
+/// # Safety
+/// backend must be a valid backend pointer and data a valid pointer to len
+/// bytes
+#[no_mangle]
+pub unsafe extern "C" fn am_load_changes(
+    backend: *mut Backend,
+    data: *const u8,
+    len: usize,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let changes = Change::load_document(bytes_arg(data, len)?)?;
+        backend.notify_changes(|b| b.load_changes(changes))?;
+        Ok(AMresult::ok())
+    })())
+}
+
+/// The result holds the patch for the whole document as JSON
+///
+/// # Safety
+/// backend must be a valid backend pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_get_patch(backend: *mut Backend) -> *mut AMresult {
+    into_raw((|| {
+        let patch = backend_arg(backend)?.get_patch()?;
+        Ok(AMresult::json(&patch))
+    })())
+}
+
+/// The result holds the saved document as its only binary. If the backend was
+/// loaded from a document with compacted history it starts with a snapshot,
+/// which only this library can load, not the JavaScript implementation.
+///
+/// # Safety
+/// backend must be a valid backend pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_save(backend: *mut Backend) -> *mut AMresult {
+    into_raw((|| {
+        let data = backend_arg(backend)?.save()?;
+        Ok(AMresult::binaries(vec![data]))
+    })())
+}
+
+/// Load a document saved with `am_save` or `automerge_save`. On success the new
+/// backend is written to `backend`, it is owned by the caller and must be
+/// freed with `automerge_free`.
+///
+/// # Safety
+/// data must be a valid pointer to len bytes and backend a valid pointer to
+/// write a backend pointer to
+#[no_mangle]
+pub unsafe extern "C" fn am_load(
+    data: *const u8,
+    len: usize,
+    backend: *mut *mut Backend,
+) -> *mut AMresult {
+    into_raw((|| {
+        if backend.is_null() {
+            return Err(AMresult::error(
+                AMerror::InvalidArgument,
+                "backend was null",
+            ));
+        }
+        let loaded = automerge_backend::Backend::load(bytes_arg(data, len)?.to_vec())?;
+        *backend = Backend::init(loaded).into();
+        Ok(AMresult::ok())
+    })())
+}
+
+/// The result holds each encoded change which is not an ancestor of the
+/// `count` heads in `heads`. Each head is 32 bytes.
+///
+/// # Safety
+/// backend must be a valid backend pointer and heads a valid pointer to
+/// count * 32 bytes
+#[no_mangle]
+pub unsafe extern "C" fn am_get_changes(
+    backend: *mut Backend,
+    heads: *const u8,
+    count: usize,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let len = count
+            .checked_mul(32)
+            .ok_or_else(|| AMresult::error(AMerror::InvalidArgument, "count was too large"))?;
+        let have_deps = bytes_arg(heads, len)?
+            .chunks(32)
+            .map(|h| h.try_into())
+            .collect::<Result<Vec<ChangeHash>, _>>()
+            .map_err(|e| AMresult::error(AMerror::InvalidArgument, e))?;
+        Ok(changes_result(backend.get_changes(&have_deps)))
+    })())
+}
+
+/// The result holds each encoded change made by `actor`
+///
+/// # Safety
+/// backend must be a valid backend pointer and actor a valid pointer to a
+/// cstring
+#[no_mangle]
+pub unsafe extern "C" fn am_get_changes_for_actor(
+    backend: *mut Backend,
+    actor: *const c_char,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let actor = str_arg(actor, "actor")?
+            .try_into()
+            .map_err(|e| AMresult::error(AMerror::InvalidArgument, e))?;
+        let changes = backend.get_changes_for_actor_id(&actor)?;
+        Ok(changes_result(changes))
+    })())
+}
+
+/// The result holds each encoded change made by `am_apply_local_change`, for
+/// the actor of the most recent one, which comes after `seq`
+///
+/// # Safety
+/// backend must be a valid backend pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_get_changes_since_local(
+    backend: *mut Backend,
+    seq: u64,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let changes = match backend.last_local_actor() {
+            Some(actor) => backend.get_changes_since_local(actor, seq),
+            None => Vec::new(),
+        };
+        Ok(changes_result(changes))
+    })())
+}
+
+/// The result holds each head of the document as a 32 byte binary
+///
+/// # Safety
+/// backend must be a valid backend pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_get_heads(backend: *mut Backend) -> *mut AMresult {
+    into_raw((|| Ok(heads_result(backend_arg(backend)?.get_heads())))())
+}
+
+/// The result holds the hashes of missing dependencies as JSON
+///
+/// # Safety
+/// backend must be a valid backend pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_get_missing_deps(backend: *mut Backend) -> *mut AMresult {
+    into_raw((|| {
+        Ok(AMresult::json(&backend_arg(backend)?.get_missing_deps()))
+    })())
+}
+
+/// The result holds the change encoded in `data` as JSON
+///
+/// # Safety
+/// data must be a valid pointer to len bytes
+#[no_mangle]
+pub unsafe extern "C" fn am_decode_change(data: *const u8, len: usize) -> *mut AMresult {
+    into_raw((|| {
+        let change = Change::from_bytes(bytes_arg(data, len)?.to_vec())?;
+        Ok(AMresult::json(&change.decode()))
+    })())
+}
+
+/// The result holds the encoded form of the JSON `change` as its only binary
+///
+/// # Safety
+/// change must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn am_encode_change(change: *const c_char) -> *mut AMresult {
+    into_raw((|| {
+        let change: UncompressedChange = serde_json::from_str(str_arg(change, "change")?)
+            .map_err(|e| AMresult::error(AMerror::InvalidArgument, e))?;
+        let change: Change = change.into();
+        Ok(AMresult::binaries(vec![change.bytes]))
+    })())
+}
+
+/// `AMerror::Ok` if the call which returned `result` succeeded. A null result
+/// is reported as `AMerror::InvalidArgument`.
+///
+/// # Safety
+/// result must be null or a valid result pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_result_error(result: *const AMresult) -> AMerror {
+    result
+        .as_ref()
+        .map(|r| r.error)
+        .unwrap_or(AMerror::InvalidArgument)
+}
+
+/// A description of the error, or null if there was no error. The string is
+/// owned by `result`.
+///
+/// # Safety
+/// result must be null or a valid result pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_result_error_message(result: *const AMresult) -> *const c_char {
+    result
+        .as_ref()
+        .and_then(|r| r.error_message.as_ref())
+        .map(|m| m.as_ptr())
+        .unwrap_or_else(ptr::null)
+}
+
+/// The null terminated JSON held by `result`, or null if it holds no JSON. The
+/// string is owned by `result`.
+///
+/// # Safety
+/// result must be null or a valid result pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_result_json(result: *const AMresult) -> *const c_char {
+    result
+        .as_ref()
+        .and_then(|r| r.json.as_ref())
+        .map(|j| j.as_ptr())
+        .unwrap_or_else(ptr::null)
+}
+
+/// The number of binaries held by `result`
+///
+/// # Safety
+/// result must be null or a valid result pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_result_binary_count(result: *const AMresult) -> usize {
+    result.as_ref().map(|r| r.binaries.len()).unwrap_or(0)
+}
+
+/// The binary at `index` in `result`, its length is written to `len`. Returns
+/// null, and writes 0 to `len`, if there is no binary at `index`. The binary is
+/// owned by `result`.
+///
+/// # Safety
+/// result must be null or a valid result pointer, len must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_result_binary(
+    result: *const AMresult,
+    index: usize,
+    len: *mut usize,
+) -> *const u8 {
+    match result.as_ref().and_then(|r| r.binaries.get(index)) {
+        Some(binary) => {
+            *len = binary.len();
+            binary.as_ptr()
+        }
+        None => {
+            *len = 0;
+            ptr::null()
+        }
+    }
+}
+
+/// Free a result returned by one of the `am_*` functions. Freeing null does
+/// nothing.
+///
+/// # Safety
+/// result must be null or a valid result pointer which has not already been
+/// freed
+#[no_mangle]
+pub unsafe extern "C" fn am_result_free(result: *mut AMresult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result))
+    }
+}
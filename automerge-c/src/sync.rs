@@ -0,0 +1,253 @@
+//! Keeping backends in sync: the sync protocol, which works out which changes
+//! two peers need to send each other, and a callback which streams changes
+//! out of a backend as they are applied.
+
+use super::{Backend, ChangeCallback};
+use crate::result::{backend_arg, bytes_arg, into_raw, str_arg, AMerror, AMresult};
+use automerge_backend::{BloomFilter, Change, SyncHave, SyncMessage, SyncState};
+use automerge_protocol::{ChangeHash, UncompressedChange};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::os::raw::{c_char, c_void};
+
+/// Called with `user_data` and each change applied to a backend, see
+/// `am_set_change_callback`. The change is only valid for the duration of the
+/// call.
+pub type AMchangeCallback = Option<unsafe extern "C" fn(*mut c_void, *const u8, usize)>;
+
+/// The state of a sync with one peer, created with `am_sync_state_init` or
+/// `am_decode_sync_state` and freed with `am_sync_state_free`. Use a separate
+/// sync state for each peer.
+pub struct AMsyncState {
+    state: SyncState,
+}
+
+impl From<AMsyncState> for *mut AMsyncState {
+    fn from(state: AMsyncState) -> Self {
+        Box::into_raw(Box::new(state))
+    }
+}
+
+unsafe fn sync_state_arg<'a>(state: *mut AMsyncState) -> Result<&'a mut SyncState, AMresult> {
+    state
+        .as_mut()
+        .map(|s| &mut s.state)
+        .ok_or_else(|| AMresult::error(AMerror::InvalidArgument, "sync state was null"))
+}
+
+/// The JSON form of a sync message, with changes decoded and bloom filters as
+/// hex
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncMessageJson {
+    heads: Vec<ChangeHash>,
+    need: Vec<ChangeHash>,
+    have: Vec<SyncHaveJson>,
+    changes: Vec<UncompressedChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncHaveJson {
+    last_sync: Vec<ChangeHash>,
+    bloom: String,
+}
+
+impl From<SyncMessage> for SyncMessageJson {
+    fn from(message: SyncMessage) -> Self {
+        SyncMessageJson {
+            heads: message.heads,
+            need: message.need,
+            have: message
+                .have
+                .into_iter()
+                .map(|have| SyncHaveJson {
+                    last_sync: have.last_sync,
+                    bloom: hex::encode(have.bloom.to_bytes()),
+                })
+                .collect(),
+            changes: message.changes.iter().map(Change::decode).collect(),
+        }
+    }
+}
+
+impl TryFrom<SyncMessageJson> for SyncMessage {
+    type Error = AMresult;
+
+    fn try_from(json: SyncMessageJson) -> Result<Self, Self::Error> {
+        let have = json
+            .have
+            .into_iter()
+            .map(|have| {
+                let bloom = hex::decode(&have.bloom)
+                    .map_err(|e| AMresult::error(AMerror::InvalidArgument, e))?;
+                Ok(SyncHave {
+                    last_sync: have.last_sync,
+                    bloom: BloomFilter::try_from(bloom.as_slice())?,
+                })
+            })
+            .collect::<Result<_, AMresult>>()?;
+        Ok(SyncMessage {
+            heads: json.heads,
+            need: json.need,
+            have,
+            changes: json.changes.into_iter().map(Change::from).collect(),
+        })
+    }
+}
+
+/// Call `callback` with `user_data` and each change applied to `backend`,
+/// whether it was made locally, received from a peer or loaded. Changes are
+/// passed in the order they are applied, which is not necessarily the order
+/// they were received in as changes wait for their dependencies. Passing a
+/// null callback stops the calls. Cloning a backend does not copy its
+/// callback.
+///
+/// The callback must not call any functions on `backend`.
+///
+/// # Safety
+/// backend must be a valid backend pointer, and user_data must be valid for
+/// as long as the callback is set
+#[no_mangle]
+pub unsafe extern "C" fn am_set_change_callback(
+    backend: *mut Backend,
+    callback: AMchangeCallback,
+    user_data: *mut c_void,
+) {
+    if let Some(backend) = backend.as_mut() {
+        backend.on_change = callback.map(|callback| ChangeCallback {
+            callback,
+            user_data,
+        });
+    }
+}
+
+/// A new sync state for a peer we have never synced with
+#[no_mangle]
+pub extern "C" fn am_sync_state_init() -> *mut AMsyncState {
+    AMsyncState {
+        state: SyncState::new(),
+    }
+    .into()
+}
+
+/// Free a sync state. Freeing null does nothing.
+///
+/// # Safety
+/// state must be null or a valid sync state pointer which has not already
+/// been freed
+#[no_mangle]
+pub unsafe extern "C" fn am_sync_state_free(state: *mut AMsyncState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state))
+    }
+}
+
+/// The result holds the part of `state` worth keeping between connections as
+/// its only binary, load it again with `am_decode_sync_state`
+///
+/// # Safety
+/// state must be a valid sync state pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_encode_sync_state(state: *mut AMsyncState) -> *mut AMresult {
+    into_raw((|| {
+        Ok(AMresult::binaries(vec![sync_state_arg(state)?.encode()]))
+    })())
+}
+
+/// Decode a sync state encoded with `am_encode_sync_state`. On success the new
+/// sync state is written to `state`, it is owned by the caller and must be
+/// freed with `am_sync_state_free`.
+///
+/// # Safety
+/// data must be a valid pointer to len bytes and state a valid pointer to
+/// write a sync state pointer to
+#[no_mangle]
+pub unsafe extern "C" fn am_decode_sync_state(
+    data: *const u8,
+    len: usize,
+    state: *mut *mut AMsyncState,
+) -> *mut AMresult {
+    into_raw((|| {
+        if state.is_null() {
+            return Err(AMresult::error(AMerror::InvalidArgument, "state was null"));
+        }
+        let decoded = SyncState::decode(bytes_arg(data, len)?)?;
+        *state = AMsyncState { state: decoded }.into();
+        Ok(AMresult::ok())
+    })())
+}
+
+/// The result holds the next message to send to the peer described by
+/// `state` as its only binary, or no binaries if there is nothing to send.
+/// Updates `state`.
+///
+/// # Safety
+/// backend must be a valid backend pointer and state a valid sync state
+/// pointer
+#[no_mangle]
+pub unsafe extern "C" fn am_generate_sync_message(
+    backend: *mut Backend,
+    state: *mut AMsyncState,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let state = sync_state_arg(state)?;
+        let message = backend.generate_sync_message(state);
+        Ok(AMresult::binaries(
+            message.iter().map(SyncMessage::encode).collect(),
+        ))
+    })())
+}
+
+/// Apply a message received from the peer described by `state` and update
+/// `state`. If the message contained changes the result holds the patch as
+/// JSON, otherwise it holds no JSON.
+///
+/// # Safety
+/// backend must be a valid backend pointer, state a valid sync state pointer
+/// and data a valid pointer to len bytes
+#[no_mangle]
+pub unsafe extern "C" fn am_receive_sync_message(
+    backend: *mut Backend,
+    state: *mut AMsyncState,
+    data: *const u8,
+    len: usize,
+) -> *mut AMresult {
+    into_raw((|| {
+        let backend = backend_arg(backend)?;
+        let state = sync_state_arg(state)?;
+        let message = SyncMessage::decode(bytes_arg(data, len)?)?;
+        let patch = backend.notify_changes(|b| b.receive_sync_message(state, message))?;
+        Ok(patch
+            .map(|patch| AMresult::json(&patch))
+            .unwrap_or_else(AMresult::ok))
+    })())
+}
+
+/// The result holds the sync message encoded in `data` as JSON
+///
+/// # Safety
+/// data must be a valid pointer to len bytes
+#[no_mangle]
+pub unsafe extern "C" fn am_decode_sync_message(data: *const u8, len: usize) -> *mut AMresult {
+    into_raw((|| {
+        let message = SyncMessage::decode(bytes_arg(data, len)?)?;
+        Ok(AMresult::json(&SyncMessageJson::from(message)))
+    })())
+}
+
+/// The result holds the encoded form of the JSON sync `message`, in the form
+/// returned by `am_decode_sync_message`, as its only binary
+///
+/// # Safety
+/// message must be a valid pointer to a cstring
+#[no_mangle]
+pub unsafe extern "C" fn am_encode_sync_message(message: *const c_char) -> *mut AMresult {
+    into_raw((|| {
+        let json: SyncMessageJson = serde_json::from_str(str_arg(message, "message")?)
+            .map_err(|e| AMresult::error(AMerror::InvalidArgument, e))?;
+        let message = SyncMessage::try_from(json)?;
+        Ok(AMresult::binaries(vec![message.encode()]))
+    })())
+}
@@ -37,6 +37,7 @@ enum Op {
     Insert,
     Delete,
     Increment,
+    Resolve,
 }
 
 fn case_insensitive_string<Input>(s: &'static str) -> impl Parser<Input, Output = String>
@@ -56,6 +57,7 @@ where
         combine::attempt(case_insensitive_string("insert")).map(|_| Op::Insert),
         combine::attempt(case_insensitive_string("delete")).map(|_| Op::Delete),
         combine::attempt(case_insensitive_string("increment")).map(|_| Op::Increment),
+        combine::attempt(case_insensitive_string("resolve")).map(|_| Op::Resolve),
     ))
 }
 
@@ -155,6 +157,9 @@ where
                 Op::Insert => value_parser::<'a>()
                     .map(move |value| amf::LocalChange::insert(path.clone(), value))
                     .boxed(),
+                Op::Resolve => value_parser::<'a>()
+                    .map(move |value| amf::LocalChange::resolve_conflict(path.clone(), value))
+                    .boxed(),
                 Op::Delete => combine::value(amf::LocalChange::delete(path)).boxed(),
                 Op::Increment => combine::value(amf::LocalChange::increment(path)).boxed(),
             };
@@ -241,6 +246,13 @@ mod tests {
                 input: "increment $[\"map\"][0]",
                 expected: amf::LocalChange::increment(amf::Path::root().key("map").index(0)),
             },
+            Scenario {
+                input: "resolve $[\"bird\"] \"robin\"",
+                expected: amf::LocalChange::resolve_conflict(
+                    amf::Path::root().key("bird"),
+                    "robin",
+                ),
+            },
         ];
         for (index, scenario) in scenarios.into_iter().enumerate() {
             let result: Result<(amf::LocalChange, _), _> =
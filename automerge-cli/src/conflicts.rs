@@ -0,0 +1,98 @@
+use anyhow::Result;
+
+fn get_conflicts_json(input_data: Vec<u8>) -> Result<serde_json::Value> {
+    let mut backend = automerge_backend::Backend::init();
+    let changes = automerge_backend::Change::load_document(&input_data)?;
+    let patch = backend.apply_changes(changes)?;
+
+    let mut frontend = automerge_frontend::Frontend::new();
+    frontend.apply_patch(patch)?;
+
+    let conflicts = frontend
+        .conflicts()
+        .into_iter()
+        .map(|(path, values)| {
+            let mut values: Vec<_> = values.into_iter().collect();
+            values.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let values: serde_json::Map<String, serde_json::Value> = values
+                .into_iter()
+                .map(|(opid, value)| (opid.to_string(), value.to_json()))
+                .collect();
+            serde_json::json!({
+                "path": path.to_string(),
+                "values": values,
+            })
+        })
+        .collect();
+    Ok(serde_json::Value::Array(conflicts))
+}
+
+pub fn print_conflicts(
+    mut changes_reader: impl std::io::Read,
+    mut writer: impl std::io::Write,
+    is_tty: bool,
+) -> Result<()> {
+    let mut input_data = vec![];
+    changes_reader.read_to_end(&mut input_data)?;
+
+    let conflicts_json = get_conflicts_json(input_data)?;
+    if is_tty {
+        colored_json::write_colored_json(&conflicts_json, &mut writer).unwrap()
+    } else {
+        writeln!(
+            writer,
+            "{}",
+            serde_json::to_string_pretty(&conflicts_json).unwrap()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use automerge_frontend::{Frontend, InvalidChangeRequest, LocalChange, Path};
+
+    fn set_bird(bird: &str) -> automerge_protocol::UncompressedChange {
+        let mut frontend = Frontend::new();
+        frontend
+            .change::<_, InvalidChangeRequest>(None, |doc| {
+                doc.add_change(LocalChange::set(Path::root().key("bird"), bird))?;
+                Ok(())
+            })
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn cli_conflicts_with_no_conflicts() {
+        assert_eq!(get_conflicts_json(vec![]).unwrap(), serde_json::json!([]))
+    }
+
+    #[test]
+    fn cli_conflicts_with_concurrent_sets() {
+        let robin = set_bird("robin");
+        let wagtail = set_bird("wagtail");
+        let robin_id = robin.actor_id.op_id_at(1).to_string();
+        let wagtail_id = wagtail.actor_id.op_id_at(1).to_string();
+
+        let mut backend1 = automerge_backend::Backend::init();
+        backend1.apply_local_change(robin).unwrap();
+        let mut backend2 = automerge_backend::Backend::init();
+        backend2.apply_local_change(wagtail).unwrap();
+        let changes = backend2.get_changes(&[]).into_iter().cloned().collect();
+        backend1.apply_changes(changes).unwrap();
+
+        let change_bytes = backend1.save().unwrap();
+        assert_eq!(
+            get_conflicts_json(change_bytes).unwrap(),
+            serde_json::json!([{
+                "path": "$[\"bird\"]",
+                "values": {
+                    robin_id: "robin",
+                    wagtail_id: "wagtail",
+                }
+            }])
+        )
+    }
+}
@@ -24,14 +24,19 @@ pub enum ExamineError {
 pub fn examine(
     mut input: impl std::io::Read,
     mut output: impl std::io::Write,
+    skip_verification: bool,
     is_tty: bool,
 ) -> Result<(), ExamineError> {
     let mut buf: Vec<u8> = Vec::new();
     input
         .read_to_end(&mut buf)
         .map_err(|e| ExamineError::ErrReadingChanges { source: e })?;
-    let changes = amb::Change::load_document(&buf)
-        .map_err(|e| ExamineError::ErrApplyingInitialChanges { source: e })?;
+    let changes = if skip_verification {
+        amb::Change::load_document_unverified(&buf)
+    } else {
+        amb::Change::load_document(&buf)
+    }
+    .map_err(|e| ExamineError::ErrApplyingInitialChanges { source: e })?;
     let uncompressed_changes: Vec<UncompressedChange> =
         changes.iter().map(|c| c.decode()).collect();
     if is_tty {
@@ -1,14 +1,8 @@
 use anyhow::Result;
 
 fn get_state_json(input_data: Vec<u8>) -> Result<serde_json::Value> {
-    let mut backend = automerge_backend::Backend::init();
-    let changes = automerge_backend::Change::load_document(&input_data)?;
-    let patch = backend.apply_changes(changes)?;
-
-    let mut frontend = automerge_frontend::Frontend::new();
-    frontend.apply_patch(patch)?;
-
-    Ok(frontend.state().to_json())
+    let mut doc = automerge::Document::load(input_data)?;
+    Ok(doc.value().to_json())
 }
 
 pub fn export_json(
@@ -1,15 +1,15 @@
 use anyhow::Result;
-use automerge_backend::Backend;
-use automerge_frontend::{Frontend, Value};
+use automerge::{Document, LocalChange, Path, Value};
 
 fn initialize_from_json(json_value: &serde_json::Value) -> Result<Vec<u8>> {
     let value: Value = Value::from_json(&json_value);
 
-    let (_, initial_change) = Frontend::new_with_initial_state(value)?;
-    let mut backend = Backend::init();
-    backend.apply_local_change(initial_change)?;
+    let mut doc = Document::new();
+    doc.change(Some("Initialization".to_string()), |d| {
+        d.add_change(LocalChange::set(Path::root(), value))
+    })?;
 
-    Ok(backend.save()?)
+    Ok(doc.save()?)
 }
 
 pub fn import_json(mut reader: impl std::io::Read, mut writer: impl std::io::Write) -> Result<()> {
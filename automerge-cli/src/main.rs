@@ -5,9 +5,11 @@ use std::path::PathBuf;
 use std::str::FromStr;
 
 mod change;
+mod conflicts;
 mod examine;
 mod export;
 mod import;
+mod status;
 
 #[derive(Debug, Clap)]
 #[clap(about = "Automerge CLI")]
@@ -64,7 +66,7 @@ enum Command {
     /// document to stdout or the specified output file.
     Change {
         /// The change script to perform. Change scripts have the form <command> <path> [<JSON value>].
-        /// The possible commands are 'set', 'insert', 'delete', and 'increment'.
+        /// The possible commands are 'set', 'insert', 'delete', 'increment' and 'resolve'.
         ///
         /// Paths look like this: $["mapkey"][0]. They always lways start with a '$', then each
         /// subsequent segment of the path is either a string in double quotes to index a key in a
@@ -87,6 +89,12 @@ enum Command {
         /// ## delete
         ///
         /// > automerge change 'delete $["someobject"]["items"]' somefile
+        ///
+        /// ## resolve
+        ///
+        /// Like set, but replaces every conflicting value at the path
+        ///
+        /// > automerge change 'resolve $["somekey"] "value"' somefile
         script: String,
 
         /// The file to change, if omitted will assume stdin
@@ -99,7 +107,31 @@ enum Command {
     },
 
     /// Read an automerge document and print a JSON representation of the changes in it to stdout
-    Examine { input_file: Option<PathBuf> },
+    Examine {
+        input_file: Option<PathBuf>,
+
+        /// Don't check checksums or heads, to see what can be recovered from a corrupted file
+        #[clap(long)]
+        skip_verification: bool,
+    },
+
+    /// Print every path in an automerge document which has conflicting values, along with the
+    /// values and the IDs of the operations which set them.
+    ///
+    /// A conflict can be resolved with `automerge change 'resolve <path> <value>'`
+    Conflicts {
+        /// Path that contains Automerge changes
+        #[clap(parse(from_os_str))]
+        changes_file: Option<PathBuf>,
+    },
+
+    /// Print the number of objects, operations, changes, actors and tombstones in an automerge
+    /// document, along with an estimate of the memory it uses once loaded.
+    Status {
+        /// Path that contains Automerge changes
+        #[clap(parse(from_os_str))]
+        changes_file: Option<PathBuf>,
+    },
 }
 
 fn open_file_or_stdin(maybe_path: Option<PathBuf>) -> Result<Box<dyn std::io::Read>> {
@@ -168,10 +200,34 @@ fn main() -> Result<()> {
             change::change(in_buffer, &mut out_buffer, script.as_str())
                 .map_err(|e| anyhow::format_err!("Unable to make changes: {:?}", e))
         }
-        Command::Examine { input_file } => {
+        Command::Conflicts { changes_file } => {
+            let mut in_buffer = open_file_or_stdin(changes_file)?;
+            conflicts::print_conflicts(
+                &mut in_buffer,
+                &mut std::io::stdout(),
+                atty::is(atty::Stream::Stdout),
+            )
+        }
+        Command::Status { changes_file } => {
+            let mut in_buffer = open_file_or_stdin(changes_file)?;
+            status::print_status(
+                &mut in_buffer,
+                &mut std::io::stdout(),
+                atty::is(atty::Stream::Stdout),
+            )
+        }
+        Command::Examine {
+            input_file,
+            skip_verification,
+        } => {
             let in_buffer = open_file_or_stdin(input_file)?;
             let out_buffer = std::io::stdout();
-            match examine::examine(in_buffer, out_buffer, atty::is(atty::Stream::Stdout)) {
+            match examine::examine(
+                in_buffer,
+                out_buffer,
+                skip_verification,
+                atty::is(atty::Stream::Stdout),
+            ) {
                 Ok(()) => {}
                 Err(e) => {
                     eprintln!("Error: {:?}", e);
@@ -0,0 +1,68 @@
+use anyhow::Result;
+
+fn get_status_json(input_data: Vec<u8>) -> Result<serde_json::Value> {
+    let mut backend = automerge_backend::Backend::init();
+    let changes = automerge_backend::Change::load_document(&input_data)?;
+    backend.load_changes(changes)?;
+
+    let stats = backend.stats();
+    let mut status = serde_json::to_value(&stats)?;
+    status["heapBytes"]["total"] = stats.heap_bytes.total().into();
+    Ok(status)
+}
+
+pub fn print_status(
+    mut changes_reader: impl std::io::Read,
+    mut writer: impl std::io::Write,
+    is_tty: bool,
+) -> Result<()> {
+    let mut input_data = vec![];
+    changes_reader.read_to_end(&mut input_data)?;
+
+    let status_json = get_status_json(input_data)?;
+    if is_tty {
+        colored_json::write_colored_json(&status_json, &mut writer).unwrap()
+    } else {
+        writeln!(
+            writer,
+            "{}",
+            serde_json::to_string_pretty(&status_json).unwrap()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use automerge_frontend::{Frontend, InvalidChangeRequest, LocalChange, Path};
+
+    #[test]
+    fn cli_status_of_an_empty_document() {
+        let status = get_status_json(vec![]).unwrap();
+        assert_eq!(status["objects"], 1);
+        assert_eq!(status["changes"], 0);
+        assert_eq!(status["heapBytes"]["changes"], 0);
+    }
+
+    #[test]
+    fn cli_status_counts_changes() {
+        let mut frontend = Frontend::new();
+        let mut backend = automerge_backend::Backend::init();
+        for bird in &["robin", "wagtail"] {
+            let change = frontend
+                .change::<_, InvalidChangeRequest>(None, |doc| {
+                    doc.add_change(LocalChange::set(Path::root().key("bird"), *bird))
+                })
+                .unwrap()
+                .unwrap();
+            backend.apply_local_change(change).unwrap();
+        }
+
+        let status = get_status_json(backend.save().unwrap()).unwrap();
+        assert_eq!(status["changes"], 2);
+        assert_eq!(status["actors"], 1);
+        assert_eq!(status["ops"], 1);
+        assert!(status["heapBytes"]["total"].as_u64().unwrap() > 0);
+    }
+}
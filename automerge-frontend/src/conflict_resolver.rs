@@ -0,0 +1,124 @@
+use crate::{Path, Value};
+use automerge_protocol as amp;
+use std::collections::HashMap;
+
+/// Decides which of a set of conflicting values is displayed.
+///
+/// By default the value with the highest `OpId` wins. A resolver only changes
+/// what `Frontend::state` and `Frontend::get_value` return, it does not change
+/// the document. To make a resolution permanent write the chosen value with
+/// `LocalChange::resolve_conflict`.
+pub trait ConflictResolver {
+    /// Returns the `OpId` of the value in `conflicts` which should be displayed
+    /// at `path`. Returning `None`, or an `OpId` which is not in `conflicts`,
+    /// falls back to the default resolution.
+    fn resolve(&self, path: &Path, conflicts: &HashMap<amp::OpId, Value>) -> Option<amp::OpId>;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: Fn(&Path, &HashMap<amp::OpId, Value>) -> Option<amp::OpId>,
+{
+    fn resolve(&self, path: &Path, conflicts: &HashMap<amp::OpId, Value>) -> Option<amp::OpId> {
+        self(path, conflicts)
+    }
+}
+
+/// Picks the value written by the change with the latest `time`, ties are
+/// broken by `OpId`.
+///
+/// The frontend does not know when an op was made, so the resolver needs to
+/// be given the changes which make up the document, e.g. by decoding the
+/// changes returned by `Backend::get_changes`.
+#[derive(Debug, Default, Clone)]
+pub struct LastWriterWins {
+    // For each actor the (start_op, end_op, time) of each change we know about
+    times: HashMap<amp::ActorId, Vec<(u64, u64, i64)>>,
+}
+
+impl LastWriterWins {
+    pub fn new() -> LastWriterWins {
+        LastWriterWins::default()
+    }
+
+    pub fn from_changes<'a, I>(changes: I) -> LastWriterWins
+    where
+        I: IntoIterator<Item = &'a amp::UncompressedChange>,
+    {
+        let mut lww = LastWriterWins::new();
+        for change in changes {
+            lww.add_change(change);
+        }
+        lww
+    }
+
+    pub fn add_change(&mut self, change: &amp::UncompressedChange) {
+        let end_op = change.start_op + change.operations.len() as u64;
+        self.times
+            .entry(change.actor_id.clone())
+            .or_default()
+            .push((change.start_op, end_op, change.time));
+    }
+
+    fn time_of(&self, opid: &amp::OpId) -> Option<i64> {
+        self.times.get(&opid.1).and_then(|ranges| {
+            ranges
+                .iter()
+                .find(|(start, end, _)| *start <= opid.0 && opid.0 < *end)
+                .map(|(_, _, time)| *time)
+        })
+    }
+}
+
+impl ConflictResolver for LastWriterWins {
+    fn resolve(&self, _path: &Path, conflicts: &HashMap<amp::OpId, Value>) -> Option<amp::OpId> {
+        conflicts
+            .keys()
+            .filter_map(|opid| self.time_of(opid).map(|t| (t, opid)))
+            .max()
+            .map(|(_, opid)| opid.clone())
+    }
+}
+
+/// Uses a different resolver for particular paths. Paths without a resolver
+/// of their own use the fallback, if there is one.
+#[derive(Default)]
+pub struct ResolveByPath {
+    resolvers: Vec<(Path, Box<dyn ConflictResolver>)>,
+    fallback: Option<Box<dyn ConflictResolver>>,
+}
+
+impl ResolveByPath {
+    pub fn new() -> ResolveByPath {
+        ResolveByPath::default()
+    }
+
+    /// Resolve conflicts at exactly `path` with `resolver`
+    pub fn with_path<R>(mut self, path: Path, resolver: R) -> ResolveByPath
+    where
+        R: ConflictResolver + 'static,
+    {
+        self.resolvers.push((path, Box::new(resolver)));
+        self
+    }
+
+    /// Resolve conflicts at any other path with `resolver`
+    pub fn with_fallback<R>(mut self, resolver: R) -> ResolveByPath
+    where
+        R: ConflictResolver + 'static,
+    {
+        self.fallback = Some(Box::new(resolver));
+        self
+    }
+}
+
+impl ConflictResolver for ResolveByPath {
+    fn resolve(&self, path: &Path, conflicts: &HashMap<amp::OpId, Value>) -> Option<amp::OpId> {
+        self.resolvers
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, r)| r)
+            .or(self.fallback.as_ref())
+            .and_then(|r| r.resolve(path, conflicts))
+    }
+}
@@ -94,6 +94,18 @@ pub enum InvalidChangeRequest {
     CannotSetNonMapObjectAsRoot { value: Value },
     #[error("attempted to increment an object which is not a counter at {path:?}")]
     IncrementForNonCounterObject { path: Path },
+    #[error("incrementing the counter at {path:?} would overflow: {source}")]
+    CounterOverflow {
+        path: Path,
+        #[source]
+        source: CounterOverflowError,
+    },
+    #[error("attempted to add a member to an object which is not a set at {path:?}")]
+    AddToSetForNonSetObject { path: Path },
+    #[error("attempted to add a row to an object which is not a table at {path:?}")]
+    AddRowForNonTableObject { path: Path },
+    #[error("table rows must be maps, attempted to add {value:?} at {path:?}")]
+    TableRowMustBeMap { path: Path, value: Value },
     #[error("attempted to insert using a path which does not end in an index: {path:?}")]
     InsertWithNonSequencePath { path: Path },
     #[error("attempted to insert into an object which is not a sequence at {path:?}")]
@@ -117,3 +129,10 @@ pub struct MissingIndexError {
     pub missing_index: usize,
     pub size_of_collection: usize,
 }
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Incrementing a counter with value {current_value} by {increment} overflows")]
+pub struct CounterOverflowError {
+    pub current_value: i64,
+    pub increment: i64,
+}
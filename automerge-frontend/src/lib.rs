@@ -2,14 +2,18 @@ use automerge_protocol::{
     ActorId, ChangeHash, MapType, ObjectId, Op, OpId, Patch, UncompressedChange,
 };
 
+mod conflict_resolver;
 mod error;
 mod mutation;
 mod path;
 mod state_tree;
+mod table;
 mod value;
 
+pub use conflict_resolver::{ConflictResolver, LastWriterWins, ResolveByPath};
 pub use error::{
-    AutomergeFrontendError, InvalidChangeRequest, InvalidInitialStateError, InvalidPatch,
+    AutomergeFrontendError, CounterOverflowError, InvalidChangeRequest, InvalidInitialStateError,
+    InvalidPatch,
 };
 pub use mutation::{LocalChange, MutableDocument};
 pub use path::Path;
@@ -18,7 +22,9 @@ use state_tree::ResolvedPath;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt;
 use std::time;
+pub use table::{Row, Rows};
 pub use value::{Conflicts, Cursor, Primitive, Value};
 
 /// Tracks the possible states of the frontend
@@ -237,9 +243,40 @@ impl FrontendState {
             FrontendState::Reconciled { root_state, .. } => root_state.value(),
         }
     }
+
+    fn resolved_value(&self, resolver: &dyn ConflictResolver) -> Value {
+        match self {
+            FrontendState::WaitingForInFlightRequests {
+                optimistically_updated_root_state,
+                ..
+            } => optimistically_updated_root_state.resolved_value(resolver),
+            FrontendState::Reconciled { root_state, .. } => root_state.resolved_value(resolver),
+        }
+    }
+
+    fn resolved_value_at(&self, path: &Path, resolver: &dyn ConflictResolver) -> Option<Value> {
+        match self {
+            FrontendState::WaitingForInFlightRequests {
+                optimistically_updated_root_state,
+                ..
+            } => optimistically_updated_root_state.resolved_value_at(path, resolver),
+            FrontendState::Reconciled { root_state, .. } => {
+                root_state.resolved_value_at(path, resolver)
+            }
+        }
+    }
+
+    fn conflicts(&self) -> Vec<(Path, HashMap<OpId, Value>)> {
+        match self {
+            FrontendState::WaitingForInFlightRequests {
+                optimistically_updated_root_state,
+                ..
+            } => optimistically_updated_root_state.conflicts(),
+            FrontendState::Reconciled { root_state, .. } => root_state.conflicts(),
+        }
+    }
 }
 
-#[derive(Debug)]
 pub struct Frontend {
     pub actor_id: ActorId,
     pub seq: u64,
@@ -249,6 +286,25 @@ pub struct Frontend {
     state: Option<FrontendState>,
     /// A cache of the value of this frontend
     cached_value: Option<Value>,
+    /// Decides which value is displayed for conflicts, if this is `None` the
+    /// value with the highest OpId is displayed
+    conflict_resolver: Option<Box<dyn ConflictResolver>>,
+    /// The state, actor and seq from before the last change made by
+    /// `change`, which `cancel_last_change` goes back to. Cleared when a
+    /// patch is applied.
+    before_last_change: Option<(FrontendState, ActorId, u64)>,
+}
+
+impl fmt::Debug for Frontend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Frontend")
+            .field("actor_id", &self.actor_id)
+            .field("seq", &self.seq)
+            .field("state", &self.state)
+            .field("cached_value", &self.cached_value)
+            .field("has_conflict_resolver", &self.conflict_resolver.is_some())
+            .finish()
+    }
 }
 
 impl Default for Frontend {
@@ -269,9 +325,28 @@ impl Frontend {
                 deps_of_last_received_patch: Vec::new(),
             }),
             cached_value: None,
+            conflict_resolver: None,
+            before_last_change: None,
         }
     }
 
+    /// Use `resolver` to decide which value is displayed wherever there is a
+    /// conflict. This only affects `state`, `get_value` and `value_at_path`,
+    /// mutations and `get_conflicts` are unchanged.
+    pub fn set_conflict_resolver<R>(&mut self, resolver: R)
+    where
+        R: ConflictResolver + 'static,
+    {
+        self.conflict_resolver = Some(Box::new(resolver));
+        self.cached_value = None;
+    }
+
+    /// Go back to displaying the value with the highest OpId for conflicts
+    pub fn clear_conflict_resolver(&mut self) {
+        self.conflict_resolver = None;
+        self.cached_value = None;
+    }
+
     pub fn new_with_initial_state(
         initial_state: Value,
     ) -> Result<(Self, UncompressedChange), InvalidInitialStateError> {
@@ -321,7 +396,11 @@ impl Frontend {
         if let Some(ref v) = self.cached_value {
             v
         } else {
-            let value = self.state.as_ref().unwrap().value();
+            let state = self.state.as_ref().unwrap();
+            let value = match &self.conflict_resolver {
+                Some(resolver) => state.resolved_value(resolver.as_ref()),
+                None => state.value(),
+            };
             self.cached_value = Some(value);
             self.cached_value.as_ref().unwrap()
         }
@@ -337,8 +416,10 @@ impl Frontend {
         F: FnOnce(&mut dyn MutableDocument) -> Result<(), E>,
     {
         let start_op = self.state.as_ref().unwrap().max_op() + 1;
-        // TODO this leaves the `state` as `None` if there's an error, it shouldn't
-        let change_result = self.state.take().unwrap().optimistically_apply_change(
+        // Cloning the state is cheap and means it is left untouched if the
+        // closure fails
+        let before = self.state.clone().unwrap();
+        let change_result = before.clone().optimistically_apply_change(
             &self.actor_id,
             change_closure,
             self.seq + 1,
@@ -346,6 +427,7 @@ impl Frontend {
         self.cached_value = None;
         self.state = Some(change_result.new_state);
         if let Some(ops) = change_result.ops {
+            self.before_last_change = Some((before, self.actor_id.clone(), self.seq));
             self.seq += 1;
             let change = UncompressedChange {
                 start_op,
@@ -364,9 +446,27 @@ impl Frontend {
         }
     }
 
+    /// Undo the last change made by `change`, for when the backend refuses
+    /// it, so the frontend doesn't wait for a patch which won't come. Returns
+    /// false if there is nothing to undo: no change has been made, it has
+    /// already been undone, a patch has been applied since or the actor has
+    /// changed.
+    pub fn cancel_last_change(&mut self) -> bool {
+        match self.before_last_change.take() {
+            Some((state, actor, seq)) if actor == self.actor_id => {
+                self.state = Some(state);
+                self.seq = seq;
+                self.cached_value = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn apply_patch(&mut self, patch: Patch) -> Result<(), InvalidPatch> {
         // TODO this leaves the `state` as `None` if there's an error, it shouldn't
         self.cached_value = None;
+        self.before_last_change = None;
         let new_state = self
             .state
             .take()
@@ -401,12 +501,47 @@ impl Frontend {
             .map(|o| o.values())
     }
 
+    /// Every path in the document which currently has more than one value,
+    /// with the values at that path, ordered by path
+    pub fn conflicts(&self) -> Vec<(Path, HashMap<OpId, Value>)> {
+        self.state
+            .as_ref()
+            .map(|s| s.conflicts())
+            .unwrap_or_default()
+    }
+
     pub fn get_value(&self, path: &Path) -> Option<Value> {
-        self.state.as_ref().and_then(|s| s.get_value(path))
+        match &self.conflict_resolver {
+            Some(resolver) => self
+                .state
+                .as_ref()
+                .and_then(|s| s.resolved_value_at(path, resolver.as_ref())),
+            // `resolve_path` can't follow a path into a value which lost a
+            // conflict, which the default resolution can
+            None if path.has_conflict() => self.state.as_ref().and_then(|s| {
+                s.resolved_value_at(path, &|_: &Path, _: &HashMap<OpId, Value>| None)
+            }),
+            None => self.state.as_ref().and_then(|s| s.get_value(path)),
+        }
+    }
+
+    /// The rows of the table at `path`, or `None` if there is no table at
+    /// `path`
+    pub fn table_rows(&self, path: &Path) -> Option<Rows> {
+        self.get_value(path).and_then(Rows::from_table)
+    }
+
+    /// The row with ID `row_id` in the table at `path`
+    pub fn table_row(&self, path: &Path, row_id: &str) -> Option<Row> {
+        self.table_rows(path)
+            .and_then(|rows| rows.into_iter().find(|r| r.id() == row_id))
     }
 
     /// Returns the value given by path, if it exists
     pub fn value_at_path(&self, path: &Path) -> Option<Value> {
+        if self.conflict_resolver.is_some() {
+            return self.get_value(path);
+        }
         self.state
             .as_ref()
             .and_then(|s| s.resolve_path(&path))
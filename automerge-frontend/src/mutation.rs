@@ -3,19 +3,69 @@ use crate::state_tree::{LocalOperationResult, SetOrInsertPayload, StateTree, Tar
 use crate::value::{Cursor, Primitive, Value};
 use crate::{Path, PathElement};
 use automerge_protocol as amp;
+use std::collections::HashSet;
 
 pub trait MutableDocument {
     fn value_at_path(&self, path: &Path) -> Option<Value>;
+    /// The type of the object at `path`, or `None` if the value there is a
+    /// primitive or there isn't one. Unlike `value_at_path` this doesn't need
+    /// to build the object's contents, nor do `keys_at_path` and
+    /// `len_at_path`.
+    fn object_type_at_path(&self, path: &Path) -> Option<amp::ObjType> {
+        match self.value_at_path(path)? {
+            Value::Map(_, map_type) => Some(amp::ObjType::Map(map_type)),
+            Value::Set(_) => Some(amp::ObjType::set()),
+            Value::Sequence(_) => Some(amp::ObjType::list()),
+            Value::Text(_) => Some(amp::ObjType::text()),
+            Value::Primitive(_) => None,
+        }
+    }
+    /// The keys of the map or table at `path`
+    fn keys_at_path(&self, path: &Path) -> Option<Vec<String>> {
+        match self.value_at_path(path)? {
+            Value::Map(props, _) => Some(props.into_keys().collect()),
+            _ => None,
+        }
+    }
+    /// The length of the list or text at `path`
+    fn len_at_path(&self, path: &Path) -> Option<usize> {
+        match self.value_at_path(path)? {
+            Value::Sequence(elems) => Some(elems.len()),
+            Value::Text(chars) => Some(chars.len()),
+            _ => None,
+        }
+    }
     fn cursor_to_path(&self, path: &Path) -> Option<Cursor>;
     fn add_change(&mut self, change: LocalChange) -> Result<(), InvalidChangeRequest>;
+    /// Add `row` to the table at `path`, returning the ID of the new row. By
+    /// default the new row is found by comparing the table's row IDs before
+    /// and after adding it.
+    fn add_row(&mut self, path: &Path, row: Value) -> Result<String, InvalidChangeRequest> {
+        let before = table_row_ids(self, path);
+        self.add_change(LocalChange::add_row(path.clone(), row))?;
+        table_row_ids(self, path)
+            .into_iter()
+            .find(|id| !before.contains(id))
+            .ok_or_else(|| InvalidChangeRequest::NoSuchPathError { path: path.clone() })
+    }
+}
+
+fn table_row_ids<D: MutableDocument + ?Sized>(doc: &D, path: &Path) -> HashSet<String> {
+    match doc.value_at_path(path) {
+        Some(Value::Map(rows, amp::MapType::Table)) => rows.into_keys().collect(),
+        _ => HashSet::new(),
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum LocalOperation {
     Set(Value),
     Delete,
-    Increment(u32),
+    Increment(i64),
     Insert(Value),
+    AddToSet(String),
+    AddRow(Value),
+    ResolveConflict(Value),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -36,6 +86,18 @@ impl LocalChange {
         }
     }
 
+    /// Set the value at `path` to `value`, replacing every conflicting value at
+    /// `path` rather than just the one which is currently displayed
+    pub fn resolve_conflict<TV>(path: Path, value: TV) -> LocalChange
+    where
+        TV: Into<Value>,
+    {
+        LocalChange {
+            path,
+            operation: LocalOperation::ResolveConflict(value.into()),
+        }
+    }
+
     /// Delete the entry at `path`
     pub fn delete(path: Path) -> LocalChange {
         LocalChange {
@@ -53,19 +115,54 @@ impl LocalChange {
     }
 
     /// Increment the counter at path by a (possibly negative) amount `by`
-    pub fn increment_by(path: Path, by: u32) -> LocalChange {
+    pub fn increment_by(path: Path, by: i64) -> LocalChange {
         LocalChange {
             path,
             operation: LocalOperation::Increment(by),
         }
     }
 
+    /// Decrement the counter at `path` by 1
+    pub fn decrement(path: Path) -> LocalChange {
+        LocalChange {
+            path,
+            operation: LocalOperation::Increment(-1),
+        }
+    }
+
     pub fn insert(path: Path, value: Value) -> LocalChange {
         LocalChange {
             path,
             operation: LocalOperation::Insert(value),
         }
     }
+
+    /// Add `row` to the table at `path`. The row is stored under a key derived
+    /// from the ID of the operation which creates it, use
+    /// `MutableDocument::add_row` if you need to know the ID of the new row.
+    pub fn add_row(path: Path, row: Value) -> LocalChange {
+        LocalChange {
+            path,
+            operation: LocalOperation::AddRow(row),
+        }
+    }
+
+    /// Add `member` to the set at `path`
+    pub fn add_to_set<S: Into<String>>(path: Path, member: S) -> LocalChange {
+        LocalChange {
+            path,
+            operation: LocalOperation::AddToSet(member.into()),
+        }
+    }
+
+    /// Remove `member` from the set at `path`. Any concurrent adds of `member`
+    /// which this actor has not seen will win over this removal.
+    pub fn remove_from_set<S: Into<String>>(path: Path, member: S) -> LocalChange {
+        LocalChange {
+            path: path.key(member),
+            operation: LocalOperation::Delete,
+        }
+    }
 }
 
 /// `MutationTracker` is used as the context in which a mutation closure is
@@ -126,6 +223,12 @@ impl MutationTracker {
         self.max_op += change.new_ops.len() as u64;
         self.ops.extend(change.new_ops);
     }
+
+    /// The ID of the next row added to a table, this is the ID of the op which
+    /// will create the row
+    fn next_row_id(&self) -> String {
+        self.actor_id.op_id_at(self.max_op + 1).to_string()
+    }
 }
 
 impl MutableDocument for MutationTracker {
@@ -133,6 +236,18 @@ impl MutableDocument for MutationTracker {
         self.state.resolve_path(path).map(|r| r.default_value())
     }
 
+    fn object_type_at_path(&self, path: &Path) -> Option<amp::ObjType> {
+        self.state.object_type_at(path)
+    }
+
+    fn keys_at_path(&self, path: &Path) -> Option<Vec<String>> {
+        self.state.keys_at(path)
+    }
+
+    fn len_at_path(&self, path: &Path) -> Option<usize> {
+        self.state.len_at(path)
+    }
+
     fn cursor_to_path(&self, path: &Path) -> Option<Cursor> {
         if let Some(PathElement::Index(i)) = path.name() {
             if let Some(parent) = self.state.resolve_path(&path.parent()) {
@@ -149,6 +264,12 @@ impl MutableDocument for MutationTracker {
         }
     }
 
+    fn add_row(&mut self, path: &Path, row: Value) -> Result<String, InvalidChangeRequest> {
+        let row_id = self.next_row_id();
+        self.add_change(LocalChange::add_row(path.clone(), row))?;
+        Ok(row_id)
+    }
+
     fn add_change(&mut self, change: LocalChange) -> Result<(), InvalidChangeRequest> {
         match &change.operation {
             LocalOperation::Set(value) => {
@@ -214,6 +335,9 @@ impl MutableDocument for MutationTracker {
                                     path: change.path.clone(),
                                 })
                             }
+                            (PathElement::Conflict(_), _) => {
+                                Err(InvalidChangeRequest::NoSuchPathError { path: change.path })
+                            }
                         }
                     } else {
                         Err(InvalidChangeRequest::NoSuchPathError { path: change.path })
@@ -268,6 +392,14 @@ impl MutableDocument for MutationTracker {
                                     })
                                 }
                             },
+                            Target::Set(s) => match name {
+                                PathElement::Key(k) => s.remove(k),
+                                _ => {
+                                    return Err(InvalidChangeRequest::NoSuchPathError {
+                                        path: change.path,
+                                    })
+                                }
+                            },
                             Target::Character(_) => {
                                 return Err(InvalidChangeRequest::NoSuchPathError {
                                     path: change.path,
@@ -296,7 +428,13 @@ impl MutableDocument for MutationTracker {
                     if let Some(pr) = self.state.resolve_path(&change.path) {
                         match pr.target {
                             Target::Counter(counter_target) => {
-                                self.apply_state_change(counter_target.increment(*by as i64));
+                                let result = counter_target.increment(*by).map_err(|e| {
+                                    InvalidChangeRequest::CounterOverflow {
+                                        path: change.path.clone(),
+                                        source: e,
+                                    }
+                                })?;
+                                self.apply_state_change(result);
                                 Ok(())
                             }
                             _ => Err(InvalidChangeRequest::IncrementForNonCounterObject {
@@ -312,6 +450,75 @@ impl MutableDocument for MutationTracker {
                     })
                 }
             }
+            LocalOperation::AddToSet(member) => {
+                if let Some(pr) = self.state.resolve_path(&change.path) {
+                    match pr.target {
+                        Target::Set(set_target) => {
+                            let payload = SetOrInsertPayload {
+                                start_op: self.max_op + 1,
+                                actor: &self.actor_id.clone(),
+                                value: member.as_str(),
+                            };
+                            self.apply_state_change(set_target.add(payload));
+                            Ok(())
+                        }
+                        _ => Err(InvalidChangeRequest::AddToSetForNonSetObject {
+                            path: change.path.clone(),
+                        }),
+                    }
+                } else {
+                    Err(InvalidChangeRequest::NoSuchPathError { path: change.path })
+                }
+            }
+            LocalOperation::ResolveConflict(value) => {
+                if change.path.is_root() {
+                    return self.add_change(LocalChange::set(change.path, value.clone()));
+                }
+                let mut conflicting: Vec<amp::OpId> = match self.state.resolve_path(&change.path) {
+                    Some(resolved) => resolved.values().keys().cloned().collect(),
+                    None => {
+                        return Err(InvalidChangeRequest::NoSuchPathError { path: change.path })
+                    }
+                };
+                conflicting.sort();
+                let first_new_op = self.ops.len();
+                // Setting replaces every value of the register in the local
+                // state, so the conflict is gone before the backend replies
+                self.add_change(LocalChange::set(change.path, value.clone()))?;
+                // The first op generated by a set is the one which assigns to
+                // the key, the rest populate the new value
+                if let Some(op) = self.ops.get_mut(first_new_op) {
+                    op.pred = conflicting;
+                }
+                Ok(())
+            }
+            LocalOperation::AddRow(row) => {
+                if !matches!(row, Value::Map(_, amp::MapType::Map)) {
+                    return Err(InvalidChangeRequest::TableRowMustBeMap {
+                        path: change.path,
+                        value: row.clone(),
+                    });
+                }
+                if let Some(pr) = self.state.resolve_path(&change.path) {
+                    match pr.target {
+                        Target::Table(table_target) => {
+                            let row_id = self.next_row_id();
+                            let payload = SetOrInsertPayload {
+                                start_op: self.max_op + 1,
+                                actor: &self.actor_id.clone(),
+                                value: row,
+                            };
+                            self.apply_state_change(table_target.set_key(&row_id, payload));
+                            Ok(())
+                        }
+                        _ => Err(InvalidChangeRequest::AddRowForNonTableObject {
+                            path: change.path.clone(),
+                        }),
+                    }
+                } else {
+                    Err(InvalidChangeRequest::NoSuchPathError { path: change.path })
+                }
+            }
             LocalOperation::Insert(value) => {
                 if let Some(name) = change.path.name() {
                     let index = match name {
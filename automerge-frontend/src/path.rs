@@ -1,12 +1,16 @@
+use automerge_protocol as amp;
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum PathElement {
     Key(String),
     Index(u32),
+    /// One of the conflicting values at the path so far, rather than the
+    /// one which won
+    Conflict(amp::OpId),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Path(Vec<PathElement>);
 
 impl Path {
@@ -24,6 +28,14 @@ impl Path {
         self
     }
 
+    /// The value written by `opid` out of the conflicting values at this
+    /// path. `Frontend::conflicts` uses this for conflicts inside a value
+    /// which isn't the one shown, such a path can be read but not changed.
+    pub fn conflict(mut self, opid: amp::OpId) -> Path {
+        self.0.push(PathElement::Conflict(opid));
+        self
+    }
+
     pub fn parent(&self) -> Self {
         if self.0.is_empty() {
             Path(Vec::new())
@@ -46,6 +58,13 @@ impl Path {
     pub(crate) fn is_root(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Whether the path goes through a value which lost a conflict
+    pub(crate) fn has_conflict(&self) -> bool {
+        self.0
+            .iter()
+            .any(|elem| matches!(elem, PathElement::Conflict(_)))
+    }
 }
 
 impl fmt::Display for PathElement {
@@ -53,6 +72,28 @@ impl fmt::Display for PathElement {
         match self {
             PathElement::Key(k) => write!(f, "{}", k),
             PathElement::Index(i) => write!(f, "{}", i),
+            PathElement::Conflict(opid) => write!(f, "@{}", opid),
+        }
+    }
+}
+
+/// Formats the path in the same syntax the CLI accepts, e.g. `$["birds"][0]`.
+/// Keys are written as JSON strings, so quotes, backslashes and control
+/// characters are escaped as in JSON. A conflicting value is written as
+/// `[@<opid>]`, which the CLI doesn't accept.
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$")?;
+        for elem in &self.0 {
+            match elem {
+                PathElement::Key(k) => {
+                    let key = serde_json::to_string(k).map_err(|_| fmt::Error)?;
+                    write!(f, "[{}]", key)?
+                }
+                PathElement::Index(i) => write!(f, "[{}]", i)?,
+                PathElement::Conflict(opid) => write!(f, "[@{}]", opid)?,
+            }
         }
+        Ok(())
     }
 }
@@ -258,12 +258,17 @@ where
         self.underlying.len()
     }
 
+    /// Replace the value at `index`. The element keeps the ID of the op which
+    /// inserted it, which is not the same as the ID of the op which set the
+    /// new value.
     pub(super) fn update(&self, index: usize, value: T) -> Self {
+        let elem_id = self
+            .underlying
+            .get(index)
+            .map(|(elem_id, _)| elem_id.clone())
+            .unwrap_or_else(|| value.default_opid());
         DiffableSequence {
-            underlying: Box::new(
-                self.underlying
-                    .update(index, (value.default_opid(), Some(value))),
-            ),
+            underlying: Box::new(self.underlying.update(index, (elem_id, Some(value)))),
         }
     }
 
@@ -271,6 +276,13 @@ where
         self.underlying.get(index).and_then(|(_, v)| v.as_ref())
     }
 
+    /// The element ID and value at `index`
+    pub(super) fn get_with_elem_id(&self, index: usize) -> Option<(&amp::OpId, &T)> {
+        self.underlying
+            .get(index)
+            .and_then(|(elem_id, v)| v.as_ref().map(|v| (elem_id, v)))
+    }
+
     pub(super) fn insert(&mut self, index: usize, value: T) {
         self.underlying
             .insert(index, (value.default_opid(), Some(value)))
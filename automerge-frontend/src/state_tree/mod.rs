@@ -1,8 +1,8 @@
 use crate::error;
-use crate::{Cursor, Primitive, Value};
+use crate::{ConflictResolver, Cursor, Primitive, Value};
 use crate::{Path, PathElement};
 use automerge_protocol as amp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 
 mod diff_application_result;
@@ -276,6 +276,8 @@ impl StateTree {
                             },
                             _ => return None,
                         },
+                        // Only the value which won can be changed
+                        PathElement::Conflict(_) => return None,
                     };
                 }
                 let resolved_path = match current_obj.default_statetree_value() {
@@ -300,6 +302,9 @@ impl StateTree {
                         Some(StateTreeComposite::List(l)) => {
                             ResolvedPath::new_list(self, current_obj, focus, l.clone())
                         }
+                        Some(StateTreeComposite::Set(s)) => {
+                            ResolvedPath::new_set(self, current_obj, focus, s.clone())
+                        }
                         Some(StateTreeComposite::Text(t)) => ResolvedPath::new_text(
                             self,
                             current_obj,
@@ -327,6 +332,114 @@ impl StateTree {
             .get(object_id)
             .map(|o| o.realise_value(&self.objects))
     }
+
+    /// The value of the document, using `resolver` to decide which value to
+    /// show wherever there is a conflict
+    pub fn resolved_value(&self, resolver: &dyn ConflictResolver) -> Value {
+        self.objects
+            .get(&amp::ObjectId::Root)
+            .map(|o| o.resolved_value(&self.objects, resolver, Path::root()))
+            .unwrap()
+    }
+
+    /// The value at `path`, using `resolver` to decide which value to show
+    /// wherever there is a conflict along `path` or inside the value. Only the
+    /// objects along `path` and inside the value are visited.
+    pub fn resolved_value_at(&self, path: &Path, resolver: &dyn ConflictResolver) -> Option<Value> {
+        let mut current = StateTreeValue::Link(amp::ObjectId::Root);
+        let mut current_path = Path::root();
+        // The register `current` was picked from, if any
+        let mut register: Option<&MultiValue> = None;
+        for elem in path.clone().elements() {
+            let object = match (&current, &elem) {
+                (_, PathElement::Conflict(opid)) => {
+                    current = register?.value_for(opid)?;
+                    current_path = current_path.conflict(opid.clone());
+                    continue;
+                }
+                (StateTreeValue::Link(object_id), _) => self.objects.get(object_id)?,
+                (StateTreeValue::Leaf(_), _) => return None,
+            };
+            let (multivalue, next_path) = match (object, elem) {
+                (StateTreeComposite::Map(StateTreeMap { props, .. }), PathElement::Key(k))
+                | (StateTreeComposite::Table(StateTreeTable { props, .. }), PathElement::Key(k)) => {
+                    (props.get(&k)?, current_path.key(k))
+                }
+                (StateTreeComposite::List(list), PathElement::Index(i)) => {
+                    (list.elements.get(i as usize)?, current_path.index(i))
+                }
+                (StateTreeComposite::Text(text), PathElement::Index(i)) => {
+                    let c = text.chars.get(i as usize)?.default_char();
+                    current = StateTreeValue::Leaf(Primitive::Str(c.to_string()));
+                    current_path = current_path.index(i);
+                    register = None;
+                    continue;
+                }
+                _ => return None,
+            };
+            current = multivalue.resolved_winner(&self.objects, resolver, &next_path);
+            current_path = next_path;
+            register = Some(multivalue);
+        }
+        Some(current.resolved_value(&self.objects, resolver, current_path))
+    }
+
+    /// The type of the object at `path`, or `None` if the value there is a
+    /// primitive or there isn't one
+    pub(crate) fn object_type_at(&self, path: &Path) -> Option<amp::ObjType> {
+        self.object_at(path).map(StateTreeComposite::obj_type)
+    }
+
+    /// The keys of the map or table at `path`
+    pub(crate) fn keys_at(&self, path: &Path) -> Option<Vec<String>> {
+        match self.object_at(path)? {
+            StateTreeComposite::Map(StateTreeMap { props, .. })
+            | StateTreeComposite::Table(StateTreeTable { props, .. }) => {
+                Some(props.keys().cloned().collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// The length of the list or text at `path`
+    pub(crate) fn len_at(&self, path: &Path) -> Option<usize> {
+        match self.object_at(path)? {
+            StateTreeComposite::List(list) => Some(list.elements.len()),
+            StateTreeComposite::Text(text) => Some(text.chars.len()),
+            _ => None,
+        }
+    }
+
+    /// The object at `path`, following the default value at each step like
+    /// `resolve_path` but without building anything along the way
+    fn object_at(&self, path: &Path) -> Option<&StateTreeComposite> {
+        let mut object = self.objects.get(&amp::ObjectId::Root)?;
+        for elem in path.clone().elements() {
+            let multivalue = match (object, elem) {
+                (StateTreeComposite::Map(StateTreeMap { props, .. }), PathElement::Key(k))
+                | (StateTreeComposite::Table(StateTreeTable { props, .. }), PathElement::Key(k)) => {
+                    props.get(&k)?
+                }
+                (StateTreeComposite::List(list), PathElement::Index(i)) => {
+                    list.elements.get(i as usize)?
+                }
+                _ => return None,
+            };
+            object = self.objects.get(multivalue.default_object_id()?)?;
+        }
+        Some(object)
+    }
+
+    /// Every path which currently has more than one value, along with the
+    /// values, ordered by path
+    pub fn conflicts(&self) -> Vec<(Path, HashMap<amp::OpId, Value>)> {
+        let mut conflicts = Vec::new();
+        if let Some(root) = self.objects.get(&amp::ObjectId::Root) {
+            root.collect_conflicts(&self.objects, Path::root(), &mut conflicts);
+        }
+        conflicts.sort_by(|(a, _), (b, _)| a.cmp(b));
+        conflicts
+    }
 }
 
 /// A node in the state tree is either a leaf node containing a scalarvalue,
@@ -341,6 +454,7 @@ enum StateTreeValue {
 enum StateTreeComposite {
     Map(StateTreeMap),
     Table(StateTreeTable),
+    Set(StateTreeSet),
     Text(StateTreeText),
     List(StateTreeList),
 }
@@ -400,6 +514,23 @@ impl StateTreeComposite {
                             .map(|d| d.map(StateTreeComposite::Table))
                     }
                 }
+                StateTreeComposite::Set(set) => {
+                    if *obj_type != amp::MapType::Set {
+                        Err(error::InvalidPatch::MismatchingObjectType {
+                            object_id: set.object_id.clone(),
+                            patch_expected_type: Some(amp::ObjType::Map(*obj_type)),
+                            actual_type: Some(self.obj_type()),
+                        })
+                    } else {
+                        set.apply_diff(&DiffToApply {
+                            parent_object_id: diff.parent_object_id,
+                            parent_key: diff.parent_key,
+                            current_objects: diff.current_objects.clone(),
+                            diff: prop_diffs,
+                        })
+                        .map(|d| d.map(StateTreeComposite::Set))
+                    }
+                }
                 _ => Err(error::InvalidPatch::MismatchingObjectType {
                     object_id: self.object_id(),
                     patch_expected_type: diff_object_type(diff.diff),
@@ -472,6 +603,7 @@ impl StateTreeComposite {
         match self {
             Self::Map(..) => amp::ObjType::map(),
             Self::Table(..) => amp::ObjType::table(),
+            Self::Set(..) => amp::ObjType::set(),
             Self::Text(..) => amp::ObjType::text(),
             Self::List(..) => amp::ObjType::list(),
         }
@@ -481,6 +613,7 @@ impl StateTreeComposite {
         match self {
             Self::Map(StateTreeMap { object_id, .. }) => object_id.clone(),
             Self::Table(StateTreeTable { object_id, .. }) => object_id.clone(),
+            Self::Set(StateTreeSet { object_id, .. }) => object_id.clone(),
             Self::Text(StateTreeText { object_id, .. }) => object_id.clone(),
             Self::List(StateTreeList { object_id, .. }) => object_id.clone(),
         }
@@ -502,6 +635,9 @@ impl StateTreeComposite {
                     .collect(),
                 amp::MapType::Table,
             ),
+            Self::Set(StateTreeSet { members, .. }) => {
+                Value::Set(members.keys().cloned().collect::<HashSet<String>>())
+            }
             Self::List(StateTreeList {
                 elements: elems, ..
             }) => Value::Sequence(elems.iter().map(|e| e.default_value(objects)).collect()),
@@ -511,6 +647,71 @@ impl StateTreeComposite {
         }
     }
 
+    fn resolved_value(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        resolver: &dyn ConflictResolver,
+        path: Path,
+    ) -> Value {
+        match self {
+            Self::Map(StateTreeMap { props, .. }) => Value::Map(
+                props
+                    .iter()
+                    .map(|(k, v)| {
+                        let value = v.resolved_value(objects, resolver, path.clone().key(k));
+                        (k.clone(), value)
+                    })
+                    .collect(),
+                amp::MapType::Map,
+            ),
+            Self::Table(StateTreeTable { props, .. }) => Value::Map(
+                props
+                    .iter()
+                    .map(|(k, v)| {
+                        let value = v.resolved_value(objects, resolver, path.clone().key(k));
+                        (k.clone(), value)
+                    })
+                    .collect(),
+                amp::MapType::Table,
+            ),
+            Self::List(StateTreeList {
+                elements: elems, ..
+            }) => Value::Sequence(
+                elems
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| e.resolved_value(objects, resolver, path.clone().index(i as u32)))
+                    .collect(),
+            ),
+            // Set members and characters are not registers, there is nothing
+            // to resolve
+            Self::Set(..) | Self::Text(..) => self.realise_value(objects),
+        }
+    }
+
+    fn collect_conflicts(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        path: Path,
+        conflicts: &mut Vec<(Path, HashMap<amp::OpId, Value>)>,
+    ) {
+        match self {
+            Self::Map(StateTreeMap { props, .. }) | Self::Table(StateTreeTable { props, .. }) => {
+                for (k, v) in props.iter() {
+                    v.collect_conflicts(objects, path.clone().key(k), conflicts);
+                }
+            }
+            Self::List(StateTreeList {
+                elements: elems, ..
+            }) => {
+                for (i, e) in elems.iter().enumerate() {
+                    e.collect_conflicts(objects, path.clone().index(i as u32), conflicts);
+                }
+            }
+            Self::Set(..) | Self::Text(..) => {}
+        }
+    }
+
     fn mutably_update_cursor(&mut self, cursor: &CursorState) {
         let cursor_value = Primitive::Cursor(Cursor::new(
             cursor.index as u32,
@@ -543,6 +744,7 @@ impl StateTreeValue {
             amp::Diff::Value(v) => {
                 let value = match v {
                     amp::ScalarValue::Str(s) => Primitive::Str(s.clone()),
+                    amp::ScalarValue::Bytes(b) => Primitive::Bytes(b.clone()),
                     amp::ScalarValue::Int(i) => Primitive::Int(*i),
                     amp::ScalarValue::Uint(u) => Primitive::Uint(*u),
                     amp::ScalarValue::F64(f) => Primitive::F64(*f),
@@ -551,6 +753,10 @@ impl StateTreeValue {
                     amp::ScalarValue::Timestamp(i) => Primitive::Timestamp(*i),
                     amp::ScalarValue::Boolean(b) => Primitive::Boolean(*b),
                     amp::ScalarValue::Null => Primitive::Null,
+                    amp::ScalarValue::Unknown { type_code, bytes } => Primitive::Unknown {
+                        type_code: *type_code,
+                        bytes: bytes.clone(),
+                    },
                     amp::ScalarValue::Cursor(..) => {
                         return Err(error::InvalidPatch::ValueDiffContainedCursor)
                     }
@@ -570,6 +776,10 @@ impl StateTreeValue {
                     object_id: object_id.clone(),
                     props: im_rc::HashMap::new(),
                 }),
+                amp::MapType::Set => StateTreeComposite::Set(StateTreeSet {
+                    object_id: object_id.clone(),
+                    members: im_rc::HashMap::new(),
+                }),
             }
             .apply_diff(&diff)
             .map(|d| d.map(|c| StateTreeValue::Link(c.object_id()))),
@@ -630,6 +840,34 @@ impl StateTreeValue {
                 .realise_value(objects),
         }
     }
+
+    fn resolved_value(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        resolver: &dyn ConflictResolver,
+        path: Path,
+    ) -> Value {
+        match self {
+            StateTreeValue::Leaf(p) => p.clone().into(),
+            StateTreeValue::Link(target_id) => objects
+                .get(target_id)
+                .expect("missing object")
+                .resolved_value(objects, resolver, path),
+        }
+    }
+
+    fn collect_conflicts(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        path: Path,
+        conflicts: &mut Vec<(Path, HashMap<amp::OpId, Value>)>,
+    ) {
+        if let StateTreeValue::Link(target_id) = self {
+            if let Some(obj) = objects.get(target_id) {
+                obj.collect_conflicts(objects, path, conflicts);
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -856,6 +1094,105 @@ impl StateTreeTable {
     }
 }
 
+/// An add-wins set. Each member is stored as a key whose value is `true`, we
+/// keep the full `MultiValue` for each member so that a removal can use every
+/// add we have observed as its `pred`.
+#[derive(Debug, Clone)]
+struct StateTreeSet {
+    object_id: amp::ObjectId,
+    members: im_rc::HashMap<String, MultiValue>,
+}
+
+impl StateTreeSet {
+    fn update(&self, member: String, value: MultiValue) -> StateTreeSet {
+        StateTreeSet {
+            object_id: self.object_id.clone(),
+            members: self.members.update(member, value),
+        }
+    }
+
+    fn without(&self, member: &str) -> StateTreeSet {
+        StateTreeSet {
+            object_id: self.object_id.clone(),
+            members: self.members.without(member),
+        }
+    }
+
+    fn apply_diff<K>(
+        &self,
+        prop_diffs: &DiffToApply<K, &HashMap<String, HashMap<amp::OpId, amp::Diff>>>,
+    ) -> Result<DiffApplicationResult<StateTreeSet>, error::InvalidPatch>
+    where
+        K: Into<amp::Key>,
+    {
+        let mut new_members = self.members.clone();
+        let mut changes = StateTreeChange::empty();
+        for (member, member_diff) in prop_diffs.diff.iter() {
+            let mut diff_iter = member_diff.iter();
+            match diff_iter.next() {
+                None => new_members = new_members.without(member),
+                Some((opid, diff)) => {
+                    let mut node_diffapp = match new_members.get(member) {
+                        Some(n) => n.apply_diff(
+                            opid,
+                            DiffToApply {
+                                current_objects: prop_diffs.current_objects.clone(),
+                                parent_object_id: &self.object_id,
+                                parent_key: member,
+                                diff,
+                            },
+                        )?,
+                        None => MultiValue::new_from_diff(
+                            opid.clone(),
+                            DiffToApply {
+                                current_objects: prop_diffs.current_objects.clone(),
+                                parent_object_id: &self.object_id,
+                                parent_key: member,
+                                diff,
+                            },
+                        )?,
+                    };
+                    node_diffapp = node_diffapp.try_and_then(move |n| {
+                        n.apply_diff_iter(&mut diff_iter.map(|(oid, diff)| {
+                            (
+                                oid,
+                                DiffToApply {
+                                    current_objects: prop_diffs.current_objects.clone(),
+                                    parent_object_id: &self.object_id,
+                                    parent_key: member,
+                                    diff,
+                                },
+                            )
+                        }))
+                    })?;
+                    changes += node_diffapp.change;
+                    new_members.insert(member.to_string(), node_diffapp.value);
+                }
+            }
+        }
+        let new_set = StateTreeSet {
+            object_id: self.object_id.clone(),
+            members: new_members,
+        };
+        Ok(
+            DiffApplicationResult::pure(new_set.clone()).with_changes(StateTreeChange::single(
+                self.object_id.clone(),
+                StateTreeComposite::Set(new_set),
+            )),
+        )
+    }
+
+    /// All the ops which currently assert that `member` is in the set. Unlike
+    /// maps we cannot just use the default op here, if we did then a concurrent
+    /// add which we have observed would resurrect the member after a remove.
+    pub fn pred_for_member(&self, member: &str) -> Vec<amp::OpId> {
+        self.members
+            .get(member)
+            .map(|v| v.opids().cloned().collect())
+            .unwrap_or_else(Vec::new)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct StateTreeText {
     object_id: amp::ObjectId,
@@ -902,8 +1239,8 @@ impl StateTreeText {
         index: usize,
     ) -> Result<(&amp::OpId, char), error::MissingIndexError> {
         self.chars
-            .get(index)
-            .map(|mc| (mc.default_opid(), mc.default_char()))
+            .get_with_elem_id(index)
+            .map(|(elem_id, mc)| (elem_id, mc.default_char()))
             .ok_or_else(|| error::MissingIndexError {
                 missing_index: index,
                 size_of_collection: self.chars.len(),
@@ -1059,8 +1396,8 @@ impl StateTreeList {
         index: usize,
     ) -> Result<(amp::OpId, &MultiValue), error::MissingIndexError> {
         self.elements
-            .get(index)
-            .map(|mv| (mv.default_opid(), mv))
+            .get_with_elem_id(index)
+            .map(|(elem_id, mv)| (elem_id.clone(), mv))
             .ok_or_else(|| error::MissingIndexError {
                 missing_index: index,
                 size_of_collection: self.elements.len(),
@@ -2,10 +2,12 @@ use automerge_protocol as amp;
 
 use super::{
     CursorState, Cursors, DiffApplicationResult, DiffToApply, DiffableSequence, StateTreeChange,
-    StateTreeComposite, StateTreeList, StateTreeMap, StateTreeTable, StateTreeText, StateTreeValue,
+    StateTreeComposite, StateTreeList, StateTreeMap, StateTreeSet, StateTreeTable, StateTreeText,
+    StateTreeValue,
 };
 use crate::error;
 use crate::value::{Primitive, Value};
+use crate::{ConflictResolver, Path};
 use std::iter::Iterator;
 
 pub(crate) struct NewValueRequest<'a, 'b, 'c, 'd> {
@@ -125,6 +127,14 @@ impl MultiValue {
         self.winning_value.1.clone()
     }
 
+    /// The object the default value links to, if it isn't a primitive
+    pub(super) fn default_object_id(&self) -> Option<&amp::ObjectId> {
+        match &self.winning_value.1 {
+            StateTreeValue::Link(object_id) => Some(object_id),
+            StateTreeValue::Leaf(_) => None,
+        }
+    }
+
     pub(super) fn default_value(
         &self,
         objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
@@ -132,6 +142,60 @@ impl MultiValue {
         self.winning_value.1.realise_value(objects)
     }
 
+    /// The value to display at `path`. If there are conflicts `resolver`
+    /// picks the value, otherwise this is the same as `default_value`
+    pub(super) fn resolved_value(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        resolver: &dyn ConflictResolver,
+        path: Path,
+    ) -> Value {
+        self.resolved_winner(objects, resolver, &path)
+            .resolved_value(objects, resolver, path)
+    }
+
+    /// The value `resolver` picks at `path`, without resolving anything
+    /// inside it
+    pub(super) fn resolved_winner(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        resolver: &dyn ConflictResolver,
+        path: &Path,
+    ) -> StateTreeValue {
+        if self.conflicts.is_empty() {
+            return self.winning_value.1.clone();
+        }
+        resolver
+            .resolve(path, &self.realise_values(objects))
+            .and_then(|opid| self.tree_values().get(&opid).cloned())
+            .unwrap_or_else(|| self.winning_value.1.clone())
+    }
+
+    /// Record the values at `path` if there is more than one, then look for
+    /// conflicts inside each of the values. Conflicts inside a value which
+    /// didn't win have the value's `OpId` in their path.
+    pub(super) fn collect_conflicts(
+        &self,
+        objects: &im_rc::HashMap<amp::ObjectId, StateTreeComposite>,
+        path: Path,
+        conflicts: &mut Vec<(Path, std::collections::HashMap<amp::OpId, Value>)>,
+    ) {
+        if !self.conflicts.is_empty() {
+            conflicts.push((path.clone(), self.realise_values(objects)));
+        }
+        for (opid, value) in self.conflicts.iter() {
+            value.collect_conflicts(objects, path.clone().conflict(opid.clone()), conflicts);
+        }
+        self.winning_value
+            .1
+            .collect_conflicts(objects, path, conflicts);
+    }
+
+    /// The value written by `opid`, whether or not it won
+    pub(super) fn value_for(&self, opid: &amp::OpId) -> Option<StateTreeValue> {
+        self.tree_values().get(opid).cloned()
+    }
+
     pub(super) fn default_opid(&self) -> amp::OpId {
         self.winning_value.0.clone()
     }
@@ -396,6 +460,7 @@ where
     fn create(self, value: &Value) -> NewValue {
         match value {
             Value::Map(props, map_type) => self.new_map_or_table(props, map_type),
+            Value::Set(members) => self.new_set(members),
             Value::Sequence(values) => self.new_list(values),
             Value::Text(chars) => self.new_text(chars),
             Value::Primitive(p) => self.new_primitive(p),
@@ -445,6 +510,10 @@ where
                 object_id: make_op_id.clone().into(),
                 props: result_props,
             }),
+            amp::MapType::Set => StateTreeComposite::Set(StateTreeSet {
+                object_id: make_op_id.clone().into(),
+                members: result_props,
+            }),
         };
         let value = StateTreeValue::Link(make_op_id.clone().into());
         objects = objects.update(make_op_id.clone().into(), map);
@@ -458,6 +527,49 @@ where
         }
     }
 
+    fn new_set(self, members: &std::collections::HashSet<String>) -> NewValue {
+        let make_op_id = self.actor.op_id_at(self.start_op);
+        let mut ops = vec![amp::Op {
+            action: amp::OpType::Make(amp::ObjType::set()),
+            obj: self.parent_obj.into(),
+            key: self.key.clone(),
+            insert: self.insert,
+            pred: self.pred,
+        }];
+        let mut current_max_op = self.start_op;
+        let mut result_members: im_rc::HashMap<String, MultiValue> = im_rc::HashMap::new();
+        for member in members.iter() {
+            current_max_op += 1;
+            let opid = self.actor.op_id_at(current_max_op);
+            ops.push(amp::Op {
+                action: amp::OpType::Set(amp::ScalarValue::Boolean(true)),
+                obj: make_op_id.clone().into(),
+                key: member.as_str().into(),
+                insert: false,
+                pred: Vec::new(),
+            });
+            result_members = result_members.update(
+                member.clone(),
+                MultiValue::from_statetree_value(
+                    StateTreeValue::Leaf(Primitive::Boolean(true)),
+                    opid,
+                ),
+            );
+        }
+        let set = StateTreeComposite::Set(StateTreeSet {
+            object_id: make_op_id.clone().into(),
+            members: result_members,
+        });
+        NewValue {
+            value: StateTreeValue::Link(make_op_id.clone().into()),
+            opid: make_op_id.clone(),
+            max_op: current_max_op,
+            new_cursors: Cursors::new(),
+            new_objects: im_rc::hashmap! {make_op_id.into() => set},
+            ops,
+        }
+    }
+
     fn new_list(self, values: &[Value]) -> NewValue {
         let make_list_opid = amp::OpId::new(self.start_op, self.actor);
         let make_op = amp::Op {
@@ -562,6 +674,7 @@ where
         };
         let value = match primitive {
             Primitive::Str(s) => amp::ScalarValue::Str(s.clone()),
+            Primitive::Bytes(b) => amp::ScalarValue::Bytes(b.clone()),
             Primitive::Int(i) => amp::ScalarValue::Int(*i),
             Primitive::Uint(u) => amp::ScalarValue::Uint(*u),
             Primitive::F64(f) => amp::ScalarValue::F64(*f),
@@ -571,6 +684,10 @@ where
             Primitive::Boolean(b) => amp::ScalarValue::Boolean(*b),
             Primitive::Cursor(c) => amp::ScalarValue::Cursor(c.elem_opid.clone()),
             Primitive::Null => amp::ScalarValue::Null,
+            Primitive::Unknown { type_code, bytes } => amp::ScalarValue::Unknown {
+                type_code: *type_code,
+                bytes: bytes.clone(),
+            },
         };
         let opid = self.actor.op_id_at(self.start_op);
         NewValue {
@@ -2,7 +2,7 @@ use super::focus::Focus;
 use super::{
     random_op_id, DiffApplicationResult, LocalOperationResult, MultiChar, MultiValue,
     NewValueRequest, StateTree, StateTreeChange, StateTreeComposite, StateTreeList, StateTreeMap,
-    StateTreeTable, StateTreeText, StateTreeValue,
+    StateTreeSet, StateTreeTable, StateTreeText, StateTreeValue,
 };
 use crate::error;
 use crate::{Cursor, Primitive, Value};
@@ -19,6 +19,7 @@ pub enum Target {
     Root(ResolvedRoot),
     Map(ResolvedMap),
     Table(ResolvedTable),
+    Set(ResolvedSet),
     List(ResolvedList),
     Text(ResolvedText),
     Character(ResolvedChar),
@@ -34,6 +35,7 @@ impl std::fmt::Debug for Target {
             Target::Table(tabletarget) => {
                 write!(f, "Table {:?}", tabletarget.value.object_id)
             }
+            Target::Set(settarget) => write!(f, "Set {:?}", settarget.value.object_id),
             Target::List(listtarget) => write!(f, "list {:?}", listtarget.value.object_id),
             Target::Text(texttarget) => write!(f, "text {:?}", texttarget.value.object_id),
             Target::Counter(countertarget) => write!(
@@ -119,6 +121,22 @@ impl<'a> ResolvedPath<'a> {
         }
     }
 
+    pub(super) fn new_set(
+        tree: &StateTree,
+        mv: MultiValue,
+        focus: Focus,
+        set: StateTreeSet,
+    ) -> ResolvedPath {
+        ResolvedPath {
+            root: tree,
+            target: Target::Set(ResolvedSet {
+                multivalue: mv,
+                focus,
+                value: set,
+            }),
+        }
+    }
+
     pub(super) fn new_counter(
         tree: &StateTree,
         object_id: amp::ObjectId,
@@ -158,6 +176,7 @@ impl<'a> ResolvedPath<'a> {
             Target::Map(maptarget) => maptarget.multivalue.default_value(&self.root.objects),
             Target::Root(root) => root.root.value(),
             Target::Table(tabletarget) => tabletarget.multivalue.default_value(&self.root.objects),
+            Target::Set(settarget) => settarget.multivalue.default_value(&self.root.objects),
             Target::List(listtarget) => listtarget.multivalue.default_value(&self.root.objects),
             Target::Text(texttarget) => texttarget.multivalue.default_value(&self.root.objects),
             Target::Counter(countertarget) => {
@@ -177,6 +196,7 @@ impl<'a> ResolvedPath<'a> {
                 result
             }
             Target::Table(tabletarget) => tabletarget.multivalue.realise_values(&self.root.objects),
+            Target::Set(settarget) => settarget.multivalue.realise_values(&self.root.objects),
             Target::List(listtarget) => listtarget.multivalue.realise_values(&self.root.objects),
             Target::Text(texttarget) => texttarget.multivalue.realise_values(&self.root.objects),
             Target::Counter(countertarget) => {
@@ -192,6 +212,7 @@ impl<'a> ResolvedPath<'a> {
             Target::Map(maptarget) => Some(maptarget.value.object_id.clone()),
             Target::Root(_) => Some(amp::ObjectId::Root),
             Target::Table(tabletarget) => Some(tabletarget.value.object_id.clone()),
+            Target::Set(settarget) => Some(settarget.value.object_id.clone()),
             Target::List(listtarget) => Some(listtarget.value.object_id.clone()),
             Target::Text(texttarget) => Some(texttarget.value.object_id.clone()),
             Target::Counter(_) => None,
@@ -266,12 +287,23 @@ pub struct ResolvedCounter {
 }
 
 impl ResolvedCounter {
-    pub(crate) fn increment(&self, by: i64) -> LocalOperationResult {
-        let diffapp = DiffApplicationResult::pure(self.multivalue.update_default(
-            StateTreeValue::Leaf(Primitive::Counter(self.current_value + by)),
-        ));
+    pub(crate) fn increment(
+        &self,
+        by: i64,
+    ) -> Result<LocalOperationResult, error::CounterOverflowError> {
+        let new_value = self
+            .current_value
+            .checked_add(by)
+            .ok_or(error::CounterOverflowError {
+                current_value: self.current_value,
+                increment: by,
+            })?;
+        let diffapp = DiffApplicationResult::pure(
+            self.multivalue
+                .update_default(StateTreeValue::Leaf(Primitive::Counter(new_value))),
+        );
         let new_state = self.focus.update(diffapp);
-        LocalOperationResult {
+        Ok(LocalOperationResult {
             new_state,
             new_ops: vec![amp::Op {
                 action: amp::OpType::Inc(by),
@@ -280,7 +312,7 @@ impl ResolvedCounter {
                 insert: false,
                 pred: vec![self.multivalue.default_opid()],
             }],
-        }
+        })
     }
 }
 
@@ -406,6 +438,60 @@ impl ResolvedTable {
     }
 }
 
+pub struct ResolvedSet {
+    pub(super) value: StateTreeSet,
+    pub(super) multivalue: MultiValue,
+    pub(super) focus: Focus,
+}
+
+impl ResolvedSet {
+    pub(crate) fn add(&self, payload: SetOrInsertPayload<&str>) -> LocalOperationResult {
+        // We generate an op even if the member is already present, otherwise a
+        // concurrent remove would win over this add
+        let member = payload.value;
+        let opid = payload.actor.op_id_at(payload.start_op);
+        let member_mv =
+            MultiValue::from_statetree_value(StateTreeValue::Leaf(Primitive::Boolean(true)), opid);
+        let new_value = self.value.update(member.to_string(), member_mv);
+        LocalOperationResult {
+            new_state: self.update(new_value),
+            new_ops: vec![amp::Op {
+                action: amp::OpType::Set(amp::ScalarValue::Boolean(true)),
+                obj: self.value.object_id.clone(),
+                key: member.into(),
+                insert: false,
+                pred: self.value.pred_for_member(member),
+            }],
+        }
+    }
+
+    pub(crate) fn remove(&self, member: &str) -> LocalOperationResult {
+        let new_value = self.value.without(member);
+        LocalOperationResult {
+            new_state: self.update(new_value),
+            new_ops: vec![amp::Op {
+                action: amp::OpType::Del,
+                obj: self.value.object_id.clone(),
+                key: member.into(),
+                insert: false,
+                pred: self.value.pred_for_member(member),
+            }],
+        }
+    }
+
+    fn update(&self, new_value: StateTreeSet) -> StateTree {
+        let new_composite = StateTreeComposite::Set(new_value);
+        let new_mv = self
+            .multivalue
+            .update_default(StateTreeValue::Link(new_composite.object_id()));
+        let diffapp = DiffApplicationResult::pure(new_mv).with_changes(StateTreeChange::single(
+            new_composite.object_id(),
+            new_composite,
+        ));
+        self.focus.update(diffapp)
+    }
+}
+
 pub struct ResolvedText {
     pub(super) value: StateTreeText,
     pub(super) multivalue: MultiValue,
@@ -0,0 +1,207 @@
+use crate::{Primitive, Value};
+use automerge_protocol as amp;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// A single row of a table object. Rows are maps from column names to values,
+/// the row ID is the key the row is stored under in the table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    id: String,
+    columns: HashMap<String, Value>,
+}
+
+impl Row {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The value of `column` in this row, if it is set
+    pub fn get<S: AsRef<str>>(&self, column: S) -> Option<&Value> {
+        self.columns.get(column.as_ref())
+    }
+
+    pub fn columns(&self) -> &HashMap<String, Value> {
+        &self.columns
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Map(self.columns, amp::MapType::Map)
+    }
+}
+
+/// A snapshot of the rows of a table object, ordered by row ID unless sorted
+/// otherwise.
+///
+/// `Rows` is a plain value, it is not updated when the document changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rows(Vec<Row>);
+
+impl Rows {
+    /// Builds the rows from the realised value of a table. Returns `None` if
+    /// `value` is not a table. Entries which are not maps are not rows and are
+    /// skipped.
+    pub(crate) fn from_table(value: Value) -> Option<Rows> {
+        match value {
+            Value::Map(entries, amp::MapType::Table) => {
+                let mut rows: Vec<Row> = entries
+                    .into_iter()
+                    .filter_map(|(id, row)| match row {
+                        Value::Map(columns, amp::MapType::Map) => Some(Row { id, columns }),
+                        _ => None,
+                    })
+                    .collect();
+                rows.sort_by(|a, b| compare_row_ids(&a.id, &b.id));
+                Some(Rows(rows))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Row> {
+        self.0.iter()
+    }
+
+    /// Look up a row by its ID
+    pub fn get<S: AsRef<str>>(&self, row_id: S) -> Option<&Row> {
+        self.0.iter().find(|r| r.id == row_id.as_ref())
+    }
+
+    /// The rows for which `predicate` returns true
+    pub fn filter<F>(self, predicate: F) -> Rows
+    where
+        F: Fn(&Row) -> bool,
+    {
+        Rows(self.0.into_iter().filter(|r| predicate(r)).collect())
+    }
+
+    /// Sort the rows using `compare`. The sort is stable so rows which compare
+    /// equal stay in their current order.
+    pub fn sort_by<F>(mut self, compare: F) -> Rows
+    where
+        F: Fn(&Row, &Row) -> Ordering,
+    {
+        self.0.sort_by(|a, b| compare(a, b));
+        self
+    }
+
+    /// Sort the rows by the value in `column`. Rows where the column is
+    /// missing, or holds something other than a primitive, sort last.
+    pub fn sort_by_column<S: AsRef<str>>(self, column: S) -> Rows {
+        let column = column.as_ref();
+        self.sort_by(
+            |a, b| match (primitive_at(a, column), primitive_at(b, column)) {
+                (Some(pa), Some(pb)) => compare_primitives(pa, pb),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        )
+    }
+
+    /// Keep only `columns` in each row
+    pub fn project(self, columns: &[&str]) -> Rows {
+        Rows(
+            self.0
+                .into_iter()
+                .map(|row| Row {
+                    id: row.id,
+                    columns: row
+                        .columns
+                        .into_iter()
+                        .filter(|(k, _)| columns.contains(&k.as_str()))
+                        .collect(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl IntoIterator for Rows {
+    type Item = Row;
+    type IntoIter = std::vec::IntoIter<Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Orders row IDs by the ID of the op which created the row, so "10@a"
+/// comes after "2@a". IDs which aren't op IDs come last, ordered as strings.
+fn compare_row_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<amp::OpId>(), b.parse::<amp::OpId>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+fn primitive_at<'a>(row: &'a Row, column: &str) -> Option<&'a Primitive> {
+    match row.get(column) {
+        Some(Value::Primitive(p)) => Some(p),
+        _ => None,
+    }
+}
+
+/// Orders primitives of the same type by value. Numeric types are compared as
+/// numbers, anything else which differs in type is ordered by type so that
+/// sorting a column with mixed types is still deterministic.
+fn compare_primitives(a: &Primitive, b: &Primitive) -> Ordering {
+    match (a, b) {
+        (Primitive::Str(a), Primitive::Str(b)) => a.cmp(b),
+        (Primitive::Bytes(a), Primitive::Bytes(b)) => a.cmp(b),
+        (
+            Primitive::Unknown {
+                type_code: a_type,
+                bytes: a,
+            },
+            Primitive::Unknown {
+                type_code: b_type,
+                bytes: b,
+            },
+        ) => (a_type, a).cmp(&(b_type, b)),
+        (Primitive::Boolean(a), Primitive::Boolean(b)) => a.cmp(b),
+        (Primitive::Timestamp(a), Primitive::Timestamp(b)) => a.cmp(b),
+        _ => match (as_f64(a), as_f64(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            _ => type_rank(a).cmp(&type_rank(b)),
+        },
+    }
+}
+
+fn as_f64(p: &Primitive) -> Option<f64> {
+    match p {
+        Primitive::Int(i) => Some(*i as f64),
+        Primitive::Uint(u) => Some(*u as f64),
+        Primitive::F64(f) => Some(*f),
+        Primitive::F32(f) => Some(f64::from(*f)),
+        Primitive::Counter(c) => Some(*c as f64),
+        _ => None,
+    }
+}
+
+fn type_rank(p: &Primitive) -> u8 {
+    match p {
+        Primitive::Null => 0,
+        Primitive::Boolean(_) => 1,
+        Primitive::Int(_)
+        | Primitive::Uint(_)
+        | Primitive::F64(_)
+        | Primitive::F32(_)
+        | Primitive::Counter(_) => 2,
+        Primitive::Timestamp(_) => 3,
+        Primitive::Str(_) => 4,
+        Primitive::Bytes(_) => 5,
+        Primitive::Cursor(_) => 6,
+        Primitive::Unknown { .. } => 7,
+    }
+}
@@ -1,6 +1,9 @@
 use automerge_protocol as amp;
 use serde::Serialize;
-use std::{borrow::Borrow, collections::HashMap};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+};
 
 #[derive(Serialize, Clone, Debug, PartialEq)]
 pub struct Conflicts(HashMap<amp::OpId, Value>);
@@ -15,6 +18,7 @@ impl From<HashMap<amp::OpId, Value>> for Conflicts {
 #[serde(untagged)]
 pub enum Value {
     Map(HashMap<String, Value>, amp::MapType),
+    Set(HashSet<String>),
     Sequence(Vec<Value>),
     Text(Vec<char>),
     Primitive(Primitive),
@@ -23,6 +27,7 @@ pub enum Value {
 #[derive(Serialize, Clone, Debug, PartialEq)]
 pub enum Primitive {
     Str(String),
+    Bytes(Vec<u8>),
     Int(i64),
     Uint(u64),
     F64(f64),
@@ -32,6 +37,11 @@ pub enum Primitive {
     Boolean(bool),
     Cursor(Cursor),
     Null,
+    /// A value of a type added in a later version
+    Unknown {
+        type_code: u8,
+        bytes: Vec<u8>,
+    },
 }
 
 #[derive(Serialize, Clone, Debug, PartialEq)]
@@ -61,6 +71,7 @@ impl From<&Primitive> for amp::ScalarValue {
     fn from(p: &Primitive) -> Self {
         match p {
             Primitive::Str(s) => amp::ScalarValue::Str(s.clone()),
+            Primitive::Bytes(b) => amp::ScalarValue::Bytes(b.clone()),
             Primitive::Int(i) => amp::ScalarValue::Int(*i),
             Primitive::Uint(u) => amp::ScalarValue::Uint(*u),
             Primitive::F64(f) => amp::ScalarValue::F64(*f),
@@ -70,6 +81,10 @@ impl From<&Primitive> for amp::ScalarValue {
             Primitive::Boolean(b) => amp::ScalarValue::Boolean(*b),
             Primitive::Null => amp::ScalarValue::Null,
             Primitive::Cursor(c) => amp::ScalarValue::Cursor(c.elem_opid.clone()),
+            Primitive::Unknown { type_code, bytes } => amp::ScalarValue::Unknown {
+                type_code: *type_code,
+                bytes: bytes.clone(),
+            },
         }
     }
 }
@@ -111,6 +126,12 @@ where
     }
 }
 
+impl From<Vec<u8>> for Primitive {
+    fn from(b: Vec<u8>) -> Self {
+        Primitive::Bytes(b)
+    }
+}
+
 impl From<i64> for Value {
     fn from(v: i64) -> Self {
         Value::Primitive(Primitive::Int(v))
@@ -154,6 +175,9 @@ impl Value {
         }
     }
 
+    /// Bytes become base64 encoded strings, the same as in patches. Values of
+    /// unknown types become an object with the type code and the base64
+    /// encoded bytes.
     pub fn to_json(&self) -> serde_json::Value {
         match self {
             Value::Map(map, _) => {
@@ -161,6 +185,16 @@ impl Value {
                     map.iter().map(|(k, v)| (k.clone(), v.to_json())).collect();
                 serde_json::Value::Object(result)
             }
+            Value::Set(members) => {
+                let mut members: Vec<&String> = members.iter().collect();
+                members.sort();
+                serde_json::Value::Array(
+                    members
+                        .into_iter()
+                        .map(|m| serde_json::Value::String(m.clone()))
+                        .collect(),
+                )
+            }
             Value::Sequence(elements) => {
                 serde_json::Value::Array(elements.iter().map(|v| v.to_json()).collect())
             }
@@ -176,6 +210,7 @@ impl Value {
                 Primitive::Uint(n) => serde_json::Value::Number(serde_json::Number::from(*n)),
                 Primitive::Int(n) => serde_json::Value::Number(serde_json::Number::from(*n)),
                 Primitive::Str(s) => serde_json::Value::String(s.to_string()),
+                Primitive::Bytes(b) => serde_json::Value::String(base64::encode(b)),
                 Primitive::Boolean(b) => serde_json::Value::Bool(*b),
                 Primitive::Counter(c) => serde_json::Value::Number(serde_json::Number::from(*c)),
                 Primitive::Timestamp(t) => serde_json::Value::Number(serde_json::Number::from(*t)),
@@ -183,6 +218,10 @@ impl Value {
                 Primitive::Cursor(c) => {
                     serde_json::Value::Number(serde_json::Number::from(c.index))
                 }
+                Primitive::Unknown { type_code, bytes } => serde_json::json!({
+                    "typeCode": type_code,
+                    "bytes": base64::encode(bytes),
+                }),
             },
         }
     }
@@ -269,6 +308,7 @@ pub(crate) fn value_to_op_requests(
             let make_action = match map_type {
                 amp::MapType::Map => amp::OpType::Make(amp::ObjType::map()),
                 amp::MapType::Table => amp::OpType::Make(amp::ObjType::table()),
+                amp::MapType::Set => amp::OpType::Make(amp::ObjType::set()),
             };
             let make_op_id = amp::OpId::new(start_op, actor);
             let make_op = amp::Op {
@@ -294,6 +334,27 @@ pub(crate) fn value_to_op_requests(
             }
             (result, op_num)
         }
+        Value::Set(members) => {
+            let make_op_id = amp::OpId::new(start_op, actor);
+            let mut ops = vec![amp::Op {
+                action: amp::OpType::Make(amp::ObjType::set()),
+                obj: parent_object,
+                key: key.clone(),
+                insert,
+                pred: Vec::new(),
+            }];
+            for member in members.iter() {
+                ops.push(amp::Op {
+                    action: amp::OpType::Set(amp::ScalarValue::Boolean(true)),
+                    obj: amp::ObjectId::from(make_op_id.clone()),
+                    key: amp::Key::from(member.as_str()),
+                    insert: false,
+                    pred: Vec::new(),
+                });
+            }
+            let op_num = start_op + ops.len() as u64;
+            (ops, op_num)
+        }
         Value::Primitive(prim_value) => {
             let ops = vec![amp::Op {
                 action: amp::OpType::Set(prim_value.into()),
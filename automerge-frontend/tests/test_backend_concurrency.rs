@@ -724,3 +724,51 @@ fn test_deps_are_filled_in_if_frontend_does_not_have_latest_patch() {
     };
     assert_eq!(change4, expected_change4);
 }
+
+#[test]
+fn cancel_a_change_the_backend_refuses() {
+    let mut doc = Frontend::new();
+    let mut backend = Backend::init();
+
+    // the backend already has a change with the seq the frontend will use
+    let mut other = Frontend::new();
+    other.actor_id = doc.actor_id.clone();
+    let existing = other
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::set(Path::root().key("bird"), "magpie"))
+        })
+        .unwrap()
+        .unwrap();
+    backend.apply_local_change(existing).unwrap();
+
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::set(Path::root().key("bird"), "wren"))
+        })
+        .unwrap()
+        .unwrap();
+    assert!(backend.apply_local_change(req).is_err());
+
+    assert!(doc.cancel_last_change());
+    assert!(!doc.cancel_last_change());
+    assert_eq!(doc.seq, 0);
+    assert!(doc.in_flight_requests().is_empty());
+    assert_eq!(doc.get_value(&Path::root().key("bird")), None);
+
+    // once the frontend has caught up it can make changes again
+    doc.apply_patch(backend.get_patch().unwrap()).unwrap();
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::set(Path::root().key("bird"), "wren"))
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(req.seq, 2);
+    let (patch, _) = backend.apply_local_change(req).unwrap();
+    doc.apply_patch(patch).unwrap();
+    assert!(!doc.cancel_last_change());
+    assert_eq!(
+        doc.get_value(&Path::root().key("bird")),
+        Some(Value::from("wren"))
+    );
+}
@@ -1,7 +1,12 @@
-use automerge_frontend::{Frontend, InvalidChangeRequest, LocalChange, Path, Primitive, Value};
+use automerge_frontend::{
+    CounterOverflowError, Cursor, Frontend, InvalidChangeRequest, LastWriterWins, LocalChange,
+    MutableDocument, Path, Primitive, ResolveByPath, Value,
+};
 use automerge_protocol as amp;
 use maplit::hashmap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::str::FromStr;
 
 #[test]
 fn test_should_be_empty_after_init() {
@@ -863,3 +868,881 @@ fn test_inserts_at_end_of_lists() {
     );
     assert_eq!(value, expected_value);
 }
+
+#[test]
+fn add_and_remove_set_members() {
+    let mut doc = Frontend::new();
+    let req1 = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::set(
+                Path::root().key("tags"),
+                Value::Set(HashSet::new()),
+            ))?;
+            doc.add_change(LocalChange::add_to_set(Path::root().key("tags"), "urgent"))?;
+            doc.add_change(LocalChange::add_to_set(Path::root().key("tags"), "home"))?;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+    let set_id: amp::ObjectId = doc.actor_id.op_id_at(1).into();
+    assert_eq!(
+        req1.operations,
+        vec![
+            amp::Op {
+                action: amp::OpType::Make(amp::ObjType::set()),
+                obj: amp::ObjectId::Root,
+                key: "tags".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+            amp::Op {
+                action: amp::OpType::Set(amp::ScalarValue::Boolean(true)),
+                obj: set_id.clone(),
+                key: "urgent".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+            amp::Op {
+                action: amp::OpType::Set(amp::ScalarValue::Boolean(true)),
+                obj: set_id.clone(),
+                key: "home".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+        ]
+    );
+
+    let req2 = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::remove_from_set(
+                Path::root().key("tags"),
+                "urgent",
+            ))?;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        req2.operations,
+        vec![amp::Op {
+            action: amp::OpType::Del,
+            obj: set_id,
+            key: "urgent".into(),
+            insert: false,
+            pred: vec![doc.actor_id.op_id_at(2)],
+        }]
+    );
+    assert_eq!(
+        doc.get_value(&Path::root().key("tags")),
+        Some(Value::Set(vec!["home".to_string()].into_iter().collect()))
+    );
+}
+
+#[test]
+fn remove_set_member_uses_all_observed_adds_as_pred() {
+    let actor1 = amp::ActorId::from_str("02ef21f3c9eb4087880ebedd7c4bbe43").unwrap();
+    let actor2 = amp::ActorId::from_str("2a1d376b24f744008d4af58252d644dd").unwrap();
+    let set_id = actor1.op_id_at(1);
+    let patch = amp::Patch {
+        actor: None,
+        seq: None,
+        max_op: 2,
+        clock: hashmap! {
+            actor1.clone() => 1,
+            actor2.clone() => 1,
+        },
+        deps: Vec::new(),
+        diffs: Some(amp::Diff::Map(amp::MapDiff {
+            object_id: amp::ObjectId::Root,
+            obj_type: amp::MapType::Map,
+            props: hashmap! {
+                "tags".into() => hashmap!{
+                    set_id.clone() => amp::Diff::Map(amp::MapDiff {
+                        object_id: set_id.clone().into(),
+                        obj_type: amp::MapType::Set,
+                        props: hashmap!{
+                            "urgent".into() => hashmap!{
+                                actor1.op_id_at(2) => amp::Diff::Value(amp::ScalarValue::Boolean(true)),
+                                actor2.op_id_at(2) => amp::Diff::Value(amp::ScalarValue::Boolean(true)),
+                            }
+                        },
+                    })
+                }
+            },
+        })),
+    };
+    let mut doc = Frontend::new();
+    doc.apply_patch(patch).unwrap();
+    assert_eq!(
+        doc.get_value(&Path::root().key("tags")),
+        Some(Value::Set(vec!["urgent".to_string()].into_iter().collect()))
+    );
+
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::remove_from_set(
+                Path::root().key("tags"),
+                "urgent",
+            ))?;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+    let mut pred = req.operations[0].pred.clone();
+    pred.sort_by_key(|o| o.to_string());
+    let mut expected = vec![actor1.op_id_at(2), actor2.op_id_at(2)];
+    expected.sort_by_key(|o| o.to_string());
+    assert_eq!(pred, expected);
+    assert_eq!(
+        doc.get_value(&Path::root().key("tags")),
+        Some(Value::Set(HashSet::new()))
+    );
+}
+
+#[test]
+fn add_rows_to_a_table() {
+    let mut doc = Frontend::new();
+    let tasks = Path::root().key("tasks");
+    let mut row_ids = Vec::new();
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::set(
+                tasks.clone(),
+                Value::Map(HashMap::new(), amp::MapType::Table),
+            ))?;
+            for (title, priority) in &[("write docs", 2), ("fix bug", 1), ("release", 3)] {
+                let row = Value::from(hashmap! {
+                    "title" => Value::from(*title),
+                    "priority" => Value::from(*priority as i64),
+                });
+                row_ids.push(doc.add_row(&tasks, row)?);
+            }
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+
+    // Each row is created by a makeMap op and two set ops, the row ID is the
+    // ID of the makeMap op
+    assert_eq!(
+        row_ids,
+        vec![
+            doc.actor_id.op_id_at(2).to_string(),
+            doc.actor_id.op_id_at(5).to_string(),
+            doc.actor_id.op_id_at(8).to_string(),
+        ]
+    );
+    assert_eq!(
+        req.operations[1],
+        amp::Op {
+            action: amp::OpType::Make(amp::ObjType::map()),
+            obj: doc.actor_id.op_id_at(1).into(),
+            key: row_ids[0].as_str().into(),
+            insert: false,
+            pred: Vec::new(),
+        }
+    );
+
+    let rows = doc.table_rows(&tasks).unwrap();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(
+        rows.get(&row_ids[1]).unwrap().get("title"),
+        Some(&Value::from("fix bug"))
+    );
+    assert_eq!(
+        doc.table_row(&tasks, &row_ids[2]).unwrap().get("priority"),
+        Some(&Value::from(3))
+    );
+
+    let sorted: Vec<String> = rows
+        .clone()
+        .sort_by_column("priority")
+        .iter()
+        .map(|r| r.id().to_string())
+        .collect();
+    assert_eq!(
+        sorted,
+        vec![row_ids[1].clone(), row_ids[0].clone(), row_ids[2].clone()]
+    );
+
+    let urgent = rows
+        .filter(|r| r.get("priority") != Some(&Value::from(3)))
+        .project(&["title"]);
+    assert_eq!(urgent.len(), 2);
+    assert!(urgent
+        .iter()
+        .all(|r| r.columns().len() == 1 && r.get("title").is_some()));
+}
+
+/// A `MutableDocument` which only implements the required methods
+struct Delegate<'a>(&'a mut dyn MutableDocument);
+
+impl<'a> MutableDocument for Delegate<'a> {
+    fn value_at_path(&self, path: &Path) -> Option<Value> {
+        self.0.value_at_path(path)
+    }
+
+    fn cursor_to_path(&self, path: &Path) -> Option<Cursor> {
+        self.0.cursor_to_path(path)
+    }
+
+    fn add_change(&mut self, change: LocalChange) -> Result<(), InvalidChangeRequest> {
+        self.0.add_change(change)
+    }
+}
+
+#[test]
+fn rows_are_ordered_by_op_id() {
+    let mut doc = Frontend::new();
+    let tasks = Path::root().key("tasks");
+    let mut row_ids = Vec::new();
+    let mut default_row_ids = Vec::new();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            tasks.clone(),
+            Value::Map(HashMap::new(), amp::MapType::Table),
+        ))?;
+        for i in 0..4 {
+            let row = Value::from(hashmap! { "n" => Value::from(i as i64) });
+            row_ids.push(doc.add_row(&tasks, row.clone())?);
+            default_row_ids.push(Delegate(doc).add_row(&tasks, row)?);
+        }
+        Ok(())
+    })
+    .unwrap();
+
+    // the default `add_row` finds the same IDs
+    let expected: Vec<_> = (0..4)
+        .map(|i| doc.actor_id.op_id_at(2 + i * 4).to_string())
+        .collect();
+    assert_eq!(row_ids, expected);
+    let expected: Vec<_> = (0..4)
+        .map(|i| doc.actor_id.op_id_at(4 + i * 4).to_string())
+        .collect();
+    assert_eq!(default_row_ids, expected);
+
+    // "10@..." and "14@..." come after "2@..."
+    let ids: Vec<_> = doc
+        .table_rows(&tasks)
+        .unwrap()
+        .iter()
+        .map(|row| row.id().to_string())
+        .collect();
+    let mut expected: Vec<_> = row_ids.into_iter().chain(default_row_ids).collect();
+    expected.sort_by_key(|id| amp::OpId::from_str(id).unwrap());
+    assert_eq!(ids, expected);
+    assert_eq!(ids[1], doc.actor_id.op_id_at(4).to_string());
+    assert_eq!(ids[7], doc.actor_id.op_id_at(16).to_string());
+}
+
+#[test]
+fn object_types_keys_and_lengths_match_the_values() {
+    let mut doc = Frontend::new();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        let birds = Value::Map(
+            hashmap! {
+                "wrens".to_string() => Value::from(3),
+                "magpies".to_string() => Value::Sequence(vec![Value::from("a"), Value::from("b")]),
+            },
+            amp::MapType::Map,
+        );
+        doc.add_change(LocalChange::set(Path::root().key("birds"), birds))?;
+        doc.add_change(LocalChange::set(
+            Path::root().key("note"),
+            Value::Text("hi".chars().collect()),
+        ))?;
+        doc.add_change(LocalChange::set(
+            Path::root().key("count"),
+            Value::Primitive(Primitive::Counter(1)),
+        ))?;
+        doc.add_change(LocalChange::set(
+            Path::root().key("tasks"),
+            Value::Map(HashMap::new(), amp::MapType::Table),
+        ))?;
+        doc.add_change(LocalChange::set(
+            Path::root().key("tags"),
+            Value::Set(maplit::hashset! {"blue".to_string()}),
+        ))?;
+
+        assert_eq!(
+            doc.object_type_at_path(&Path::root()),
+            Some(amp::ObjType::map())
+        );
+        assert_eq!(
+            doc.len_at_path(&Path::root().key("birds").key("magpies")),
+            Some(2)
+        );
+        assert_eq!(doc.len_at_path(&Path::root().key("note")), Some(2));
+        let magpie = Path::root().key("birds").key("magpies").index(1);
+        assert_eq!(doc.object_type_at_path(&magpie), None);
+        assert_eq!(doc.value_at_path(&magpie), Some(Value::from("b")));
+
+        // the state tree gives the same answers as the defaults, which
+        // build the whole value
+        let paths = vec![
+            Path::root(),
+            Path::root().key("birds"),
+            Path::root().key("birds").key("wrens"),
+            Path::root().key("birds").key("magpies"),
+            magpie,
+            Path::root().key("birds").key("magpies").index(2),
+            Path::root().key("birds").key("wrens").key("nest"),
+            Path::root().key("note"),
+            Path::root().key("count"),
+            Path::root().key("tasks"),
+            Path::root().key("tags"),
+            Path::root().key("missing"),
+        ];
+        for path in paths {
+            let sorted_keys = |doc: &dyn MutableDocument| {
+                doc.keys_at_path(&path).map(|mut keys| {
+                    keys.sort();
+                    keys
+                })
+            };
+            let actual = (
+                doc.object_type_at_path(&path),
+                sorted_keys(doc),
+                doc.len_at_path(&path),
+            );
+            let delegate = Delegate(doc);
+            let expected = (
+                delegate.object_type_at_path(&path),
+                sorted_keys(&delegate),
+                delegate.len_at_path(&path),
+            );
+            assert_eq!(actual, expected, "{}", path);
+        }
+        Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn add_row_requires_a_table_and_a_map() {
+    let mut doc = Frontend::new();
+    let result = doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            Path::root().key("tasks"),
+            Value::Map(HashMap::new(), amp::MapType::Table),
+        ))?;
+        doc.add_change(LocalChange::add_row(
+            Path::root().key("tasks"),
+            Value::from("not a map"),
+        ))
+    });
+    assert!(matches!(
+        result,
+        Err(InvalidChangeRequest::TableRowMustBeMap { .. })
+    ));
+
+    let mut doc = Frontend::new();
+    let result = doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            Path::root().key("tasks"),
+            Value::Sequence(Vec::new()),
+        ))?;
+        doc.add_row(
+            &Path::root().key("tasks"),
+            Value::from(HashMap::<String, Value>::new()),
+        )
+        .map(|_| ())
+    });
+    assert!(matches!(
+        result,
+        Err(InvalidChangeRequest::AddRowForNonTableObject { .. })
+    ));
+}
+
+#[test]
+fn decrement_and_negative_increments() {
+    let mut doc = Frontend::new();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            Path::root().key("stock"),
+            Value::Primitive(Primitive::Counter(10)),
+        ))
+    })
+    .unwrap();
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::decrement(Path::root().key("stock")))?;
+            doc.add_change(LocalChange::increment_by(Path::root().key("stock"), -4))
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        req.operations
+            .iter()
+            .map(|op| op.action.clone())
+            .collect::<Vec<_>>(),
+        vec![amp::OpType::Inc(-1), amp::OpType::Inc(-4)]
+    );
+    assert_eq!(
+        doc.get_value(&Path::root().key("stock")),
+        Some(Value::Primitive(Primitive::Counter(5)))
+    );
+}
+
+#[test]
+fn counter_overflow_is_an_error() {
+    let mut doc = Frontend::new();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            Path::root().key("big"),
+            Value::Primitive(Primitive::Counter(i64::MAX - 1)),
+        ))
+    })
+    .unwrap();
+    let result = doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::increment_by(Path::root().key("big"), 2))
+    });
+    assert_eq!(
+        result,
+        Err(InvalidChangeRequest::CounterOverflow {
+            path: Path::root().key("big"),
+            source: CounterOverflowError {
+                current_value: i64::MAX - 1,
+                increment: 2,
+            },
+        })
+    );
+}
+
+#[test]
+fn set_counters_inside_lists_and_nested_maps() {
+    let mut doc = Frontend::new();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            Path::root().key("counts"),
+            vec![Value::from("placeholder")],
+        ))?;
+        doc.add_change(LocalChange::set(
+            Path::root().key("nested"),
+            Value::from(hashmap! {"inner" => Value::from(HashMap::<String, Value>::new())}),
+        ))
+    })
+    .unwrap();
+    let counts_id = doc.get_object_id(&Path::root().key("counts")).unwrap();
+    let inner_id = doc
+        .get_object_id(&Path::root().key("nested").key("inner"))
+        .unwrap();
+
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::set(
+                Path::root().key("counts").index(0),
+                Value::Primitive(Primitive::Counter(1)),
+            ))?;
+            doc.add_change(LocalChange::increment(Path::root().key("counts").index(0)))?;
+            doc.add_change(LocalChange::set(
+                Path::root().key("nested").key("inner").key("hits"),
+                Value::Primitive(Primitive::Counter(0)),
+            ))?;
+            doc.add_change(LocalChange::decrement(
+                Path::root().key("nested").key("inner").key("hits"),
+            ))
+        })
+        .unwrap()
+        .unwrap();
+
+    // The element ID of the list element is the ID of the op which inserted
+    // it, not the op which set the counter
+    let elem_id: amp::Key = doc.actor_id.op_id_at(2).into();
+    assert_eq!(
+        req.operations,
+        vec![
+            amp::Op {
+                action: amp::OpType::Set(amp::ScalarValue::Counter(1)),
+                obj: counts_id.clone(),
+                key: elem_id.clone(),
+                insert: false,
+                pred: vec![doc.actor_id.op_id_at(2)],
+            },
+            amp::Op {
+                action: amp::OpType::Inc(1),
+                obj: counts_id,
+                key: elem_id,
+                insert: false,
+                pred: vec![doc.actor_id.op_id_at(5)],
+            },
+            amp::Op {
+                action: amp::OpType::Set(amp::ScalarValue::Counter(0)),
+                obj: inner_id.clone(),
+                key: "hits".into(),
+                insert: false,
+                pred: Vec::new(),
+            },
+            amp::Op {
+                action: amp::OpType::Inc(-1),
+                obj: inner_id,
+                key: "hits".into(),
+                insert: false,
+                pred: vec![doc.actor_id.op_id_at(7)],
+            },
+        ]
+    );
+    assert_eq!(
+        doc.get_value(&Path::root().key("counts").index(0)),
+        Some(Value::Primitive(Primitive::Counter(2)))
+    );
+    assert_eq!(
+        doc.get_value(&Path::root().key("nested").key("inner").key("hits")),
+        Some(Value::Primitive(Primitive::Counter(-1)))
+    );
+}
+
+fn conflicting_birds() -> (Frontend, amp::ActorId, amp::ActorId) {
+    let actor1 = amp::ActorId::from_str("02ef21f3c9eb4087880ebedd7c4bbe43").unwrap();
+    let actor2 = amp::ActorId::from_str("2a1d376b24f744008d4af58252d644dd").unwrap();
+    let patch = amp::Patch {
+        actor: None,
+        seq: None,
+        max_op: 1,
+        clock: hashmap! {
+            actor1.clone() => 1,
+            actor2.clone() => 1,
+        },
+        deps: Vec::new(),
+        diffs: Some(amp::Diff::Map(amp::MapDiff {
+            object_id: amp::ObjectId::Root,
+            obj_type: amp::MapType::Map,
+            props: hashmap! {
+                "bird".into() => hashmap!{
+                    actor1.op_id_at(1) => amp::Diff::Value("robin".into()),
+                    actor2.op_id_at(1) => amp::Diff::Value("wagtail".into()),
+                }
+            },
+        })),
+    };
+    let mut doc = Frontend::new();
+    doc.apply_patch(patch).unwrap();
+    (doc, actor1, actor2)
+}
+
+fn set_bird_change(actor: &amp::ActorId, bird: &str, time: i64) -> amp::UncompressedChange {
+    amp::UncompressedChange {
+        operations: vec![amp::Op {
+            action: amp::OpType::Set(bird.into()),
+            obj: amp::ObjectId::Root,
+            key: "bird".into(),
+            pred: Vec::new(),
+            insert: false,
+        }],
+        actor_id: actor.clone(),
+        hash: None,
+        seq: 1,
+        start_op: 1,
+        time,
+        message: None,
+        deps: Vec::new(),
+        extra_bytes: Vec::new(),
+    }
+}
+
+#[test]
+fn conflict_resolvers_change_the_displayed_value() {
+    let (mut doc, actor1, actor2) = conflicting_birds();
+    let bird = Path::root().key("bird");
+    assert_eq!(doc.get_value(&bird), Some("wagtail".into()));
+
+    let changes = vec![
+        set_bird_change(&actor1, "robin", 20),
+        set_bird_change(&actor2, "wagtail", 10),
+    ];
+    doc.set_conflict_resolver(LastWriterWins::from_changes(&changes));
+    assert_eq!(doc.get_value(&bird), Some("robin".into()));
+    assert_eq!(
+        doc.state(),
+        &Into::<Value>::into(hashmap! {"bird" => "robin"})
+    );
+
+    let prefer_actor2 = {
+        let actor2 = actor2.clone();
+        move |_: &Path, conflicts: &HashMap<amp::OpId, Value>| {
+            conflicts.keys().find(|o| o.1 == actor2).cloned()
+        }
+    };
+    doc.set_conflict_resolver(
+        ResolveByPath::new()
+            .with_path(
+                Path::root().key("other"),
+                LastWriterWins::from_changes(&changes),
+            )
+            .with_fallback(prefer_actor2),
+    );
+    assert_eq!(doc.get_value(&bird), Some("wagtail".into()));
+
+    // The conflicts themselves are unaffected
+    assert_eq!(
+        doc.get_conflicts(&bird),
+        Some(hashmap! {
+            actor1.op_id_at(1) => "robin".into(),
+            actor2.op_id_at(1) => "wagtail".into(),
+        })
+    );
+
+    doc.clear_conflict_resolver();
+    assert_eq!(doc.get_value(&bird), Some("wagtail".into()));
+}
+
+#[test]
+fn conflict_resolvers_pick_objects_along_the_path() {
+    let actor1 = amp::ActorId::from_str("02ef21f3c9eb4087880ebedd7c4bbe43").unwrap();
+    let actor2 = amp::ActorId::from_str("2a1d376b24f744008d4af58252d644dd").unwrap();
+    let nested = |actor: &amp::ActorId, bird: &str| {
+        amp::Diff::Map(amp::MapDiff {
+            object_id: actor.op_id_at(1).into(),
+            obj_type: amp::MapType::Map,
+            props: hashmap! {
+                "bird".into() => hashmap!{
+                    actor.op_id_at(2) => amp::Diff::Value(bird.into()),
+                }
+            },
+        })
+    };
+    let patch = amp::Patch {
+        actor: None,
+        seq: None,
+        max_op: 2,
+        clock: hashmap! {
+            actor1.clone() => 1,
+            actor2.clone() => 1,
+        },
+        deps: Vec::new(),
+        diffs: Some(amp::Diff::Map(amp::MapDiff {
+            object_id: amp::ObjectId::Root,
+            obj_type: amp::MapType::Map,
+            props: hashmap! {
+                "nested".into() => hashmap!{
+                    actor1.op_id_at(1) => nested(&actor1, "robin"),
+                    actor2.op_id_at(1) => nested(&actor2, "wagtail"),
+                }
+            },
+        })),
+    };
+    let mut doc = Frontend::new();
+    doc.apply_patch(patch).unwrap();
+    let bird = Path::root().key("nested").key("bird");
+    assert_eq!(doc.get_value(&bird), Some("wagtail".into()));
+
+    doc.set_conflict_resolver(move |path: &Path, _: &HashMap<amp::OpId, Value>| {
+        assert_eq!(path, &Path::root().key("nested"));
+        Some(actor1.op_id_at(1))
+    });
+    assert_eq!(doc.get_value(&bird), Some("robin".into()));
+    assert_eq!(doc.get_value(&bird.clone().key("missing")), None);
+}
+
+#[test]
+fn resolve_conflict_overwrites_every_conflicting_value() {
+    let (mut doc, actor1, actor2) = conflicting_birds();
+    let bird = Path::root().key("bird");
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::resolve_conflict(bird.clone(), "robin"))?;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(req.operations.len(), 1);
+    let mut expected = vec![actor1.op_id_at(1), actor2.op_id_at(1)];
+    expected.sort();
+    assert_eq!(req.operations[0].pred, expected);
+    assert_eq!(doc.get_value(&bird), Some("robin".into()));
+    assert_eq!(doc.get_conflicts(&bird).map(|c| c.len()), Some(1));
+}
+
+#[test]
+fn resolve_conflict_drops_nested_conflicts_before_the_patch_arrives() {
+    let actor1 = amp::ActorId::from_str("02ef21f3c9eb4087880ebedd7c4bbe43").unwrap();
+    let actor2 = amp::ActorId::from_str("2a1d376b24f744008d4af58252d644dd").unwrap();
+    let list_id = actor1.op_id_at(1);
+    let patch = amp::Patch {
+        actor: None,
+        seq: None,
+        max_op: 3,
+        clock: hashmap! {
+            actor1.clone() => 1,
+            actor2.clone() => 1,
+        },
+        deps: Vec::new(),
+        diffs: Some(amp::Diff::Map(amp::MapDiff {
+            object_id: amp::ObjectId::Root,
+            obj_type: amp::MapType::Map,
+            props: hashmap! {
+                "birds".into() => hashmap!{
+                    list_id.clone() => amp::Diff::Seq(amp::SeqDiff {
+                        object_id: list_id.clone().into(),
+                        obj_type: amp::SequenceType::List,
+                        edits: vec![amp::DiffEdit::Insert {
+                            index: 0,
+                            elem_id: actor1.op_id_at(2).into(),
+                        }],
+                        props: hashmap! {
+                            0 => hashmap!{
+                                actor1.op_id_at(2) => amp::Diff::Value("robin".into()),
+                                actor2.op_id_at(3) => amp::Diff::Value("wagtail".into()),
+                            }
+                        },
+                    })
+                }
+            },
+        })),
+    };
+    let mut doc = Frontend::new();
+    doc.apply_patch(patch).unwrap();
+    let first = Path::root().key("birds").index(0);
+    assert_eq!(doc.conflicts().len(), 1);
+
+    let req = doc
+        .change::<_, InvalidChangeRequest>(None, |doc| {
+            doc.add_change(LocalChange::resolve_conflict(first.clone(), "magpie"))?;
+            Ok(())
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        req.operations[0].pred,
+        vec![actor1.op_id_at(2), actor2.op_id_at(3)]
+    );
+    assert!(doc.conflicts().is_empty());
+    assert_eq!(doc.get_value(&first), Some("magpie".into()));
+}
+
+#[test]
+fn list_every_conflicted_path() {
+    let (mut doc, actor1, actor2) = conflicting_birds();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(
+            Path::root().key("nested"),
+            Value::from_json(&serde_json::json!({"birds": ["magpie"]})),
+        ))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        doc.conflicts(),
+        vec![(
+            Path::root().key("bird"),
+            hashmap! {
+                actor1.op_id_at(1) => "robin".into(),
+                actor2.op_id_at(1) => "wagtail".into(),
+            }
+        )]
+    );
+    assert_eq!(Path::root().key("bird").to_string(), r#"$["bird"]"#);
+    assert_eq!(
+        Path::root().key("nested").index(0).to_string(),
+        r#"$["nested"][0]"#
+    );
+    assert_eq!(
+        Path::root().key("say \"hi\"\u{1}").to_string(),
+        r#"$["say \"hi\"\u0001"]"#
+    );
+
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::resolve_conflict(
+            Path::root().key("bird"),
+            "robin",
+        ))?;
+        Ok(())
+    })
+    .unwrap();
+    assert!(doc.conflicts().is_empty());
+}
+
+#[test]
+fn list_conflicts_inside_values_which_lost() {
+    let actor1 = amp::ActorId::from_str("02ef21f3c9eb4087880ebedd7c4bbe43").unwrap();
+    let actor2 = amp::ActorId::from_str("2a1d376b24f744008d4af58252d644dd").unwrap();
+    let patch = amp::Patch {
+        actor: None,
+        seq: None,
+        max_op: 3,
+        clock: hashmap! {
+            actor1.clone() => 1,
+            actor2.clone() => 1,
+        },
+        deps: Vec::new(),
+        diffs: Some(amp::Diff::Map(amp::MapDiff {
+            object_id: amp::ObjectId::Root,
+            obj_type: amp::MapType::Map,
+            props: hashmap! {
+                "nested".into() => hashmap!{
+                    actor1.op_id_at(1) => amp::Diff::Map(amp::MapDiff {
+                        object_id: actor1.op_id_at(1).into(),
+                        obj_type: amp::MapType::Map,
+                        props: hashmap! {
+                            "bird".into() => hashmap!{
+                                actor1.op_id_at(2) => amp::Diff::Value("robin".into()),
+                                actor2.op_id_at(3) => amp::Diff::Value("wagtail".into()),
+                            }
+                        },
+                    }),
+                    actor2.op_id_at(1) => amp::Diff::Map(amp::MapDiff {
+                        object_id: actor2.op_id_at(1).into(),
+                        obj_type: amp::MapType::Map,
+                        props: HashMap::new(),
+                    }),
+                }
+            },
+        })),
+    };
+    let mut doc = Frontend::new();
+    doc.apply_patch(patch).unwrap();
+
+    // The map written by actor2 wins, so actor1's map and the conflict in
+    // it aren't visible until the conflict is resolved
+    let hidden_bird = Path::root()
+        .key("nested")
+        .conflict(actor1.op_id_at(1))
+        .key("bird");
+    let conflicts = doc.conflicts();
+    assert_eq!(
+        conflicts.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>(),
+        vec![Path::root().key("nested"), hidden_bird.clone()]
+    );
+    assert_eq!(
+        conflicts[1].1,
+        hashmap! {
+            actor1.op_id_at(2) => "robin".into(),
+            actor2.op_id_at(3) => "wagtail".into(),
+        }
+    );
+    assert_eq!(
+        hidden_bird.to_string(),
+        format!(r#"$["nested"][@{}]["bird"]"#, actor1.op_id_at(1))
+    );
+
+    assert_eq!(doc.get_value(&hidden_bird), Some("wagtail".into()));
+}
+
+#[test]
+fn failed_change_leaves_document_unchanged() {
+    let mut doc = Frontend::new();
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(Path::root().key("bird"), "magpie"))?;
+        Ok(())
+    })
+    .unwrap();
+    let result = doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(Path::root().key("dog"), "mastiff"))?;
+        doc.add_change(LocalChange::increment(Path::root().key("bird")))?;
+        Ok(())
+    });
+    assert!(result.is_err());
+    assert_eq!(
+        doc.state(),
+        &Into::<Value>::into(hashmap! {"bird" => "magpie"})
+    );
+    doc.change::<_, InvalidChangeRequest>(None, |doc| {
+        doc.add_change(LocalChange::set(Path::root().key("dog"), "mastiff"))?;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        doc.state(),
+        &Into::<Value>::into(hashmap! {"bird" => "magpie", "dog" => "mastiff"})
+    );
+}
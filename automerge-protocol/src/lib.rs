@@ -60,6 +60,10 @@ impl ObjType {
         ObjType::Map(MapType::Table)
     }
 
+    pub fn set() -> ObjType {
+        ObjType::Map(MapType::Set)
+    }
+
     pub fn text() -> ObjType {
         ObjType::Sequence(SequenceType::Text)
     }
@@ -74,6 +78,10 @@ impl ObjType {
 pub enum MapType {
     Map,
     Table,
+    /// An add-wins set. Members are stored as keys whose value is `true`, a
+    /// member is removed by deleting the key with all the observed adds as
+    /// `pred`, so a concurrent add which was not observed survives the remove.
+    Set,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Copy, Hash)]
@@ -170,6 +178,8 @@ pub enum DataType {
     Timestamp,
     #[serde(rename = "cursor")]
     Cursor,
+    #[serde(rename = "bytes")]
+    Bytes,
     #[serde(rename = "undefined")]
     Undefined,
 }
@@ -181,10 +191,13 @@ impl DataType {
     }
 }
 
+/// In JSON `Bytes` is a base64 encoded string, alongside a `"datatype":
+/// "bytes"` field in ops and diffs so it isn't mistaken for a string
 #[derive(Serialize, PartialEq, Debug, Clone)]
 #[serde(untagged)]
 pub enum ScalarValue {
     Str(String),
+    Bytes(#[serde(serialize_with = "serde_impls::serialize_base64")] Vec<u8>),
     Int(i64),
     Uint(u64),
     F64(f64),
@@ -194,6 +207,16 @@ pub enum ScalarValue {
     Cursor(OpId),
     Boolean(bool),
     Null,
+    /// A value with a type code this version doesn't understand, kept so
+    /// that it's written back out unchanged. In JSON it is an object with
+    /// the type code and the base64 encoded bytes. The type code must be
+    /// one of the unknown codes, see `ScalarValue::is_unknown_type_code`.
+    Unknown {
+        #[serde(rename = "typeCode")]
+        type_code: u8,
+        #[serde(serialize_with = "serde_impls::serialize_base64")]
+        bytes: Vec<u8>,
+    },
 }
 
 impl ScalarValue {
@@ -234,6 +257,22 @@ impl ScalarValue {
                 unexpected: v.to_string(),
                 datatype,
             }),
+            (DataType::Bytes, ScalarValue::Bytes(_)) => Ok(self.clone()),
+            (DataType::Bytes, ScalarValue::Str(s)) => match base64::decode(s) {
+                Ok(bytes) => Ok(ScalarValue::Bytes(bytes)),
+                Err(_) => Err(error::InvalidScalarValue {
+                    raw_value: self.clone(),
+                    expected: "a base64 encoded string".to_string(),
+                    unexpected: "a string which is not valid base64".to_string(),
+                    datatype,
+                }),
+            },
+            (DataType::Bytes, v) => Err(error::InvalidScalarValue {
+                raw_value: self.clone(),
+                expected: "a base64 encoded string".to_string(),
+                unexpected: v.to_string(),
+                datatype,
+            }),
             (DataType::Cursor, v) => Err(error::InvalidScalarValue {
                 raw_value: self.clone(),
                 expected: "a cursor".to_string(),
@@ -261,9 +300,17 @@ impl ScalarValue {
         match self {
             ScalarValue::Counter(..) => Some(DataType::Counter),
             ScalarValue::Timestamp(..) => Some(DataType::Timestamp),
+            ScalarValue::Bytes(..) => Some(DataType::Bytes),
             _ => None,
         }
     }
+
+    /// Whether `type_code` is one of the value type codes the binary format
+    /// leaves for later versions, the only type codes a
+    /// `ScalarValue::Unknown` can have
+    pub fn is_unknown_type_code(type_code: u8) -> bool {
+        (11..=15).contains(&type_code)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -272,6 +319,22 @@ pub enum OpType {
     Del,
     Inc(i64),
     Set(ScalarValue),
+    /// An op with an action this version doesn't understand, kept so that
+    /// it's written back out unchanged. In JSON the action is a number,
+    /// which mustn't be a known action, see `OpType::is_unknown_action`.
+    Unknown {
+        action: u64,
+        value: ScalarValue,
+    },
+}
+
+impl OpType {
+    /// Whether `action` is a number the binary format doesn't assign to an
+    /// action this version knows, the only numbers an `OpType::Unknown` can
+    /// have
+    pub fn is_unknown_action(action: u64) -> bool {
+        !matches!(action, 0..=6 | 8)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
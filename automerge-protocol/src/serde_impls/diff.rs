@@ -34,6 +34,12 @@ impl Serialize for Diff {
                     op.serialize_field("datatype", "timestamp")?;
                     op.end()
                 }
+                ScalarValue::Bytes(_) => {
+                    let mut op = serializer.serialize_struct("Value", 2)?;
+                    op.serialize_field("value", &val)?;
+                    op.serialize_field("datatype", "bytes")?;
+                    op.end()
+                }
                 _ => {
                     let mut op = serializer.serialize_struct("Value", 1)?;
                     op.serialize_field("value", &val)?;
@@ -114,7 +120,7 @@ impl<'de> Deserialize<'de> for Diff {
                         }
                         _ => {
                             let value = value.ok_or_else(|| Error::missing_field("value"))?;
-                            let value_with_datatype = maybe_add_datatype_to_value(value, datatype);
+                            let value_with_datatype = maybe_add_datatype_to_value(value, datatype)?;
                             Ok(Diff::Value(value_with_datatype))
                         }
                     }
@@ -158,8 +164,11 @@ impl<'de> Deserialize<'de> for Diff {
     }
 }
 
-fn maybe_add_datatype_to_value(value: ScalarValue, datatype: DataType) -> ScalarValue {
-    match datatype {
+fn maybe_add_datatype_to_value<E: Error>(
+    value: ScalarValue,
+    datatype: DataType,
+) -> Result<ScalarValue, E> {
+    Ok(match datatype {
         DataType::Counter => {
             if let Some(n) = value.to_i64() {
                 ScalarValue::Counter(n)
@@ -174,13 +183,21 @@ fn maybe_add_datatype_to_value(value: ScalarValue, datatype: DataType) -> Scalar
                 value
             }
         }
+        DataType::Bytes => value.as_datatype(DataType::Bytes).map_err(|e| {
+            Error::invalid_value(
+                Unexpected::Other(e.unexpected.as_str()),
+                &e.expected.as_str(),
+            )
+        })?,
         _ => value,
-    }
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{CursorDiff, Diff, MapDiff, MapType, ObjectId, OpId, SeqDiff, SequenceType};
+    use crate::{
+        CursorDiff, Diff, MapDiff, MapType, ObjectId, OpId, ScalarValue, SeqDiff, SequenceType,
+    };
     use maplit::hashmap;
     use std::convert::TryInto;
     use std::str::FromStr;
@@ -241,6 +258,46 @@ mod tests {
         assert_eq!(serde_json::from_value::<Diff>(json).unwrap(), diff);
     }
 
+    #[test]
+    fn bytes_value_diff_serialization_round_trip() {
+        let json = serde_json::json!({
+            "value": "AAEC/w==",
+            "datatype": "bytes"
+        });
+        let diff = Diff::Value(ScalarValue::Bytes(vec![0, 1, 2, 255]));
+
+        assert_eq!(json, serde_json::to_value(diff.clone()).unwrap());
+        assert_eq!(serde_json::from_value::<Diff>(json).unwrap(), diff);
+    }
+
+    #[test]
+    fn bytes_value_diff_with_invalid_base64_is_an_error() {
+        let json = serde_json::json!({
+            "value": "not base64!",
+            "datatype": "bytes"
+        });
+        assert!(serde_json::from_value::<Diff>(json).is_err());
+        let json = serde_json::json!({
+            "value": 5,
+            "datatype": "bytes"
+        });
+        assert!(serde_json::from_value::<Diff>(json).is_err());
+    }
+
+    #[test]
+    fn unknown_value_diff_serialization_round_trip() {
+        let json = serde_json::json!({
+            "value": {"typeCode": 12, "bytes": "AAEC/w=="},
+        });
+        let diff = Diff::Value(ScalarValue::Unknown {
+            type_code: 12,
+            bytes: vec![0, 1, 2, 255],
+        });
+
+        assert_eq!(json, serde_json::to_value(diff.clone()).unwrap());
+        assert_eq!(serde_json::from_value::<Diff>(json).unwrap(), diff);
+    }
+
     #[test]
     fn cursor_diff_serialization_round_trip() {
         let json = serde_json::json!({
@@ -15,6 +15,8 @@ mod op_type;
 mod opid;
 mod scalar_value;
 
+pub(crate) use scalar_value::serialize_base64;
+
 // Helper method for use in custom deserialize impls
 pub(crate) fn read_field<'de, T, M>(
     name: &'static str,
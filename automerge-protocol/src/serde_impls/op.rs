@@ -20,9 +20,10 @@ impl Serialize for Op {
         }
 
         match &self.action {
-            OpType::Set(ScalarValue::Timestamp(_)) => fields += 2,
-            OpType::Set(ScalarValue::Counter(_)) => fields += 2,
-            OpType::Inc(_) | OpType::Set(_) => fields += 1,
+            OpType::Set(value) | OpType::Unknown { value, .. } if value.datatype().is_some() => {
+                fields += 2
+            }
+            OpType::Inc(_) | OpType::Set(_) | OpType::Unknown { .. } => fields += 1,
             _ => {}
         }
 
@@ -50,7 +51,17 @@ impl Serialize for Op {
                 op.serialize_field("value", &value)?;
                 op.serialize_field("datatype", &DataType::Timestamp)?;
             }
+            OpType::Set(value @ ScalarValue::Bytes(_)) => {
+                op.serialize_field("value", &value)?;
+                op.serialize_field("datatype", &DataType::Bytes)?;
+            }
             OpType::Set(value) => op.serialize_field("value", &value)?,
+            OpType::Unknown { value, .. } => {
+                op.serialize_field("value", &value)?;
+                if let Some(datatype) = value.datatype() {
+                    op.serialize_field("datatype", &datatype)?;
+                }
+            }
             _ => {}
         }
         op.serialize_field("pred", &self.pred)?;
@@ -63,6 +74,7 @@ impl Serialize for Op {
 pub enum RawOpType {
     MakeMap,
     MakeTable,
+    MakeSet,
     MakeList,
     MakeText,
     Del,
@@ -70,6 +82,14 @@ pub enum RawOpType {
     Set,
 }
 
+/// Actions we don't know the name of are numbers
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(untagged)]
+enum RawAction {
+    Known(RawOpType),
+    Unknown(u64),
+}
+
 impl<'de> Deserialize<'de> for Op {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -88,7 +108,7 @@ impl<'de> Deserialize<'de> for Op {
             where
                 V: MapAccess<'de>,
             {
-                let mut action: Option<RawOpType> = None;
+                let mut action: Option<RawAction> = None;
                 let mut obj: Option<ObjectId> = None;
                 let mut key: Option<Key> = None;
                 let mut pred: Option<Vec<OpId>> = None;
@@ -115,38 +135,35 @@ impl<'de> Deserialize<'de> for Op {
                 let key = key.ok_or_else(|| Error::missing_field("key"))?;
                 let pred = pred.ok_or_else(|| Error::missing_field("pred"))?;
                 let insert = insert.unwrap_or(false);
+                let action = match action {
+                    RawAction::Known(action) => action,
+                    RawAction::Unknown(action) if !OpType::is_unknown_action(action) => {
+                        return Err(Error::invalid_value(
+                            Unexpected::Unsigned(action),
+                            &"an action name, or the number of an unknown action",
+                        ))
+                    }
+                    RawAction::Unknown(action) => {
+                        return Ok(Op {
+                            action: OpType::Unknown {
+                                action,
+                                value: read_value(datatype, value, ref_id)?,
+                            },
+                            obj,
+                            key,
+                            insert,
+                            pred,
+                        })
+                    }
+                };
                 let action = match action {
                     RawOpType::MakeMap => OpType::Make(ObjType::Map(MapType::Map)),
                     RawOpType::MakeTable => OpType::Make(ObjType::Map(MapType::Table)),
+                    RawOpType::MakeSet => OpType::Make(ObjType::Map(MapType::Set)),
                     RawOpType::MakeList => OpType::Make(ObjType::Sequence(SequenceType::List)),
                     RawOpType::MakeText => OpType::Make(ObjType::Sequence(SequenceType::Text)),
                     RawOpType::Del => OpType::Del,
-                    RawOpType::Set => {
-                        let value = if let Some(datatype) = datatype {
-                            match datatype {
-                                DataType::Cursor => match ref_id {
-                                    Some(opid) => ScalarValue::Cursor(opid),
-                                    None => return Err(Error::missing_field("ref")),
-                                },
-                                _ => {
-                                    let raw_value = value
-                                        .ok_or_else(|| Error::missing_field("value"))?
-                                        .unwrap_or(ScalarValue::Null);
-                                    raw_value.as_datatype(datatype).map_err(|e| {
-                                        Error::invalid_value(
-                                            Unexpected::Other(e.unexpected.as_str()),
-                                            &e.expected.as_str(),
-                                        )
-                                    })?
-                                }
-                            }
-                        } else {
-                            value
-                                .ok_or_else(|| Error::missing_field("value"))?
-                                .unwrap_or(ScalarValue::Null)
-                        };
-                        OpType::Set(value)
-                    }
+                    RawOpType::Set => OpType::Set(read_value(datatype, value, ref_id)?),
                     RawOpType::Inc => match value.flatten() {
                         Some(ScalarValue::Int(n)) => Ok(OpType::Inc(n)),
                         Some(ScalarValue::Uint(n)) => Ok(OpType::Inc(n as i64)),
@@ -157,6 +174,10 @@ impl<'de> Deserialize<'de> for Op {
                         Some(ScalarValue::Str(s)) => {
                             Err(Error::invalid_value(Unexpected::Str(&s), &"a number"))
                         }
+                        Some(ScalarValue::Bytes(_)) => Err(Error::invalid_value(
+                            Unexpected::Other("bytes"),
+                            &"a number",
+                        )),
                         Some(ScalarValue::Boolean(b)) => {
                             Err(Error::invalid_value(Unexpected::Bool(b), &"a number"))
                         }
@@ -167,6 +188,10 @@ impl<'de> Deserialize<'de> for Op {
                             Unexpected::Other("a cursor"),
                             &"a number",
                         )),
+                        Some(ScalarValue::Unknown { .. }) => Err(Error::invalid_value(
+                            Unexpected::Other("a value of an unknown type"),
+                            &"a number",
+                        )),
                         None => Err(Error::missing_field("value")),
                     }?,
                 };
@@ -183,6 +208,36 @@ impl<'de> Deserialize<'de> for Op {
     }
 }
 
+fn read_value<E: Error>(
+    datatype: Option<DataType>,
+    value: Option<Option<ScalarValue>>,
+    ref_id: Option<OpId>,
+) -> Result<ScalarValue, E> {
+    if let Some(datatype) = datatype {
+        match datatype {
+            DataType::Cursor => match ref_id {
+                Some(opid) => Ok(ScalarValue::Cursor(opid)),
+                None => Err(Error::missing_field("ref")),
+            },
+            _ => {
+                let raw_value = value
+                    .ok_or_else(|| Error::missing_field("value"))?
+                    .unwrap_or(ScalarValue::Null);
+                raw_value.as_datatype(datatype).map_err(|e| {
+                    Error::invalid_value(
+                        Unexpected::Other(e.unexpected.as_str()),
+                        &e.expected.as_str(),
+                    )
+                })
+            }
+        }
+    } else {
+        Ok(value
+            .ok_or_else(|| Error::missing_field("value"))?
+            .unwrap_or(ScalarValue::Null))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -214,6 +269,47 @@ mod tests {
                     pred: Vec::new(),
                 }),
             },
+            Scenario {
+                name: "Set with bytes",
+                json: serde_json::json!({
+                    "action": "set",
+                    "obj": "_root",
+                    "key": "somekey",
+                    "value": "AAEC/w==",
+                    "datatype": "bytes",
+                    "pred": []
+                }),
+                expected: Ok(Op {
+                    action: OpType::Set(ScalarValue::Bytes(vec![0, 1, 2, 255])),
+                    obj: ObjectId::Root,
+                    key: "somekey".into(),
+                    insert: false,
+                    pred: Vec::new(),
+                }),
+            },
+            Scenario {
+                name: "Unknown action with a value of an unknown type",
+                json: serde_json::json!({
+                    "action": 12,
+                    "obj": "_root",
+                    "key": "somekey",
+                    "value": {"typeCode": 13, "bytes": "AAEC/w=="},
+                    "pred": []
+                }),
+                expected: Ok(Op {
+                    action: OpType::Unknown {
+                        action: 12,
+                        value: ScalarValue::Unknown {
+                            type_code: 13,
+                            bytes: vec![0, 1, 2, 255],
+                        },
+                    },
+                    obj: ObjectId::Root,
+                    key: "somekey".into(),
+                    insert: false,
+                    pred: Vec::new(),
+                }),
+            },
             Scenario {
                 name: "Set with Int",
                 json: serde_json::json!({
@@ -282,6 +378,34 @@ mod tests {
                     pred: Vec::new(),
                 }),
             },
+            Scenario {
+                name: "Number of a known action",
+                json: serde_json::json!({
+                    "action": 1,
+                    "obj": "_root",
+                    "key": "somekey",
+                    "value": 123,
+                    "pred": []
+                }),
+                expected: Err(serde_json::Error::invalid_value(
+                    Unexpected::Unsigned(1),
+                    &"an action name, or the number of an unknown action",
+                )),
+            },
+            Scenario {
+                name: "Value with a known type code",
+                json: serde_json::json!({
+                    "action": 12,
+                    "obj": "_root",
+                    "key": "somekey",
+                    "value": {"typeCode": 6, "bytes": "AAEC/w=="},
+                    "pred": []
+                }),
+                expected: Err(serde_json::Error::invalid_value(
+                    Unexpected::Unsigned(6),
+                    &"an unknown type code, from 11 to 15",
+                )),
+            },
             Scenario {
                 name: "Set without value",
                 json: serde_json::json!({
@@ -447,6 +571,22 @@ mod tests {
                     &"A valid OpID",
                 )),
             },
+            Scenario {
+                name: "Make set",
+                json: serde_json::json!({
+                    "action": "makeSet",
+                    "obj": "_root",
+                    "key": "somekey",
+                    "pred": []
+                }),
+                expected: Ok(Op {
+                    action: OpType::Make(ObjType::set()),
+                    obj: ObjectId::Root,
+                    key: "somekey".into(),
+                    insert: false,
+                    pred: Vec::new(),
+                }),
+            },
         ];
 
         for scenario in scenarios.into_iter() {
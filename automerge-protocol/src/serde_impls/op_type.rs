@@ -9,11 +9,13 @@ impl Serialize for OpType {
         let s = match self {
             OpType::Make(ObjType::Map(MapType::Map)) => "makeMap",
             OpType::Make(ObjType::Map(MapType::Table)) => "makeTable",
+            OpType::Make(ObjType::Map(MapType::Set)) => "makeSet",
             OpType::Make(ObjType::Sequence(SequenceType::List)) => "makeList",
             OpType::Make(ObjType::Sequence(SequenceType::Text)) => "makeText",
             OpType::Del => "del",
             OpType::Inc(_) => "inc",
             OpType::Set(_) => "set",
+            OpType::Unknown { action, .. } => return serializer.serialize_u64(*action),
         };
         serializer.serialize_str(s)
     }
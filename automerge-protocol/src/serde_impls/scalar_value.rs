@@ -1,5 +1,6 @@
+use super::read_field;
 use crate::ScalarValue;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serializer};
 
 impl<'de> Deserialize<'de> for ScalarValue {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -11,7 +12,7 @@ impl<'de> Deserialize<'de> for ScalarValue {
             type Value = ScalarValue;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a number, string, bool, or null")
+                formatter.write_str("a number, string, bool, null or value of an unknown type")
             }
 
             fn visit_bool<E>(self, value: bool) -> Result<ScalarValue, E>
@@ -55,7 +56,42 @@ impl<'de> Deserialize<'de> for ScalarValue {
             {
                 Ok(ScalarValue::Null)
             }
+
+            fn visit_map<M>(self, mut map: M) -> Result<ScalarValue, M::Error>
+            where
+                M: de::MapAccess<'de>,
+            {
+                const FIELDS: &[&str] = &["typeCode", "bytes"];
+                let mut type_code: Option<u8> = None;
+                let mut bytes: Option<String> = None;
+                while let Some(field) = map.next_key::<String>()? {
+                    match field.as_ref() {
+                        "typeCode" => read_field("typeCode", &mut type_code, &mut map)?,
+                        "bytes" => read_field("bytes", &mut bytes, &mut map)?,
+                        _ => return Err(de::Error::unknown_field(&field, FIELDS)),
+                    }
+                }
+                let type_code = type_code.ok_or_else(|| de::Error::missing_field("typeCode"))?;
+                if !ScalarValue::is_unknown_type_code(type_code) {
+                    return Err(de::Error::invalid_value(
+                        de::Unexpected::Unsigned(type_code.into()),
+                        &"an unknown type code, from 11 to 15",
+                    ));
+                }
+                let bytes = bytes.ok_or_else(|| de::Error::missing_field("bytes"))?;
+                let bytes = base64::decode(&bytes).map_err(|_| {
+                    de::Error::invalid_value(de::Unexpected::Str(&bytes), &"base64 encoded bytes")
+                })?;
+                Ok(ScalarValue::Unknown { type_code, bytes })
+            }
         }
         deserializer.deserialize_any(ValueVisitor)
     }
 }
+
+pub(crate) fn serialize_base64<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&base64::encode(bytes))
+}
@@ -31,6 +31,12 @@ impl From<bool> for ScalarValue {
     }
 }
 
+impl From<Vec<u8>> for ScalarValue {
+    fn from(b: Vec<u8>) -> Self {
+        ScalarValue::Bytes(b)
+    }
+}
+
 impl From<char> for ScalarValue {
     fn from(c: char) -> Self {
         ScalarValue::Str(c.to_string())
@@ -41,6 +47,7 @@ impl fmt::Display for ScalarValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ScalarValue::Str(s) => write!(f, "\"{}\"", s),
+            ScalarValue::Bytes(b) => write!(f, "Bytes: {}", base64::encode(b)),
             ScalarValue::Int(i) => write!(f, "{}", i),
             ScalarValue::Uint(i) => write!(f, "{}", i),
             ScalarValue::F32(n) => write!(f, "{:.32}", n),
@@ -50,6 +57,9 @@ impl fmt::Display for ScalarValue {
             ScalarValue::Boolean(b) => write!(f, "{}", b),
             ScalarValue::Null => write!(f, "null"),
             ScalarValue::Cursor(elemid) => write!(f, "Cursor: {}", elemid),
+            ScalarValue::Unknown { type_code, bytes } => {
+                write!(f, "Unknown type {}: {}", type_code, base64::encode(bytes))
+            }
         }
     }
 }
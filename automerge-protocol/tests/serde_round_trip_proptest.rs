@@ -2,7 +2,11 @@ extern crate automerge_protocol as amp;
 use proptest::prelude::*;
 
 fn arb_maptype() -> impl Strategy<Value = amp::MapType> {
-    prop_oneof![Just(amp::MapType::Map), Just(amp::MapType::Table),]
+    prop_oneof![
+        Just(amp::MapType::Map),
+        Just(amp::MapType::Table),
+        Just(amp::MapType::Set),
+    ]
 }
 
 fn arb_seqtype() -> impl Strategy<Value = amp::SequenceType> {
@@ -19,6 +23,7 @@ fn arb_objtype() -> impl Strategy<Value = amp::ObjType> {
 fn arb_scalar_value() -> impl Strategy<Value = amp::ScalarValue> {
     prop_oneof![
         any::<String>().prop_map(amp::ScalarValue::Str),
+        any::<Vec<u8>>().prop_map(amp::ScalarValue::Bytes),
         any::<i64>().prop_map(amp::ScalarValue::Int),
         //This is necessary because we don't support integers larger than i64 in the JSON protocol
         //any::<i64>().prop_map(|i| amp::ScalarValue::Uint(i as u64)),
@@ -29,6 +34,8 @@ fn arb_scalar_value() -> impl Strategy<Value = amp::ScalarValue> {
         any::<i64>().prop_map(amp::ScalarValue::Timestamp),
         any::<bool>().prop_map(amp::ScalarValue::Boolean),
         Just(amp::ScalarValue::Null),
+        (11..=15u8, any::<Vec<u8>>())
+            .prop_map(|(type_code, bytes)| amp::ScalarValue::Unknown { type_code, bytes }),
     ]
 }
 
@@ -38,6 +45,11 @@ fn arb_optype() -> impl Strategy<Value = amp::OpType> {
         Just(amp::OpType::Del),
         any::<i64>().prop_map(amp::OpType::Inc),
         arb_scalar_value().prop_map(amp::OpType::Set),
+        (
+            any::<u64>().prop_filter("known action", |a| amp::OpType::is_unknown_action(*a)),
+            arb_scalar_value()
+        )
+            .prop_map(|(action, value)| amp::OpType::Unknown { action, value }),
     ]
 }
 
@@ -123,26 +135,11 @@ fn normalize_change(change: &amp::UncompressedChange) -> amp::UncompressedChange
     let mut result = change.clone();
     for op in result.operations.iter_mut() {
         let new_action = match &op.action {
-            amp::OpType::Set(amp::ScalarValue::F32(f)) => {
-                let serialized = serde_json::to_string(f).unwrap();
-                let deserialized: f64 = serde_json::from_str(&serialized).unwrap();
-                amp::OpType::Set(amp::ScalarValue::F64(deserialized))
-            }
-            amp::OpType::Set(amp::ScalarValue::Int(i)) => {
-                let val = if *i > 0 {
-                    amp::ScalarValue::Uint((*i) as u64)
-                } else {
-                    amp::ScalarValue::Int(*i)
-                };
-                amp::OpType::Set(val)
-            }
-            //amp::OpType::Set(amp::ScalarValue::Uint(u)) => {
-            //if *u > (i64::max_value() as u64) {
-            //amp::OpType::Set(amp::ScalarValue::Uint(*u))
-            //} else {
-            //amp::OpType::Set(amp::ScalarValue::Int((*u).try_into().unwrap()))
-            //}
-            //}
+            amp::OpType::Set(value) => amp::OpType::Set(normalize_value(value)),
+            amp::OpType::Unknown { action, value } => amp::OpType::Unknown {
+                action: *action,
+                value: normalize_value(value),
+            },
             a => a.clone(),
         };
         op.action = new_action;
@@ -150,6 +147,31 @@ fn normalize_change(change: &amp::UncompressedChange) -> amp::UncompressedChange
     result
 }
 
+fn normalize_value(value: &amp::ScalarValue) -> amp::ScalarValue {
+    match value {
+        amp::ScalarValue::F32(f) => {
+            let serialized = serde_json::to_string(f).unwrap();
+            let deserialized: f64 = serde_json::from_str(&serialized).unwrap();
+            amp::ScalarValue::F64(deserialized)
+        }
+        amp::ScalarValue::Int(i) => {
+            if *i >= 0 {
+                amp::ScalarValue::Uint((*i) as u64)
+            } else {
+                amp::ScalarValue::Int(*i)
+            }
+        }
+        //amp::ScalarValue::Uint(u) => {
+        //if *u > (i64::max_value() as u64) {
+        //amp::ScalarValue::Uint(*u)
+        //} else {
+        //amp::ScalarValue::Int((*u).try_into().unwrap())
+        //}
+        //}
+        v => v.clone(),
+    }
+}
+
 proptest! {
     #[test]
     fn test_round_trip_serialization(change in arb_change()) {
@@ -0,0 +1,20 @@
+use automerge_backend::AutomergeError;
+use thiserror::Error;
+
+use crate::StorageError;
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+    #[error("Storage error: {0}")]
+    Storage(#[source] StorageError),
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("Message for document {0} which the peer hasn't subscribed to")]
+    NotSubscribed(String),
+    #[error("The peer fell too far behind reading messages")]
+    PeerTooSlow,
+}
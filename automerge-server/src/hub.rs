@@ -0,0 +1,336 @@
+use automerge_backend::{Backend, Change};
+use automerge_protocol::ChangeHash;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, WriteHalf};
+use tokio::sync::{mpsc, watch, Mutex, OnceCell};
+
+use crate::{read_message, write_message, Message, ServerError, Storage};
+
+type PeerId = u64;
+
+/// How many messages can be waiting to be written to a peer. A peer which
+/// falls further behind than this is disconnected, and can sync again by
+/// reconnecting and subscribing with its heads.
+pub const PEER_QUEUE_LEN: usize = 256;
+
+/// A document which is loaded the first time it's asked for
+type DocCell = OnceCell<Arc<Mutex<Doc>>>;
+
+/// Holds documents keyed by ID and syncs them with any number of peers.
+/// Cloning a hub is cheap and the clones share the same documents, so a
+/// clone can be moved into the task handling each connection.
+pub struct Hub<S> {
+    inner: Arc<HubInner<S>>,
+}
+
+struct HubInner<S> {
+    storage: S,
+    /// The cell is filled once the document is loaded, so loading one
+    /// document doesn't hold up the others
+    docs: Mutex<HashMap<String, Arc<DocCell>>>,
+    next_peer_id: AtomicU64,
+}
+
+struct Doc {
+    backend: Backend,
+    peers: HashMap<PeerId, PeerSender>,
+    /// The size of the document when it was last saved in full, and of the
+    /// changes appended to it in storage since
+    saved_len: usize,
+    appended_len: usize,
+}
+
+/// The state of one connection
+struct Peer {
+    id: PeerId,
+    sender: PeerSender,
+    subscriptions: HashSet<String>,
+}
+
+/// Queues messages to be written to a peer, and tells the connection to
+/// close if the queue is full
+#[derive(Clone)]
+struct PeerSender {
+    messages: mpsc::Sender<Message>,
+    too_slow: Arc<watch::Sender<bool>>,
+}
+
+impl<S> Clone for Hub<S> {
+    fn clone(&self) -> Self {
+        Hub {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: Storage> Hub<S> {
+    pub fn new(storage: S) -> Hub<S> {
+        Hub {
+            inner: Arc::new(HubInner {
+                storage,
+                docs: Mutex::new(HashMap::new()),
+                next_peer_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// A snapshot of the document with ID `doc_id`, loading it from storage
+    /// if it isn't already loaded. Documents which don't exist are empty.
+    pub async fn backend(&self, doc_id: &str) -> Result<Backend, ServerError> {
+        let doc = self.doc(doc_id).await?;
+        let doc = doc.lock().await;
+        Ok(doc.backend.clone())
+    }
+
+    /// Handle a connection until the peer closes it. Errors reading or
+    /// applying the peer's messages end the connection, as does the peer
+    /// falling more than `PEER_QUEUE_LEN` messages behind, see
+    /// `ServerError::PeerTooSlow`.
+    pub async fn serve<T>(&self, stream: T) -> Result<(), ServerError>
+    where
+        T: AsyncRead + AsyncWrite,
+    {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (messages, receiver) = mpsc::channel(PEER_QUEUE_LEN);
+        let (too_slow, mut reader_too_slow) = watch::channel(false);
+        let writer_too_slow = reader_too_slow.clone();
+        let mut peer = Peer {
+            id: self.inner.next_peer_id.fetch_add(1, Ordering::Relaxed),
+            sender: PeerSender {
+                messages,
+                too_slow: Arc::new(too_slow),
+            },
+            subscriptions: HashSet::new(),
+        };
+
+        let reading = async {
+            let mut result = Ok(());
+            loop {
+                let message = tokio::select! {
+                    message = read_message(&mut reader) => message,
+                    Ok(()) = reader_too_slow.changed() => Err(ServerError::PeerTooSlow),
+                };
+                match message {
+                    Ok(Some(message)) => {
+                        if let Err(e) = self.handle_message(&mut peer, message).await {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            self.disconnect(peer).await;
+            result
+        };
+        let (read_result, write_result) =
+            tokio::join!(reading, write_loop(writer, receiver, writer_too_slow));
+        read_result.and(write_result)
+    }
+
+    async fn doc(&self, doc_id: &str) -> Result<Arc<Mutex<Doc>>, ServerError> {
+        let cell = self
+            .inner
+            .docs
+            .lock()
+            .await
+            .entry(doc_id.to_string())
+            .or_default()
+            .clone();
+        // If loading fails the cell is left empty and the next caller tries
+        // again
+        let doc = cell
+            .get_or_try_init(|| async {
+                let data = self
+                    .inner
+                    .storage
+                    .load(doc_id)
+                    .await
+                    .map_err(ServerError::Storage)?;
+                let saved_len = data.as_ref().map_or(0, Vec::len);
+                let backend = match data {
+                    Some(data) => Backend::load(data)?,
+                    None => Backend::init(),
+                };
+                Ok::<_, ServerError>(Arc::new(Mutex::new(Doc {
+                    backend,
+                    peers: HashMap::new(),
+                    saved_len,
+                    appended_len: 0,
+                })))
+            })
+            .await?;
+        Ok(doc.clone())
+    }
+
+    /// Store a batch of new changes, appending them to the saved document
+    /// until they outgrow it, then saving the whole document again
+    async fn store(
+        &self,
+        doc_id: &str,
+        doc: &mut Doc,
+        changes: &[Change],
+    ) -> Result<(), ServerError> {
+        let storage = &self.inner.storage;
+        let appended: Vec<u8> = changes
+            .iter()
+            .flat_map(|c| c.bytes.iter().copied())
+            .collect();
+        if doc.appended_len + appended.len() > doc.saved_len {
+            let data = doc.backend.save()?;
+            doc.saved_len = data.len();
+            doc.appended_len = 0;
+            storage
+                .save(doc_id, data)
+                .await
+                .map_err(ServerError::Storage)
+        } else {
+            doc.appended_len += appended.len();
+            storage
+                .append(doc_id, appended)
+                .await
+                .map_err(ServerError::Storage)
+        }
+    }
+
+    async fn handle_message(&self, peer: &mut Peer, message: Message) -> Result<(), ServerError> {
+        tracing::trace!(peer = peer.id, ?message, "received message");
+        // Only subscribing brings a document into existence, everything else
+        // is about a document the peer is already syncing
+        if !matches!(message, Message::Subscribe { .. })
+            && !peer.subscriptions.contains(message.doc_id())
+        {
+            return Err(ServerError::NotSubscribed(message.doc_id().to_string()));
+        }
+        let doc = self.doc(message.doc_id()).await?;
+        let mut doc = doc.lock().await;
+        match message {
+            Message::Subscribe { doc_id, heads } => {
+                doc.peers.insert(peer.id, peer.sender.clone());
+                send_changes_since(peer, &doc.backend, &doc_id, &heads);
+                peer.send(Message::Heads {
+                    heads: doc.backend.get_heads(),
+                    doc_id: doc_id.clone(),
+                });
+                peer.subscriptions.insert(doc_id);
+            }
+            Message::Heads { doc_id, heads } => {
+                send_changes_since(peer, &doc.backend, &doc_id, &heads);
+            }
+            Message::Need { doc_id, hashes } => {
+                let changes: Vec<Change> = hashes
+                    .iter()
+                    .filter_map(|hash| doc.backend.get_change_by_hash(hash))
+                    .cloned()
+                    .collect();
+                if !changes.is_empty() {
+                    peer.send(Message::Changes { doc_id, changes });
+                }
+            }
+            Message::Changes { doc_id, changes } => {
+                // Changes which are only queued waiting for their deps have
+                // already been stored and relayed too
+                let mut hashes = HashSet::new();
+                let new_changes: Vec<Change> = changes
+                    .into_iter()
+                    .filter(|change| {
+                        !doc.backend.has_received(change) && hashes.insert(change.hash)
+                    })
+                    .collect();
+                if new_changes.is_empty() {
+                    return Ok(());
+                }
+                doc.backend.apply_changes(new_changes.clone())?;
+                self.store(&doc_id, &mut doc, &new_changes).await?;
+
+                for (peer_id, sender) in &doc.peers {
+                    if *peer_id != peer.id {
+                        sender.send(Message::Changes {
+                            doc_id: doc_id.clone(),
+                            changes: new_changes.clone(),
+                        });
+                    }
+                }
+
+                let missing = doc.backend.get_missing_deps_for(&[]);
+                if !missing.is_empty() {
+                    peer.send(Message::Need {
+                        doc_id,
+                        hashes: missing,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn disconnect(&self, peer: Peer) {
+        for doc_id in &peer.subscriptions {
+            let cell = self.inner.docs.lock().await.get(doc_id).cloned();
+            if let Some(doc) = cell.as_ref().and_then(|cell| cell.get()) {
+                doc.lock().await.peers.remove(&peer.id);
+            }
+        }
+    }
+}
+
+impl Peer {
+    fn send(&self, message: Message) {
+        self.sender.send(message)
+    }
+}
+
+impl PeerSender {
+    fn send(&self, message: Message) {
+        match self.messages.try_send(message) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                // The peer's connection closes and it has to subscribe again
+                let _ = self.too_slow.send(true);
+            }
+            // Writing to the peer has already failed, or the peer has gone
+            // and will be removed from `peers` when its connection finishes
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+fn send_changes_since(peer: &Peer, backend: &Backend, doc_id: &str, heads: &[ChangeHash]) {
+    let changes: Vec<Change> = backend.get_changes(heads).into_iter().cloned().collect();
+    if !changes.is_empty() {
+        peer.send(Message::Changes {
+            doc_id: doc_id.to_string(),
+            changes,
+        });
+    }
+}
+
+/// Write messages to the peer until they run out or the peer is too slow to
+/// keep up, in which case the reading side returns the error
+async fn write_loop<T: AsyncWrite>(
+    mut writer: WriteHalf<T>,
+    mut receiver: mpsc::Receiver<Message>,
+    mut too_slow: watch::Receiver<bool>,
+) -> Result<(), ServerError> {
+    loop {
+        let message = tokio::select! {
+            biased;
+            message = receiver.recv() => message,
+            Ok(()) = too_slow.changed() => None,
+        };
+        let message = match message {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+        tokio::select! {
+            result = write_message(&mut writer, &message) => result?,
+            Ok(()) = too_slow.changed() => return Ok(()),
+        }
+    }
+}
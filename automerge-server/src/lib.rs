@@ -0,0 +1,34 @@
+//! An async hub which holds automerge documents and relays changes between
+//! the peers connected to it.
+//!
+//! Each connection is any `AsyncRead + AsyncWrite` stream, passed to
+//! `Hub::serve`. Peers talk to the hub with the `Message`s in this crate, a
+//! peer sends `Subscribe` with the heads of its copy of a document, the hub
+//! replies with the changes the peer is missing and its own heads, so the
+//! peer can send back the changes the hub is missing. After that every new
+//! change the hub receives is forwarded to the other subscribed peers, and
+//! either side can ask for the dependencies of a change with `Need`. Any
+//! other message about a document the peer hasn't subscribed to closes the
+//! connection, as does the peer falling more than `PEER_QUEUE_LEN` messages
+//! behind.
+//!
+//! Documents are loaded from and saved to a `Storage` implementation.
+//!
+//! ```no_run
+//! # async fn run(socket: tokio::io::DuplexStream) -> Result<(), automerge_server::ServerError> {
+//! use automerge_server::{Hub, MemoryStorage};
+//!
+//! let hub = Hub::new(MemoryStorage::default());
+//! hub.serve(socket).await
+//! # }
+//! ```
+
+mod error;
+mod hub;
+mod message;
+mod storage;
+
+pub use error::ServerError;
+pub use hub::{Hub, PEER_QUEUE_LEN};
+pub use message::{read_message, write_message, Message, MAX_MESSAGE_SIZE};
+pub use storage::{MemoryStorage, Storage, StorageError};
@@ -0,0 +1,241 @@
+use automerge_backend::Change;
+use automerge_protocol::ChangeHash;
+use std::convert::TryFrom;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::ServerError;
+
+/// Messages longer than this are rejected rather than read into memory
+pub const MAX_MESSAGE_SIZE: u32 = 64 * 1024 * 1024;
+
+const SUBSCRIBE: u8 = 0;
+const HEADS: u8 = 1;
+const CHANGES: u8 = 2;
+const NEED: u8 = 3;
+
+/// A message between a peer and a hub. Every message is about a single
+/// document, so one connection can sync any number of documents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// Start receiving changes to `doc_id`. `heads` are the heads of the
+    /// sender's copy, the reply is the changes the sender is missing followed
+    /// by a `Heads` message.
+    Subscribe {
+        doc_id: String,
+        heads: Vec<ChangeHash>,
+    },
+    /// The heads of the sender's copy, the receiver should reply with any
+    /// changes the sender is missing
+    Heads {
+        doc_id: String,
+        heads: Vec<ChangeHash>,
+    },
+    Changes {
+        doc_id: String,
+        changes: Vec<Change>,
+    },
+    /// The sender is missing the changes with these hashes, the receiver
+    /// should reply with any of them that it has
+    Need {
+        doc_id: String,
+        hashes: Vec<ChangeHash>,
+    },
+}
+
+impl Message {
+    pub fn doc_id(&self) -> &str {
+        match self {
+            Message::Subscribe { doc_id, .. }
+            | Message::Heads { doc_id, .. }
+            | Message::Changes { doc_id, .. }
+            | Message::Need { doc_id, .. } => doc_id,
+        }
+    }
+
+    /// The message type, then the document ID, then the hashes or changes.
+    /// Strings, byte arrays and lists are prefixed with their length as a
+    /// big endian u32.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let (message_type, doc_id) = match self {
+            Message::Subscribe { doc_id, .. } => (SUBSCRIBE, doc_id),
+            Message::Heads { doc_id, .. } => (HEADS, doc_id),
+            Message::Changes { doc_id, .. } => (CHANGES, doc_id),
+            Message::Need { doc_id, .. } => (NEED, doc_id),
+        };
+        bytes.push(message_type);
+        encode_bytes(&mut bytes, doc_id.as_bytes());
+        match self {
+            Message::Subscribe { heads: hashes, .. }
+            | Message::Heads { heads: hashes, .. }
+            | Message::Need { hashes, .. } => {
+                encode_len(&mut bytes, hashes.len());
+                for hash in hashes {
+                    bytes.extend_from_slice(&hash.0);
+                }
+            }
+            Message::Changes { changes, .. } => {
+                encode_len(&mut bytes, changes.len());
+                for change in changes {
+                    encode_bytes(&mut bytes, &change.bytes);
+                }
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Message, ServerError> {
+        let mut decoder = Decoder { bytes };
+        let message_type = decoder.take(1)?[0];
+        let doc_id = String::from_utf8(decoder.bytes()?.to_vec())
+            .map_err(|_| ServerError::InvalidMessage("document ID is not UTF-8".into()))?;
+        let message = match message_type {
+            SUBSCRIBE => Message::Subscribe {
+                doc_id,
+                heads: decoder.hashes()?,
+            },
+            HEADS => Message::Heads {
+                doc_id,
+                heads: decoder.hashes()?,
+            },
+            NEED => Message::Need {
+                doc_id,
+                hashes: decoder.hashes()?,
+            },
+            CHANGES => {
+                let len = decoder.len()?;
+                let mut changes = Vec::new();
+                for _ in 0..len {
+                    changes.push(Change::from_bytes(decoder.bytes()?.to_vec())?);
+                }
+                Message::Changes { doc_id, changes }
+            }
+            other => {
+                return Err(ServerError::InvalidMessage(format!(
+                    "unknown message type {}",
+                    other
+                )))
+            }
+        };
+        if !decoder.bytes.is_empty() {
+            return Err(ServerError::InvalidMessage(
+                "unexpected bytes after the end of the message".into(),
+            ));
+        }
+        Ok(message)
+    }
+}
+
+/// Read a message prefixed with its length as a big endian u32, returns
+/// `None` if the stream ends before the next message
+pub async fn read_message<R>(reader: &mut R) -> Result<Option<Message>, ServerError>
+where
+    R: AsyncRead + Unpin,
+{
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_MESSAGE_SIZE {
+        return Err(ServerError::InvalidMessage(format!(
+            "message of {} bytes is too long",
+            len
+        )));
+    }
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).await?;
+    Message::decode(&bytes).map(Some)
+}
+
+pub async fn write_message<W>(writer: &mut W, message: &Message) -> Result<(), ServerError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let bytes = message.encode();
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|len| *len <= MAX_MESSAGE_SIZE)
+        .ok_or_else(|| {
+            ServerError::InvalidMessage(format!("message of {} bytes is too long", bytes.len()))
+        })?;
+    writer.write_u32(len).await?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn encode_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u32).to_be_bytes());
+}
+
+fn encode_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    encode_len(bytes, data.len());
+    bytes.extend_from_slice(data);
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ServerError> {
+        if self.bytes.len() < n {
+            return Err(ServerError::InvalidMessage("message is truncated".into()));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn len(&mut self) -> Result<usize, ServerError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], ServerError> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn hashes(&mut self) -> Result<Vec<ChangeHash>, ServerError> {
+        let len = self.len()?;
+        let bytes = self.take(
+            len.checked_mul(32)
+                .ok_or_else(|| ServerError::InvalidMessage("too many hashes".into()))?,
+        )?;
+        Ok(bytes
+            .chunks_exact(32)
+            .map(|chunk| ChangeHash::try_from(chunk).unwrap())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_round_trip() {
+        let message = Message::Need {
+            doc_id: "birds".into(),
+            hashes: vec![ChangeHash([1; 32]), ChangeHash([2; 32])],
+        };
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_truncated_messages_are_rejected() {
+        let message = Message::Heads {
+            doc_id: "birds".into(),
+            heads: vec![ChangeHash([1; 32])],
+        };
+        let bytes = message.encode();
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                Message::decode(&bytes[..len]),
+                Err(ServerError::InvalidMessage(_))
+            ));
+        }
+    }
+}
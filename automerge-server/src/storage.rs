@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where a `Hub` keeps its documents. Documents are stored in the format
+/// produced by `Backend::save`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// The saved document with ID `doc_id`, or `None` if there isn't one
+    async fn load(&self, doc_id: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Replace the saved document with ID `doc_id`. The hub does this when
+    /// the changes appended since the last save outgrow the saved document,
+    /// so the appended changes are compacted into it.
+    async fn save(&self, doc_id: &str, data: Vec<u8>) -> Result<(), StorageError>;
+
+    /// Append `data`, the encoded changes of a batch the hub hasn't seen
+    /// before, to the saved document with ID `doc_id`, or save it as the
+    /// document if there isn't one. `Backend::load` reads a document
+    /// followed by changes.
+    async fn append(&self, doc_id: &str, data: Vec<u8>) -> Result<(), StorageError>;
+}
+
+/// Keeps documents in memory, clones share the same documents
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    docs: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn load(&self, doc_id: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.docs.lock().unwrap().get(doc_id).cloned())
+    }
+
+    async fn save(&self, doc_id: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        self.docs.lock().unwrap().insert(doc_id.to_string(), data);
+        Ok(())
+    }
+
+    async fn append(&self, doc_id: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        self.docs
+            .lock()
+            .unwrap()
+            .entry(doc_id.to_string())
+            .or_default()
+            .extend(data);
+        Ok(())
+    }
+}
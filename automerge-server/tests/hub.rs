@@ -0,0 +1,396 @@
+use automerge_backend::{Backend, Change};
+use automerge_protocol as amp;
+use automerge_protocol::{ActorId, ChangeHash, ObjectId, Op, UncompressedChange};
+use automerge_server::{
+    read_message, write_message, Hub, MemoryStorage, Message, ServerError, Storage, PEER_QUEUE_LEN,
+};
+use std::convert::TryInto;
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, DuplexStream};
+use tokio::task::JoinHandle;
+
+const DOC_ID: &str = "birds";
+
+fn make_change(backend: &Backend, actor: &ActorId, seq: u64, key: &str, value: i64) -> Change {
+    UncompressedChange {
+        actor_id: actor.clone(),
+        seq,
+        start_op: backend
+            .get_changes(&[])
+            .iter()
+            .map(|c| c.max_op())
+            .max()
+            .unwrap_or(0)
+            + 1,
+        time: 0,
+        message: None,
+        hash: None,
+        deps: backend.get_heads(),
+        operations: vec![Op {
+            obj: ObjectId::Root,
+            action: amp::OpType::Set(amp::ScalarValue::Int(value)),
+            key: key.into(),
+            insert: false,
+            pred: Vec::new(),
+        }],
+        extra_bytes: Vec::new(),
+    }
+    .into()
+}
+
+/// A peer which syncs with the hub the way a client would
+struct TestPeer {
+    actor: ActorId,
+    backend: Backend,
+    stream: DuplexStream,
+}
+
+impl TestPeer {
+    fn connect(
+        hub: &Hub<MemoryStorage>,
+        actor: &str,
+    ) -> (TestPeer, JoinHandle<Result<(), ServerError>>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let hub = hub.clone();
+        let connection = tokio::spawn(async move { hub.serve(server).await });
+        let peer = TestPeer {
+            actor: actor.try_into().unwrap(),
+            backend: Backend::init(),
+            stream: client,
+        };
+        (peer, connection)
+    }
+
+    fn set(&mut self, key: &str, value: i64) -> Change {
+        let seq = self
+            .backend
+            .get_changes_for_actor_id(&self.actor)
+            .unwrap()
+            .len() as u64
+            + 1;
+        let change = make_change(&self.backend, &self.actor, seq, key, value);
+        self.backend.apply_changes(vec![change.clone()]).unwrap();
+        change
+    }
+
+    async fn send(&mut self, message: Message) {
+        write_message(&mut self.stream, &message).await.unwrap();
+    }
+
+    async fn subscribe(&mut self) {
+        let heads = self.backend.get_heads();
+        self.send(Message::Subscribe {
+            doc_id: DOC_ID.into(),
+            heads,
+        })
+        .await;
+    }
+
+    /// Read one message and reply to it
+    async fn receive(&mut self) -> Message {
+        let message = read_message(&mut self.stream).await.unwrap().unwrap();
+        match &message {
+            Message::Heads { heads, .. } => {
+                let changes: Vec<Change> = self
+                    .backend
+                    .get_changes(heads)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                if !changes.is_empty() {
+                    self.send(Message::Changes {
+                        doc_id: DOC_ID.into(),
+                        changes,
+                    })
+                    .await;
+                }
+            }
+            Message::Changes { changes, .. } => {
+                self.backend.apply_changes(changes.clone()).unwrap();
+            }
+            Message::Need { hashes, .. } => {
+                let changes = hashes
+                    .iter()
+                    .filter_map(|hash| self.backend.get_change_by_hash(hash))
+                    .cloned()
+                    .collect();
+                self.send(Message::Changes {
+                    doc_id: DOC_ID.into(),
+                    changes,
+                })
+                .await;
+            }
+            Message::Subscribe { .. } => panic!("the hub sent a subscribe message"),
+        }
+        message
+    }
+
+    async fn receive_until_heads(&mut self, heads: &[ChangeHash]) {
+        let mut heads = heads.to_vec();
+        heads.sort();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while self.backend.get_heads() != heads {
+                self.receive().await;
+            }
+        })
+        .await
+        .expect("timed out waiting for changes");
+    }
+}
+
+async fn wait_for_hub(hub: &Hub<MemoryStorage>, heads: &[ChangeHash]) {
+    let mut heads = heads.to_vec();
+    heads.sort();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while hub.backend(DOC_ID).await.unwrap().get_heads() != heads {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("timed out waiting for the hub");
+}
+
+#[tokio::test]
+async fn test_changes_are_relayed_between_peers() {
+    let hub = Hub::new(MemoryStorage::default());
+    let (mut alice, _) = TestPeer::connect(&hub, "01234567");
+    let (mut bob, _) = TestPeer::connect(&hub, "89abcdef");
+
+    // Alice has a change before subscribing, the hub asks for it
+    alice.set("magpies", 1);
+    alice.subscribe().await;
+    assert!(matches!(alice.receive().await, Message::Heads { heads, .. } if heads.is_empty()));
+
+    bob.subscribe().await;
+    bob.receive_until_heads(&alice.backend.get_heads()).await;
+
+    // Changes sent after subscribing are forwarded to the other peers
+    let change = bob.set("wrens", 2);
+    bob.send(Message::Changes {
+        doc_id: DOC_ID.into(),
+        changes: vec![change],
+    })
+    .await;
+    alice.receive_until_heads(&bob.backend.get_heads()).await;
+
+    assert_eq!(alice.backend.get_patch(), bob.backend.get_patch());
+}
+
+#[tokio::test]
+async fn test_subscribing_sends_only_missing_changes() {
+    let hub = Hub::new(MemoryStorage::default());
+    let (mut alice, _) = TestPeer::connect(&hub, "01234567");
+    alice.set("magpies", 1);
+    alice.subscribe().await;
+    alice.receive().await;
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+
+    let (mut bob, _) = TestPeer::connect(&hub, "89abcdef");
+    let first = alice.backend.get_changes(&[])[0].clone();
+    bob.backend.apply_changes(vec![first]).unwrap();
+    alice.set("wrens", 2);
+    let second = alice.backend.get_changes(&bob.backend.get_heads())[0].clone();
+    alice
+        .send(Message::Changes {
+            doc_id: DOC_ID.into(),
+            changes: vec![second.clone()],
+        })
+        .await;
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+
+    bob.subscribe().await;
+    match bob.receive().await {
+        Message::Changes { changes, .. } => assert_eq!(changes, vec![second]),
+        other => panic!("unexpected message {:?}", other),
+    }
+    match bob.receive().await {
+        Message::Heads { heads, .. } => assert_eq!(heads, alice.backend.get_heads()),
+        other => panic!("unexpected message {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_missing_dependencies_are_requested() {
+    let hub = Hub::new(MemoryStorage::default());
+    let (mut alice, _) = TestPeer::connect(&hub, "01234567");
+    let first = alice.set("magpies", 1);
+    let second = alice.set("wrens", 2);
+    alice
+        .send(Message::Subscribe {
+            doc_id: DOC_ID.into(),
+            heads: Vec::new(),
+        })
+        .await;
+    let heads = read_message(&mut alice.stream).await.unwrap().unwrap();
+    assert!(matches!(heads, Message::Heads { heads, .. } if heads.is_empty()));
+
+    alice
+        .send(Message::Changes {
+            doc_id: DOC_ID.into(),
+            changes: vec![second],
+        })
+        .await;
+    assert_eq!(
+        alice.receive().await,
+        Message::Need {
+            doc_id: DOC_ID.into(),
+            hashes: vec![first.hash],
+        }
+    );
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+}
+
+#[tokio::test]
+async fn test_queued_changes_are_relayed_once() {
+    let hub = Hub::new(MemoryStorage::default());
+    let (mut alice, _) = TestPeer::connect(&hub, "01234567");
+    let (mut bob, _) = TestPeer::connect(&hub, "89abcdef");
+    alice.subscribe().await;
+    alice.receive().await;
+    bob.subscribe().await;
+    bob.receive().await;
+
+    let first = alice.set("magpies", 1);
+    let second = alice.set("wrens", 2);
+    let third = alice.set("jays", 3);
+    // The second change is queued until the first arrives, sending it again
+    // in the meantime does nothing
+    let batches = [
+        vec![second.clone()],
+        vec![second.clone(), second.clone()],
+        vec![first.clone()],
+        vec![third.clone()],
+    ];
+    for changes in &batches {
+        alice
+            .send(Message::Changes {
+                doc_id: DOC_ID.into(),
+                changes: changes.clone(),
+            })
+            .await;
+    }
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+
+    for expected in &[second, first, third] {
+        match read_message(&mut bob.stream).await.unwrap().unwrap() {
+            Message::Changes { changes, .. } => assert_eq!(changes, std::slice::from_ref(expected)),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_slow_peers_are_disconnected() {
+    let hub = Hub::new(MemoryStorage::default());
+    let (mut alice, _) = TestPeer::connect(&hub, "01234567");
+    let (mut bob, bob_connection) = TestPeer::connect(&hub, "89abcdef");
+    alice.subscribe().await;
+    alice.receive().await;
+    bob.subscribe().await;
+    bob.receive().await;
+
+    // Bob stops reading, so the messages relayed to him back up
+    for value in 0..(PEER_QUEUE_LEN as i64 * 2) {
+        let change = alice.set("magpies", value);
+        alice
+            .send(Message::Changes {
+                doc_id: DOC_ID.into(),
+                changes: vec![change],
+            })
+            .await;
+    }
+    let result = tokio::time::timeout(Duration::from_secs(5), bob_connection)
+        .await
+        .expect("the slow peer wasn't disconnected");
+    assert!(matches!(result.unwrap(), Err(ServerError::PeerTooSlow)));
+
+    // Alice is still connected
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+    let change = alice.set("wrens", 1);
+    alice
+        .send(Message::Changes {
+            doc_id: DOC_ID.into(),
+            changes: vec![change],
+        })
+        .await;
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+}
+
+#[tokio::test]
+async fn test_documents_are_saved_and_loaded() {
+    let storage = MemoryStorage::default();
+    let hub = Hub::new(storage.clone());
+    let (mut alice, connection) = TestPeer::connect(&hub, "01234567");
+    alice.set("magpies", 1);
+    alice.subscribe().await;
+    alice.receive().await;
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+    alice.stream.shutdown().await.unwrap();
+    drop(alice.stream);
+    connection.await.unwrap().unwrap();
+
+    let restarted = Hub::new(storage);
+    let (mut bob, _) = TestPeer::connect(&restarted, "89abcdef");
+    bob.subscribe().await;
+    bob.receive_until_heads(&alice.backend.get_heads()).await;
+}
+
+#[tokio::test]
+async fn test_new_changes_are_appended_to_the_saved_document() {
+    let storage = MemoryStorage::default();
+    let hub = Hub::new(storage.clone());
+    let (mut alice, _) = TestPeer::connect(&hub, "01234567");
+    alice.set("magpies", 1);
+    alice.subscribe().await;
+    alice.receive().await;
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+    let saved = storage.load(DOC_ID).await.unwrap().unwrap();
+
+    let change = alice.set("wrens", 2);
+    alice
+        .send(Message::Changes {
+            doc_id: DOC_ID.into(),
+            changes: vec![change.clone()],
+        })
+        .await;
+    wait_for_hub(&hub, &alice.backend.get_heads()).await;
+
+    let mut expected = saved;
+    expected.extend_from_slice(&change.bytes);
+    let stored = storage.load(DOC_ID).await.unwrap().unwrap();
+    assert_eq!(stored, expected);
+    assert_eq!(
+        Backend::load(stored).unwrap().get_heads(),
+        alice.backend.get_heads()
+    );
+}
+
+#[tokio::test]
+async fn test_messages_for_unsubscribed_documents_are_rejected() {
+    let storage = MemoryStorage::default();
+    let hub = Hub::new(storage.clone());
+    let (mut alice, connection) = TestPeer::connect(&hub, "01234567");
+    let change = alice.set("magpies", 1);
+    alice
+        .send(Message::Changes {
+            doc_id: DOC_ID.into(),
+            changes: vec![change],
+        })
+        .await;
+    assert!(matches!(
+        connection.await.unwrap(),
+        Err(ServerError::NotSubscribed(doc_id)) if doc_id == DOC_ID
+    ));
+    assert_eq!(storage.load(DOC_ID).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_invalid_messages_close_the_connection() {
+    let hub = Hub::new(MemoryStorage::default());
+    let (mut alice, connection) = TestPeer::connect(&hub, "01234567");
+    alice.stream.write_all(&[0, 0, 0, 1, 9]).await.unwrap();
+    assert!(matches!(
+        connection.await.unwrap(),
+        Err(ServerError::InvalidMessage(_))
+    ));
+}
@@ -1,67 +1,52 @@
-use automerge::{Backend, Frontend, InvalidChangeRequest, LocalChange, Path, Primitive, Value};
+use automerge::{Document, LocalChange, MapType, Path, Primitive, Value};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::{thread_rng, Rng};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::default::Default;
 
+/// Two documents which both have an empty text object at "text"
+fn docs_with_text() -> (Document, Document) {
+    let mut doc1 = Document::new();
+    doc1.change(None, |d| {
+        d.add_change(LocalChange::set(
+            Path::root().key("text"),
+            Value::Text(Vec::new()),
+        ))
+    })
+    .unwrap();
+    let mut doc2 = Document::new();
+    doc2.merge(&doc1).unwrap();
+    (doc1, doc2)
+}
+
 pub fn b1_1(c: &mut Criterion) {
     c.bench_function("B1.1 Append N characters", move |b| {
         b.iter_batched(
             || {
-                let mut doc1 = Frontend::new();
-                let changedoc1 = doc1
-                    .change::<_, InvalidChangeRequest>(None, |d| {
-                        d.add_change(LocalChange::set(
-                            Path::root().key("text"),
-                            Value::Text(Vec::new()),
-                        ))?;
-                        Ok(())
-                    })
-                    .unwrap()
-                    .unwrap();
-                let mut backend1 = Backend::init();
-                let (patch1, _) = backend1.apply_local_change(changedoc1).unwrap();
-                doc1.apply_patch(patch1).unwrap();
-
-                let mut doc2 = Frontend::new();
-                let changedoc2 = backend1.get_changes(&[]);
-                let mut backend2 = Backend::init();
-                let patch2 = backend2
-                    .apply_changes(changedoc2.into_iter().cloned().collect())
-                    .unwrap();
-                doc2.apply_patch(patch2).unwrap();
-
+                let (doc1, doc2) = docs_with_text();
                 let random_string: String = thread_rng()
                     .sample_iter(&rand::distributions::Alphanumeric)
                     .take(6000)
                     .map(char::from)
                     .collect();
-                (doc1, backend1, doc2, backend2, random_string)
+                (doc1, doc2, random_string)
             },
-            |(mut doc1, mut backend1, mut doc2, mut backend2, random_string)| {
+            |(mut doc1, mut doc2, random_string)| {
                 #[allow(clippy::unit_arg)]
                 black_box({
                     for (index, c) in random_string.chars().enumerate() {
                         let index: u32 = index.try_into().unwrap();
-                        let doc1_insert_change = doc1
-                            .change::<_, InvalidChangeRequest>(None, |d| {
+                        let change_to_send = doc1
+                            .change(None, |d| {
                                 d.add_change(LocalChange::insert(
                                     Path::root().key("text").index(index),
                                     c.into(),
-                                ))?;
-                                Ok(())
+                                ))
                             })
                             .unwrap()
                             .unwrap();
-                        let (patch, change_to_send) =
-                            backend1.apply_local_change(doc1_insert_change).unwrap();
-                        doc1.apply_patch(patch).unwrap();
-
-                        let patch2 = backend2
-                            .apply_changes(vec![(*change_to_send).clone()])
-                            .unwrap();
-                        doc2.apply_patch(patch2).unwrap()
+                        doc2.apply_changes(vec![change_to_send]).unwrap()
                     }
                 })
             },
@@ -74,29 +59,7 @@ pub fn b1_2(c: &mut Criterion) {
     c.bench_function("B1.2 Append string of length N", move |b| {
         b.iter_batched(
             || {
-                let mut doc1 = Frontend::new();
-                let changedoc1 = doc1
-                    .change::<_, InvalidChangeRequest>(None, |d| {
-                        d.add_change(LocalChange::set(
-                            Path::root().key("text"),
-                            Value::Text(Vec::new()),
-                        ))?;
-                        Ok(())
-                    })
-                    .unwrap()
-                    .unwrap();
-                let mut backend1 = Backend::init();
-                let (patch1, _) = backend1.apply_local_change(changedoc1).unwrap();
-                doc1.apply_patch(patch1).unwrap();
-
-                let mut doc2 = Frontend::new();
-                let changedoc2 = backend1.get_changes(&[]);
-                let mut backend2 = Backend::init();
-                let patch2 = backend2
-                    .apply_changes(changedoc2.into_iter().cloned().collect())
-                    .unwrap();
-                doc2.apply_patch(patch2).unwrap();
-
+                let (doc1, doc2) = docs_with_text();
                 let random_string: String = thread_rng()
                     .sample_iter(&rand::distributions::Alphanumeric)
                     .take(6000)
@@ -104,26 +67,19 @@ pub fn b1_2(c: &mut Criterion) {
                     .collect();
                 let chars: Vec<char> = random_string.chars().collect();
                 let text = Value::Text(chars);
-                (doc1, backend1, doc2, backend2, text)
+                (doc1, doc2, text)
             },
-            |(mut doc1, mut backend1, mut doc2, mut backend2, text)| {
+            |(mut doc1, mut doc2, text)| {
                 #[allow(clippy::unit_arg)]
                 black_box({
-                    let doc1_insert_change = doc1
-                        .change::<_, InvalidChangeRequest>(None, |d| {
+                    let change_to_send = doc1
+                        .change(None, |d| {
                             d.add_change(LocalChange::set(Path::root().key("text"), text))
                         })
                         .unwrap()
                         .unwrap();
-                    let (patch, change_to_send) =
-                        backend1.apply_local_change(doc1_insert_change).unwrap();
-                    doc1.apply_patch(patch).unwrap();
-
-                    let patch2 = backend2
-                        .apply_changes(vec![(*change_to_send).clone()])
-                        .unwrap();
-                    doc2.apply_patch(patch2).unwrap();
-                    (doc1, backend1, doc2, backend2)
+                    doc2.apply_changes(vec![change_to_send]).unwrap();
+                    (doc1, doc2)
                 })
             },
             criterion::BatchSize::SmallInput,
@@ -137,49 +93,34 @@ pub fn b3_1(c: &mut Criterion) {
             || {
                 let n: f64 = 6000.0;
                 let root_n: i64 = n.sqrt().floor() as i64;
-                let mut local_doc = Frontend::new();
-                let mut local_backend = Backend::init();
+                let mut local_doc = Document::new();
                 let init_change = local_doc
-                    .change::<_, InvalidChangeRequest>(None, |d| {
+                    .change(None, |d| {
                         d.add_change(LocalChange::set(
                             Path::root().key("map"),
-                            Value::Map(HashMap::new(), automerge_protocol::MapType::Map),
+                            Value::Map(HashMap::new(), MapType::Map),
                         ))
                     })
                     .unwrap()
                     .unwrap();
-                let (patch, init_binchange) =
-                    local_backend.apply_local_change(init_change).unwrap();
-                local_doc.apply_patch(patch).unwrap();
 
-                let other_docs: Vec<Frontend> = (1..root_n).map(|_| Frontend::new()).collect();
-                let updates: Vec<automerge_backend::Change> = other_docs
-                    .into_iter()
-                    .enumerate()
-                    .map(|(index, mut doc)| {
-                        let mut backend = Backend::init();
-                        let patch = backend
-                            .apply_changes(vec![(*init_binchange).clone()])
-                            .unwrap();
-                        doc.apply_patch(patch).unwrap();
-                        let change = doc
-                            .change(None, |d| {
-                                d.add_change(LocalChange::set(
-                                    Path::root().key("map").key("v"),
-                                    Value::Primitive(Primitive::Int(index as i64 + 1)),
-                                ))
-                            })
-                            .unwrap()
-                            .unwrap();
-                        (*backend.apply_local_change(change).unwrap().1).clone()
+                let updates: Vec<automerge::Change> = (1..root_n)
+                    .map(|index| {
+                        let mut doc = Document::new();
+                        doc.apply_changes(vec![init_change.clone()]).unwrap();
+                        doc.change(None, |d| {
+                            d.add_change(LocalChange::set(
+                                Path::root().key("map").key("v"),
+                                Value::Primitive(Primitive::Int(index)),
+                            ))
+                        })
+                        .unwrap()
+                        .unwrap()
                     })
                     .collect();
-                (local_doc, local_backend, updates)
-            },
-            |(mut local_doc, mut local_backend, updates)| {
-                let patch = local_backend.apply_changes(updates).unwrap();
-                local_doc.apply_patch(patch)
+                (local_doc, updates)
             },
+            |(mut local_doc, updates)| local_doc.apply_changes(updates),
             criterion::BatchSize::SmallInput,
         );
     });
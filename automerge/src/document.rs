@@ -0,0 +1,148 @@
+use automerge_backend::{AutomergeError, Backend, Change};
+use automerge_frontend::{
+    Frontend, InvalidChangeRequest, InvalidPatch, MutableDocument, Path, Value,
+};
+use automerge_protocol::{ActorId, ChangeHash};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DocumentError {
+    #[error(transparent)]
+    Backend(#[from] AutomergeError),
+    #[error(transparent)]
+    InvalidPatch(#[from] InvalidPatch),
+    #[error(transparent)]
+    InvalidChangeRequest(#[from] InvalidChangeRequest),
+}
+
+/// A `Frontend` and a `Backend` in one, for applications which don't need to
+/// run them separately. Every local change is applied to the backend and the
+/// resulting patch to the frontend before `change` returns, so the two are
+/// always in step.
+///
+/// ```
+/// use automerge::{Document, LocalChange, Path, Value};
+///
+/// let mut doc = Document::new();
+/// doc.change(None, |d| d.add_change(LocalChange::set(Path::root().key("bird"), "magpie")))
+///     .unwrap();
+/// assert_eq!(doc.value().to_json(), serde_json::json!({"bird": "magpie"}));
+/// ```
+#[derive(Debug)]
+pub struct Document {
+    frontend: Frontend,
+    backend: Backend,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Document {
+    /// An empty document with a random actor ID
+    pub fn new() -> Document {
+        Document {
+            frontend: Frontend::new(),
+            backend: Backend::init(),
+        }
+    }
+
+    pub fn new_with_actor_id(actor_id: ActorId) -> Document {
+        let mut doc = Document::new();
+        doc.frontend.actor_id = actor_id;
+        doc
+    }
+
+    /// Load a document saved with `save`, local changes will be made with a
+    /// random actor ID
+    pub fn load(data: Vec<u8>) -> Result<Document, DocumentError> {
+        let backend = Backend::load(data)?;
+        let mut frontend = Frontend::new();
+        frontend.apply_patch(backend.get_patch()?)?;
+        Ok(Document { frontend, backend })
+    }
+
+    pub fn save(&self) -> Result<Vec<u8>, DocumentError> {
+        Ok(self.backend.save()?)
+    }
+
+    pub fn actor_id(&self) -> &ActorId {
+        &self.frontend.actor_id
+    }
+
+    /// Make a change with `change_closure` and apply it. Returns the change
+    /// so it can be sent to other peers, or `None` if the closure didn't
+    /// change anything. If the closure returns an error, or the backend
+    /// refuses the change, the document is left unchanged.
+    pub fn change<F>(
+        &mut self,
+        message: Option<String>,
+        change_closure: F,
+    ) -> Result<Option<Change>, DocumentError>
+    where
+        F: FnOnce(&mut dyn MutableDocument) -> Result<(), InvalidChangeRequest>,
+    {
+        match self.frontend.change(message, change_closure)? {
+            Some(change) => {
+                let (patch, change) = match self.backend.apply_local_change(change) {
+                    Ok(applied) => applied,
+                    Err(e) => {
+                        self.frontend.cancel_last_change();
+                        return Err(e.into());
+                    }
+                };
+                self.frontend.apply_patch(patch)?;
+                Ok(Some((*change).clone()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Apply changes from another peer, changes whose dependencies haven't
+    /// been applied yet are held until they have
+    pub fn apply_changes(&mut self, changes: Vec<Change>) -> Result<(), DocumentError> {
+        let patch = self.backend.apply_changes(changes)?;
+        self.frontend.apply_patch(patch)?;
+        Ok(())
+    }
+
+    /// Apply every change in `other` which isn't in this document
+    pub fn merge(&mut self, other: &Document) -> Result<(), DocumentError> {
+        let changes = other
+            .get_changes(&self.get_heads())
+            .into_iter()
+            .cloned()
+            .collect();
+        self.apply_changes(changes)
+    }
+
+    /// The changes which aren't ancestors of `have_deps`, pass an empty slice
+    /// to get every change
+    pub fn get_changes(&self, have_deps: &[ChangeHash]) -> Vec<&Change> {
+        self.backend.get_changes(have_deps)
+    }
+
+    pub fn get_heads(&self) -> Vec<ChangeHash> {
+        self.backend.get_heads()
+    }
+
+    /// The whole document
+    pub fn value(&mut self) -> &Value {
+        self.frontend.state()
+    }
+
+    /// The value at `path`, or `None` if there is nothing there
+    pub fn get_value(&self, path: &Path) -> Option<Value> {
+        self.frontend.get_value(path)
+    }
+
+    pub fn frontend(&self) -> &Frontend {
+        &self.frontend
+    }
+
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+}
@@ -1,3 +1,8 @@
+mod document;
+
 pub use automerge_backend::{Backend, Change};
-pub use automerge_frontend::{Frontend, InvalidChangeRequest, LocalChange, Path, Primitive, Value};
-pub use automerge_protocol::{MapType, ObjType, ScalarValue, SequenceType};
+pub use automerge_frontend::{
+    Frontend, InvalidChangeRequest, LocalChange, MutableDocument, Path, Primitive, Value,
+};
+pub use automerge_protocol::{ActorId, ChangeHash, MapType, ObjType, ScalarValue, SequenceType};
+pub use document::{Document, DocumentError};
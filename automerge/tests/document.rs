@@ -0,0 +1,151 @@
+use automerge::{
+    ActorId, Document, DocumentError, InvalidChangeRequest, LocalChange, Path, Primitive, Value,
+};
+use std::convert::TryInto;
+
+fn set(doc: &mut Document, key: &str, value: &str) {
+    doc.change(None, |d| {
+        d.add_change(LocalChange::set(Path::root().key(key), value))
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_change_updates_the_value() {
+    let mut doc = Document::new();
+    let change = doc
+        .change(Some("add birds".into()), |d| {
+            d.add_change(LocalChange::set(
+                Path::root().key("birds"),
+                Value::Sequence(vec!["wren".into()]),
+            ))
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(change.actor_id(), doc.actor_id());
+    assert_eq!(doc.get_heads(), vec![change.hash]);
+    assert_eq!(
+        doc.value().to_json(),
+        serde_json::json!({"birds": ["wren"]})
+    );
+    assert_eq!(
+        doc.get_value(&Path::root().key("birds").index(0)),
+        Some("wren".into())
+    );
+}
+
+#[test]
+fn test_empty_and_failed_changes_do_nothing() {
+    let mut doc = Document::new();
+    assert!(doc.change(None, |_| Ok(())).unwrap().is_none());
+    assert!(doc
+        .change(None, |d| {
+            d.add_change(LocalChange::set(Path::root().key("bird"), "magpie"))?;
+            Err(InvalidChangeRequest::CannotSetNonMapObjectAsRoot {
+                value: Value::Text(Vec::new()),
+            })
+        })
+        .is_err());
+    assert!(doc.get_changes(&[]).is_empty());
+    assert_eq!(doc.value().to_json(), serde_json::json!({}));
+}
+
+#[test]
+fn test_changes_the_backend_refuses_do_nothing() {
+    let mut doc = Document::new();
+    set(&mut doc, "bird", "magpie");
+    // the frontend allows an unknown value with a known type code, the
+    // backend doesn't
+    let result = doc.change(None, |d| {
+        d.add_change(LocalChange::set(
+            Path::root().key("bird"),
+            Primitive::Unknown {
+                type_code: 3,
+                bytes: vec![1],
+            },
+        ))
+    });
+    assert!(matches!(result, Err(DocumentError::Backend(_))));
+    assert_eq!(doc.value().to_json(), serde_json::json!({"bird": "magpie"}));
+    assert!(doc.frontend().in_flight_requests().is_empty());
+
+    // the next change uses the seq the refused one would have had
+    set(&mut doc, "bird", "wren");
+    assert_eq!(doc.get_changes(&[]).len(), 2);
+    assert_eq!(doc.value().to_json(), serde_json::json!({"bird": "wren"}));
+}
+
+#[test]
+fn test_bytes_values() {
+    let mut doc = Document::new();
+    doc.change(None, |d| {
+        d.add_change(LocalChange::set(
+            Path::root().key("thumbnail"),
+            Primitive::Bytes(vec![0, 1, 2, 255]),
+        ))
+    })
+    .unwrap();
+    let mut loaded = Document::load(doc.save().unwrap()).unwrap();
+    assert_eq!(
+        loaded.get_value(&Path::root().key("thumbnail")),
+        Some(Value::Primitive(Primitive::Bytes(vec![0, 1, 2, 255])))
+    );
+    assert_eq!(
+        loaded.value().to_json(),
+        serde_json::json!({"thumbnail": "AAEC/w=="})
+    );
+}
+
+#[test]
+fn test_save_and_load() {
+    let mut doc = Document::new();
+    set(&mut doc, "bird", "magpie");
+    let mut loaded = Document::load(doc.save().unwrap()).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(loaded.value(), doc.value());
+
+    // The loaded document can carry on making changes
+    set(&mut loaded, "fish", "cod");
+    assert_eq!(
+        loaded.value().to_json(),
+        serde_json::json!({"bird": "magpie", "fish": "cod"})
+    );
+}
+
+#[test]
+fn test_merge_and_apply_changes() {
+    let actor1: ActorId = "01234567".try_into().unwrap();
+    let actor2: ActorId = "89abcdef".try_into().unwrap();
+    let mut doc1 = Document::new_with_actor_id(actor1);
+    let mut doc2 = Document::new_with_actor_id(actor2);
+    set(&mut doc1, "bird", "magpie");
+    set(&mut doc2, "bird", "wren");
+    set(&mut doc2, "fish", "cod");
+
+    doc1.merge(&doc2).unwrap();
+    // Merging again doesn't apply anything twice
+    doc1.merge(&doc2).unwrap();
+    assert_eq!(doc1.get_changes(&[]).len(), 3);
+
+    let changes = doc1
+        .get_changes(&doc2.get_heads())
+        .into_iter()
+        .cloned()
+        .collect();
+    doc2.apply_changes(changes).unwrap();
+
+    assert_eq!(doc1.get_heads(), doc2.get_heads());
+    assert_eq!(doc1.value(), doc2.value());
+    // The change with the highest actor ID wins the conflict
+    assert_eq!(
+        doc1.value().to_json(),
+        serde_json::json!({"bird": "wren", "fish": "cod"})
+    );
+    assert_eq!(
+        doc1.frontend()
+            .get_conflicts(&Path::root().key("bird"))
+            .unwrap()
+            .len(),
+        2
+    );
+}